@@ -0,0 +1,74 @@
+use std::path::Path;
+
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::{Range, TextDocumentIdentifier, Url};
+
+use crate::vault::{Referenceable, Vault};
+
+/// The method of the custom request for the backlinks of a note
+pub const BACKLINKS_METHOD: &str = "markdown-oxide/backlinks";
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct BacklinksParams {
+    pub text_document: TextDocumentIdentifier,
+}
+
+/// The links to a note from one file
+#[derive(Serialize, Debug, PartialEq)]
+pub struct FileBacklinks {
+    pub uri: Url,
+    pub links: Vec<Backlink>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Backlink {
+    pub range: Range,
+    /// The whole line the link is on, for context
+    pub text: String,
+}
+
+/// The links to the file and to its headings and blocks, grouped by the file they are in; the most recently modified
+/// files are first
+pub fn backlinks(vault: &Vault, path: &Path) -> Option<Vec<FileBacklinks>> {
+    let md_file = vault.md_files.get(path)?;
+    let path = path.to_path_buf();
+    let references =
+        vault.select_references_for_referenceable(&Referenceable::File(&path, md_file))?;
+
+    let grouped = references.into_iter().fold(
+        IndexMap::<&Path, Vec<Backlink>>::new(),
+        |mut grouped, (ref_path, reference)| {
+            let range = *reference.data().range;
+            let text = vault
+                .select_line(ref_path, range.start.line as isize)
+                .map(|line| {
+                    String::from_iter(line)
+                        .trim_end_matches(['\n', '\r'])
+                        .to_string()
+                })
+                .unwrap_or_default();
+
+            grouped
+                .entry(ref_path)
+                .or_default()
+                .push(Backlink { range, text });
+            grouped
+        },
+    );
+
+    Some(
+        grouped
+            .into_iter()
+            .filter_map(|(ref_path, mut links)| {
+                links.sort_by_key(|link| (link.range.start.line, link.range.start.character));
+
+                Some(FileBacklinks {
+                    uri: Url::from_file_path(ref_path).ok()?,
+                    links,
+                })
+            })
+            .collect(),
+    )
+}
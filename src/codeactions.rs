@@ -1,45 +1,70 @@
-use std::path::{Path, PathBuf};
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    path::{Component, Path, PathBuf},
+};
 
 use pathdiff::diff_paths;
 use tower_lsp::lsp_types::{
-    CodeAction, CodeActionOrCommand, CodeActionParams, CreateFile, CreateFileOptions,
-    DocumentChangeOperation, DocumentChanges, OneOf, OptionalVersionedTextDocumentIdentifier,
-    Position, Range, ResourceOp, TextDocumentEdit, TextEdit, Url, WorkspaceEdit,
+    CodeAction, CodeActionKind, CodeActionOrCommand, CodeActionParams, CreateFile,
+    CreateFileOptions, DocumentChangeOperation, DocumentChanges, OneOf,
+    OptionalVersionedTextDocumentIdentifier, Position, Range, ResourceOp, TextDocumentEdit,
+    TextEdit, Url, WorkspaceEdit,
 };
 
 use crate::{
-    diagnostics::path_unresolved_references,
-    vault::{Reference, Vault},
+    completion::{markdown_infile_ref, markdown_link_path, percent_encode_refname},
+    config::{LinkPathEncoding, LinkStyle, MarkdownHeadingAnchors, Settings},
+    diagnostics::{
+        duplicate_indexed_blocks, path_unresolved_references, similar_files, unresolved_link_file,
+    },
+    extract::{extract_note_edit, ExtractNoteArgs},
+    mentions::{line_mentions, mention_names},
+    resolve::{existing_file, new_note_path},
+    vault::{get_obsidian_ref_path, Reference, ReferenceData, Referenceable, Vault},
 };
 
 pub fn code_actions(
     vault: &Vault,
+    settings: &Settings,
     params: &CodeActionParams,
     path: &Path,
 ) -> Option<Vec<CodeActionOrCommand>> {
+    let convert_link = convert_link_action(vault, settings, params, path);
+    let regenerate_block_index = regenerate_block_index_action(vault, settings, params, path);
+    let extract_note = extract_note_actions(vault, settings, params, path);
+    let link_mention = link_mention_action(vault, settings, params, path);
+
     // Diagnostics
     // get all links for changed file
 
-    let unresolved = path_unresolved_references(vault, path)?;
+    let unresolved = path_unresolved_references(vault, path).unwrap_or_default();
 
     let unresolved_file_links = unresolved;
 
-    let code_action_unresolved = unresolved_file_links.into_iter().filter(|(_, reference)| {
-        reference.data().range.start.line <= params.range.start.line
-            && reference.data().range.end.line >= params.range.end.line
-            && reference.data().range.start.character <= params.range.start.character
-            && reference.data().range.end.character >= params.range.end.character
-    });
+    let code_action_unresolved = unresolved_file_links
+        .into_iter()
+        .filter(|(_, reference)| {
+            reference.data().range.start.line <= params.range.start.line
+                && reference.data().range.end.line >= params.range.end.line
+                && reference.data().range.start.character <= params.range.start.character
+                && reference.data().range.end.character >= params.range.end.character
+        })
+        .collect::<Vec<_>>();
+
+    let similar_file = code_action_unresolved
+        .iter()
+        .flat_map(|(_, reference)| similar_file_actions(vault, settings, path, reference))
+        .collect::<Vec<_>>();
 
     Some(
-        code_action_unresolved
-            .flat_map(|(_path, reference)| {
+        similar_file
+            .into_iter()
+            .chain(code_action_unresolved.into_iter().flat_map(|(_path, reference)| {
                 match reference {
-                    Reference::WikiFileLink(_data) => {
-                        let mut new_path_buf = PathBuf::new();
-                        new_path_buf.push(vault.root_dir());
-                        new_path_buf.push(&reference.data().reference_text);
-                        new_path_buf.set_extension("md");
+                    Reference::WikiFileLink(data) => {
+                        let new_path_buf =
+                            new_note_path(vault, settings, path, &data.reference_text, true)?;
 
                         let new_path = Url::from_file_path(&new_path_buf).ok()?;
 
@@ -62,11 +87,8 @@ pub fn code_actions(
                         }))
                     }
                     Reference::WikiHeadingLink(_data, link_path, heading) => {
-
-                        let mut new_path_buf = PathBuf::new();
-                        new_path_buf.push(vault.root_dir());
-                        new_path_buf.push(link_path);
-                        new_path_buf.set_extension("md");
+                        let new_path_buf = existing_file(vault, path, link_path, true)
+                            .or_else(|| new_note_path(vault, settings, path, link_path, true))?;
 
                         let new_path = Url::from_file_path(&new_path_buf).ok()?;
 
@@ -130,7 +152,552 @@ pub fn code_actions(
                     _ => None
                 }
 
-            })
+            }))
+            .chain(convert_link)
+            .chain(regenerate_block_index)
+            .chain(extract_note)
+            .chain(link_mention)
             .collect(),
     )
 }
+
+/// Point an unresolved link to a note with a similar name, for typos like `[[projetcs/foo]]`; the closest match is
+/// preferred. The link keeps its heading or block and its display text.
+fn similar_file_actions(
+    vault: &Vault,
+    settings: &Settings,
+    path: &Path,
+    reference: &Reference,
+) -> Vec<CodeActionOrCommand> {
+    let Some(link_file) = unresolved_link_file(vault, path, reference) else {
+        return vec![];
+    };
+    let infile = match reference {
+        Reference::WikiHeadingLink(_, _, heading) | Reference::MDHeadingLink(_, _, heading) => {
+            format!("#{}", heading)
+        }
+        Reference::WikiIndexedBlockLink(_, _, index)
+        | Reference::MDIndexedBlockLink(_, _, index) => format!("#^{}", index),
+        _ => String::new(),
+    };
+    let data = reference.data();
+    let display = data.display_text.as_deref();
+
+    similar_files(vault, link_file)
+        .into_iter()
+        .enumerate()
+        .flat_map(|(i, (target, name))| {
+            let new_text = match reference {
+                Reference::WikiFileLink(..)
+                | Reference::WikiHeadingLink(..)
+                | Reference::WikiIndexedBlockLink(..) => format!(
+                    "[[{}{}{}]]",
+                    name,
+                    infile,
+                    display
+                        .map(|display| format!("|{}", display))
+                        .unwrap_or_default()
+                ),
+                _ => {
+                    let link_path = markdown_link_path(settings, vault.root_dir(), path, target)?;
+                    // keep the `.md` only if the link had it
+                    let link_path = match vault.markdown_link_has_extension(path, reference) {
+                        true => link_path,
+                        false => link_path
+                            .strip_suffix(".md")
+                            .unwrap_or(&link_path)
+                            .to_string(),
+                    };
+                    let link = format!("{}{}", link_path, infile);
+
+                    let link = match settings.link_path_encoding {
+                        LinkPathEncoding::Percent => percent_encode_refname(&link),
+                        LinkPathEncoding::Angle if link.contains(' ') => format!("<{}>", link),
+                        LinkPathEncoding::Angle => link,
+                    };
+
+                    format!("[{}]({})", display.unwrap_or(""), link)
+                }
+            };
+
+            Some(CodeActionOrCommand::CodeAction(CodeAction {
+                title: format!("Did you mean `{}`?", name),
+                kind: Some(CodeActionKind::QUICKFIX),
+                is_preferred: Some(i == 0),
+                edit: Some(WorkspaceEdit {
+                    changes: Some(HashMap::from([(
+                        Url::from_file_path(path).ok()?,
+                        vec![TextEdit {
+                            range: *data.range,
+                            new_text,
+                        }],
+                    )])),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }))
+        })
+        .collect()
+}
+
+/// Rewrite the link under the cursor from a markdown link to a wikilink or the other way around
+fn convert_link_action(
+    vault: &Vault,
+    settings: &Settings,
+    params: &CodeActionParams,
+    path: &Path,
+) -> Option<CodeActionOrCommand> {
+    let reference = vault.select_reference_at_position(path, params.range.start)?;
+    let new_text = converted_link(vault, settings, reference, path)?;
+
+    let title = match reference {
+        Reference::WikiFileLink(..)
+        | Reference::WikiHeadingLink(..)
+        | Reference::WikiIndexedBlockLink(..) => "Convert to Markdown Link",
+        _ => "Convert to Wikilink",
+    };
+
+    Some(CodeActionOrCommand::CodeAction(CodeAction {
+        title: title.into(),
+        kind: Some(CodeActionKind::REFACTOR_REWRITE),
+        edit: Some(WorkspaceEdit {
+            changes: Some(HashMap::from([(
+                Url::from_file_path(path).ok()?,
+                vec![TextEdit {
+                    range: *reference.data().range,
+                    new_text,
+                }],
+            )])),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }))
+}
+
+/// The text of a markdown link as a wikilink or the other way around, keeping its display text and infile ref.
+/// Wikilinks are by file name, or by path from the vault root when the name is shared by another file; markdown link
+/// paths follow the `markdown_link_paths` and `link_path_encoding` settings. A wikilink without display text becomes a
+/// markdown link with the heading or the note's name as its text, which is left out again when it is converted back.
+pub fn converted_link(
+    vault: &Vault,
+    settings: &Settings,
+    reference: &Reference,
+    path: &Path,
+) -> Option<String> {
+    let (file, infile) = match reference {
+        Reference::WikiFileLink(data) | Reference::MDFileLink(data) => {
+            (data.reference_text.as_str(), None)
+        }
+        Reference::WikiHeadingLink(_, file, heading)
+        | Reference::MDHeadingLink(_, file, heading) => (file.as_str(), Some(heading.to_string())),
+        Reference::WikiIndexedBlockLink(_, file, index)
+        | Reference::MDIndexedBlockLink(_, file, index) => {
+            (file.as_str(), Some(format!("^{}", index)))
+        }
+        _ => return None,
+    };
+    let is_wikilink = matches!(
+        reference,
+        Reference::WikiFileLink(..)
+            | Reference::WikiHeadingLink(..)
+            | Reference::WikiIndexedBlockLink(..)
+    );
+
+    let resolved = vault
+        .select_referenceables_for_reference(reference, path)
+        .into_iter()
+        .find(|referenceable| !referenceable.is_unresolved());
+
+    // a markdown link may go to the slug of a heading, which a wikilink can't
+    let infile = match &resolved {
+        Some(Referenceable::Heading(_, heading)) if !is_wikilink => {
+            Some(heading.anchor.link_text.clone())
+        }
+        _ => infile,
+    };
+
+    // the file the link goes to, whether or not it exists
+    let target = match &resolved {
+        _ if file.is_empty() => path.to_path_buf(),
+        Some(referenceable) => referenceable.get_path().to_path_buf(),
+        None => new_note_path(vault, settings, path, file, is_wikilink)?,
+    };
+    let same_file = target == path && infile.is_some();
+
+    let default_display = match &resolved {
+        Some(Referenceable::Heading(_, heading)) => heading.anchor.link_text.clone(),
+        _ => target.file_stem()?.to_str()?.to_string(),
+    };
+    let data = reference.data();
+    let display = data
+        .display_text
+        .as_deref()
+        .filter(|display| !display.is_empty());
+    let infile = infile
+        .map(|infile| match is_wikilink {
+            true => match &resolved {
+                Some(Referenceable::Heading(_, heading))
+                    if settings.markdown_heading_anchors == MarkdownHeadingAnchors::Slug =>
+                {
+                    format!("#{}", heading.anchor.slug)
+                }
+                _ => format!("#{}", markdown_infile_ref(settings, &infile)),
+            },
+            false => format!("#{}", infile),
+        })
+        .unwrap_or_default();
+
+    match is_wikilink {
+        true => {
+            let link_path = match same_file {
+                true => String::new(),
+                false => markdown_link_path(settings, vault.root_dir(), path, &target)?,
+            };
+            let link = format!("{}{}", link_path, infile);
+
+            let link = match settings.link_path_encoding {
+                LinkPathEncoding::Percent => percent_encode_refname(&link),
+                LinkPathEncoding::Angle if link.contains(' ') => format!("<{}>", link),
+                LinkPathEncoding::Angle => link,
+            };
+
+            Some(format!(
+                "[{}]({})",
+                display.unwrap_or(&default_display),
+                link
+            ))
+        }
+        false => {
+            let refname = match same_file {
+                true => String::new(),
+                false => wikilink_refname(vault, &target)?,
+            };
+
+            Some(format!(
+                "[[{}{}{}]]",
+                refname,
+                infile,
+                display
+                    .filter(|display| *display != refname && *display != default_display)
+                    .map(|display| format!("|{}", display))
+                    .unwrap_or_default()
+            ))
+        }
+    }
+}
+
+/// Move the selection to a new note, named by its first line, and link to or embed the note in its place
+fn extract_note_actions(
+    vault: &Vault,
+    settings: &Settings,
+    params: &CodeActionParams,
+    path: &Path,
+) -> Vec<CodeActionOrCommand> {
+    if params.range.start == params.range.end {
+        return vec![];
+    }
+
+    let actions = [
+        ("Extract to Note", false),
+        ("Extract to Embedded Note", true),
+    ];
+
+    actions
+        .into_iter()
+        .flat_map(|(title, embed)| {
+            let args = ExtractNoteArgs {
+                uri: params.text_document.uri.clone(),
+                range: params.range,
+                embed,
+                title: None,
+            };
+
+            Some(CodeActionOrCommand::CodeAction(CodeAction {
+                title: title.into(),
+                kind: Some(CodeActionKind::REFACTOR_EXTRACT),
+                edit: Some(extract_note_edit(vault, settings, path, &args)?),
+                ..Default::default()
+            }))
+        })
+        .collect()
+}
+
+/// Link the unlinked mention of another note under the cursor, its name or an alias written as plain text, to the
+/// note: `Project Alpha` -> `[[project-alpha|Project Alpha]]`. Where mentions of several notes overlap, the longest
+/// one is linked.
+fn link_mention_action(
+    vault: &Vault,
+    settings: &Settings,
+    params: &CodeActionParams,
+    path: &Path,
+) -> Option<CodeActionOrCommand> {
+    let position = params.range.start;
+    if vault
+        .select_frontmatter_end_line(path)
+        .is_some_and(|end| position.line as usize <= end)
+    {
+        return None;
+    }
+
+    let (target, range) = vault
+        .md_files
+        .keys()
+        .filter(|target| target.as_path() != path && !vault.is_excluded(target))
+        .flat_map(|target| {
+            let names = mention_names(vault, target)?;
+            line_mentions(vault, path, position.line, &names)
+                .into_iter()
+                .find(|range| {
+                    range.start as u32 <= position.character
+                        && position.character <= range.end as u32
+                })
+                .map(|range| (target, range))
+        })
+        .min_by_key(|(target, range)| (Reverse(range.len()), (*target).clone()))?;
+
+    let mention = String::from_iter(
+        vault
+            .select_line(path, position.line as isize)?
+            .get(range.clone())?,
+    );
+    let refname = wikilink_refname(vault, target)?;
+    let display = (mention != refname).then(|| mention.clone());
+
+    let new_text = match settings.link_style {
+        Some(LinkStyle::Markdown) => {
+            let link = Reference::WikiFileLink(ReferenceData {
+                reference_text: refname.clone(),
+                display_text: display,
+                ..Default::default()
+            });
+            converted_link(vault, settings, &link, path)?
+        }
+        _ => format!(
+            "[[{}{}]]",
+            refname,
+            display
+                .map(|display| format!("|{}", display))
+                .unwrap_or_default()
+        ),
+    };
+
+    Some(CodeActionOrCommand::CodeAction(CodeAction {
+        title: format!("Link to `{}`", refname),
+        kind: Some(CodeActionKind::REFACTOR_REWRITE),
+        edit: Some(WorkspaceEdit {
+            changes: Some(HashMap::from([(
+                Url::from_file_path(path).ok()?,
+                vec![TextEdit {
+                    range: Range {
+                        start: Position {
+                            line: position.line,
+                            character: range.start as u32,
+                        },
+                        end: Position {
+                            line: position.line,
+                            character: range.end as u32,
+                        },
+                    },
+                    new_text,
+                }],
+            )])),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }))
+}
+
+/// Give the block under the cursor a new index when an earlier block in the file already has its index. Links to the
+/// index are left going to the earlier block, except those whose display text is this block's text.
+fn regenerate_block_index_action(
+    vault: &Vault,
+    settings: &Settings,
+    params: &CodeActionParams,
+    path: &Path,
+) -> Option<CodeActionOrCommand> {
+    let duplicates = duplicate_indexed_blocks(vault, path);
+    let line = params.range.start.line;
+    let (_, block) = duplicates.iter().enumerate().find(|(i, block)| {
+        block.range.start.line <= line
+            && line <= block.range.end.line
+            && duplicates[..*i]
+                .iter()
+                .any(|earlier| earlier.index == block.index)
+    })?;
+
+    let new_index = vault.new_block_index(path, settings);
+
+    // the text of the line before the ` ^index`
+    let block_text = vault
+        .select_line(path, block.range.start.line as isize)?
+        .into_iter()
+        .take(block.range.start.character as usize)
+        .collect::<String>()
+        .trim()
+        .to_string();
+
+    let path_buf = path.to_path_buf();
+    let links = vault
+        .select_references_for_referenceable(&Referenceable::IndexedBlock(&path_buf, block))
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(_, reference)| reference.data().display_text.as_deref() == Some(&block_text))
+        .flat_map(|(ref_path, reference)| {
+            let range = reference.data().range;
+            let link = vault
+                .select_line(ref_path, range.start.line as isize)?
+                .into_iter()
+                .skip(range.start.character as usize)
+                .take((range.end.character - range.start.character) as usize)
+                .collect::<String>();
+            let index_start = link.find(&format!("#^{}", block.index))?;
+            let start = range.start.character + link[..index_start].chars().count() as u32 + 1;
+
+            Some((
+                Url::from_file_path(ref_path).ok()?,
+                TextEdit {
+                    range: Range {
+                        start: Position {
+                            line: range.start.line,
+                            character: start,
+                        },
+                        end: Position {
+                            line: range.start.line,
+                            character: start + 1 + block.index.chars().count() as u32,
+                        },
+                    },
+                    new_text: format!("^{}", new_index),
+                },
+            ))
+        });
+
+    let changes = std::iter::once((
+        Url::from_file_path(path).ok()?,
+        TextEdit {
+            range: *block.range,
+            new_text: format!("^{}", new_index),
+        },
+    ))
+    .chain(links)
+    .fold(
+        HashMap::<Url, Vec<TextEdit>>::new(),
+        |mut changes, (url, edit)| {
+            changes.entry(url).or_default().push(edit);
+            changes
+        },
+    );
+
+    Some(CodeActionOrCommand::CodeAction(CodeAction {
+        title: format!("Regenerate Block Id ^{}", block.index),
+        kind: Some(CodeActionKind::QUICKFIX),
+        edit: Some(WorkspaceEdit {
+            changes: Some(changes),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }))
+}
+
+/// The file's name, or its path from the vault root if another file has the same name
+fn wikilink_refname(vault: &Vault, target: &Path) -> Option<String> {
+    let stem = target.file_stem()?;
+    let shared = vault
+        .md_files
+        .keys()
+        .any(|other| other != target && other.file_stem() == Some(stem));
+
+    match shared {
+        true => get_obsidian_ref_path(vault.root_dir(), target),
+        false => Some(stem.to_str()?.to_string()),
+    }
+}
+
+/// Resolve the `.` and `..` in a path without going to the file system
+pub fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .fold(PathBuf::new(), |mut normalized, component| {
+            match component {
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    normalized.pop();
+                }
+                component => normalized.push(component),
+            }
+            normalized
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+    use tower_lsp::lsp_types::{CodeActionOrCommand, TextEdit};
+
+    use crate::vault::{apply_edits, TestVault};
+
+    use super::{converted_link, similar_file_actions};
+
+    /// Convert every link of the file to the other style
+    fn convert_links(test: &mut TestVault, file: &str) -> String {
+        let path = test.path(file);
+        let edits = test.vault.md_files[&path]
+            .references
+            .iter()
+            .filter_map(|reference| {
+                Some(TextEdit {
+                    range: *reference.data().range,
+                    new_text: converted_link(&test.vault, &test.settings, reference, &path)?,
+                })
+            })
+            .collect::<Vec<_>>();
+        let text = apply_edits(&test.vault.ropes[&path].to_string(), &edits);
+        test.edit(&path, &text);
+
+        text
+    }
+
+    #[test]
+    fn test_convert_links_round_trip() {
+        let wikilinks = "[[note]], [[note#Some Heading]], [[note#^block]] and [[note|shown]]\n";
+        let mut test = TestVault::new(&[
+            ("note.md", "# Some Heading\n\ntext ^block\n"),
+            ("today.md", wikilinks),
+        ]);
+
+        // links without display text get the heading or the note's name, rather than an empty `[]`
+        assert_eq!(
+            convert_links(&mut test, "today.md"),
+            "[note](note.md), [Some Heading](<note.md#Some Heading>), [note](note.md#^block) and \
+             [shown](note.md)\n"
+        );
+        assert_eq!(convert_links(&mut test, "today.md"), wikilinks);
+    }
+
+    #[test]
+    fn test_similar_file_actions() {
+        let test = TestVault::new(&[
+            ("meeting.md", ""),
+            ("today.md", "[x](meetng.md) [y](meetng) [[meetng]]\n"),
+        ]);
+        let path = test.path("today.md");
+
+        // the markdown links keep the `.md` only if they had it
+        let fixes = test.vault.md_files[&path]
+            .references
+            .iter()
+            .map(|reference| {
+                match &similar_file_actions(&test.vault, &test.settings, &path, reference)[..] {
+                    [CodeActionOrCommand::CodeAction(action)] => {
+                        let changes = action.edit.as_ref().unwrap().changes.as_ref().unwrap();
+                        changes.values().next().unwrap()[0].new_text.clone()
+                    }
+                    actions => panic!("{:?}", actions),
+                }
+            })
+            .sorted()
+            .collect::<Vec<_>>();
+        assert_eq!(
+            fixes,
+            vec!["[[meeting]]", "[x](meeting.md)", "[y](meeting)"]
+        );
+    }
+}
@@ -1,11 +1,11 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use itertools::Itertools;
-use tower_lsp::lsp_types::{CodeLens, CodeLensParams, Command, Location, Position, Url};
+use tower_lsp::lsp_types::{CodeLens, CodeLensParams, Command, Location, Position, Range, Url};
 
 use crate::vault::{Referenceable, Vault};
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 #[derive(Serialize)]
 struct FindReferencesData {
@@ -14,71 +14,189 @@ struct FindReferencesData {
     locations: Vec<Location>,
 }
 
+/// What a lens is on, kept in its data until the lens is resolved
+#[derive(Serialize, Deserialize)]
+struct CodeLensData {
+    uri: Url,
+    range: Range,
+    kind: CodeLensKind,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy)]
+enum CodeLensKind {
+    File,
+    Heading,
+    IndexedBlock,
+}
+
+impl CodeLensKind {
+    fn of(referenceable: &Referenceable) -> Option<CodeLensKind> {
+        match referenceable {
+            Referenceable::File(..) => Some(CodeLensKind::File),
+            Referenceable::Heading(..) => Some(CodeLensKind::Heading),
+            Referenceable::IndexedBlock(..) => Some(CodeLensKind::IndexedBlock),
+            _ => None,
+        }
+    }
+}
+
+/// A lens for the file and for each heading and block in it, without its command; the references are only counted
+/// when the lens is resolved, for the lenses the editor shows.
 pub fn code_lens(vault: &Vault, path: &Path, _params: &CodeLensParams) -> Option<Vec<CodeLens>> {
-    let referenceables = vault.select_referenceable_nodes(Some(path));
-    let data = referenceables
+    let uri = Url::from_file_path(path).ok()?;
+
+    let lens = vault
+        .select_referenceable_nodes(Some(path))
         .into_iter()
         .filter_map(|referenceable| {
-            let references = vault
-                .select_references_for_referenceable(&referenceable)?
-                .into_iter()
-                .collect_vec();
+            let range = lens_range(&referenceable)?;
+            let data = CodeLensData {
+                uri: uri.clone(),
+                range,
+                kind: CodeLensKind::of(&referenceable)?,
+            };
 
-            Some((referenceable, references))
+            Some(CodeLens {
+                range,
+                command: None,
+                data: serde_json::to_value(data).ok(),
+            })
         })
         .collect_vec();
 
-    let lens = data
+    Some(lens)
+}
+
+/// The file a lens is in, from its data
+pub fn lens_path(lens: &CodeLens) -> Option<PathBuf> {
+    let data = serde_json::from_value::<CodeLensData>(lens.data.clone()?).ok()?;
+    data.uri.to_file_path().ok()
+}
+
+/// Count the references to what the lens is on, and give it the command that lists them. A lens on something that is
+/// gone, like a heading that was edited, lists no references.
+pub fn resolve_code_lens(vault: &Vault, lens: CodeLens) -> CodeLens {
+    let command = lens
+        .data
+        .clone()
+        .and_then(|data| serde_json::from_value::<CodeLensData>(data).ok())
+        .and_then(|data| lens_command(vault, &data))
+        .unwrap_or_else(|| Command {
+            title: "0 references".to_string(),
+            command: "moxide.findReferences".into(),
+            arguments: None,
+        });
+
+    CodeLens {
+        command: Some(command),
+        ..lens
+    }
+}
+
+fn lens_command(vault: &Vault, data: &CodeLensData) -> Option<Command> {
+    let path = data.uri.to_file_path().ok()?;
+    let referenceable = vault
+        .select_referenceable_nodes(Some(&path))
         .into_iter()
-        .filter(|(_, references)| !references.is_empty())
-        .filter_map(|(referenceable, references)| {
-            let title = match (&referenceable, references.len()) {
-                (Referenceable::File(..), num) if num == 1 => "1 reference to file".to_string(),
-                (Referenceable::File(..), num) => format!("{} references to file ", num),
-                (_, n) if n == 1 => "1 reference".to_string(),
-                (_, n) => format!("{} references", n),
-            };
+        .find(|referenceable| {
+            CodeLensKind::of(referenceable) == Some(data.kind)
+                && lens_range(referenceable) == Some(data.range)
+        })?;
+    let references = vault.select_references_for_referenceable(&referenceable)?;
 
-            let locations = references
-                .into_iter()
-                .filter_map(|(path, reference)| {
-                    Some(Location {
-                        uri: Url::from_file_path(path).ok()?,
-                        range: *reference.data().range,
-                    })
-                })
-                .collect_vec();
-
-            let range = match referenceable {
-                Referenceable::File(..) => tower_lsp::lsp_types::Range {
-                    start: Position {
-                        line: 0,
-                        character: 0,
-                    },
-                    end: Position {
-                        line: 0,
-                        character: 1,
-                    },
-                },
-                _ => *referenceable.get_range()?,
-            };
+    let title = match (&referenceable, references.len()) {
+        (Referenceable::File(..), 1) => "1 reference to file".to_string(),
+        (Referenceable::File(..), num) => format!("{} references to file", num),
+        (_, 1) => "1 reference".to_string(),
+        (_, n) => format!("{} references", n),
+    };
 
-            Some(CodeLens {
-                range,
-                command: Some(Command {
-                    title,
-                    command: "moxide.findReferences".into(),
-                    arguments: Some(vec![serde_json::to_value(FindReferencesData {
-                        uri: Url::from_file_path(path).ok()?,
-                        position: range.start,
-                        locations,
-                    })
-                    .ok()?]),
-                }),
-                data: None,
+    let locations = references
+        .into_iter()
+        .filter_map(|(path, reference)| {
+            Some(Location {
+                uri: Url::from_file_path(path).ok()?,
+                range: *reference.data().range,
             })
         })
         .collect_vec();
 
-    Some(lens)
+    Some(Command {
+        title,
+        command: "moxide.findReferences".into(),
+        arguments: Some(vec![serde_json::to_value(FindReferencesData {
+            uri: data.uri.clone(),
+            position: data.range.start,
+            locations,
+        })
+        .ok()?]),
+    })
+}
+
+/// The lens for a file goes on its first line
+fn lens_range(referenceable: &Referenceable) -> Option<Range> {
+    match referenceable {
+        Referenceable::File(..) => Some(Range {
+            start: Position {
+                line: 0,
+                character: 0,
+            },
+            end: Position {
+                line: 0,
+                character: 1,
+            },
+        }),
+        _ => Some(*referenceable.get_range()?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::{
+        CodeLensParams, PartialResultParams, TextDocumentIdentifier, Url, WorkDoneProgressParams,
+    };
+
+    use crate::vault::TestVault;
+
+    use super::{code_lens, resolve_code_lens};
+
+    #[test]
+    fn test_code_lens() {
+        let test = TestVault::new(&[
+            (
+                "note.md",
+                "# Note\n\n## Linked\n\n## Unlinked\n\ntext ^block\n\n[^1]: footnote\n",
+            ),
+            ("other.md", "[[note]] [[note#Linked]] [[note#Linked]]\n"),
+        ]);
+        let path = test.path("note.md");
+        let params = CodeLensParams {
+            text_document: TextDocumentIdentifier {
+                uri: Url::from_file_path(&path).unwrap(),
+            },
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        };
+
+        let lenses = code_lens(&test.vault, &path, &params).unwrap();
+        assert!(lenses.iter().all(|lens| lens.command.is_none()));
+
+        let titles = lenses
+            .into_iter()
+            .map(|lens| resolve_code_lens(&test.vault, lens))
+            .map(|lens| (lens.range.start.line, lens.command.unwrap().title))
+            .collect::<Vec<_>>();
+
+        // the footnote has no lens, but the headings and the block have one even with no references
+        assert_eq!(
+            titles,
+            vec![
+                (0, "3 references to file".to_string()),
+                (0, "0 references".to_string()),
+                (2, "2 references".to_string()),
+                (4, "0 references".to_string()),
+                (6, "0 references".to_string()),
+            ]
+        );
+    }
 }
@@ -5,13 +5,32 @@ use tower_lsp::lsp_types::{
     InsertTextFormat, Position, Range, TextEdit,
 };
 
-use super::{Completable, Completer};
+use super::{
+    matcher::{fuzzy_match_completions, Matchable},
+    Completable, Completer,
+};
 
 pub struct CalloutCompleter {
     nested_level: usize,
     line: u32,
     character: u32,
     preceding_text: String,
+    /// Set when the cursor is inside a `> [!` callout header
+    header: Option<CalloutHeader>,
+    /// Callout types used in the vault that are not one of the standard types
+    custom_types: Vec<String>,
+    insensitive_matching: bool,
+}
+
+struct CalloutHeader {
+    /// The callout type typed so far
+    typed_type: String,
+    /// The foldable suffix (`+` or `-`) if one was typed
+    fold: Option<char>,
+    /// End of the header text to be replaced; this includes any part of the header after the cursor
+    end: u32,
+    /// Whether the header already has a title after it
+    has_title: bool,
 }
 
 impl<'a> Completer<'a> for CalloutCompleter {
@@ -24,10 +43,79 @@ impl<'a> Completer<'a> for CalloutCompleter {
         static PARTIAL_CALLOUT: Lazy<Regex> =
             Lazy::new(|| Regex::new(r"^(?<preceding>(> *)+)").unwrap()); // [display](relativePath)
 
+        // > [!type]- with the cursor somewhere in the header
+        static PARTIAL_CALLOUT_HEADER: Lazy<Regex> = Lazy::new(|| {
+            Regex::new(r"^(?<preceding>(> *)+)\[!(?<type>[^\]\s]*)(?<close>\](?<fold>[+-])?)?$")
+                .unwrap()
+        });
+
+        static REST_OF_HEADER: Lazy<Regex> =
+            Lazy::new(|| Regex::new(r"^[^\]\s]*(\](?<fold>[+-])?)?").unwrap());
+
+        let custom_types = context
+            .vault
+            .select_callout_types()
+            .into_iter()
+            .filter(|callout_type| {
+                !CalloutCompletion::STANDARD
+                    .iter()
+                    .any(|standard| standard.name().eq_ignore_ascii_case(callout_type))
+            })
+            .map(ToString::to_string)
+            .collect();
+
+        let line_to_cursor = String::from_iter(line_chars.get(0..character)?);
+
+        if let Some(captures) = PARTIAL_CALLOUT_HEADER.captures(&line_to_cursor) {
+            let preceding = captures.name("preceding")?;
+
+            let line_after_cursor = String::from_iter(line_chars.get(character..)?);
+
+            let (rest_len, rest_fold) = match captures.name("close") {
+                Some(_) => (0, None),
+                None => REST_OF_HEADER
+                    .captures(&line_after_cursor)
+                    .map(|rest| {
+                        (
+                            rest.get(0)
+                                .map(|it| it.as_str().chars().count())
+                                .unwrap_or(0),
+                            rest.name("fold"),
+                        )
+                    })
+                    .unwrap_or((0, None)),
+            };
+
+            let fold = captures
+                .name("fold")
+                .or(rest_fold)
+                .and_then(|fold| fold.as_str().chars().next());
+
+            let has_title = line_after_cursor
+                .chars()
+                .skip(rest_len)
+                .any(|c| !c.is_whitespace());
+
+            return Some(Self {
+                nested_level: preceding.as_str().matches('>').count(),
+                preceding_text: preceding.as_str().to_string(),
+                line: line as u32,
+                character: character as u32,
+                header: Some(CalloutHeader {
+                    typed_type: captures.name("type")?.as_str().to_string(),
+                    fold,
+                    end: (character + rest_len) as u32,
+                    has_title,
+                }),
+                custom_types,
+                insensitive_matching: context.settings.insensitive_matching,
+            });
+        }
+
         let binding = String::from_iter(line_chars);
         let captures = PARTIAL_CALLOUT.captures(&binding)?;
 
-        let (full, preceding) = (captures.get(0)?, captures.name("preceding")?);
+        let preceding = captures.name("preceding")?;
 
         let nested_level = preceding.as_str().matches('>').count();
 
@@ -36,6 +124,9 @@ impl<'a> Completer<'a> for CalloutCompleter {
             preceding_text: preceding.as_str().to_string(),
             line: line as u32,
             character: character as u32,
+            header: None,
+            custom_types,
+            insensitive_matching: context.settings.insensitive_matching,
         });
     }
 
@@ -43,41 +134,29 @@ impl<'a> Completer<'a> for CalloutCompleter {
     where
         Self: Sized,
     {
-        vec![
-            CalloutCompletion::Note,
-            CalloutCompletion::Abstract,
-            CalloutCompletion::Summary,
-            CalloutCompletion::Tldr,
-            CalloutCompletion::Info,
-            CalloutCompletion::Todo,
-            CalloutCompletion::Tip,
-            CalloutCompletion::Hint,
-            CalloutCompletion::Important,
-            CalloutCompletion::Success,
-            CalloutCompletion::Check,
-            CalloutCompletion::Done,
-            CalloutCompletion::Question,
-            CalloutCompletion::Help,
-            CalloutCompletion::Faq,
-            CalloutCompletion::Warning,
-            CalloutCompletion::Caution,
-            CalloutCompletion::Attention,
-            CalloutCompletion::Failure,
-            CalloutCompletion::Fail,
-            CalloutCompletion::Missing,
-            CalloutCompletion::Danger,
-            CalloutCompletion::Error,
-            CalloutCompletion::Bug,
-            CalloutCompletion::Example,
-            CalloutCompletion::Quote,
-            CalloutCompletion::Cite,
-        ]
+        // custom types first so that they are not cut off by the standard types
+        let callouts = self
+            .custom_types
+            .iter()
+            .map(|custom| CalloutCompletion::Custom(custom.clone()))
+            .chain(CalloutCompletion::STANDARD);
+
+        let filter_text = self
+            .header
+            .as_ref()
+            .map(|header| header.typed_type.as_str())
+            .unwrap_or("");
+
+        fuzzy_match_completions(filter_text, callouts, self.insensitive_matching)
     }
 
     // TODO: get rid of this in the API
-    type FilterParams = &'static str;
+    type FilterParams = &'a str;
     fn completion_filter_text(&self, params: Self::FilterParams) -> String {
-        format!("{}{}", self.preceding_text, params)
+        match self.header {
+            Some(_) => format!("{}[!{}", self.preceding_text, params),
+            None => format!("{}{}", self.preceding_text, params),
+        }
     }
 }
 
@@ -109,11 +188,43 @@ enum CalloutCompletion {
     Example,
     Quote,
     Cite,
+    /// A callout type that is used in the vault but is not one of Obsidian's standard types
+    Custom(String),
 }
 
-impl Completable<'_, CalloutCompleter> for CalloutCompletion {
-    fn completions(&self, completer: &CalloutCompleter) -> Option<CompletionItem> {
-        let name = match self {
+impl CalloutCompletion {
+    const STANDARD: [CalloutCompletion; 27] = [
+        Self::Note,
+        Self::Abstract,
+        Self::Summary,
+        Self::Tldr,
+        Self::Info,
+        Self::Todo,
+        Self::Tip,
+        Self::Hint,
+        Self::Important,
+        Self::Success,
+        Self::Check,
+        Self::Done,
+        Self::Question,
+        Self::Help,
+        Self::Faq,
+        Self::Warning,
+        Self::Caution,
+        Self::Attention,
+        Self::Failure,
+        Self::Fail,
+        Self::Missing,
+        Self::Danger,
+        Self::Error,
+        Self::Bug,
+        Self::Example,
+        Self::Quote,
+        Self::Cite,
+    ];
+
+    fn name(&self) -> &str {
+        match self {
             Self::Note => "note",
             Self::Abstract => "abstract",
             Self::Summary => "summary",
@@ -141,7 +252,20 @@ impl Completable<'_, CalloutCompleter> for CalloutCompletion {
             Self::Example => "example",
             Self::Quote => "quote",
             Self::Cite => "cite",
-        };
+            Self::Custom(name) => name,
+        }
+    }
+}
+
+impl Matchable for CalloutCompletion {
+    fn match_string(&self) -> &str {
+        self.name()
+    }
+}
+
+impl Completable<'_, CalloutCompleter> for CalloutCompletion {
+    fn completions(&self, completer: &CalloutCompleter) -> Option<CompletionItem> {
+        let name = self.name();
 
         let label_detail = match self {
             Self::Summary | Self::Tldr => Some("alias of Abstract"),
@@ -152,13 +276,31 @@ impl Completable<'_, CalloutCompleter> for CalloutCompletion {
             Self::Fail | Self::Missing => Some("alias of Failure"),
             Self::Error => Some("alias of Danger"),
             Self::Cite => Some("alias of Quote"),
+            Self::Custom(_) => Some("used in vault"),
             _ => None,
         };
 
-        let snippet = format!(
-            "{prefix}[!{name}] ${{1:Title}}\n{prefix}${{2:Description}}",
-            prefix = "> ".repeat(completer.nested_level)
-        );
+        let prefix = "> ".repeat(completer.nested_level);
+
+        let (snippet, end) = match &completer.header {
+            Some(CalloutHeader {
+                fold,
+                end,
+                has_title,
+                ..
+            }) => (
+                format!(
+                    "{prefix}[!{name}]{fold}{title}",
+                    fold = fold.map(String::from).unwrap_or_default(),
+                    title = if *has_title { "" } else { " ${1:Title}" }
+                ),
+                *end,
+            ),
+            None => (
+                format!("{prefix}[!{name}] ${{1:Title}}\n{prefix}${{2:Description}}"),
+                completer.character,
+            ),
+        };
 
         let filter_text = completer.completion_filter_text(name);
 
@@ -178,7 +320,7 @@ impl Completable<'_, CalloutCompleter> for CalloutCompletion {
                     },
                     end: Position {
                         line: completer.line,
-                        character: completer.character,
+                        character: end,
                     },
                 },
                 new_text: snippet,
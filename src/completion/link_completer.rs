@@ -1,5 +1,5 @@
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     iter::once,
     path::{Path, PathBuf},
     time::SystemTime,
@@ -8,20 +8,37 @@ use std::{
 use chrono::{Duration, NaiveDate};
 use itertools::Itertools;
 use once_cell::sync::Lazy;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
 use rayon::prelude::*;
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 use tower_lsp::lsp_types::{
-    CompletionItem, CompletionItemKind, CompletionItemLabelDetails, CompletionTextEdit,
-    Documentation, InsertTextFormat, Position, Range, TextEdit,
+    Command, CompletionItem, CompletionItemKind, CompletionItemLabelDetails, CompletionTextEdit,
+    DocumentChanges, Documentation, InsertTextFormat, OneOf,
+    OptionalVersionedTextDocumentIdentifier, Position, Range, TextDocumentEdit, TextEdit, Url,
+    WorkspaceEdit,
 };
 
 use crate::{
-    completion::util::check_in_code_block, config::Settings, ui::preview_referenceable, vault::{MDFile, MDHeading, Rangeable, Reference, Referenceable, Vault}
+    completion::util::check_in_code_block,
+    config::{
+        LinkDisplayText, LinkPathEncoding, MarkdownHeadingAnchors, MarkdownLinkPaths, Settings,
+    },
+    daily::{
+        create_dailynote_edit, dailynote_date, dailynote_path, dailynote_refname,
+        parse_relative_date, relative_date_string,
+    },
+    resolve::{existing_file, new_note_path, CreateNoteArgs, CREATE_NOTE_COMMAND},
+    ui::{preview_embed, preview_referenceable},
+    vault::{
+        get_obsidian_ref_path, heading_link_text, heading_slug, is_image, MDFile, MDHeading,
+        Rangeable, Reference, ReferenceData, Referenceable, Vault,
+    },
 };
 
 use super::{
-    matcher::{fuzzy_match_completions, Matchable, OrderedCompletion},
-    Completable, Completer, Context,
+    matcher::{fuzzy_match, Matchable, OrderedCompletion},
+    CancelToken, Completable, Completer, Context, RecentFiles,
 };
 
 /// Range on a single line; assumes that the line number is known.
@@ -36,22 +53,137 @@ pub struct MarkdownLinkCompleter<'a> {
     pub infile_ref: Option<(PartialInfileRef, LineRange)>,
 
     pub partial_link: (String, LineRange),
+    /// Whether the path is written in angle brackets: `[display](<path>)`
+    pub angle_bracket: bool,
     pub full_range: LineRange,
+    /// Whether the link already has its closing `)`, as when completing inside an existing link, which already has
+    /// the text after it
+    pub closed: bool,
     pub line_nr: usize,
     pub position: Position,
     pub file_path: std::path::PathBuf,
     pub vault: &'a Vault,
     pub context_path: &'a Path,
     pub settings: &'a Settings,
+    pub cancel: &'a CancelToken,
+    pub recent_files: &'a RecentFiles,
 }
 
 pub trait LinkCompleter<'a>: Completer<'a> {
     fn settings(&self) -> &'a Settings;
-    fn completion_text_edit(&self, display: Option<&str>, refname: &str) -> CompletionTextEdit;
+    /// The edit inserting a link to the refname, followed by `#infile_ref` for a heading or block
+    fn completion_text_edit(
+        &self,
+        display: Option<&str>,
+        refname: &str,
+        infile_ref: Option<&str>,
+    ) -> CompletionTextEdit;
     fn entered_refname(&self) -> String;
+    /// The file and the partial infile ref around the `#`, if an infile ref is being entered. The file is empty for
+    /// the current file.
+    fn entered_infile_ref(&self) -> Option<(String, String)>;
+    /// The file of the entered infile ref, whether or not it is opened; None if there is no such file yet
+    fn entered_infile_ref_path(&self) -> Option<&'a Path> {
+        let (file, _) = self.entered_infile_ref()?;
+        if file.is_empty() {
+            return Some(self.path());
+        }
+
+        let file_reference = Reference::WikiFileLink(ReferenceData {
+            reference_text: file,
+            ..Default::default()
+        });
+
+        self.vault()
+            .md_files
+            .iter()
+            .find(|(path, mdfile)| {
+                Referenceable::File(path, mdfile).matches_reference(
+                    self.vault().root_dir(),
+                    &file_reference,
+                    self.path(),
+                    self.vault().case_insensitive_links(),
+                )
+            })
+            .map(|(path, _)| path.as_path())
+    }
+
+    /// Whether the link being completed is an embed, like `![[file]]`
+    fn is_embed(&self) -> bool {
+        false
+    }
+
+    /// Whether the link being completed is a wikilink rather than a markdown link
+    fn is_wikilink(&self) -> bool {
+        false
+    }
+
+    /// Whether the file already embeds the current file, directly or through other embeds, so that embedding it would
+    /// make a cycle
+    fn embeds_current_file(&self, _path: &Path) -> bool {
+        false
+    }
+
+    /// Whether attachments, like images, are completed along with notes
+    fn completes_attachments(&self) -> bool {
+        false
+    }
+
+    /// The partial heading after `[[##`, which is searched for in every file, for when the note it is in isn't known
+    fn entered_vault_heading(&self) -> Option<String> {
+        None
+    }
+
+    /// The display text to insert; a `${1:display}` tab stop when link completions are inserted as snippets
+    fn display_snippet(&self, display: &str) -> String {
+        match self.settings().link_snippets {
+            true => format!("${{1:{}}}", self.snippet_escape(display)),
+            false => display.to_string(),
+        }
+    }
+
+    /// The text inserted after a completed link, from the `link_completion_suffix` setting
+    fn link_suffix(&self) -> String {
+        self.snippet_escape(&self.settings().link_completion_suffix)
+    }
+
+    /// Escape the characters that have a meaning in snippets, when link completions are inserted as snippets
+    fn snippet_escape(&self, text: &str) -> String {
+        match self.settings().link_snippets {
+            true => text
+                .replace('\\', r"\\")
+                .replace('$', r"\$")
+                .replace('}', r"\}"),
+            false => text.to_string(),
+        }
+    }
+
+    fn insert_text_format(&self) -> InsertTextFormat {
+        match self.settings().link_snippets {
+            true => InsertTextFormat::SNIPPET,
+            false => InsertTextFormat::PLAIN_TEXT,
+        }
+    }
     fn vault(&self) -> &'a Vault;
     fn position(&self) -> Position;
     fn path(&self) -> &'a Path;
+    /// Set when the completion request is cancelled, so the rest of the work can be skipped
+    fn cancel_token(&self) -> &'a CancelToken;
+    fn recent_files(&self) -> &'a RecentFiles;
+    /// When the file was last opened or edited in this session, or else when it was last modified
+    fn last_touched(&self, path: &Path) -> Option<SystemTime> {
+        self.recent_files().get(path).copied().or_else(|| {
+            std::fs::metadata(path)
+                .and_then(|meta| meta.modified())
+                .ok()
+        })
+    }
+    /// Links to the current file itself are only completed with `self_link_completions`; its headings and blocks are
+    /// always completed
+    fn allows_link_to(&self, referenceable: &Referenceable) -> bool {
+        self.settings().self_link_completions
+            || !matches!(referenceable, Referenceable::File(path, _) if *path == self.path())
+    }
     fn link_completions(&self) -> Vec<LinkCompletion<'a>>
     where
         Self: Sync,
@@ -84,12 +216,62 @@ pub trait LinkCompleter<'a>: Completer<'a> {
             }
         });
 
-        let heading_completions = self.settings().heading_completions;
+        // Once a file and `#` are entered, only that file's headings and blocks are completed
+        let infile_ref_file = self.entered_infile_ref().map(|(file, _)| file);
+
+        // only headings are completed after `[[##`
+        let vault_heading = self.entered_vault_heading().is_some();
+
+        // headings are always completed for section embeds, `![[file#heading]]`, and once a `#` is entered
+        let heading_completions = self.settings().heading_completions
+            || self.is_embed()
+            || infile_ref_file.is_some()
+            || vault_heading;
+        let in_entered_file = |referenceable: &Referenceable| {
+            let Some(file) = &infile_ref_file else {
+                return true;
+            };
+
+            match referenceable {
+                Referenceable::Heading(path, _) | Referenceable::IndexedBlock(path, _)
+                    if file.is_empty() =>
+                {
+                    *path == self.path()
+                }
+                Referenceable::Heading(path, _) | Referenceable::IndexedBlock(path, _) => {
+                    let file_reference = Reference::WikiFileLink(ReferenceData {
+                        reference_text: file.clone(),
+                        ..Default::default()
+                    });
+
+                    self.vault().md_files.get(*path).is_some_and(|mdfile| {
+                        Referenceable::File(path, mdfile).matches_reference(
+                            self.vault().root_dir(),
+                            &file_reference,
+                            self.path(),
+                            self.vault().case_insensitive_links(),
+                        )
+                    })
+                }
+                Referenceable::UnresolvedHeading(_, end_path, _)
+                | Referenceable::UnresovledIndexedBlock(_, end_path, _) => {
+                    end_path.as_str() == file.as_str()
+                }
+                _ => false,
+            }
+        };
 
         // Get and filter referenceables
-        let completions = referenceables
+        let mut completions = referenceables
             .into_par_iter()
+            .take_any_while(|_| !self.cancel_token().is_cancelled())
+            .filter(|referenceable| !self.vault().is_excluded(referenceable.get_path()))
             .filter(|referenceable| Some(referenceable) != single_unresolved_under_cursor.as_ref())
+            .filter(|referenceable| self.allows_link_to(referenceable))
+            .filter(|referenceable| in_entered_file(referenceable))
+            .filter(|referenceable| {
+                !vault_heading || matches!(referenceable, Referenceable::Heading(..))
+            })
             .filter(|referenceable| {
                 heading_completions
                     || !matches!(
@@ -97,6 +279,12 @@ pub trait LinkCompleter<'a>: Completer<'a> {
                         Referenceable::Heading(..) | Referenceable::UnresolvedHeading(..)
                     )
             })
+            .filter(|referenceable| match referenceable {
+                Referenceable::Heading(_, heading) => {
+                    heading.level.0 <= self.settings().heading_completion_level
+                }
+                _ => true,
+            })
             .flat_map(|referenceable| {
                 LinkCompletion::new(referenceable.clone(), self)
                     .into_iter()
@@ -105,24 +293,392 @@ pub trait LinkCompleter<'a>: Completer<'a> {
             .flatten()
             .collect::<Vec<_>>();
 
+        if vault_heading {
+            return completions;
+        }
+
+        if self.settings().link_text_aliases && infile_ref_file.is_none() {
+            let linked = linked_aliases(self.vault(), &completions);
+            completions.extend(linked);
+        }
+
+        if self.completes_attachments() && infile_ref_file.is_none() {
+            completions.extend(attachment_completions(self.vault(), self.settings()));
+        }
+
+        // Files with the same name in different folders are linked to by their path from the vault root, as are all
+        // files once a leading `/` is entered
+        let from_root = infile_ref_file.is_none() && self.entered_refname().starts_with('/');
+        let mut stem_counts = HashMap::<String, usize>::new();
+        for completion in &completions {
+            if let File { match_string, .. } = completion {
+                *stem_counts.entry(match_string.clone()).or_default() += 1;
+            }
+        }
+        let root_dir = self.vault().root_dir();
+        for completion in &mut completions {
+            if let File {
+                match_string,
+                qualified_refname,
+                title,
+                referenceable,
+                ..
+            } = completion
+            {
+                if from_root {
+                    if let Some(path) = get_obsidian_ref_path(root_dir, referenceable.get_path()) {
+                        *match_string = format!("/{}", path);
+                        *qualified_refname = Some(match_string.clone());
+                        *title = None;
+                    }
+                } else if stem_counts.get(match_string.as_str()) > Some(&1) {
+                    *qualified_refname = get_obsidian_ref_path(root_dir, referenceable.get_path())
+                        .map(|path| match path.contains('/') {
+                            true => path,
+                            false => format!("/{}", path),
+                        });
+                }
+            }
+        }
+
+        // `notes.md` and `notes.canvas` are told apart by their extension
+        let attachment_names = self
+            .vault()
+            .attachments
+            .iter()
+            .filter(|path| {
+                path.extension()
+                    .and_then(|extension| extension.to_str())
+                    .is_some_and(|extension| {
+                        self.settings()
+                            .link_extensions
+                            .iter()
+                            .any(|link_extension| {
+                                link_extension
+                                    .trim_start_matches('.')
+                                    .eq_ignore_ascii_case(extension)
+                            })
+                    })
+            })
+            .filter_map(|path| Some(path.file_stem()?.to_str()?.to_lowercase()))
+            .collect::<HashSet<_>>();
+        if !attachment_names.is_empty() {
+            for completion in &mut completions {
+                if let File {
+                    shares_name,
+                    referenceable,
+                    ..
+                } = completion
+                {
+                    *shares_name = referenceable
+                        .get_path()
+                        .file_stem()
+                        .and_then(|stem| stem.to_str())
+                        .is_some_and(|stem| attachment_names.contains(&stem.to_lowercase()));
+                }
+            }
+        }
+
+        if infile_ref_file.is_some() || self.cancel_token().is_cancelled() {
+            completions.extend(new_heading_completion(self));
+            return completions;
+        }
+
+        // `[[projects/` only completes the notes in the projects folder, by their path from the vault root
+        let entered_refname = self.entered_refname();
+        if let Some((folder, _)) = entered_refname
+            .rsplit_once('/')
+            .filter(|(folder, _)| self.is_wikilink() && !folder.trim_start_matches('/').is_empty())
+        {
+            let prefix = format!("{}/", folder.trim_start_matches('/'));
+            let case_insensitive = self.vault().case_insensitive_links();
+            let starts_with = |text: &str, prefix: &str| match case_insensitive {
+                true => text.to_lowercase().starts_with(&prefix.to_lowercase()),
+                false => text.starts_with(prefix),
+            };
+
+            return completions
+                .into_iter()
+                .filter_map(|mut completion| {
+                    match &mut completion {
+                        File {
+                            match_string,
+                            qualified_refname,
+                            title,
+                            referenceable,
+                            ..
+                        } => {
+                            let path = get_obsidian_ref_path(root_dir, referenceable.get_path())?;
+                            if !starts_with(&path, &prefix) {
+                                return None;
+                            }
+
+                            *match_string = match from_root {
+                                true => format!("/{}", path),
+                                false => path,
+                            };
+                            *qualified_refname = Some(match_string.clone());
+                            *title = None;
+                        }
+                        Unresolved { match_string, .. }
+                            if starts_with(match_string.trim_start_matches('/'), &prefix) => {}
+                        _ => return None,
+                    }
+
+                    Some(completion)
+                })
+                .collect();
+        }
+
         // TODO: This could be slow
         let refnames = completions
             .par_iter()
             .map(|completion| completion.refname())
             .collect::<HashSet<_>>();
+        // days that already have a note, which may be in an older daily note format
+        let daily_dates = completions
+            .iter()
+            .filter_map(|completion| match completion {
+                DailyNote(daily) => Some(daily.date),
+                _ => None,
+            })
+            .collect::<HashSet<_>>();
 
         // Get daily notes for convienience
         let today = chrono::Local::now().date_naive();
         let days = (-7..=7)
             .flat_map(|i| Some(today + Duration::try_days(i)?))
             .flat_map(|date| MDDailyNote::from_date(date, self))
-            .filter(|date| !refnames.contains(&date.ref_name))
+            .filter(|daily| {
+                !refnames.contains(&daily.ref_name) && !daily_dates.contains(&daily.date)
+            })
             .map(LinkCompletion::DailyNote);
 
-        completions.into_iter().chain(days).collect::<Vec<_>>()
+        // Daily notes for the entered text as a relative phrase (in 3 days, last friday, ...) and for the configured phrases
+        let phrase_days = once(self.entered_refname())
+            .chain(self.settings().dailynote_phrases.keys().cloned())
+            .flat_map(|phrase| MDDailyNote::from_phrase(&phrase, self))
+            .unique_by(|daily| daily.match_string.clone())
+            .map(LinkCompletion::DailyNote)
+            .collect_vec();
+
+        completions
+            .into_iter()
+            .chain(days)
+            .chain(phrase_days)
+            .collect::<Vec<_>>()
+    }
+
+    /// Fuzzy match the link completions, boosting the best matches by how recently their file was opened, edited or
+    /// modified and by how many references they have
+    fn ranked_link_completions(
+        &self,
+        filter_text: &str,
+    ) -> Vec<OrderedCompletion<'a, Self, LinkCompletion<'a>>>
+    where
+        Self: Sync + Sized,
+        LinkCompletion<'a>: Completable<'a, Self>,
+    {
+        // `[[file#` with nothing after it yet; the file's headings and blocks are listed top to bottom
+        if self
+            .entered_infile_ref()
+            .is_some_and(|(_, infile)| infile.is_empty())
+        {
+            return self
+                .link_completions()
+                .into_iter()
+                .map(|completion| completion.ordered(0))
+                .collect();
+        }
+
+        let settings = self.settings();
+        let matches = fuzzy_match(
+            filter_text,
+            self.link_completions(),
+            settings.insensitive_matching,
+        );
+
+        let (recency_weight, popularity_weight) = (
+            settings.completion_recency_weight,
+            settings.completion_popularity_weight,
+        );
+
+        if recency_weight == 0.0 && popularity_weight == 0.0 {
+            return matches
+                .into_iter()
+                .map(|(completion, score)| completion.ordered(score))
+                .collect();
+        }
+
+        // Only the best fuzzy matches are boosted; counting references for every match would be too slow
+        let mut matches = matches;
+        let rest = matches.split_off(matches.len().min(RANKED_COMPLETIONS));
+
+        let vault = self.vault();
+        let references = vault.select_references(None).unwrap_or_default();
+        let now = SystemTime::now();
+
+        let ranked = matches
+            .into_par_iter()
+            .take_any_while(|_| !self.cancel_token().is_cancelled())
+            .map(|(completion, score)| {
+                let referenceable = completion.referenceable(self);
+
+                let age_days = completion
+                    .target_path(self)
+                    .and_then(|path| self.last_touched(&path))
+                    .and_then(|touched| now.duration_since(touched).ok())
+                    .map(|age| age.as_secs_f64() / 86400.0);
+                let recency = age_days.map(|days| 1.0 / (1.0 + days)).unwrap_or(0.0);
+
+                // links to attachments aren't indexed as references
+                let inbound = referenceable.map_or(0, |referenceable| {
+                    references
+                        .iter()
+                        .filter(|(path, reference)| {
+                            referenceable.matches_reference(
+                                vault.root_dir(),
+                                reference,
+                                path,
+                                vault.case_insensitive_links(),
+                            )
+                        })
+                        .count()
+                });
+                let popularity = (1.0 + inbound as f64).ln();
+
+                let rank = score as f64
+                    * (1.0 + recency_weight * recency + popularity_weight * popularity);
+
+                (completion, rank as u32)
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .sorted_by_key(|(_, rank)| std::cmp::Reverse(*rank));
+
+        ranked
+            .chain(rest)
+            .map(|(completion, rank)| completion.ordered(rank))
+            .collect()
+    }
+}
+
+/// The display texts files have been linked with, like `Annual Report` for `[[2023-report|Annual Report]]`, as
+/// aliases of the files; texts that are already the file's name or one of its aliases are left out
+fn linked_aliases<'a>(
+    vault: &'a Vault,
+    completions: &[LinkCompletion<'a>],
+) -> Vec<LinkCompletion<'a>> {
+    completions
+        .iter()
+        .filter_map(|completion| match completion {
+            File {
+                mdfile,
+                referenceable,
+                ..
+            } => Some((*mdfile, referenceable)),
+            _ => None,
+        })
+        .flat_map(|(mdfile, referenceable)| {
+            let filename = mdfile.file_name()?;
+            let aliases = mdfile.metadata.iter().flat_map(|it| it.aliases());
+            let known = once(filename)
+                .chain(aliases.map(String::as_str))
+                .collect::<HashSet<_>>();
+
+            // only the links that may be to the file, which the vault keeps by the name they link to
+            let aliases = vault
+                .select_displayed_links(filename)
+                .into_iter()
+                .filter(|(path, reference)| {
+                    referenceable.matches_reference(
+                        vault.root_dir(),
+                        reference,
+                        path,
+                        vault.case_insensitive_links(),
+                    )
+                })
+                .flat_map(|(_, reference)| reference.data().display_text.as_deref())
+                .map(str::trim)
+                .filter(|display| !known.contains(display))
+                .unique()
+                .map(|display| Alias {
+                    filename,
+                    match_string: display,
+                    linked: true,
+                    referenceable: referenceable.clone(),
+                })
+                .collect_vec();
+
+            Some(aliases)
+        })
+        .flatten()
+        .collect()
+}
+
+/// The attachments with one of the `attachment_extensions`, by name when it is unique among them and by path from
+/// the vault root otherwise
+fn attachment_completions<'a>(vault: &'a Vault, settings: &Settings) -> Vec<LinkCompletion<'a>> {
+    let attachments = vault
+        .attachments
+        .iter()
+        .filter(|path| !vault.is_excluded(path))
+        .filter(|path| {
+            path.extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| {
+                    settings
+                        .attachment_extensions
+                        .iter()
+                        .any(|allowed| allowed.eq_ignore_ascii_case(ext))
+                })
+        })
+        .flat_map(|path| Some((path.as_path(), path.file_name()?.to_str()?)))
+        .collect_vec();
+
+    let name_counts = attachments.iter().counts_by(|(_, name)| *name);
+
+    attachments
+        .into_iter()
+        .flat_map(|(path, name)| {
+            let refname = match name_counts.get(name) > Some(&1) {
+                true => pathdiff::diff_paths(path, vault.root_dir())?
+                    .to_str()?
+                    .replace('\\', "/"),
+                false => name.to_string(),
+            };
+
+            Some(Attachment { path, refname })
+        })
+        .collect()
+}
+
+/// What kind of attachment the file is, shown next to its completion
+fn attachment_kind(path: &Path) -> &'static str {
+    if is_image(path) {
+        return "Image";
+    }
+
+    let ext = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+
+    match ext.as_str() {
+        "pdf" => "PDF",
+        "mp3" | "wav" | "m4a" | "ogg" | "flac" | "3gp" => "Audio",
+        "mp4" | "webm" | "mov" | "mkv" | "ogv" => "Video",
+        _ => "Attachment",
     }
 }
 
+/// The number of best fuzzy matches that are reranked by recency and popularity
+const RANKED_COMPLETIONS: usize = 50;
+
+/// How many embeds away from the current file embed completions look for the files that embed it
+const EMBED_CYCLE_DEPTH: usize = 4;
+
 impl<'a> LinkCompleter<'a> for MarkdownLinkCompleter<'a> {
     fn settings(&self) -> &'a Settings {
         self.settings
@@ -131,6 +687,13 @@ impl<'a> LinkCompleter<'a> for MarkdownLinkCompleter<'a> {
     fn path(&self) -> &'a Path {
         self.context_path
     }
+    fn cancel_token(&self) -> &'a CancelToken {
+        self.cancel
+    }
+
+    fn recent_files(&self) -> &'a RecentFiles {
+        self.recent_files
+    }
     fn position(&self) -> Position {
         self.position
     }
@@ -139,6 +702,16 @@ impl<'a> LinkCompleter<'a> for MarkdownLinkCompleter<'a> {
         self.vault
     }
 
+    fn entered_infile_ref(&self) -> Option<(String, String)> {
+        let (infile, _) = self.infile_ref.as_ref()?;
+        let file = percent_decode_str(&self.path.0).decode_utf8_lossy();
+
+        Some((
+            file.trim_end_matches(".md").to_string(),
+            infile.completion_string(),
+        ))
+    }
+
     fn entered_refname(&self) -> String {
         format!(
             "{}{}",
@@ -150,11 +723,23 @@ impl<'a> LinkCompleter<'a> for MarkdownLinkCompleter<'a> {
         )
     }
 
-    /// Will add <$1> to the refname if it contains spaces
-    fn completion_text_edit(&self, display: Option<&str>, refname: &str) -> CompletionTextEdit {
-        let link_ref_text = match refname.contains(' ') {
-            true => format!("<{}>", refname),
-            false => refname.to_owned(),
+    /// Will add <$1> to the refname if it contains spaces, or percent encode it, depending on the settings.
+    /// Angle brackets that were already typed are kept.
+    fn completion_text_edit(
+        &self,
+        display: Option<&str>,
+        refname: &str,
+        infile_ref: Option<&str>,
+    ) -> CompletionTextEdit {
+        let target = match infile_ref {
+            Some(infile) => format!("{}#{}", refname, infile),
+            None => refname.to_owned(),
+        };
+        let link_ref_text = match self.settings.link_path_encoding {
+            _ if self.angle_bracket => format!("<{}>", target),
+            LinkPathEncoding::Percent => markdown_link_target(refname, infile_ref),
+            LinkPathEncoding::Angle if target.contains(' ') => format!("<{}>", target),
+            LinkPathEncoding::Angle => target,
         };
 
         CompletionTextEdit::Edit(TextEdit {
@@ -168,13 +753,50 @@ impl<'a> LinkCompleter<'a> for MarkdownLinkCompleter<'a> {
                     character: self.full_range.end as u32,
                 },
             },
-            new_text: format!("[{}]({})", display.unwrap_or(""), link_ref_text),
+            new_text: format!(
+                "[{}]({}){}",
+                display.unwrap_or(""),
+                self.snippet_escape(&link_ref_text),
+                match self.closed {
+                    true => String::new(),
+                    false => self.link_suffix(),
+                }
+            ),
         })
     }
 }
 
 
 
+impl MarkdownLinkCompleter<'_> {
+    /// The path to link to `target` with, relative to the current file or to the vault root depending on the settings
+    fn link_path(&self, target: &Path) -> Option<String> {
+        markdown_link_path(
+            self.settings,
+            self.vault.root_dir(),
+            self.context_path,
+            target,
+        )
+    }
+}
+
+/// The path for a markdown link in `from` to `target`, relative to `from` or to the vault root depending on the settings
+pub fn markdown_link_path(
+    settings: &Settings,
+    root_dir: &Path,
+    from: &Path,
+    target: &Path,
+) -> Option<String> {
+    let base = match settings.markdown_link_paths {
+        MarkdownLinkPaths::Relative => from.parent()?,
+        MarkdownLinkPaths::Absolute => root_dir,
+    };
+
+    let path = pathdiff::diff_paths(target, base)?;
+
+    Some(path.to_str()?.replace('\\', "/"))
+}
+
 impl<'a> Completer<'a> for MarkdownLinkCompleter<'a> {
     fn construct(context: Context<'a>, line: usize, character: usize) -> Option<Self>
     where
@@ -195,20 +817,26 @@ impl<'a> Completer<'a> for MarkdownLinkCompleter<'a> {
         let line_to_cursor = line_chars.get(0..character)?;
 
         static PARTIAL_MDLINK_REGEX: Lazy<Regex> = Lazy::new(|| {
-            Regex::new(r"\[(?<display>[^\[\]\(\)]*)\]\((?<path>[^\[\]\(\)\#]*)(\#(?<infileref>[^\[\]\(\)]*))?$").unwrap()
-        }); // [display](relativePath)
+            Regex::new(r"\[(?<display>[^\[\]\(\)]*)\]\((?<angle><)?(?<path>[^\[\]\(\)\#<>]*)(\#(?<infileref>[^\[\]\(\)<>]*))?$").unwrap()
+        }); // [display](relativePath) or [display](<relative path>)
 
         let line_string_to_cursor = String::from_iter(line_to_cursor);
 
         let captures = PARTIAL_MDLINK_REGEX.captures(&line_string_to_cursor)?;
 
-        let (full, display, reftext, infileref) = (
+        let (full, display, reftext, infileref, angle_bracket) = (
             captures.get(0)?,
             captures.name("display")?,
             captures.name("path")?,
             captures.name("infileref"),
+            captures.name("angle").is_some(),
         );
 
+        // a `#` in a url is not an infile ref
+        if reftext.as_str().contains("://") {
+            return None;
+        }
+
         let line_string = String::from_iter(&line_chars);
 
         let reference_under_cursor = Reference::new(&line_string).into_iter().find(|reference| {
@@ -222,6 +850,25 @@ impl<'a> Completer<'a> for MarkdownLinkCompleter<'a> {
                 | Reference::MDHeadingLink(..)
                 | Reference::MDIndexedBlockLink(..)),
             ) => reference.range.start.character as usize..reference.range.end.character as usize,
+            // The bracketed path ends at the first `>` after the cursor, along with the `)` right after it
+            None if angle_bracket => {
+                let closing = line_chars
+                    .get(character..)?
+                    .iter()
+                    .take_while(|c| !matches!(c, '[' | ']' | '(' | ')' | '<'))
+                    .position(|c| *c == '>');
+
+                match closing {
+                    Some(closing) if line_chars.get(character + closing + 1) == Some(&')') => {
+                        full.range().start..full.range().end + closing + 2
+                    }
+                    Some(closing) => full.range().start..full.range().end + closing + 1,
+                    None if line_chars.get(character) == Some(&')') => {
+                        full.range().start..full.range().end + 1
+                    }
+                    None => full.range(),
+                }
+            }
             None if line_chars.get(character) == Some(&')') => {
                 full.range().start..full.range().end + 1
             }
@@ -244,6 +891,8 @@ impl<'a> Completer<'a> for MarkdownLinkCompleter<'a> {
             display: (display.as_str().to_string(), display.range()),
             infile_ref: partial_infileref,
             partial_link: (full.as_str().to_string(), full.range()),
+            angle_bracket,
+            closed: full_range.end > full.range().end,
             full_range,
             line_nr: line,
             position: Position {
@@ -254,26 +903,22 @@ impl<'a> Completer<'a> for MarkdownLinkCompleter<'a> {
             vault,
             context_path: context.path,
             settings: context.settings,
+            cancel: context.cancel,
+            recent_files: context.recent_files,
         });
 
         partial
     }
 
     fn completions(&self) -> Vec<impl Completable<'a, MarkdownLinkCompleter<'a>>> {
-        let filter_text = format!(
-            "{}{}",
-            self.path.0,
-            self.infile_ref
-                .clone()
-                .map(|(infile, _)| format!("#{}", infile.completion_string()))
-                .unwrap_or("".to_string())
-        );
-
-        let link_completions = self.link_completions();
-
-        let matches = fuzzy_match_completions(&filter_text, link_completions);
+        // the completions are already limited to the entered file, so only the infile ref is matched
+        let filter_text = match self.entered_infile_ref() {
+            Some((_, infile)) => format!("#{}", infile),
+            None if self.path.0.chars().count() < self.settings.min_chars => return vec![],
+            None => self.path.0.clone(),
+        };
 
-        matches
+        self.ranked_link_completions(&filter_text)
     }
 
     /// The completions refname
@@ -286,6 +931,56 @@ impl<'a> Completer<'a> for MarkdownLinkCompleter<'a> {
     }
 }
 
+/// Characters that would end or break the path of a markdown link; non-ascii characters are always encoded
+const LINK_PATH_ENCODE_SET: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'%')
+    .add(b'#')
+    .add(b'<')
+    .add(b'>')
+    .add(b'(')
+    .add(b')')
+    .add(b'[')
+    .add(b']')
+    .add(b'|');
+
+/// Percent encode the file path and infile ref of a refname separately, keeping the `#` between them
+pub fn percent_encode_refname(refname: &str) -> String {
+    match refname.split_once('#') {
+        Some((path, infile)) => markdown_link_target(path, Some(infile)),
+        None => markdown_link_target(refname, None),
+    }
+}
+
+/// The heading or block of a markdown link, with the heading written as the `markdown_heading_anchors` setting says
+pub fn markdown_infile_ref(settings: &Settings, infile: &str) -> String {
+    match settings.markdown_heading_anchors {
+        MarkdownHeadingAnchors::Slug if !infile.starts_with('^') => heading_slug(infile),
+        _ => infile.to_string(),
+    }
+}
+
+/// The percent encoded target of a markdown link to the path and infile ref; a `#` in the path is encoded, so file
+/// names that can't be in a wikilink can be linked to
+fn markdown_link_target(path: &str, infile: Option<&str>) -> String {
+    match infile {
+        Some(infile) => format!(
+            "{}#{}",
+            utf8_percent_encode(path, LINK_PATH_ENCODE_SET),
+            utf8_percent_encode(infile, LINK_PATH_ENCODE_SET)
+        ),
+        None => utf8_percent_encode(path, LINK_PATH_ENCODE_SET).to_string(),
+    }
+}
+
+/// Whether the file can be linked to with a wikilink; `[`, `]`, `|`, `#` and `^` in its name would end or split the link
+fn is_wikilink_safe(target: &Path) -> bool {
+    target
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .is_some_and(|stem| !stem.contains(['[', ']', '|', '#', '^']))
+}
+
 #[derive(Debug, Clone)]
 pub enum PartialInfileRef {
     HeadingRef(String),
@@ -320,7 +1015,16 @@ pub struct WikiLinkCompleter<'a> {
     line: u32,
     context_path: &'a Path,
     settings: &'a Settings,
-    chars_in_line: u32,
+    cancel: &'a CancelToken,
+    recent_files: &'a RecentFiles,
+    /// The link is an embed, `![[`
+    embed: bool,
+    /// The files embedding the current file, up to `EMBED_CYCLE_DEPTH` embeds away; only found for an embed
+    embedders: HashSet<PathBuf>,
+    /// When the cursor is inside an existing link, the character right after its closing `]]`
+    closing: Option<u32>,
+    /// When the cursor is in the refname of an existing link with display text, the character of the `|`
+    display_start: Option<u32>,
 }
 
 impl<'a> LinkCompleter<'a> for WikiLinkCompleter<'a> {
@@ -332,6 +1036,14 @@ impl<'a> LinkCompleter<'a> for WikiLinkCompleter<'a> {
         self.context_path
     }
 
+    fn cancel_token(&self) -> &'a CancelToken {
+        self.cancel
+    }
+
+    fn recent_files(&self) -> &'a RecentFiles {
+        self.recent_files
+    }
+
     fn position(&self) -> Position {
         Position {
             line: self.line,
@@ -347,7 +1059,79 @@ impl<'a> LinkCompleter<'a> for WikiLinkCompleter<'a> {
         String::from_iter(&self.cmp_text)
     }
 
-    fn completion_text_edit(&self, display: Option<&str>, refname: &str) -> CompletionTextEdit {
+    fn entered_infile_ref(&self) -> Option<(String, String)> {
+        if self.entered_vault_heading().is_some() {
+            return None;
+        }
+
+        let entered = self.entered_refname();
+        let (file, infile) = entered.split_once('#')?;
+
+        Some((file.to_string(), infile.to_string()))
+    }
+
+    fn entered_vault_heading(&self) -> Option<String> {
+        self.entered_refname()
+            .strip_prefix("##")
+            .map(|heading| heading.to_string())
+    }
+
+    fn is_embed(&self) -> bool {
+        self.embed
+    }
+
+    fn is_wikilink(&self) -> bool {
+        true
+    }
+
+    fn embeds_current_file(&self, path: &Path) -> bool {
+        self.embedders.contains(path)
+    }
+
+    fn completes_attachments(&self) -> bool {
+        self.embed || self.settings.attachment_completions
+    }
+
+    /// The `!` of an embed is before the replaced range, so it is kept. In an existing link with display text, only
+    /// the refname is replaced and the display text is kept.
+    fn completion_text_edit(
+        &self,
+        display: Option<&str>,
+        refname: &str,
+        infile_ref: Option<&str>,
+    ) -> CompletionTextEdit {
+        let target = format!(
+            "{}{}",
+            self.snippet_escape(refname),
+            infile_ref
+                .map(|infile| format!("#{}", self.snippet_escape(infile)))
+                .unwrap_or_default(),
+        );
+
+        let (end, new_text) = match self.display_start {
+            Some(display_start) => (display_start, target),
+            None => (
+                self.closing.unwrap_or(self.character),
+                // the suffix goes before the final tab stop, so that the cursor ends up after it; an existing link
+                // already has the text after it
+                format!(
+                    "{}{}]]{}{}",
+                    target,
+                    display
+                        .map(|display| format!("|{}", display))
+                        .unwrap_or("".to_string()),
+                    match self.closing {
+                        Some(_) => String::new(),
+                        None => self.link_suffix(),
+                    },
+                    match self.settings.link_snippets {
+                        true => "${2:}",
+                        false => "",
+                    }
+                ),
+            ),
+        };
+
         CompletionTextEdit::Edit(TextEdit {
             range: Range {
                 start: Position {
@@ -356,18 +1140,96 @@ impl<'a> LinkCompleter<'a> for WikiLinkCompleter<'a> {
                 },
                 end: Position {
                     line: self.line,
-                    character: (self.chars_in_line).min(self.character + 2_u32),
+                    character: end,
+                },
+            },
+            new_text,
+        })
+    }
+}
+
+impl<'a> WikiLinkCompleter<'a> {
+    /// Replace the whole `[[...` with a markdown link, for files that can't be linked to with a wikilink
+    fn markdown_text_edit(&self, display: &str, link_target: &str) -> CompletionTextEdit {
+        CompletionTextEdit::Edit(TextEdit {
+            range: Range {
+                start: Position {
+                    line: self.line,
+                    character: self.index - 1, // the first '[' in [[link]]
+                },
+                end: Position {
+                    line: self.line,
+                    character: self.closing.unwrap_or(self.character),
                 },
             },
             new_text: format!(
-                "{}{}]]${{2:}}",
-                refname,
-                display
-                    .map(|display| format!("|{}", display))
-                    .unwrap_or("".to_string())
+                "[{}]({}){}",
+                display,
+                self.snippet_escape(link_target),
+                // as for a wikilink, an existing link already has the text after it
+                match self.closing {
+                    Some(_) => String::new(),
+                    None => self.link_suffix(),
+                }
             ),
         })
     }
+
+    /// Give recent referenceables, for when less than `min_chars` has been entered: those of the files opened or edited
+    /// in this session, most recently first, then those of the other open files by when they were modified. Only the
+    /// referenceables of the most recent files, up to the `completion_limit`, are made into completions.
+    fn recent_completions(&self) -> Vec<OrderedCompletion<'a, Self, LinkCompletion<'a>>> {
+        let WikiLinkCompleter {
+            vault,
+            recent_files,
+            ..
+        } = *self;
+
+        // files touched in this session come before the rest, the most recent first
+        let files = self
+            .files
+            .iter()
+            .chain(recent_files.keys())
+            .unique()
+            .filter(|path| !vault.is_excluded(path))
+            .map(|path| {
+                let modified = std::fs::metadata(path)
+                    .and_then(|meta| meta.modified())
+                    .unwrap_or(SystemTime::UNIX_EPOCH);
+
+                (path, recent_files.get(path).copied(), modified)
+            })
+            .sorted_by_key(|(_, touched, modified)| std::cmp::Reverse((*touched, *modified)))
+            .collect_vec();
+        let count = files.len();
+
+        files
+            .into_iter()
+            .enumerate()
+            .flat_map(|(index, (path, ..))| {
+                let referenceables = vault
+                    .select_referenceable_nodes(Some(path))
+                    .into_iter()
+                    .filter(|referenceable| self.allows_link_to(referenceable))
+                    .filter(|referenceable| {
+                        self.settings().heading_completions
+                            || self.embed
+                            || !matches!(
+                                referenceable,
+                                Referenceable::Heading(..) | Referenceable::UnresolvedHeading(..)
+                            )
+                    })
+                    .collect::<Vec<_>>();
+
+                referenceables
+                    .into_iter()
+                    .flat_map(move |referenceable| LinkCompletion::new(referenceable, self))
+                    .flatten()
+                    .map(move |completion| completion.ordered((count - index) as u32))
+            })
+            .take(self.settings().completion_limit)
+            .collect_vec()
+    }
 }
 
 impl<'a> Completer<'a> for WikiLinkCompleter<'a> {
@@ -410,6 +1272,10 @@ impl<'a> Completer<'a> for WikiLinkCompleter<'a> {
 
         index.and_then(|index| {
             let cmp_text = line_chars.get(index + 1..character)?;
+            let (closing, display_start) = existing_link_end(line_chars.get(character..)?);
+            // the cursor is already in the display text
+            let display_start = display_start.filter(|_| !cmp_text.contains(&'|'));
+            let embed = index >= 2 && line_chars.get(index - 2) == Some(&'!');
 
             Some(WikiLinkCompleter {
                 vault,
@@ -420,7 +1286,18 @@ impl<'a> Completer<'a> for WikiLinkCompleter<'a> {
                 line: line as u32,
                 context_path: context.path,
                 settings: context.settings,
-                chars_in_line: line_chars.len() as u32,
+                cancel: context.cancel,
+                recent_files: context.recent_files,
+                embed,
+                embedders: match embed {
+                    true => vault.select_embedders(path, EMBED_CYCLE_DEPTH, || {
+                        context.cancel.is_cancelled()
+                    }),
+                    false => HashSet::new(),
+                },
+                closing: closing.map(|closing| (character + closing) as u32),
+                display_start: display_start
+                    .map(|display_start| (character + display_start) as u32),
             })
         })
     }
@@ -429,64 +1306,63 @@ impl<'a> Completer<'a> for WikiLinkCompleter<'a> {
     where
         Self: Sized,
     {
-        let WikiLinkCompleter { vault, .. } = self;
-
         match *self.cmp_text {
-            // Give recent referenceables; TODO: improve this;
-            [] => self
-                .files
-                .iter()
-                .map(
-                    |path| match std::fs::metadata(path).and_then(|meta| meta.modified()) {
-                        Ok(modified) => (path, modified),
-                        Err(_) => (path, SystemTime::UNIX_EPOCH),
-                    },
-                )
-                .sorted_by_key(|(_, modified)| *modified)
-                .flat_map(|(path, modified)| {
-                    let referenceables = vault
-                        .select_referenceable_nodes(Some(path))
-                        .into_iter()
-                        .filter(|referenceable| {
-                            self.settings().heading_completions
-                                || !matches!(
-                                    referenceable,
-                                    Referenceable::Heading(..)
-                                        | Referenceable::UnresolvedHeading(..)
-                                )
-                        })
-                        .collect::<Vec<_>>();
-
-                    let modified_string = modified
-                        .duration_since(SystemTime::UNIX_EPOCH)
-                        .ok()?
-                        .as_secs()
-                        .to_string();
+            [] => self.recent_completions(),
+            // [[file|display; complete the display text with the file's aliases
+            ref filter_text @ [..] if filter_text.contains(&'|') => {
+                let (refname, display) = String::from_iter(filter_text)
+                    .split_once('|')
+                    .map(|(refname, display)| (refname.to_string(), display.to_string()))
+                    .unwrap_or_default();
+
+                let (aliases, files): (Vec<_>, Vec<_>) = self
+                    .link_completions()
+                    .into_iter()
+                    .filter(|completion| completion.refname() == refname)
+                    .filter(|completion| matches!(completion, Alias { .. } | File { .. }))
+                    .partition(|completion| matches!(completion, Alias { .. }));
 
-                    Some(
-                        referenceables
-                            .into_iter()
-                            .flat_map(move |referenceable| LinkCompletion::new(referenceable, self))
-                            .flatten()
-                            .flat_map(move |completion| {
-                                Some(OrderedCompletion::<WikiLinkCompleter, LinkCompletion>::new(
-                                    completion,
-                                    modified_string.clone(),
-                                ))
-                            }),
-                    )
-                })
-                .flatten()
-                .collect_vec(),
+                // offer to add the display text to the file's frontmatter aliases if it isn't one
+                let alias = display.trim();
+                let new_alias = files
+                    .into_iter()
+                    .find_map(|completion| match completion {
+                        File { referenceable, .. } => Some(NewAlias {
+                            refname: refname.clone(),
+                            alias: alias.to_string(),
+                            referenceable,
+                        }),
+                        _ => None,
+                    })
+                    .filter(|_| {
+                        !alias.is_empty()
+                            && !aliases.iter().any(|existing| {
+                                matches!(existing, Alias { linked: false, .. })
+                                    && existing.match_string() == alias
+                            })
+                    });
+
+                fuzzy_match(&display, aliases, self.settings.insensitive_matching)
+                    .into_iter()
+                    .chain(new_alias.map(|completion| (completion, 0)))
+                    .map(|(completion, score)| completion.ordered(score))
+                    .collect()
+            }
             ref filter_text @ [..] if !filter_text.contains(&']') => {
-                let filter_text = &self.cmp_text;
-
-                let link_completions = self.link_completions();
+                if let Some(heading) = self.entered_vault_heading() {
+                    return self.ranked_link_completions(&heading);
+                }
 
-                let matches =
-                    fuzzy_match_completions(&String::from_iter(filter_text), link_completions);
+                // the completions are already limited to the entered file, so only the infile ref is matched
+                let filter_text = match self.entered_infile_ref() {
+                    Some((_, infile)) => format!("#{}", infile),
+                    None if filter_text.len() < self.settings.min_chars => {
+                        return self.recent_completions()
+                    }
+                    None => String::from_iter(filter_text),
+                };
 
-                matches
+                self.ranked_link_completions(&filter_text)
             }
             _ => vec![],
         }
@@ -498,25 +1374,66 @@ impl<'a> Completer<'a> for WikiLinkCompleter<'a> {
     }
 }
 
+/// For the text after the cursor in an existing wikilink, the offsets right after its closing `]]` and of the `|` before
+/// its display text. A `[` before the `]]` starts another link, so the cursor is not in an existing link.
+fn existing_link_end(after_cursor: &[char]) -> (Option<usize>, Option<usize>) {
+    let link = after_cursor
+        .iter()
+        .take_while(|c| !matches!(c, '[' | '\n'))
+        .collect::<Vec<_>>();
+
+    let Some(closing) = link
+        .windows(2)
+        .position(|pair| *pair[0] == ']' && *pair[1] == ']')
+    else {
+        return (None, None);
+    };
+    let display_start = link.iter().take(closing).position(|c| **c == '|');
+
+    (Some(closing + 2), display_start)
+}
+
 #[derive(Debug, Clone)]
 pub enum LinkCompletion<'a> {
     File {
         mdfile: &'a MDFile,
         match_string: String,
+        /// The file's path from the vault root, like `projects/meeting`, when another file has the same name
+        qualified_refname: Option<String>,
+        /// The file's first `#` heading, matched and shown instead of its name if `title_from_first_heading` is
+        /// enabled; the link still goes to the file by name
+        title: Option<&'a str>,
+        /// Another file with one of the `link_extensions` has the same name, like `notes.canvas` for `notes.md`
+        shares_name: bool,
         referenceable: Referenceable<'a>,
     },
     Alias {
         filename: &'a str,
         match_string: &'a str,
+        /// A display text the file has been linked with elsewhere, rather than one of its frontmatter `aliases`
+        linked: bool,
+        referenceable: Referenceable<'a>,
+    },
+    /// Display text entered after `[[file|` that is not an alias of the file yet; accepting it adds it to the file's
+    /// frontmatter `aliases`
+    NewAlias {
+        refname: String,
+        alias: String,
         referenceable: Referenceable<'a>,
     },
     Heading {
         heading: &'a MDHeading,
         match_string: String,
+        /// The parent headings, like `Setup > Dependencies > Rust`, if `heading_breadcrumbs` is enabled
+        breadcrumb: Option<String>,
         referenceable: Referenceable<'a>,
     },
     Block {
+        /// The block's refname, followed by its text if `block_text_completions` is enabled
         match_string: String,
+        refname: String,
+        /// The text of the block, without its index
+        block_text: String,
         referenceable: Referenceable<'a>,
     },
     Unresolved {
@@ -525,12 +1442,318 @@ pub enum LinkCompletion<'a> {
         infile_ref: Option<String>,
         referenceable: Referenceable<'a>,
     },
+    /// A heading entered after `[[file#` that the file doesn't have; accepting it adds the heading to the file
+    NewHeading {
+        /// The file and heading, like `file#heading`
+        refname: String,
+        heading: String,
+        path: &'a Path,
+    },
     DailyNote(MDDailyNote<'a>),
+    /// A file that isn't a note, like an image; linked to by name, or by path from the vault root when another
+    /// attachment has the same name
+    Attachment {
+        path: &'a Path,
+        refname: String,
+    },
 }
 
 use LinkCompletion::*;
 
-impl LinkCompletion<'_> {
+/// The heading with its parent headings, like `Setup > Dependencies > Rust`. Headings with the same text and parents
+/// are told apart by their line. None for a top level heading that needs no disambiguation.
+fn heading_breadcrumb(mdfile: &MDFile, heading: &MDHeading) -> Option<String> {
+    let crumbs = |heading: &MDHeading| {
+        mdfile
+            .heading_ancestors(heading)
+            .into_iter()
+            .chain(once(heading))
+            .map(|heading| heading.heading_text.as_str())
+            .join(" > ")
+    };
+
+    let breadcrumb = crumbs(heading);
+
+    let ambiguous = mdfile.headings.iter().any(|other| {
+        other != heading
+            && other.heading_text == heading.heading_text
+            && crumbs(other) == breadcrumb
+    });
+
+    match (ambiguous, breadcrumb.contains(" > ")) {
+        (true, _) => Some(format!(
+            "{} (line {})",
+            breadcrumb,
+            heading.range.start.line + 1
+        )),
+        (false, true) => Some(breadcrumb),
+        (false, false) => None,
+    }
+}
+
+/// The display text for a link to the file, by the `link_display_text` setting
+fn file_display_text(mdfile: &MDFile, display: LinkDisplayText) -> Option<&str> {
+    match display {
+        LinkDisplayText::None => None,
+        LinkDisplayText::Stem => mdfile.file_name(),
+        LinkDisplayText::Heading => mdfile
+            .headings
+            .first()
+            .map(|heading| heading.heading_text.as_str()),
+    }
+}
+
+/// The display text for a link to a block: its first few words, without list and quote markers, formatting, or the
+/// characters that would end the wikilink
+pub fn block_display_text(block_text: &str) -> Option<String> {
+    static MARKER_RE: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"^(\s*([-*+>]|\d+[.)]|\[.\])\s+)+").unwrap());
+    const WORDS: usize = 6;
+
+    let text = heading_link_text(&MARKER_RE.replace(block_text, ""));
+    let words = text.split_whitespace().collect_vec();
+
+    match words.len() {
+        0 => None,
+        len if len <= WORDS => Some(words.join(" ")),
+        _ => Some(format!("{}…", words[..WORDS].join(" "))),
+    }
+}
+
+/// How many headings and blocks the file has, like `meeting.md: 4 headings, 2 blocks`, shown with the completions
+/// after `[[file#` to confirm the file before picking one of them
+fn infile_ref_summary(vault: &Vault, path: &Path) -> Option<String> {
+    let mdfile = vault.md_files.get(path)?;
+    let count = |count: usize, noun: &str| match count {
+        1 => format!("1 {}", noun),
+        count => format!("{} {}s", count, noun),
+    };
+
+    Some(format!(
+        "{}: {}, {}",
+        path.file_name()?.to_str()?,
+        count(mdfile.headings.len(), "heading"),
+        count(mdfile.indexed_blocks.len(), "block")
+    ))
+}
+
+/// The phrase with its first letter upper case, like `Last Friday` for the label of a daily note
+fn capitalized(phrase: &str) -> String {
+    let mut chars = phrase.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Command to append the alias to the `aliases` in the frontmatter of the file being linked to
+fn add_alias_command<'a>(
+    completer: &impl LinkCompleter<'a>,
+    referenceable: &Referenceable,
+    alias: &str,
+) -> Option<Command> {
+    let path = referenceable.get_path();
+    let edit = completer.vault().select_add_alias_edit(path, alias)?;
+
+    Some(Command {
+        title: "Add Alias".into(),
+        command: "apply_edits".into(),
+        arguments: Some(vec![serde_json::to_value(WorkspaceEdit {
+            document_changes: Some(DocumentChanges::Edits(vec![TextDocumentEdit {
+                text_document: OptionalVersionedTextDocumentIdentifier {
+                    uri: Url::from_file_path(path).ok()?,
+                    version: None,
+                },
+                edits: vec![OneOf::Left(edit)],
+            }])),
+            ..Default::default()
+        })
+        .ok()?]),
+    })
+}
+
+/// The heading entered after `[[file#`, if the file exists and has no such heading. Markdown links are left out, as
+/// their infile refs may be slugs rather than the heading text.
+fn new_heading_completion<'a>(completer: &impl LinkCompleter<'a>) -> Option<LinkCompletion<'a>> {
+    if !completer.is_wikilink() || completer.is_embed() {
+        return None;
+    }
+
+    let (file, heading) = completer.entered_infile_ref()?;
+    let heading = heading.trim();
+    if heading.is_empty() || heading.starts_with('^') {
+        return None;
+    }
+
+    let path = completer.entered_infile_ref_path()?;
+    let exists = completer
+        .vault()
+        .md_files
+        .get(path)?
+        .headings
+        .iter()
+        .any(|existing| heading_link_text(&existing.heading_text) == heading_link_text(heading));
+
+    (!exists).then(|| NewHeading {
+        refname: format!("{}#{}", file, heading),
+        heading: heading.to_string(),
+        path,
+    })
+}
+
+/// Command to add the heading to the file, under the `new_heading_section` if the file has it
+fn add_heading_command<'a>(
+    completer: &impl LinkCompleter<'a>,
+    path: &Path,
+    heading: &str,
+) -> Option<Command> {
+    let edit = completer.vault().select_new_heading_edit(
+        path,
+        heading,
+        completer.settings().new_heading_section.as_deref(),
+    )?;
+
+    Some(Command {
+        title: "Add Heading".into(),
+        command: "apply_edits".into(),
+        arguments: Some(vec![serde_json::to_value(WorkspaceEdit {
+            document_changes: Some(DocumentChanges::Edits(vec![TextDocumentEdit {
+                text_document: OptionalVersionedTextDocumentIdentifier {
+                    uri: Url::from_file_path(path).ok()?,
+                    version: None,
+                },
+                edits: vec![OneOf::Left(edit)],
+            }])),
+            ..Default::default()
+        })
+        .ok()?]),
+    })
+}
+
+/// Command to create the missing note of an unresolved link completion, where following the link would create it;
+/// None if only the heading or block is missing
+fn create_note_command<'a>(
+    completer: &impl LinkCompleter<'a>,
+    referenceable: &Referenceable,
+) -> Option<Command> {
+    let (file, heading) = match referenceable {
+        Referenceable::UnresovledFile(_, file) => (file, None),
+        Referenceable::UnresolvedHeading(_, file, heading) => (file, Some(heading.to_string())),
+        Referenceable::UnresovledIndexedBlock(_, file, _) => (file, None),
+        _ => return None,
+    };
+
+    let (vault, path) = (completer.vault(), completer.path());
+    if existing_file(vault, path, file, completer.is_wikilink()).is_some() {
+        return None;
+    }
+    let new_path = new_note_path(
+        vault,
+        completer.settings(),
+        path,
+        file,
+        completer.is_wikilink(),
+    )?;
+
+    Some(Command {
+        title: "Create Note".into(),
+        command: CREATE_NOTE_COMMAND.into(),
+        arguments: Some(vec![serde_json::to_value(CreateNoteArgs {
+            uri: Url::from_file_path(new_path).ok()?,
+            heading,
+        })
+        .ok()?]),
+    })
+}
+
+/// Enough to find the referenceable of a link completion again when it is resolved
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct LinkCompletionData {
+    uri: Url,
+    /// The start of the heading or block; None for a file
+    position: Option<Position>,
+    /// A referenceable that doesn't exist yet has no position, so it is rebuilt from its refname
+    unresolved: Option<UnresolvedRefname>,
+    /// Headings and blocks are previewed as they would be embedded, for `![[file#heading]]` and `![[file#^block]]`
+    embed_preview: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+enum UnresolvedRefname {
+    File(String),
+    Heading(String, String),
+    IndexedBlock(String, String),
+}
+
+impl LinkCompletionData {
+    fn new(referenceable: &Referenceable, embed_preview: bool) -> Option<LinkCompletionData> {
+        Some(LinkCompletionData {
+            uri: Url::from_file_path(referenceable.get_path()).ok()?,
+            position: referenceable.get_range().map(|range| range.start),
+            unresolved: match referenceable {
+                Referenceable::UnresovledFile(_, file) => {
+                    Some(UnresolvedRefname::File(file.to_string()))
+                }
+                Referenceable::UnresolvedHeading(_, file, heading) => Some(
+                    UnresolvedRefname::Heading(file.to_string(), heading.to_string()),
+                ),
+                Referenceable::UnresovledIndexedBlock(_, file, index) => Some(
+                    UnresolvedRefname::IndexedBlock(file.to_string(), index.to_string()),
+                ),
+                _ => None,
+            },
+            embed_preview,
+        })
+    }
+
+    /// The referenceable among those of the file, or rebuilt if it is unresolved
+    fn referenceable<'a>(
+        &'a self,
+        path: &Path,
+        in_file: Vec<Referenceable<'a>>,
+    ) -> Option<Referenceable<'a>> {
+        let path = path.to_path_buf();
+
+        match &self.unresolved {
+            Some(UnresolvedRefname::File(file)) => Some(Referenceable::UnresovledFile(path, file)),
+            Some(UnresolvedRefname::Heading(file, heading)) => {
+                Some(Referenceable::UnresolvedHeading(path, file, heading))
+            }
+            Some(UnresolvedRefname::IndexedBlock(file, index)) => {
+                Some(Referenceable::UnresovledIndexedBlock(path, file, index))
+            }
+            None => in_file.into_iter().find(|referenceable| {
+                referenceable.get_range().map(|range| range.start) == self.position
+            }),
+        }
+    }
+}
+
+/// Fill in the preview of a link completion. Rendering it for every completion in a long list is slow, so it is only
+/// done for the one the editor resolves. Other completions, and those whose referenceable is gone, are unchanged.
+pub fn resolve_link_completion(vault: &Vault, item: CompletionItem) -> CompletionItem {
+    let documentation = (|| {
+        let data = serde_json::from_value::<LinkCompletionData>(item.data.clone()?).ok()?;
+        let path = data.uri.to_file_path().ok()?;
+        let referenceable =
+            data.referenceable(&path, vault.select_referenceable_nodes(Some(&path)))?;
+
+        data.embed_preview
+            .then(|| preview_embed(vault, &referenceable))
+            .flatten()
+            .or_else(|| preview_referenceable(vault, &referenceable))
+    })();
+
+    match documentation {
+        Some(documentation) => CompletionItem {
+            documentation: Some(Documentation::MarkupContent(documentation)),
+            ..item
+        },
+        None => item,
+    }
+}
+
+impl LinkCompletion<'_> {
     fn new<'a>(
         referenceable: Referenceable<'a>,
         completer: &impl LinkCompleter<'a>,
@@ -544,6 +1767,16 @@ impl LinkCompletion<'_> {
                         once(File {
                             mdfile,
                             match_string: mdfile.file_name()?.to_string(),
+                            qualified_refname: None,
+                            title: completer
+                                .settings()
+                                .title_from_first_heading
+                                .then(|| {
+                                    mdfile.headings.iter().find(|heading| heading.level.0 == 1)
+                                })
+                                .flatten()
+                                .map(|heading| heading.heading_text.as_str()),
+                            shares_name: false,
                             referenceable: referenceable.clone(),
                         })
                         .chain(mdfile.metadata.iter().flat_map(|it| it.aliases()).flat_map(
@@ -551,6 +1784,7 @@ impl LinkCompletion<'_> {
                                 Some(Alias {
                                     filename: mdfile.file_name()?,
                                     match_string: alias,
+                                    linked: false,
                                     referenceable: referenceable.clone(),
                                 })
                             },
@@ -564,19 +1798,49 @@ impl LinkCompletion<'_> {
                         match_string: format!(
                             "{}#{}",
                             path.file_stem()?.to_str()?,
-                            mdheading.heading_text
+                            heading_link_text(&mdheading.heading_text)
                         ),
+                        breadcrumb: completer
+                            .settings()
+                            .heading_breadcrumbs
+                            .then(|| {
+                                heading_breadcrumb(completer.vault().md_files.get(path)?, mdheading)
+                            })
+                            .flatten(),
                         referenceable,
                     })
                     .collect(),
                 ),
-                Referenceable::IndexedBlock(path, indexed) => Some(
-                    once(Block {
-                        match_string: format!("{}#^{}", path.file_stem()?.to_str()?, indexed.index),
-                        referenceable,
-                    })
-                    .collect(),
-                ),
+                Referenceable::IndexedBlock(path, indexed) => {
+                    let refname = format!("{}#^{}", path.file_stem()?.to_str()?, indexed.index);
+                    // the line with the index, not the nested items the block preview includes
+                    let block_text = completer
+                        .vault()
+                        .select_line(path, indexed.range.start.line as isize)
+                        .map(|line| {
+                            String::from_iter(line)
+                                .trim()
+                                .trim_end_matches(&format!("^{}", indexed.index))
+                                .trim()
+                                .to_string()
+                        })
+                        .unwrap_or_default();
+
+                    let match_string = match completer.settings().block_text_completions {
+                        true => format!("{} {}", refname, block_text),
+                        false => refname.clone(),
+                    };
+
+                    Some(
+                        once(Block {
+                            match_string,
+                            refname,
+                            block_text,
+                            referenceable,
+                        })
+                        .collect(),
+                    )
+                }
                 Referenceable::UnresovledFile(_, file) => Some(
                     once(Unresolved {
                         match_string: file.clone(),
@@ -612,30 +1876,40 @@ impl LinkCompletion<'_> {
         filter_text: &str,
         completer: &impl LinkCompleter<'a>,
     ) -> CompletionItem {
-        let vault = completer.vault();
-        let referenceable = match self {
-            Self::File { referenceable, .. }
-            | Self::Heading { referenceable, .. }
-            | Self::Block { referenceable, .. }
-            | Self::Unresolved { referenceable, .. }
-            | Self::Alias { referenceable, .. } => referenceable.to_owned(),
-            Self::DailyNote(daily) => daily.referenceable(completer),
-        };
+        let referenceable = self.referenceable(completer);
 
-        let label = self.match_string();
+        let label = match self {
+            Self::Block { refname, .. } => refname.to_string(),
+            Self::NewHeading { heading, path, .. } => format!(
+                "Create heading \"{}\" in {}",
+                heading,
+                path.file_stem().unwrap_or_default().to_string_lossy()
+            ),
+            Self::DailyNote(daily) => daily
+                .relative_name(completer)
+                .map(|name| capitalized(&name))
+                .unwrap_or_else(|| self.match_string().to_string()),
+            Self::Heading { heading, .. } if completer.settings().heading_level_labels => {
+                format!("{} {}", "#".repeat(heading.level.0), self.match_string())
+            }
+            _ => self.match_string().to_string(),
+        };
 
         CompletionItem {
-            label: label.to_string(),
+            label,
             kind: Some(match self {
                 Self::File { .. } => CompletionItemKind::FILE,
-                Self::Heading { .. } | Self::Block { .. } => CompletionItemKind::REFERENCE,
+                Self::Heading { .. } | Self::Block { .. } | Self::NewHeading { .. } => {
+                    CompletionItemKind::REFERENCE
+                }
                 Self::Unresolved {
                     match_string: _,
                     infile_ref: _,
                     ..
                 } => CompletionItemKind::KEYWORD,
-                Self::Alias { .. } => CompletionItemKind::ENUM,
+                Self::Alias { .. } | Self::NewAlias { .. } => CompletionItemKind::ENUM,
                 Self::DailyNote { .. } => CompletionItemKind::EVENT,
+                Self::Attachment { .. } => CompletionItemKind::FILE,
             }),
             label_details: match self {
                 Self::Unresolved {
@@ -646,14 +1920,72 @@ impl LinkCompletion<'_> {
                     detail: Some("Unresolved".into()),
                     description: None,
                 }),
+                Alias {
+                    filename,
+                    linked: true,
+                    ..
+                } => Some(CompletionItemLabelDetails {
+                    detail: Some(format!("Linked as: {}.md", filename)),
+                    description: None,
+                }),
                 Alias { filename, .. } => Some(CompletionItemLabelDetails {
                     detail: Some(format!("Alias: {}.md", filename)),
                     description: None,
                 }),
+                NewAlias { refname, .. } => Some(CompletionItemLabelDetails {
+                    detail: Some(format!("New alias: {}.md", refname)),
+                    description: None,
+                }),
+                File { title: Some(_), .. } => Some(CompletionItemLabelDetails {
+                    detail: Some(format!("Title of: {}.md", self.refname())),
+                    description: None,
+                }),
+                File {
+                    qualified_refname: Some(qualified_refname),
+                    ..
+                } => Some(CompletionItemLabelDetails {
+                    detail: Some(match qualified_refname.rsplit_once('/') {
+                        Some(("", _)) | None => " in /".to_string(),
+                        Some((folder, _)) => format!(" in {}/", folder),
+                    }),
+                    description: None,
+                }),
+                File {
+                    shares_name: true, ..
+                } => Some(CompletionItemLabelDetails {
+                    detail: Some(" .md".into()),
+                    description: None,
+                }),
                 File { .. } => None,
+                Heading {
+                    breadcrumb: Some(breadcrumb),
+                    ..
+                } => Some(CompletionItemLabelDetails {
+                    detail: None,
+                    description: Some(breadcrumb.clone()),
+                }),
                 Heading { .. } => None,
+                Block { block_text, .. } if !block_text.is_empty() => {
+                    Some(CompletionItemLabelDetails {
+                        detail: None,
+                        description: Some(match block_text.chars().count() > 50 {
+                            true => {
+                                format!("{}...", block_text.chars().take(50).collect::<String>())
+                            }
+                            false => block_text.to_string(),
+                        }),
+                    })
+                }
                 Block { .. } => None,
-                DailyNote(_) => None,
+                NewHeading { .. } => None,
+                DailyNote(daily) => Some(CompletionItemLabelDetails {
+                    detail: None,
+                    description: Some(daily.date.format("%a %Y-%m-%d").to_string()),
+                }),
+                Attachment { path, .. } => Some(CompletionItemLabelDetails {
+                    detail: None,
+                    description: Some(attachment_kind(path).into()),
+                }),
             },
             text_edit: Some(text_edit),
             preselect: Some(match self {
@@ -662,24 +1994,183 @@ impl LinkCompletion<'_> {
                 }
                 link_completion => link_completion.refname() == completer.entered_refname(),
             }),
+            detail: self.embed_cycle_warning(completer).or_else(|| {
+                completer
+                    .entered_infile_ref()
+                    .filter(|_| completer.settings().infile_ref_summary)
+                    .and_then(|_| {
+                        infile_ref_summary(completer.vault(), &self.target_path(completer)?)
+                    })
+            }),
             filter_text: Some(filter_text.to_string()),
-            documentation: preview_referenceable(vault, &referenceable)
-                .map(Documentation::MarkupContent),
+            // the preview is only rendered when the completion is resolved
+            data: referenceable
+                .as_ref()
+                .and_then(|referenceable| {
+                    LinkCompletionData::new(
+                        referenceable,
+                        completer.is_embed() && completer.settings().embed_block_previews,
+                    )
+                })
+                .and_then(|data| serde_json::to_value(data).ok()),
+            command: match (self, &referenceable) {
+                (Self::DailyNote(daily), Some(referenceable)) if referenceable.is_unresolved() => {
+                    daily.create_command(completer)
+                }
+                (Self::NewAlias { alias, .. }, Some(referenceable)) => {
+                    add_alias_command(completer, referenceable, alias)
+                }
+                (Self::Unresolved { referenceable, .. }, _)
+                    if completer.settings().create_unresolved_notes =>
+                {
+                    create_note_command(completer, referenceable)
+                }
+                (Self::NewHeading { heading, path, .. }, _) => {
+                    add_heading_command(completer, path, heading)
+                }
+                _ => None,
+            },
             ..Default::default()
         }
     }
 
+    /// None for attachments, which aren't referenceables
+    fn referenceable<'a, 'b>(
+        &'b self,
+        completer: &impl LinkCompleter<'a>,
+    ) -> Option<Referenceable<'b>> {
+        match self {
+            Self::File { referenceable, .. }
+            | Self::Heading { referenceable, .. }
+            | Self::Block { referenceable, .. }
+            | Self::Unresolved { referenceable, .. }
+            | Self::Alias { referenceable, .. }
+            | Self::NewAlias { referenceable, .. } => Some(referenceable.to_owned()),
+            Self::DailyNote(daily) => Some(daily.referenceable(completer)),
+            Self::NewHeading { .. } | Self::Attachment { .. } => None,
+        }
+    }
+
+    /// The file the completion links to, if it is known where it is or should be created
+    fn target_path<'a>(&self, completer: &impl LinkCompleter<'a>) -> Option<PathBuf> {
+        match self {
+            Self::DailyNote(daily) => Some(daily.file_path(completer)),
+            Self::NewHeading { path, .. } | Self::Attachment { path, .. } => {
+                Some(path.to_path_buf())
+            }
+            _ => {
+                let referenceable = self.referenceable(completer)?;
+                (!referenceable.is_unresolved()).then(|| referenceable.get_path().to_path_buf())
+            }
+        }
+    }
+
+    /// A warning that embedding the note makes a cycle, which some editors render endlessly: the note is the current
+    /// one, or it already embeds the current note. The embed is still inserted.
+    fn embed_cycle_warning<'a>(&self, completer: &impl LinkCompleter<'a>) -> Option<String> {
+        if !completer.is_embed() {
+            return None;
+        }
+
+        let target = self.target_path(completer)?;
+        match self {
+            File { .. } | Alias { .. } if target == completer.path() => {
+                Some("Embed cycle: this is the current note".into())
+            }
+            _ if completer.embeds_current_file(&target) => {
+                Some("Embed cycle: this note already embeds the current note".into())
+            }
+            _ => None,
+        }
+    }
+
+    /// A note that a wikilink by its name doesn't go to, as another file with the same name has an extension before
+    /// `md` in `link_extensions`
+    fn shadowed_by_attachment<'a>(&self, completer: &impl LinkCompleter<'a>) -> bool {
+        match self {
+            File {
+                shares_name: true, ..
+            } => completer
+                .vault()
+                .extensionless_link_attachment(&self.refname())
+                .is_some(),
+            _ => false,
+        }
+    }
+
     /// Refname to be inserted into the document
     fn refname(&self) -> String {
         match self {
             Self::DailyNote(MDDailyNote { ref_name, .. }) => ref_name.to_string(),
+            File {
+                qualified_refname: Some(qualified_refname),
+                ..
+            } => qualified_refname.to_string(),
             File { match_string, .. }
             | Heading { match_string, .. }
-            | Block { match_string, .. }
             | Unresolved { match_string, .. } => match_string.to_string(),
+            Block { refname, .. } => refname.to_string(),
             Alias { filename, .. } => filename.to_string(),
+            NewAlias { refname, .. } | NewHeading { refname, .. } | Attachment { refname, .. } => {
+                refname.to_string()
+            }
         }
     }
+
+    /// The refname split at the `#` into the file and the heading or block in it
+    fn refname_parts(&self) -> (String, Option<String>) {
+        let refname = self.refname();
+
+        match self {
+            Heading { .. }
+            | Block { .. }
+            | NewHeading { .. }
+            | Unresolved {
+                infile_ref: Some(_),
+                ..
+            } => match refname.split_once('#') {
+                Some((file, infile)) => (file.to_string(), Some(infile.to_string())),
+                None => (refname, None),
+            },
+            _ => (refname, None),
+        }
+    }
+}
+
+impl<'a> LinkCompletion<'a> {
+    /// The infile ref of a markdown link to the completion, as `markdown_infile_ref` writes it; a heading's slug is
+    /// the one worked out with its file, which is numbered when an earlier heading has the same slug
+    fn markdown_infile_ref(&self, settings: &Settings, infile: &str) -> String {
+        match self {
+            Heading { heading, .. }
+                if settings.markdown_heading_anchors == MarkdownHeadingAnchors::Slug =>
+            {
+                heading.anchor.slug.clone()
+            }
+            _ => markdown_infile_ref(settings, infile),
+        }
+    }
+
+    /// Ordered by the rank, and, for the same rank, headings and blocks by their line in the file and daily notes by
+    /// how many days they are from today
+    fn ordered<C: Completer<'a>>(self, rank: u32) -> OrderedCompletion<'a, C, Self>
+    where
+        Self: Completable<'a, C>,
+    {
+        let line = match &self {
+            Heading { heading, .. } => heading.range.start.line,
+            Block { referenceable, .. } => referenceable
+                .get_range()
+                .map(|range| range.start.line)
+                .unwrap_or_default(),
+            DailyNote(daily) => (daily.date - chrono::Local::now().date_naive())
+                .num_days()
+                .unsigned_abs() as u32,
+            _ => 0,
+        };
+
+        OrderedCompletion::new(self, rank).with_tiebreak(line)
+    }
 }
 
 impl<'a> Completable<'a, MarkdownLinkCompleter<'a>> for LinkCompletion<'a> {
@@ -687,7 +2178,14 @@ impl<'a> Completable<'a, MarkdownLinkCompleter<'a>> for LinkCompletion<'a> {
         &self,
         markdown_link_completer: &MarkdownLinkCompleter<'a>,
     ) -> Option<CompletionItem> {
-        let refname = self.refname();
+        // link by path, keeping the infile ref of the refname
+        let (refname, infile_ref) = self.refname_parts();
+        let infile_ref = infile_ref
+            .map(|infile| self.markdown_infile_ref(markdown_link_completer.settings(), &infile));
+        let refname = self
+            .target_path(markdown_link_completer)
+            .and_then(|target| markdown_link_completer.link_path(&target))
+            .unwrap_or(refname);
         let match_string = self.match_string();
 
         let display = &markdown_link_completer.display;
@@ -702,12 +2200,15 @@ impl<'a> Completable<'a, MarkdownLinkCompleter<'a>> for LinkCompletion<'a> {
                 match_string: _, ..
             } => None,
             Self::Alias { match_string, .. } => Some(match_string.to_string()),
+            Self::NewAlias { alias, .. } => Some(alias.to_string()),
+            Self::NewHeading { heading, .. } => Some(heading.to_string()),
             Self::DailyNote(daily) => daily.relative_name(markdown_link_completer),
+            Self::Attachment { .. } => None,
             Self::Heading {
                 heading,
                 match_string: _,
                 ..
-            } => Some(heading.heading_text.to_string()),
+            } => Some(heading_link_text(&heading.heading_text)),
             Self::Unresolved {
                 match_string: _,
                 infile_ref,
@@ -718,13 +2219,17 @@ impl<'a> Completable<'a, MarkdownLinkCompleter<'a>> for LinkCompletion<'a> {
         let binding = (display.0.as_str(), link_display_text);
         let link_display_text = match binding {
             ("", Some(ref infile)) => infile,
-            // Get the first heading of the file, if possible.
-            ("", None) if markdown_link_completer.settings().title_headings => match self {
-                Self::File { mdfile, .. } => mdfile
-                    .headings
-                    .first()
-                    .map(|heading| heading.heading_text.as_str())
-                    .unwrap_or(""),
+            ("", None) => match self {
+                Self::File { mdfile, .. } => {
+                    let settings = markdown_link_completer.settings();
+                    let display = match (settings.link_display_text, settings.title_headings) {
+                        (Some(display), _) => display,
+                        (None, true) => LinkDisplayText::Heading,
+                        (None, false) => LinkDisplayText::None,
+                    };
+
+                    file_display_text(mdfile, display).unwrap_or("")
+                }
                 Self::Alias {
                     match_string: alias,
                     ..
@@ -734,15 +2239,18 @@ impl<'a> Completable<'a, MarkdownLinkCompleter<'a>> for LinkCompletion<'a> {
             (display, _) => display,
         };
 
-        let link_display_text = format!("${{1:{}}}", link_display_text,);
+        let link_display_text = markdown_link_completer.display_snippet(link_display_text);
 
-        let text_edit =
-            markdown_link_completer.completion_text_edit(Some(&link_display_text), &refname);
+        let text_edit = markdown_link_completer.completion_text_edit(
+            Some(&link_display_text),
+            &refname,
+            infile_ref.as_deref(),
+        );
 
         let filter_text = markdown_link_completer.completion_filter_text(match_string); // TODO: abstract into default_completion
 
         Some(CompletionItem {
-            insert_text_format: Some(InsertTextFormat::SNIPPET),
+            insert_text_format: Some(markdown_link_completer.insert_text_format()),
             ..self.default_completion(text_edit, &filter_text, markdown_link_completer)
         })
     }
@@ -750,24 +2258,101 @@ impl<'a> Completable<'a, MarkdownLinkCompleter<'a>> for LinkCompletion<'a> {
 
 impl<'a> Completable<'a, WikiLinkCompleter<'a>> for LinkCompletion<'a> {
     fn completions(&self, completer: &WikiLinkCompleter<'a>) -> Option<CompletionItem> {
-        let refname = self.refname();
-        let match_text = self.match_string();
+        // headings and blocks insert their own `#infile`, so `![[file` can be completed to `![[file#heading]]`
+        let (refname, infile_ref) = self.refname_parts();
+
+        // [[#heading]] for the current file's headings and blocks
+        let same_file_infile_ref = completer
+            .entered_infile_ref()
+            .filter(|(file, _)| file.is_empty())
+            .and_then(|_| Some(format!("#{}", infile_ref.as_ref()?)));
+
+        let refname = match same_file_infile_ref {
+            Some(_) => String::new(),
+            None => refname,
+        };
+        let match_text = same_file_infile_ref
+            .as_deref()
+            .unwrap_or_else(|| self.match_string());
+
+        // an embed shows the block itself, not the display text
+        let block_display = match self {
+            Block { block_text, .. }
+                if completer.settings().block_display_text && !completer.is_embed() =>
+            {
+                block_display_text(block_text)
+            }
+            _ => None,
+        };
 
         let wikilink_display_text = match self {
-            File { .. } => None,
-            Alias { match_string, .. } => Some(format!("${{1:{}}}", match_string)),
+            File { mdfile, .. } => completer
+                .settings()
+                .link_display_text
+                .and_then(|display| file_display_text(mdfile, display)),
+            Alias { match_string, .. } => Some(*match_string),
+            NewAlias { alias, .. } => Some(alias.as_str()),
             Heading { .. } => None,
-            Block { .. } => None,
+            Block { .. } => block_display.as_deref(),
             Unresolved { .. } => None,
+            NewHeading { .. } => None,
             DailyNote(_) => None,
+            Attachment { .. } => None,
+        }
+        // no [[foo|foo]]
+        .filter(|display| *display != refname);
+
+        // a file named like `A [draft] note` would end the wikilink early, so it is linked with a markdown link, as is
+        // a note whose name goes to another file, like `notes.canvas`
+        let markdown_target = same_file_infile_ref
+            .is_none()
+            .then(|| self.target_path(completer))
+            .flatten()
+            .filter(|target| {
+                !is_wikilink_safe(target)
+                    || wikilink_display_text
+                        .is_some_and(|display| display.contains(['[', ']', '|']))
+                    || self.shadowed_by_attachment(completer)
+            });
+
+        let text_edit = match markdown_target {
+            Some(target) => {
+                let link_path = markdown_link_path(
+                    completer.settings(),
+                    completer.vault().root_dir(),
+                    completer.path(),
+                    &target,
+                )?;
+                // brackets would end the display text of the markdown link too
+                let display = wikilink_display_text
+                    .unwrap_or(target.file_stem()?.to_str()?)
+                    .replace(['[', ']'], "");
+
+                let infile_ref = infile_ref
+                    .map(|infile| self.markdown_infile_ref(completer.settings(), &infile));
+
+                completer.markdown_text_edit(
+                    &completer.display_snippet(&display),
+                    &markdown_link_target(&link_path, infile_ref.as_deref()),
+                )
+            }
+            None => completer.completion_text_edit(
+                wikilink_display_text
+                    .map(|display| completer.display_snippet(display))
+                    .as_deref(),
+                &refname,
+                infile_ref.as_deref(),
+            ),
         };
 
-        let text_edit = completer.completion_text_edit(wikilink_display_text.as_deref(), &refname);
-
-        let filter_text = completer.completion_filter_text(match_text);
+        // the editor filters by the `##` that was entered too
+        let filter_text = match completer.entered_vault_heading() {
+            Some(_) => completer.completion_filter_text(&format!("##{}", match_text)),
+            None => completer.completion_filter_text(match_text),
+        };
 
         Some(CompletionItem {
-            insert_text_format: Some(InsertTextFormat::SNIPPET),
+            insert_text_format: Some(completer.insert_text_format()),
             ..self.default_completion(text_edit, &filter_text, completer)
         })
     }
@@ -777,6 +2362,9 @@ impl Matchable for LinkCompletion<'_> {
     /// The string used for fuzzy matching
     fn match_string(&self) -> &str {
         match self {
+            File {
+                title: Some(title), ..
+            } => title,
             File {
                 mdfile: _,
                 match_string,
@@ -791,6 +2379,8 @@ impl Matchable for LinkCompletion<'_> {
             | Unresolved { match_string, .. }
             | DailyNote(MDDailyNote { match_string, .. }) => match_string,
             Alias { match_string, .. } => match_string,
+            NewAlias { alias, .. } => alias,
+            NewHeading { refname, .. } | Attachment { refname, .. } => refname,
         }
     }
 }
@@ -799,36 +2389,26 @@ impl Matchable for LinkCompletion<'_> {
 pub struct MDDailyNote<'a> {
     match_string: String,
     ref_name: String,
+    date: NaiveDate,
     real_referenceaable: Option<Referenceable<'a>>,
+    /// The relative phrase that was typed to get this daily note, such as `in 3 days`
+    relative_phrase: Option<String>,
 }
 
 impl MDDailyNote<'_> {
     pub fn relative_name<'a>(&self, completer: &impl LinkCompleter<'a>) -> Option<String> {
-        let self_date = self.get_self_date(completer)?;
+        if let Some(phrase) = &self.relative_phrase {
+            return Some(phrase.clone());
+        }
 
-        Self::relative_date_string(self_date)
+        relative_date_string(
+            self.get_self_date(completer)?,
+            chrono::Local::now().date_naive(),
+        )
     }
 
-    pub fn get_self_date<'a>(&self, completer: &impl LinkCompleter<'a>) -> Option<NaiveDate> {
-        let dailynote_format = &completer.settings().dailynote;
-
-        chrono::NaiveDate::parse_from_str(&self.ref_name, dailynote_format).ok()
-    }
-
-    fn relative_date_string(date: NaiveDate) -> Option<String> {
-        let today = chrono::Local::now().date_naive();
-
-        if today == date {
-            Some("today".to_string())
-        } else {
-            match (date - today).num_days() {
-                1 => Some("tomorrow".to_string()),
-                2..=7 => Some(format!("next {}", date.format("%A"))),
-                -1 => Some("yesterday".to_string()),
-                -7..=-1 => Some(format!("last {}", date.format("%A"))),
-                _ => None,
-            }
-        }
+    pub fn get_self_date<'a>(&self, _completer: &impl LinkCompleter<'a>) -> Option<NaiveDate> {
+        Some(self.date)
     }
 
     /// The refname used for fuzzy matching a completion - not the actual inserted text
@@ -836,21 +2416,31 @@ impl MDDailyNote<'_> {
         referenceable: Referenceable<'a>,
         completer: &impl LinkCompleter<'a>,
     ) -> Option<MDDailyNote<'a>> {
-        let Some((filerefname, filter_refname)) = (match referenceable {
+        let Some((filerefname, filter_refname, date)) = (match referenceable {
             Referenceable::File(&ref path, _) | Referenceable::UnresovledFile(ref path, _) => {
-                let filename = path.file_name();
-                let dailynote_format = &completer.settings().dailynote;
-                let (date, filename) = filename.and_then(|filename| {
-                    let filename = filename.to_str()?;
-                    let filename = filename.replace(".md", "");
-                    Some((
-                        chrono::NaiveDate::parse_from_str(&filename, dailynote_format).ok(),
-                        filename,
-                    ))
-                })?;
-
-                date.and_then(Self::relative_date_string)
-                    .map(|thing| (filename.clone(), format!("{}: {}", thing, filename)))
+                let settings = completer.settings();
+                let date = dailynote_date(
+                    path,
+                    completer.vault().root_dir(),
+                    &settings.dailynote,
+                    settings.daily_note_folder.as_deref(),
+                )?;
+
+                // notes in formats like `%Y/%m/%d` share their file name with other days' notes, so they are linked by path
+                let stem = path.file_stem();
+                let filename = match completer
+                    .vault()
+                    .md_files
+                    .keys()
+                    .filter(|other| other.file_stem() == stem)
+                    .count()
+                {
+                    0 | 1 => path.file_name()?.to_str()?.replace(".md", ""),
+                    _ => get_obsidian_ref_path(completer.vault().root_dir(), path)?,
+                };
+
+                relative_date_string(date, chrono::Local::now().date_naive())
+                    .map(|thing| (filename.clone(), format!("{}: {}", thing, filename), date))
             }
             _ => None,
         }) else {
@@ -860,7 +2450,9 @@ impl MDDailyNote<'_> {
         Some(MDDailyNote {
             match_string: filter_refname,
             ref_name: filerefname,
+            date,
             real_referenceaable: Some(referenceable),
+            relative_phrase: None,
         })
     }
 
@@ -868,28 +2460,563 @@ impl MDDailyNote<'_> {
         date: NaiveDate,
         completer: &impl LinkCompleter<'a>,
     ) -> Option<MDDailyNote<'a>> {
-        let filerefname = date.format(&completer.settings().dailynote).to_string();
-        let match_string = format!("{}: {}", Self::relative_date_string(date)?, filerefname);
+        let filerefname = dailynote_refname(date, completer.settings().dailynote_format());
+        let match_string = format!(
+            "{}: {}",
+            relative_date_string(date, chrono::Local::now().date_naive())?,
+            filerefname
+        );
 
         // path on unresolved file is useless
         Some(MDDailyNote {
             match_string,
             ref_name: filerefname.clone(),
+            date,
             real_referenceaable: None,
+            relative_phrase: None,
         })
     }
 
+    /// A daily note for a relative phrase like `in 3 days`; None if the phrase is not a date or is already
+    /// the standard relative name of the date (today, next monday, ...), as those are completed by `from_date`
+    fn from_phrase<'a>(
+        phrase: &str,
+        completer: &impl LinkCompleter<'a>,
+    ) -> Option<MDDailyNote<'a>> {
+        let settings = completer.settings();
+        let today = chrono::Local::now().date_naive();
+
+        let date = parse_relative_date(phrase, today, &settings.dailynote_phrases)?;
+        let phrase = phrase.split_whitespace().join(" ");
+
+        if relative_date_string(date, today)
+            .is_some_and(|standard| standard.eq_ignore_ascii_case(&phrase))
+        {
+            return None;
+        }
+
+        let filerefname = dailynote_refname(date, settings.dailynote_format());
+
+        let mut daily = MDDailyNote {
+            match_string: format!("{}: {}", phrase, filerefname),
+            ref_name: filerefname,
+            date,
+            real_referenceaable: None,
+            relative_phrase: Some(phrase),
+        };
+
+        daily.real_referenceaable = completer
+            .vault()
+            .md_files
+            .get_key_value(&daily.file_path(completer))
+            .map(|(path, mdfile)| Referenceable::File(path, mdfile));
+
+        Some(daily)
+    }
+
+    /// Command to create the daily note file, filled with the daily note template, when the completion is accepted
+    fn create_command<'a>(&self, completer: &impl LinkCompleter<'a>) -> Option<Command> {
+        let edit = create_dailynote_edit(
+            &self.file_path(completer),
+            &self.ref_name,
+            completer.vault().root_dir(),
+            completer.settings().dailynote_template.as_deref(),
+        )?;
+
+        Some(Command {
+            title: "Create Daily Note".into(),
+            command: "apply_edits".into(),
+            arguments: Some(vec![serde_json::to_value(edit).ok()?]),
+        })
+    }
+
+    /// Path of the daily note file, whether or not it exists; inside the daily note folder if one is configured
+    fn file_path<'a>(&self, completer: &impl LinkCompleter<'a>) -> PathBuf {
+        let settings = completer.settings();
+
+        dailynote_path(
+            self.date,
+            completer.vault().root_dir(),
+            settings.dailynote_format(),
+            settings.daily_note_folder.as_deref(),
+        )
+    }
+
     /// mock referenceable for kicks
     fn referenceable<'a, 'b>(&'b self, completer: &impl LinkCompleter<'a>) -> Referenceable<'b> {
         if let Some(referencaable) = &self.real_referenceaable {
             return referencaable.clone();
         }
 
-        let mut path = completer.vault().root_dir().to_path_buf();
-        path.push(format!("{}.md", self.ref_name));
+        let path = self.file_path(completer);
 
-        let unresolved_file = Referenceable::UnresovledFile(path.to_path_buf(), &self.ref_name);
+        let unresolved_file = Referenceable::UnresovledFile(path, &self.ref_name);
 
         unresolved_file
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::{Path, PathBuf},
+        time::{Duration, SystemTime},
+    };
+
+    use itertools::Itertools;
+    use tower_lsp::lsp_types::{
+        CompletionItem, CompletionItemKind, CompletionParams, CompletionResponse,
+        CompletionTextEdit, DocumentChangeOperation, DocumentChanges, OneOf, Position, Range,
+        ResourceOp, TextDocumentIdentifier, TextDocumentPositionParams, TextEdit, Url,
+    };
+
+    use crate::completion::{
+        get_completions, test_completions, CancelToken, Completer, Context, RecentFiles,
+    };
+    use crate::config::MarkdownHeadingAnchors;
+    use crate::resolve::{create_note_edit, CreateNoteArgs};
+    use crate::vault::{MDFile, MDHeading, MDIndexedBlock, Reference, Referenceable, TestVault};
+
+    use super::{
+        block_display_text, existing_link_end, is_wikilink_safe, markdown_link_target,
+        LinkCompletionData, WikiLinkCompleter,
+    };
+
+    #[test]
+    fn test_unsafe_wikilink_titles() {
+        let root_dir = Path::new("/home/vault");
+        let reference_path = Path::new("/home/vault/today.md");
+
+        for title in ["A [draft] note", "C# notes", "this | that", "]] broken"] {
+            let path = PathBuf::from(format!("/home/vault/{}.md", title));
+            assert!(!is_wikilink_safe(&path), "{}", title);
+
+            // the markdown link completed instead of the wikilink resolves to the file
+            let text = format!(
+                "[{}]({})",
+                title.replace(['[', ']'], ""),
+                markdown_link_target(&format!("{}.md", title), None)
+            );
+            let references = Reference::new(&text).collect::<Vec<_>>();
+            let md_file = MDFile::default();
+            let referenceable = Referenceable::File(&path, &md_file);
+
+            assert_eq!(references.len(), 1, "{}", text);
+            assert!(
+                referenceable.matches_reference(root_dir, &references[0], reference_path, false),
+                "{}",
+                text
+            );
+        }
+
+        assert!(is_wikilink_safe(Path::new("/home/vault/plain note.md")));
+    }
+
+    #[test]
+    fn test_existing_link_end() {
+        let end = |text: &str| existing_link_end(&text.chars().collect::<Vec<_>>());
+
+        // [[fo|o]] more
+        assert_eq!(end("o]] more"), (Some(3), None));
+        // [[fo|o|display]]
+        assert_eq!(end("o|display]]"), (Some(11), Some(1)));
+        // [[|]], right after the `[[` of an empty link
+        assert_eq!(end("]]"), (Some(2), None));
+        // [[fo| and [[other]]
+        assert_eq!(end(" and [[other]]"), (None, None));
+        assert_eq!(end(""), (None, None));
+    }
+
+    #[test]
+    fn test_completion_data_referenceable() {
+        let path = PathBuf::from("/home/vault/note.md");
+        let md_file = MDFile::default();
+        let range = |line: u32| {
+            Range {
+                start: Position { line, character: 0 },
+                end: Position { line, character: 5 },
+            }
+            .into()
+        };
+        let heading = MDHeading {
+            heading_text: "Heading".into(),
+            range: range(2),
+            ..Default::default()
+        };
+        let block = MDIndexedBlock {
+            index: "abc".into(),
+            range: range(4),
+        };
+        let in_file = vec![
+            Referenceable::File(&path, &md_file),
+            Referenceable::Heading(&path, &heading),
+            Referenceable::IndexedBlock(&path, &block),
+        ];
+
+        let missing = PathBuf::from("/home/vault/missing");
+        let (file, heading_text) = ("missing".to_string(), "Later".to_string());
+        let unresolved = Referenceable::UnresolvedHeading(missing.clone(), &file, &heading_text);
+
+        for referenceable in in_file.iter().chain([&unresolved]) {
+            // the data makes a round trip through the client as json
+            let data = LinkCompletionData::new(referenceable, false).unwrap();
+            let data =
+                serde_json::from_value::<LinkCompletionData>(serde_json::to_value(&data).unwrap())
+                    .unwrap();
+
+            let path = data.uri.to_file_path().unwrap();
+            assert_eq!(
+                data.referenceable(&path, in_file.clone()).as_ref(),
+                Some(referenceable)
+            );
+        }
+    }
+
+    #[test]
+    fn test_block_display_text() {
+        assert_eq!(
+            block_display_text("A short block").as_deref(),
+            Some("A short block")
+        );
+        assert_eq!(
+            block_display_text("The first few words of a much longer block of text").as_deref(),
+            Some("The first few words of a…")
+        );
+        assert_eq!(
+            block_display_text("- [ ] **Call** [[Alice|her]] about #project").as_deref(),
+            Some("Call her about project")
+        );
+        assert_eq!(
+            block_display_text("> 1. quoted item").as_deref(),
+            Some("quoted item")
+        );
+        assert_eq!(block_display_text("- ").as_deref(), None);
+    }
+
+    #[test]
+    fn test_shared_name_completions() {
+        let files = [
+            ("today.md", "[[notes\n"),
+            ("notes.md", "# Notes\n"),
+            ("notes.canvas", "{}"),
+        ];
+        let note_completion = |test_vault: &TestVault| {
+            test_completions(test_vault, "today.md", Position::new(0, 7))
+                .into_iter()
+                .find(|item| item.label == "notes")
+                .unwrap()
+        };
+        let new_text = |item: &CompletionItem| match &item.text_edit {
+            Some(CompletionTextEdit::Edit(edit)) => edit.new_text.clone(),
+            _ => panic!("expected a text edit"),
+        };
+
+        // the note is told apart from the canvas by its extension
+        let item = note_completion(&TestVault::new(&files));
+        assert_eq!(
+            item.label_details
+                .clone()
+                .and_then(|details| details.detail),
+            Some(" .md".to_string())
+        );
+        assert_eq!(new_text(&item), "notes]]${2:}");
+
+        // and linked with a markdown link when `[[notes]]` would go to the canvas
+        let test_vault = TestVault::with_settings(&files, |settings| {
+            settings.link_extensions = vec!["canvas".into(), "md".into()]
+        });
+        let item = note_completion(&test_vault);
+        assert_eq!(
+            item.label_details
+                .clone()
+                .and_then(|details| details.detail),
+            Some(" .md".to_string())
+        );
+        assert_eq!(new_text(&item), "[${1:notes}](notes.md)");
+    }
+
+    #[test]
+    fn test_attachment_completions() {
+        let files = [
+            ("today.md", "![[n\n[[n\n"),
+            ("assets/diagram.Png", ""),
+            ("scan.pdf", ""),
+            ("song.xyz", ""),
+            ("a/dup.png", ""),
+            ("b/dup.png", ""),
+        ];
+        let attachments = |test_vault: &TestVault, line| {
+            test_completions(test_vault, "today.md", Position::new(line, 4 - line))
+                .into_iter()
+                .filter(|item| item.kind == Some(CompletionItemKind::FILE))
+                .map(|item| {
+                    let kind = item.label_details.and_then(|details| details.description);
+                    (item.label, kind.unwrap_or_default())
+                })
+                .sorted()
+                .collect::<Vec<_>>()
+        };
+        let attachment = |label: &str, kind: &str| (label.to_string(), kind.to_string());
+
+        // in embeds, by name or by path when the name is shared, with their kind
+        let test_vault = TestVault::new(&files);
+        assert_eq!(
+            attachments(&test_vault, 0),
+            vec![
+                attachment("a/dup.png", "Image"),
+                attachment("b/dup.png", "Image"),
+                attachment("diagram.Png", "Image"),
+                attachment("scan.pdf", "PDF"),
+            ]
+        );
+        assert_eq!(attachments(&test_vault, 1), vec![]);
+
+        // in all wikilinks behind the setting, and with other extensions
+        let test_vault = TestVault::with_settings(&files, |settings| {
+            settings.attachment_completions = true;
+            settings.attachment_extensions = vec!["xyz".into(), "pdf".into()];
+        });
+        assert_eq!(
+            attachments(&test_vault, 1),
+            vec![
+                attachment("scan.pdf", "PDF"),
+                attachment("song.xyz", "Attachment"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_linked_aliases() {
+        let files = [
+            ("today.md", "[[Annual\n"),
+            ("2023-report.md", "---\naliases: [Report]\n---\n"),
+            (
+                "other.md",
+                "[[2023-report|Annual Report]] [[2023-report|Report]] [[missing|Annual Plan]]",
+            ),
+        ];
+        let mut test_vault =
+            TestVault::with_settings(&files, |settings| settings.link_text_aliases = true);
+        let aliases = |test_vault: &TestVault| {
+            test_completions(test_vault, "today.md", Position::new(0, 8))
+                .into_iter()
+                .filter(|item| item.label.contains("Annual"))
+                .map(|item| item.label)
+                .collect::<Vec<_>>()
+        };
+
+        // the display text the file was linked with, not the one it already has as an alias nor that of another link
+        assert_eq!(aliases(&test_vault), vec!["Annual Report"]);
+
+        // and none once the link is gone
+        let other = test_vault.path("other.md");
+        test_vault.edit(&other, "[[2023-report]]");
+        assert_eq!(aliases(&test_vault), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_create_note_command() {
+        let files = [("today.md", "[[miss\n"), ("other.md", "[[missing#Plan]]\n")];
+        let command = |test_vault: &TestVault| {
+            test_completions(test_vault, "today.md", Position::new(0, 6))
+                .into_iter()
+                .find(|item| item.label.starts_with("missing#Plan"))
+                .unwrap()
+                .command
+        };
+
+        assert_eq!(command(&TestVault::new(&files)), None);
+
+        let test_vault =
+            TestVault::with_settings(&files, |settings| settings.create_unresolved_notes = true);
+        let command = command(&test_vault).unwrap();
+        assert_eq!(command.command, "markdown-oxide.createNote");
+
+        let args: CreateNoteArgs =
+            serde_json::from_value(command.arguments.unwrap()[0].clone()).unwrap();
+        let uri = Url::from_file_path(test_vault.path("missing.md")).unwrap();
+        assert_eq!(
+            args,
+            CreateNoteArgs {
+                uri: uri.clone(),
+                heading: Some("Plan".into())
+            }
+        );
+
+        // the note is created with the heading linked to
+        let edit = create_note_edit(&args).unwrap();
+        let Some(DocumentChanges::Operations(operations)) = edit.document_changes else {
+            panic!("expected document change operations");
+        };
+        assert!(matches!(
+            &operations[0],
+            DocumentChangeOperation::Op(ResourceOp::Create(create)) if create.uri == uri
+        ));
+        assert!(matches!(
+            &operations[1],
+            DocumentChangeOperation::Edit(edit)
+                if edit.edits == vec![OneOf::Left(TextEdit {
+                    range: Range::default(),
+                    new_text: "# Plan\n".into(),
+                })]
+        ));
+    }
+
+    #[test]
+    fn test_link_completion_suffix() {
+        let files = [
+            (
+                "today.md",
+                "[[not\n[[not]] after\n[x](not\n[x](not) after\n[[A [dr\n[[A [dr]] after\n",
+            ),
+            ("notes.md", ""),
+            ("A [draft] note.md", ""),
+        ];
+        let test_vault = TestVault::with_settings(&files, |settings| {
+            settings.link_completion_suffix = " ".into()
+        });
+        let new_text = |line: u32, character: u32, label: &str| {
+            test_completions(&test_vault, "today.md", Position::new(line, character))
+                .into_iter()
+                .find(|item| item.label == label)
+                .and_then(|item| match item.text_edit? {
+                    CompletionTextEdit::Edit(edit) => Some(edit.new_text),
+                    _ => None,
+                })
+                .unwrap()
+        };
+
+        // after a new link, not inside an existing one
+        assert_eq!(new_text(0, 5, "notes"), "notes]] ${2:}");
+        assert_eq!(new_text(1, 5, "notes"), "notes]]${2:}");
+        assert_eq!(new_text(2, 7, "notes"), "[${1:x}](notes.md) ");
+        assert_eq!(new_text(3, 7, "notes"), "[${1:x}](notes.md)");
+        // as for a wikilink completed as a markdown link
+        assert_eq!(
+            new_text(4, 6, "A [draft] note"),
+            "[${1:A draft note}](A%20%5Bdraft%5D%20note.md) "
+        );
+        assert_eq!(
+            new_text(5, 6, "A [draft] note"),
+            "[${1:A draft note}](A%20%5Bdraft%5D%20note.md)"
+        );
+    }
+
+    #[test]
+    fn test_markdown_heading_slugs() {
+        let files = [
+            ("today.md", "[x](notes#\n"),
+            ("notes.md", "# Notes\n\n## Next steps\n\n## Next Steps!\n"),
+        ];
+        let test_vault = TestVault::with_settings(&files, |settings| {
+            settings.markdown_heading_anchors = MarkdownHeadingAnchors::Slug
+        });
+
+        let new_texts = test_completions(&test_vault, "today.md", Position::new(0, 10))
+            .into_iter()
+            .flat_map(|item| match item.text_edit? {
+                CompletionTextEdit::Edit(edit) => Some(edit.new_text),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        // the second heading with the slug is numbered, as on GitHub
+        for slug in ["notes", "next-steps", "next-steps-1"] {
+            assert!(
+                new_texts
+                    .iter()
+                    .any(|text| text.contains(&format!("notes.md#{})", slug))),
+                "{:?}",
+                new_texts
+            );
+        }
+    }
+
+    #[test]
+    fn test_recent_completions() {
+        let files = [
+            ("today.md", "[[\n"),
+            ("old.md", ""),
+            ("older.md", ""),
+            ("newest.md", ""),
+            ("untouched.md", ""),
+        ];
+        let test_vault = TestVault::with_settings(&files, |settings| settings.completion_limit = 2);
+        let path = test_vault.path("today.md");
+
+        let now = SystemTime::now();
+        let recent_files = ["older.md", "old.md", "newest.md"]
+            .into_iter()
+            .enumerate()
+            .map(|(age, file)| {
+                (
+                    test_vault.path(file),
+                    now - Duration::from_secs(3 - age as u64),
+                )
+            })
+            .collect::<RecentFiles>();
+        let params = CompletionParams {
+            text_document_position: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier {
+                    uri: Url::from_file_path(&path).unwrap(),
+                },
+                position: Position::new(0, 2),
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+            context: None,
+        };
+
+        let Some(CompletionResponse::List(list)) = get_completions(
+            &test_vault.vault,
+            std::slice::from_ref(&path),
+            &recent_files,
+            &params,
+            &path,
+            &test_vault.settings,
+            &CancelToken::default(),
+        ) else {
+            panic!("no completions")
+        };
+
+        // only the most recently touched files, up to the limit, best first
+        let labels = list
+            .items
+            .into_iter()
+            .sorted_by_key(|item| item.sort_text.clone())
+            .map(|item| item.label)
+            .collect::<Vec<_>>();
+        assert_eq!(labels, vec!["newest", "old"]);
+    }
+
+    #[test]
+    fn test_cancelled_completions() {
+        let files = [("today.md", "[[no\n"), ("notes.md", ""), ("nothing.md", "")];
+        let test_vault = TestVault::new(&files);
+        let path = test_vault.path("today.md");
+        let recent_files = RecentFiles::new();
+
+        let completions = |cancel: &CancelToken| {
+            let context = Context {
+                vault: &test_vault.vault,
+                opened_files: std::slice::from_ref(&path),
+                recent_files: &recent_files,
+                path: &path,
+                settings: &test_vault.settings,
+                cancel,
+            };
+
+            WikiLinkCompleter::construct(context, 0, 4)
+                .unwrap()
+                .completions()
+                .len()
+        };
+
+        assert!(completions(&CancelToken::default()) > 0);
+
+        // the referenceables are no longer matched once the request is cancelled
+        let cancel = CancelToken::default();
+        cancel.cancel();
+        assert_eq!(completions(&cancel), 0);
+    }
+}
@@ -1,7 +1,8 @@
 use std::ops::Deref;
 
 use nucleo_matcher::{
-    pattern::{self, Normalization},
+    chars,
+    pattern::{self, CaseMatching, Normalization},
     Matcher,
 };
 use tower_lsp::lsp_types::CompletionItem;
@@ -32,20 +33,28 @@ where
     T: Completable<'a, C>,
 {
     completable: T,
-    rank: String,
+    /// Higher for better completions, like the fuzzy score or how recently the file was modified
+    rank: u32,
+    /// Orders completions with the same rank, lowest first, like headings by their line in the file
+    tiebreak: u32,
     __phantom: std::marker::PhantomData<&'a T>,
     __phantom2: std::marker::PhantomData<C>,
 }
 
 impl<'a, C: Completer<'a>, T: Completable<'a, C>> OrderedCompletion<'a, C, T> {
-    pub fn new(completable: T, rank: String) -> Self {
+    pub fn new(completable: T, rank: u32) -> Self {
         Self {
             completable,
             rank,
+            tiebreak: 0,
             __phantom: std::marker::PhantomData,
             __phantom2: std::marker::PhantomData,
         }
     }
+
+    pub fn with_tiebreak(self, tiebreak: u32) -> Self {
+        Self { tiebreak, ..self }
+    }
 }
 
 impl<'a, C: Completer<'a>, T: Completable<'a, C>> Completable<'a, C>
@@ -55,40 +64,92 @@ impl<'a, C: Completer<'a>, T: Completable<'a, C>> Completable<'a, C>
         let completion = self.completable.completions(completer);
 
         completion.map(|completion| CompletionItem {
-            sort_text: Some(self.rank.to_string()),
+            sort_text: Some(sort_text(self.rank, self.tiebreak)),
             ..completion
         })
     }
 }
 
+/// Clients sort completions by their `sortText` as strings, ascending; the rank is inverted and zero padded so the best
+/// completion is first in every client, followed by the tiebreak for completions with the same rank
+fn sort_text(rank: u32, tiebreak: u32) -> String {
+    format!("{:010}{:010}", u32::MAX - rank, tiebreak)
+}
+
 pub fn fuzzy_match_completions<'a, 'b, C: Completer<'a>, T: Matchable + Completable<'a, C>>(
     filter_text: &'b str,
     items: impl IntoIterator<Item = T>,
+    insensitive: bool,
 ) -> Vec<OrderedCompletion<'a, C, T>> {
-    let normal_fuzzy_match = fuzzy_match(filter_text, items);
+    let normal_fuzzy_match = fuzzy_match(filter_text, items, insensitive);
 
     normal_fuzzy_match
         .into_iter()
-        .map(|(item, score)| OrderedCompletion::new(item, score.to_string()))
+        .map(|(item, score)| OrderedCompletion::new(item, score))
         .collect::<Vec<_>>()
 }
 
+/// If `insensitive`, case and diacritics are ignored in both the filter text and the match strings; `cafe` matches
+/// `Café`. Only the matching is affected, the items themselves are returned unchanged.
 pub fn fuzzy_match<'a, T: Matchable>(
     filter_text: &str,
     items: impl IntoIterator<Item = T>,
+    insensitive: bool,
 ) -> Vec<(T, u32)> {
     let items = items.into_iter().map(NucleoMatchable);
 
+    let pattern = match insensitive {
+        // The filter text is normalized here so that the matcher also normalizes the match strings
+        true => pattern::Pattern::parse(
+            &filter_text
+                .chars()
+                .map(chars::normalize)
+                .collect::<String>(),
+            CaseMatching::Ignore,
+            Normalization::Smart,
+        ),
+        false => pattern::Pattern::parse(filter_text, CaseMatching::Respect, Normalization::Never),
+    };
+
     let mut matcher = Matcher::new(nucleo_matcher::Config::DEFAULT);
-    let matches = pattern::Pattern::parse(
-        filter_text,
-        pattern::CaseMatching::Smart,
-        Normalization::Smart,
-    )
-    .match_list(items, &mut matcher);
+    let matches = pattern.match_list(items, &mut matcher);
 
     matches
         .into_iter()
         .map(|(item, score)| (item.0, score))
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+
+    use super::sort_text;
+
+    #[test]
+    fn test_sort_text_order() {
+        let ranks = [0, 9, 10, 99, 100, 12_345, u32::MAX];
+
+        let sorted = ranks
+            .iter()
+            .sorted_by_key(|rank| sort_text(**rank, 0))
+            .copied()
+            .collect_vec();
+
+        assert_eq!(sorted, ranks.iter().rev().copied().collect_vec());
+    }
+
+    #[test]
+    fn test_sort_text_tiebreak() {
+        // (rank, line)
+        let completions = [(5, 120), (5, 3), (7, 40), (5, 12)];
+
+        let sorted = completions
+            .iter()
+            .sorted_by_key(|(rank, line)| sort_text(*rank, *line))
+            .copied()
+            .collect_vec();
+
+        assert_eq!(sorted, vec![(7, 40), (5, 3), (5, 12), (5, 120)]);
+    }
+}
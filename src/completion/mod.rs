@@ -1,4 +1,8 @@
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::SystemTime;
 
 use rayon::prelude::*;
 
@@ -8,6 +12,7 @@ use crate::{config::Settings, vault::Vault};
 
 use self::callout_completer::CalloutCompleter;
 use self::link_completer::WikiLinkCompleter;
+use self::property_completer::PropertyCompleter;
 use self::{
     footnote_completer::FootnoteCompleter, link_completer::MarkdownLinkCompleter,
     tag_completer::TagCompleter, unindexed_block_completer::UnindexedBlockCompleter,
@@ -17,16 +22,52 @@ mod callout_completer;
 mod footnote_completer;
 mod link_completer;
 mod matcher;
+mod property_completer;
 mod tag_completer;
 mod unindexed_block_completer;
 mod util;
 
+pub use self::link_completer::{
+    block_display_text, markdown_infile_ref, markdown_link_path, percent_encode_refname,
+    resolve_link_completion,
+};
+
 #[derive(Clone, Copy)]
 pub struct Context<'a> {
     vault: &'a Vault,
     opened_files: &'a [PathBuf],
+    recent_files: &'a RecentFiles,
     path: &'a Path,
     settings: &'a Settings,
+    cancel: &'a CancelToken,
+}
+
+/// When each file was last opened or edited in this session. Link completions rank recent files by it rather than by
+/// when the file was modified, which changes for other reasons too, like syncing.
+pub type RecentFiles = HashMap<PathBuf, SystemTime>;
+
+/// Set when the completion request is cancelled, so the completers stop their work early; the client doesn't want
+/// the result anymore, usually because more was typed
+#[derive(Clone, Default, Debug)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Cancels the token when dropped, like when the future of a cancelled request is dropped
+pub struct CancelOnDrop(pub CancelToken);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
 }
 
 pub trait Completer<'a>: Sized {
@@ -55,15 +96,19 @@ type LineRange<T> = std::ops::Range<T>;
 pub fn get_completions(
     vault: &Vault,
     initial_completion_files: &[PathBuf],
+    recent_files: &RecentFiles,
     params: &CompletionParams,
     path: &Path,
     config: &Settings,
+    cancel: &CancelToken,
 ) -> Option<CompletionResponse> {
     let completion_context = Context {
         vault,
         opened_files: initial_completion_files,
+        recent_files,
         path,
         settings: config,
+        cancel,
     };
 
     // I would refactor this if I could figure out generic closures
@@ -93,6 +138,13 @@ pub fn get_completions(
             params.text_document_position.position.character,
         )
     })
+    .or_else(|| {
+        run_completer::<PropertyCompleter>(
+            completion_context,
+            params.text_document_position.position.line,
+            params.text_document_position.position.character,
+        )
+    })
     .or_else(|| {
         run_completer::<TagCompleter>(
             completion_context,
@@ -116,6 +168,43 @@ pub fn get_completions(
     })
 }
 
+/// The completions at a position in a file of a test vault, which is the only file opened
+#[cfg(test)]
+pub fn test_completions(
+    test_vault: &crate::vault::TestVault,
+    file: &str,
+    position: tower_lsp::lsp_types::Position,
+) -> Vec<CompletionItem> {
+    use tower_lsp::lsp_types::{TextDocumentIdentifier, TextDocumentPositionParams, Url};
+
+    let path = test_vault.path(file);
+    let params = CompletionParams {
+        text_document_position: TextDocumentPositionParams {
+            text_document: TextDocumentIdentifier {
+                uri: Url::from_file_path(&path).unwrap(),
+            },
+            position,
+        },
+        work_done_progress_params: Default::default(),
+        partial_result_params: Default::default(),
+        context: None,
+    };
+
+    match get_completions(
+        &test_vault.vault,
+        std::slice::from_ref(&path),
+        &RecentFiles::new(),
+        &params,
+        &path,
+        &test_vault.settings,
+        &CancelToken::default(),
+    ) {
+        Some(CompletionResponse::List(list)) => list.items,
+        Some(CompletionResponse::Array(items)) => items,
+        None => vec![],
+    }
+}
+
 // #[cfg(test)]
 // mod tests {
 //     use itertools::Itertools;
@@ -316,9 +405,14 @@ fn run_completer<'a, T: Completer<'a>>(
     let completer = T::construct(context, line as usize, character as usize)?;
     let completions = completer.completions();
 
+    // the other completers aren't tried either; the request fails as cancelled
+    if context.cancel.is_cancelled() {
+        return Some(CompletionResponse::Array(vec![]));
+    }
+
+    // the completables are ranked, so the best matches are kept
     let completions = completions
         .into_iter()
-        .take(20)
         .flat_map(|completable| {
             completable
                 .completions(&completer)
@@ -326,8 +420,11 @@ fn run_completer<'a, T: Completer<'a>>(
                 .collect::<Vec<_>>()
                 .into_iter()
         })
+        .take(context.settings.completion_limit)
         .collect::<Vec<CompletionItem>>();
 
+    // always incomplete, so that the client asks again as the query narrows, both for the matches cut off by the limit
+    // and because the completions are ranked and edited for the text entered so far
     Some(CompletionResponse::List(CompletionList {
         is_incomplete: true,
         items: completions,
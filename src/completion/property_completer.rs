@@ -0,0 +1,229 @@
+use std::path::Path;
+
+use indexmap::IndexMap;
+use itertools::Itertools;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use tower_lsp::lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionTextEdit, Position, Range, TextEdit,
+};
+
+use crate::vault::{Referenceable, Vault};
+
+use super::{
+    matcher::{fuzzy_match_completions, Matchable},
+    Completable, Completer, LineRange,
+};
+
+/// Values are only completed for properties that have been given at most this many distinct values
+const MAX_PROPERTY_VALUES: usize = 20;
+
+pub struct PropertyCompleter<'a> {
+    /// The property whose value is being completed; None if the property key itself is being completed
+    key: Option<String>,
+    /// The text typed so far and its range on the line
+    typed: (String, LineRange<u32>),
+    line: u32,
+    properties: IndexMap<&'a str, Vec<&'a str>>,
+    /// The tags used in the vault, inline and in frontmatter, for the values of the `tags` property
+    tags: Vec<&'a str>,
+    insensitive_matching: bool,
+}
+
+impl<'a> Completer<'a> for PropertyCompleter<'a> {
+    fn construct(context: super::Context<'a>, line: usize, character: usize) -> Option<Self>
+    where
+        Self: Sized + Completer<'a>,
+    {
+        // only between the opening and closing `---` of the frontmatter
+        let frontmatter_end = context.vault.select_frontmatter_end_line(context.path)?;
+        if line == 0 || line >= frontmatter_end {
+            return None;
+        }
+
+        let line_chars = context.vault.select_line(context.path, line as isize)?;
+        let line_to_cursor = String::from_iter(line_chars.get(0..character)?);
+
+        static KEY_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(?<typed>[\w-]*)$").unwrap());
+        // key: value or key: [value, value
+        static VALUE_RE: Lazy<Regex> = Lazy::new(|| {
+            Regex::new(r"^(?<key>[\w-]+):\s*(\[([^\]]*,\s*)?)?(?<typed>[^\[\],]*)$").unwrap()
+        });
+        //   - value, under a `key:` line
+        static LIST_ITEM_RE: Lazy<Regex> =
+            Lazy::new(|| Regex::new(r"^\s+-\s*(?<typed>.*)$").unwrap());
+        static LIST_KEY_RE: Lazy<Regex> =
+            Lazy::new(|| Regex::new(r"^(?<key>[\w-]+):\s*$").unwrap());
+
+        let (key, typed) = if let Some(captures) = KEY_RE.captures(&line_to_cursor) {
+            (None, captures.name("typed")?)
+        } else if let Some(captures) = VALUE_RE.captures(&line_to_cursor) {
+            (
+                Some(captures.name("key")?.as_str().to_string()),
+                captures.name("typed")?,
+            )
+        } else {
+            let captures = LIST_ITEM_RE.captures(&line_to_cursor)?;
+
+            let key = (1..line).rev().find_map(|line| {
+                let text =
+                    String::from_iter(context.vault.select_line(context.path, line as isize)?);
+                LIST_KEY_RE
+                    .captures(text.trim_end())
+                    .and_then(|captures| Some(captures.name("key")?.as_str().to_string()))
+            })?;
+
+            (Some(key), captures.name("typed")?)
+        };
+
+        let start = line_to_cursor[..typed.start()].chars().count() as u32;
+        let tags = match key.as_deref() {
+            Some(key) if is_tags_key(key) => vault_tags(context.vault, context.path, line),
+            _ => vec![],
+        };
+
+        Some(Self {
+            key,
+            typed: (typed.as_str().to_string(), start..character as u32),
+            line: line as u32,
+            properties: context.vault.select_properties(),
+            tags,
+            insensitive_matching: context.settings.insensitive_matching,
+        })
+    }
+
+    fn completions(&self) -> Vec<impl Completable<'a, Self>>
+    where
+        Self: Sized,
+    {
+        let completions = match &self.key {
+            None => self
+                .properties
+                .keys()
+                .map(|key| PropertyCompletion::Key(key))
+                .collect::<Vec<_>>(),
+            Some(key) if is_tags_key(key) => {
+                let hashed = self.hashed_tags(key);
+                let completions = self
+                    .tags
+                    .iter()
+                    .map(|tag| PropertyCompletion::Tag(tag, hashed))
+                    .collect::<Vec<_>>();
+
+                // a typed `#` isn't part of the tag
+                return fuzzy_match_completions(
+                    self.typed.0.trim_start_matches('#'),
+                    completions,
+                    self.insensitive_matching,
+                );
+            }
+            Some(key) => match self.properties.get(key.as_str()) {
+                Some(values) if values.len() <= MAX_PROPERTY_VALUES => values
+                    .iter()
+                    .map(|value| PropertyCompletion::Value(value))
+                    .collect(),
+                _ => vec![],
+            },
+        };
+
+        fuzzy_match_completions(&self.typed.0, completions, self.insensitive_matching)
+    }
+
+    type FilterParams = &'a str;
+    fn completion_filter_text(&self, params: Self::FilterParams) -> String {
+        params.to_string()
+    }
+}
+
+impl PropertyCompleter<'_> {
+    /// Whether the tags in the vault's frontmatter are mostly written with their `#`, which YAML usually goes without
+    fn hashed_tags(&self, key: &str) -> bool {
+        let Some(values) = self.properties.get(key) else {
+            return false;
+        };
+
+        values.iter().filter(|value| value.starts_with('#')).count() * 2 > values.len()
+    }
+}
+
+fn is_tags_key(key: &str) -> bool {
+    matches!(key, "tags" | "tag")
+}
+
+/// The distinct tags in the vault, leaving out the one being typed on the line
+fn vault_tags<'a>(vault: &'a Vault, path: &Path, line: usize) -> Vec<&'a str> {
+    vault
+        .select_referenceable_nodes(None)
+        .into_iter()
+        .filter_map(|referenceable| match referenceable {
+            Referenceable::Tag(tag_path, tag) => Some((tag_path, tag)),
+            _ => None,
+        })
+        .filter(|(tag_path, _)| !vault.is_excluded(tag_path))
+        // the partial tag is indexed as it is typed
+        .filter(|(tag_path, tag)| *tag_path != path || tag.range.start.line as usize != line)
+        .map(|(_, tag)| tag.tag_ref.as_str())
+        .unique()
+        .collect()
+}
+
+enum PropertyCompletion<'a> {
+    Key(&'a str),
+    Value(&'a str),
+    /// A tag, and whether it is written with its `#`
+    Tag(&'a str, bool),
+}
+
+impl Matchable for PropertyCompletion<'_> {
+    fn match_string(&self) -> &str {
+        match self {
+            Self::Key(key) => key,
+            Self::Value(value) => value,
+            Self::Tag(tag, _) => tag,
+        }
+    }
+}
+
+impl<'a> Completable<'a, PropertyCompleter<'a>> for PropertyCompletion<'a> {
+    fn completions(&self, completer: &PropertyCompleter<'a>) -> Option<CompletionItem> {
+        let (label, new_text, kind) = match self {
+            Self::Key(key) => (*key, format!("{}: ", key), CompletionItemKind::PROPERTY),
+            Self::Value(value) => (*value, value.to_string(), CompletionItemKind::VALUE),
+            Self::Tag(tag, hashed) => (
+                *tag,
+                match hashed {
+                    true => format!("#{}", tag),
+                    false => tag.to_string(),
+                },
+                CompletionItemKind::KEYWORD,
+            ),
+        };
+
+        let range = &completer.typed.1;
+
+        Some(CompletionItem {
+            label: label.to_string(),
+            kind: Some(kind),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                range: Range {
+                    start: Position {
+                        line: completer.line,
+                        character: range.start,
+                    },
+                    end: Position {
+                        line: completer.line,
+                        character: range.end,
+                    },
+                },
+                new_text,
+            })),
+            filter_text: Some(match self {
+                Self::Tag(..) if completer.typed.0.starts_with('#') => {
+                    completer.completion_filter_text(&format!("#{}", label))
+                }
+                _ => completer.completion_filter_text(label),
+            }),
+            ..Default::default()
+        })
+    }
+}
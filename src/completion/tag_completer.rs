@@ -9,7 +9,7 @@ use tower_lsp::lsp_types::{
 };
 
 use crate::{
-    completion::util::check_in_code_block, ui, vault::{MDTag, Referenceable, Vault}
+    completion::util::check_in_code_block, config::Settings, ui, vault::{MDTag, Referenceable, Vault}
 };
 
 use super::{
@@ -24,6 +24,7 @@ pub struct TagCompleter<'a> {
     /// Tag name and range not including the '#'
     inputted_tag: (String, LineRange<usize>),
     vault: &'a Vault,
+    settings: &'a Settings,
     line: usize,
     character: usize,
 }
@@ -62,6 +63,7 @@ impl<'a> Completer<'a> for TagCompleter<'a> {
                         full_range: full.range(),
                         inputted_tag: (tag_text.as_str().to_string(), tag_text.range()),
                         vault: context.vault,
+                        settings: context.settings,
                         line,
                         character,
                     })
@@ -80,6 +82,7 @@ impl<'a> Completer<'a> for TagCompleter<'a> {
             .vault
             .select_referenceable_nodes(None)
             .into_par_iter()
+            .filter(|referenceable| !self.vault.is_excluded(referenceable.get_path()))
             .flat_map(TagCompletable::from_referenceable)
             .filter(|tag| {
                 !(tag.tag.1.range.start.line <= self.line as u32
@@ -97,7 +100,11 @@ impl<'a> Completer<'a> for TagCompleter<'a> {
 
         let filter_text = &self.inputted_tag.0;
 
-        let filtered = fuzzy_match_completions(filter_text, tag_referenceables);
+        let filtered = fuzzy_match_completions(
+            filter_text,
+            tag_referenceables,
+            self.settings.insensitive_matching,
+        );
 
         filtered
     }
@@ -1,56 +1,70 @@
+use std::path::Path;
+
 use itertools::Itertools;
+use once_cell::sync::Lazy;
 use rayon::prelude::*;
+use regex::Regex;
 use tower_lsp::lsp_types::{
     Command, CompletionItem, CompletionItemKind, CompletionItemLabelDetails, Documentation,
-    InsertTextFormat, MarkupContent, MarkupKind, Position, Range, TextEdit, Url,
+    MarkupContent, MarkupKind, Position, Range, TextEdit, Url,
 };
 
 use crate::{
     ui::preview_referenceable,
     vault::{get_obsidian_ref_path, Block, Referenceable},
 };
-use nanoid::nanoid;
 
 use super::{
     link_completer::{LinkCompleter, MarkdownLinkCompleter, WikiLinkCompleter},
-    matcher::{fuzzy_match_completions, Matchable},
+    matcher::{fuzzy_match, Matchable, OrderedCompletion},
     Completable, Completer,
 };
 
 pub struct UnindexedBlockCompleter<'a, T: LinkCompleter<'a>> {
     link_completer: T,
-    new_id: String,
+    /// The file of a `[[file#^` block ref, whose blocks are the only ones completed
+    file: Option<&'a Path>,
     __phantom: std::marker::PhantomData<&'a T>,
 }
 
 impl<'a, C: LinkCompleter<'a>> UnindexedBlockCompleter<'a, C> {
+    /// For `[[ text`, the blocks of all files; for `[[file#^text`, the blocks of the file. A block ref to a file that
+    /// doesn't exist yet is left to the link completer, which completes its unresolved block refs.
     fn from_link_completer(link_completer: C) -> Option<UnindexedBlockCompleter<'a, C>> {
         if link_completer.entered_refname().starts_with(' ') {
-            Some(UnindexedBlockCompleter::new(link_completer))
+            Some(UnindexedBlockCompleter::new(link_completer, None))
+        } else if link_completer
+            .entered_infile_ref()
+            .is_some_and(|(_, infile)| infile.starts_with('^'))
+        {
+            let file = link_completer.entered_infile_ref_path()?;
+            Some(UnindexedBlockCompleter::new(link_completer, Some(file)))
         } else {
             None
         }
     }
 
-    fn new(completer: C) -> Self {
-        let rand_id = nanoid!(
-            5,
-            &['a', 'b', 'c', 'd', 'e', 'f', 'g', '1', '2', '3', '4', '5', '6', '7', '8', '9']
-        );
-
+    fn new(completer: C, file: Option<&'a Path>) -> Self {
         Self {
             link_completer: completer,
-            new_id: rand_id,
+            file,
             __phantom: std::marker::PhantomData,
         }
     }
 
     fn completables(&self) -> Vec<UnindexedBlock<'a>> {
-        let blocks = self.link_completer.vault().select_blocks();
+        let vault = self.link_completer.vault();
+        let blocks = match self.file {
+            Some(file) => vault.select_file_blocks(file),
+            None => vault.select_blocks(),
+        };
         let position = self.link_completer.position();
+        let cancel = self.link_completer.cancel_token();
 
         blocks
             .into_par_iter()
+            .take_any_while(|_| !cancel.is_cancelled())
+            .filter(|block| !vault.is_excluded(block.file))
             .filter(|block| {
                 !(block.range.start.line <= position.line
                     && block.range.start.character <= position.character
@@ -62,7 +76,48 @@ impl<'a, C: LinkCompleter<'a>> UnindexedBlockCompleter<'a, C> {
     }
 
     fn grep_match_text(&self) -> String {
-        self.link_completer.entered_refname()
+        match self.file {
+            Some(_) => self
+                .link_completer
+                .entered_infile_ref()
+                .map(|(_, infile)| infile.trim_start_matches('^').to_string())
+                .unwrap_or_default(),
+            None => self.link_completer.entered_refname(),
+        }
+    }
+
+    /// Fuzzy match the blocks; blocks of one file that match equally well are listed top to bottom
+    fn matched_completions(&self) -> Vec<OrderedCompletion<'a, Self, UnindexedBlock<'a>>>
+    where
+        UnindexedBlock<'a>: Completable<'a, Self>,
+        Self: Completer<'a>,
+    {
+        let matches = fuzzy_match(
+            &self.grep_match_text(),
+            self.completables(),
+            self.link_completer.settings().insensitive_matching,
+        );
+
+        matches
+            .into_iter()
+            .map(|(block, score)| {
+                let tiebreak = match self.file {
+                    Some(_) => block.0.range.start.line,
+                    None => 0,
+                };
+
+                OrderedCompletion::new(block, score).with_tiebreak(tiebreak)
+            })
+            .collect()
+    }
+
+    /// The display text to keep or insert; a block ref to a file is entered without display text
+    fn display(&self, display: &str) -> Option<String> {
+        match self.file {
+            Some(_) if display.is_empty() => None,
+            Some(_) => Some(self.link_completer.display_snippet(display)),
+            None => Some(self.link_completer.display_snippet(display.trim())),
+        }
     }
 }
 
@@ -82,13 +137,7 @@ impl<'a> Completer<'a> for UnindexedBlockCompleter<'a, MarkdownLinkCompleter<'a>
     where
         Self: Sized,
     {
-        let completables = self.completables();
-
-        let grep_match_text = self.grep_match_text();
-
-        let matches = fuzzy_match_completions(&grep_match_text, completables);
-
-        matches
+        self.matched_completions()
     }
 
     type FilterParams = <MarkdownLinkCompleter<'a> as Completer<'a>>::FilterParams;
@@ -111,11 +160,7 @@ impl<'a> Completer<'a> for UnindexedBlockCompleter<'a, WikiLinkCompleter<'a>> {
     where
         Self: Sized,
     {
-        let completables = self.completables();
-        let filter_text = self.grep_match_text();
-        let matches = fuzzy_match_completions(&filter_text, completables);
-
-        matches
+        self.matched_completions()
     }
 
     type FilterParams = <WikiLinkCompleter<'a> as Completer<'a>>::FilterParams;
@@ -131,17 +176,23 @@ impl<'a> UnindexedBlock<'a> {
     fn partial_completion<T: LinkCompleter<'a>>(
         &self,
         completer: &'a UnindexedBlockCompleter<'a, T>,
-    ) -> Option<(String, CompletionItem)> {
-        let rand_id = &completer.new_id;
-
+    ) -> Option<(String, String, CompletionItem)> {
         let path_ref =
             get_obsidian_ref_path(completer.link_completer.vault().root_dir(), self.0.file)?;
         let url = Url::from_file_path(self.0.file).ok()?;
 
         let block = self.0;
 
+        // A line that already ends in a block index, even one the vault did not parse as an indexed block
+        static TRAILING_INDEX_RE: Lazy<Regex> =
+            Lazy::new(|| Regex::new(r"(^|\s)\^(?<index>[\w-]+)$").unwrap());
+        let trailing_index = TRAILING_INDEX_RE
+            .captures(block.text)
+            .and_then(|captures| captures.name("index"))
+            .map(|index| index.as_str());
+
         // check if the block is already indexed
-        let (documentation, command, kind, label_detail, refname): (
+        let (documentation, command, kind, label_detail, infile_ref): (
             Option<Documentation>,
             Option<Command>,
             CompletionItemKind,
@@ -167,71 +218,100 @@ impl<'a> UnindexedBlock<'a> {
                     detail: Some("Indexed Block".to_string()),
                     description: None,
                 }),
-                format!("{}#^{}", path_ref, indexed_block.index),
+                format!("^{}", indexed_block.index),
             ),
-            _ => (
-                Some(Documentation::MarkupContent(MarkupContent {
-                    kind: MarkupKind::Markdown,
-                    value: (block.range.start.line as isize - 1
-                        ..=block.range.start.line as isize + 1)
-                        .flat_map(|i| {
-                            Some((
-                                completer
-                                    .link_completer
-                                    .vault()
-                                    .select_line(block.file, i)?,
-                                i,
-                            ))
-                        })
-                        .map(|(iter, ln)| {
-                            if ln == block.range.start.line as isize {
-                                format!("**{}**\n", String::from_iter(iter).trim())
-                                // highlight the block to be references
-                            } else {
-                                String::from_iter(iter)
-                            }
-                        })
-                        .join(""),
-                })),
-                Some(Command {
-                    title: "Insert Block Reference Into File".into(),
-                    command: "apply_edits".into(),
-                    arguments: Some(vec![serde_json::to_value(
-                        tower_lsp::lsp_types::WorkspaceEdit {
-                            changes: Some(
-                                vec![(
-                                    url,
-                                    vec![TextEdit {
-                                        range: Range {
-                                            start: Position {
-                                                line: block.range.end.line,
-                                                character: block.range.end.character - 1,
-                                            },
-                                            end: Position {
-                                                line: block.range.end.line,
-                                                character: block.range.end.character - 1,
-                                            },
-                                        },
-                                        new_text: format!("   ^{}", rand_id),
-                                    }],
-                                )]
-                                .into_iter()
-                                .collect(),
-                            ),
-                            change_annotations: None,
-                            document_changes: None,
-                        },
-                    )
-                    .ok()?]),
-                }),
-                CompletionItemKind::TEXT,
+            _ if trailing_index.is_some() => (
                 None,
-                format!("{}#^{}", path_ref, rand_id),
+                None,
+                CompletionItemKind::REFERENCE,
+                Some(CompletionItemLabelDetails {
+                    detail: Some("Indexed Block".to_string()),
+                    description: None,
+                }),
+                format!("^{}", trailing_index?),
             ),
+            _ => {
+                let rand_id = completer
+                    .link_completer
+                    .vault()
+                    .new_block_index(block.file, completer.link_completer.settings());
+                // end of the line, not including the line break; lsp positions count utf-16 code units
+                let line_end = completer
+                    .link_completer
+                    .vault()
+                    .select_line(block.file, block.range.end.line as isize)
+                    .map(|line| {
+                        String::from_iter(line)
+                            .trim_end_matches(['\n', '\r'])
+                            .encode_utf16()
+                            .count() as u32
+                    })?;
+
+                (
+                    Some(Documentation::MarkupContent(MarkupContent {
+                        kind: MarkupKind::Markdown,
+                        value: (block.range.start.line as isize - 1
+                            ..=block.range.start.line as isize + 1)
+                            .flat_map(|i| {
+                                Some((
+                                    completer
+                                        .link_completer
+                                        .vault()
+                                        .select_line(block.file, i)?,
+                                    i,
+                                ))
+                            })
+                            .map(|(iter, ln)| {
+                                if ln == block.range.start.line as isize {
+                                    format!("**{}**\n", String::from_iter(iter).trim())
+                                    // highlight the block to be references
+                                } else {
+                                    String::from_iter(iter)
+                                }
+                            })
+                            .join(""),
+                    })),
+                    Some(Command {
+                        title: "Insert Block Reference Into File".into(),
+                        command: "apply_edits".into(),
+                        arguments: Some(vec![serde_json::to_value(
+                            tower_lsp::lsp_types::WorkspaceEdit {
+                                changes: Some(
+                                    vec![(
+                                        url,
+                                        vec![TextEdit {
+                                            range: Range {
+                                                start: Position {
+                                                    line: block.range.end.line,
+                                                    character: line_end,
+                                                },
+                                                end: Position {
+                                                    line: block.range.end.line,
+                                                    character: line_end,
+                                                },
+                                            },
+                                            new_text: format!("   ^{}", rand_id),
+                                        }],
+                                    )]
+                                    .into_iter()
+                                    .collect(),
+                                ),
+                                change_annotations: None,
+                                document_changes: None,
+                            },
+                        )
+                        .ok()?]),
+                    }),
+                    CompletionItemKind::TEXT,
+                    None,
+                    format!("^{}", rand_id),
+                )
+            }
         };
 
         Some((
-            refname,
+            path_ref,
+            infile_ref,
             CompletionItem {
                 label: block.text.to_string(),
                 documentation,
@@ -252,21 +332,24 @@ impl<'a> Completable<'a, UnindexedBlockCompleter<'a, MarkdownLinkCompleter<'a>>>
         &self,
         completer: &UnindexedBlockCompleter<'a, MarkdownLinkCompleter<'a>>,
     ) -> Option<CompletionItem> {
-        let (refname, partial_completion) = self.partial_completion(completer)?;
+        let (refname, infile_ref, partial_completion) = self.partial_completion(completer)?;
 
-        let binding = completer.link_completer.entered_refname();
-        let display = &binding.trim();
+        // `[text](file#^` keeps its display text
+        let display = match completer.file {
+            Some(_) => completer.display(&completer.link_completer.display.0),
+            None => completer.display(&completer.link_completer.entered_refname()),
+        };
 
         Some(CompletionItem {
-            text_edit: Some(
-                completer
-                    .link_completer
-                    .completion_text_edit(Some(&format!("${{1:{}}}", display)), &refname),
-            ),
+            text_edit: Some(completer.link_completer.completion_text_edit(
+                display.as_deref(),
+                &refname,
+                Some(&infile_ref),
+            )),
             filter_text: Some(
                 completer.completion_filter_text(&completer.link_completer.entered_refname()),
             ),
-            insert_text_format: Some(InsertTextFormat::SNIPPET),
+            insert_text_format: Some(completer.link_completer.insert_text_format()),
             ..partial_completion
         })
     }
@@ -279,21 +362,23 @@ impl<'a> Completable<'a, UnindexedBlockCompleter<'a, WikiLinkCompleter<'a>>>
         &self,
         completer: &UnindexedBlockCompleter<'a, WikiLinkCompleter<'a>>,
     ) -> Option<CompletionItem> {
-        let (refname, partial_completion) = self.partial_completion(completer)?;
+        let (refname, infile_ref, partial_completion) = self.partial_completion(completer)?;
 
-        let binding = completer.link_completer.entered_refname();
-        let display = &binding.trim();
+        let display = match completer.file {
+            Some(_) => None,
+            None => completer.display(&completer.link_completer.entered_refname()),
+        };
 
         Some(CompletionItem {
-            text_edit: Some(
-                completer
-                    .link_completer
-                    .completion_text_edit(Some(&format!("${{1:{}}}", display)), &refname),
-            ),
+            text_edit: Some(completer.link_completer.completion_text_edit(
+                display.as_deref(),
+                &refname,
+                Some(&infile_ref),
+            )),
             filter_text: Some(
                 completer.completion_filter_text(&completer.link_completer.entered_refname()),
             ),
-            insert_text_format: Some(InsertTextFormat::SNIPPET),
+            insert_text_format: Some(completer.link_completer.insert_text_format()),
             ..partial_completion
         })
     }
@@ -3,48 +3,313 @@ use std::path::Path;
 use anyhow::anyhow;
 use config::{Config, File};
 use indexmap::IndexMap;
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
 use tower_lsp::lsp_types::ClientCapabilities;
 
+use crate::vault::IMAGE_EXTENSIONS;
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct Settings {
-    /// Format of daily notes
-    pub dailynote: String,
+    /// Formats of daily notes; new notes are created with the first, and notes in any of them are daily notes
+    #[serde(deserialize_with = "one_or_many")]
+    pub dailynote: Vec<String>,
+    /// Extra relative phrases for daily notes, mapped to a day offset from today
+    pub dailynote_phrases: HashMap<String, i64>,
+    /// Path to the template used when a daily note is created from a completion; relative to the vault root
+    pub dailynote_template: Option<String>,
+    /// Folder daily notes are kept in and created in; relative to the vault root
+    pub daily_note_folder: Option<String>,
+    /// Folder notes created from links to missing notes go in; links by path from the vault root keep their path
+    pub new_note_folder: NewNoteFolder,
     pub heading_completions: bool,
+    /// The deepest heading level that is completed; 2 completes `#` and `##` headings
+    pub heading_completion_level: usize,
+    /// Show the parent headings of heading completions
+    pub heading_breadcrumbs: bool,
+    /// Start the labels of heading completions with the heading's `#`s, like `## file#Setup`; the inserted link and the
+    /// text the completions are filtered by are unchanged
+    pub heading_level_labels: bool,
+    /// Fuzzy match block completions against the text of the block as well as its index
+    pub block_text_completions: bool,
+    /// Preview the whole block or heading section that is transcluded when completing an embed, `![[file#`
+    pub embed_block_previews: bool,
+    /// Show how many headings and blocks the file has with the completions after `[[file#`
+    pub infile_ref_summary: bool,
+    /// Ignore case and diacritics when fuzzy matching completions
+    pub insensitive_matching: bool,
+    /// Resolve links to files whose names differ only in case, like `[[My Note]]` to `my note.md`, as Obsidian does
+    pub case_insensitive_links: bool,
+    /// How much link completions for recently opened, edited or modified files are boosted over the fuzzy match score
+    pub completion_recency_weight: f64,
+    /// How much link completions for often referenced notes are boosted over the fuzzy match score
+    pub completion_popularity_weight: f64,
+    /// How many characters of a link have to be entered before every referenceable is matched
+    pub min_chars: usize,
+    /// The most completion items returned at once; the best matches are kept
+    pub completion_limit: usize,
+    /// Complete links to the current file itself, not just to its headings and blocks
+    pub self_link_completions: bool,
     pub title_headings: bool,
+    /// Match and show file link completions by the file's first `#` heading instead of its name, for notes titled by
+    /// their heading; the completed link still goes to the file by name
+    pub title_from_first_heading: bool,
+    /// The display text inserted with file link completions; if unset, markdown links use the first heading when
+    /// `title_headings` is set and wikilinks have none
+    pub link_display_text: Option<LinkDisplayText>,
+    /// Complete the display texts files have been linked with, like `Annual Report` from
+    /// `[[2023-report|Annual Report]]`, as aliases of the files
+    pub link_text_aliases: bool,
+    /// Complete attachments, like images, in all wikilinks; they are always completed in embeds, `![[`
+    pub attachment_completions: bool,
+    /// Extensions of the attachments that are completed, without the `.`
+    #[serde(deserialize_with = "one_or_many")]
+    pub attachment_extensions: Vec<String>,
+    /// The extensions a wikilink without one, like `[[notes]]`, goes to, in order of priority; of the files named like
+    /// the link, the one with the first of these extensions is linked to. Without the `.`
+    #[serde(deserialize_with = "one_or_many")]
+    pub link_extensions: Vec<String>,
+    /// Complete wikilinks to blocks with the start of the block's text as their display text, instead of the bare id
+    pub block_display_text: bool,
+    /// How new block indexes, like the `^index` added when completing a link to a block without one, are made
+    pub block_id_style: BlockIdStyle,
+    /// The number of characters of new alphanumeric block indexes
+    pub block_id_length: usize,
+    /// Insert link completions as snippets, with a tab stop on the display text; off for clients without snippet
+    /// support
+    pub link_snippets: bool,
+    /// Text inserted after a completed link, like a space to keep typing after `[[file]]`
+    pub link_completion_suffix: String,
+    /// Create the missing note of an unresolved link completion when it is accepted
+    pub create_unresolved_notes: bool,
+    /// The section headings added from `[[file#heading` completions go in, one level below its heading; the end of the
+    /// file if unset or the file has no such section
+    pub new_heading_section: Option<String>,
+    /// Start the hover preview of a note with the number of backlinks to it
+    pub hover_backlink_count: bool,
+    /// Show the first words of the block, or the heading, that a link to a block or heading goes to as an inlay hint
+    pub link_inlay_hints: bool,
+    /// How many of the notes with a tag are linked when hovering the tag
+    pub tag_hover_notes: usize,
+    /// How markdown link paths with spaces are written
+    pub link_path_encoding: LinkPathEncoding,
+    /// Whether completed markdown link paths are relative to the current file or to the vault root
+    pub markdown_link_paths: MarkdownLinkPaths,
+    /// How the heading of a completed or converted markdown link is written; wikilinks always use the heading text
+    pub markdown_heading_anchors: MarkdownHeadingAnchors,
+    /// The style formatting a document rewrites its links to; links are left as they are if unset
+    pub link_style: Option<LinkStyle>,
+    /// Tidy the links of a range when it is formatted; off by default, so that editors keep formatting selections, like
+    /// wrapping text, their own way
+    pub range_formatting: bool,
+    /// Write the file of a link as the note is named when formatting a range, like `[[My Note]]` for `[[my note]]`
+    pub canonical_link_case: bool,
     pub unresolved_diagnostics: bool,
+    /// How many milliseconds without edits diagnostics wait for before they are recomputed, and semantic tokens and
+    /// inlay hints before they are refreshed, so that they aren't on every keystroke
+    pub diagnostics_debounce: u64,
+    /// Which unresolved links are reported, as warnings; if unset, all of them are reported as information
+    pub resolution_mode: Option<ResolutionMode>,
     pub semantic_tokens: bool,
     pub tags_in_codeblocks: bool,
+    /// Resolve links in fenced and indented code blocks and in inline code, where they are usually literal text
     pub references_in_codeblocks: bool,
+    /// Glob patterns of paths from the vault root, like `templates/**`, left out of completions, workspace symbols
+    /// and diagnostics
+    #[serde(deserialize_with = "one_or_many")]
+    pub exclude: Vec<String>,
+    /// Index the files in hidden folders, like `.obsidian`, and those matched by `.gitignore`, which are skipped by
+    /// default
+    pub index_ignored_files: bool,
+    /// The folder the parsed notes of each vault are cached in, for a quick start; `~` is the home folder
+    pub cache_folder: String,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkPathEncoding {
+    /// `[display](<path with spaces>)`
+    Angle,
+    /// `[display](path%20with%20spaces)`
+    Percent,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkDisplayText {
+    /// `[[file]]`, `[](file)`
+    None,
+    /// `[[folder/file|file]]`, `[file](folder/file)`
+    Stem,
+    /// `[[file|First Heading]]`, `[First Heading](file)`
+    Heading,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkStyle {
+    /// `[[file#heading|display]]`
+    Wiki,
+    /// `[display](file.md#heading)`
+    Markdown,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ResolutionMode {
+    /// Every unresolved link
+    Strict,
+    /// Links to missing headings and blocks, and links to missing notes whose name is a few edits away from an
+    /// existing note's; other links to missing notes are taken to be placeholders for notes to write
+    Loose,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BlockIdStyle {
+    /// Random lowercase letters and digits, like Obsidian's `^a1b2c3`
+    Alphanumeric,
+    /// The time the index is made, like `^20240131154502`
+    Timestamp,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MarkdownHeadingAnchors {
+    /// `[display](file.md#My Heading)`
+    Text,
+    /// `[display](file.md#my-heading)`, the GitHub style slug of the heading
+    Slug,
+}
+
+/// Written as `/` for the vault root, `.` for the folder of the linking file, or a path from the vault root
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(from = "String")]
+pub enum NewNoteFolder {
+    Root,
+    Current,
+    Folder(String),
+}
+
+impl From<String> for NewNoteFolder {
+    fn from(folder: String) -> Self {
+        match folder.trim_matches('/') {
+            "" => NewNoteFolder::Root,
+            "." => NewNoteFolder::Current,
+            folder => NewNoteFolder::Folder(folder.to_string()),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MarkdownLinkPaths {
+    /// `[display](../projects/foo.md)`
+    Relative,
+    /// `[display](projects/foo.md)`, from the vault root
+    Absolute,
 }
 
 impl Settings {
+    /// The settings of the vault, from its `.moxide` file and the user's `~/.config/moxide/settings`, over the defaults
     pub fn new(root_dir: &Path, capabilities: &ClientCapabilities) -> anyhow::Result<Settings> {
+        let expanded = shellexpand::tilde("~/.config/moxide/settings");
+        Settings::with_user_settings(root_dir, capabilities, Some(&expanded))
+    }
+
+    /// The settings of the vault without the user's settings file, so that tests don't depend on it
+    #[cfg(test)]
+    pub fn without_user_settings(root_dir: &Path) -> anyhow::Result<Settings> {
+        Settings::with_user_settings(root_dir, &ClientCapabilities::default(), None)
+    }
+
+    fn with_user_settings(
+        root_dir: &Path,
+        capabilities: &ClientCapabilities,
+        user_settings: Option<&str>,
+    ) -> anyhow::Result<Settings> {
         let obsidian_daily_note = obsidian_dailynote_converted(root_dir);
+        let obsidian_daily_note_template = obsidian_dailynote_template(root_dir);
+        let obsidian_daily_note_folder = obsidian_dailynote_folder(root_dir);
+        let obsidian_new_note_folder = obsidian_new_note_folder(root_dir);
 
-        let expanded = shellexpand::tilde("~/.config/moxide/settings");
+        let settings = Config::builder().add_source(
+            File::with_name(&format!(
+                "{}/.moxide",
+                root_dir
+                    .to_str()
+                    .ok_or(anyhow!("Can't convert root_dir to str"))?
+            ))
+            .required(false),
+        );
+        let settings = match user_settings {
+            Some(user_settings) => {
+                settings.add_source(File::with_name(user_settings).required(false))
+            }
+            None => settings,
+        };
 
-        let settings = Config::builder()
-            .add_source(
-                File::with_name(&format!(
-                    "{}/.moxide",
-                    root_dir
-                        .to_str()
-                        .ok_or(anyhow!("Can't convert root_dir to str"))?
-                ))
-                .required(false),
-            )
-            .add_source(File::with_name(&expanded).required(false))
+        let settings = settings
             .set_default(
                 "dailynote",
                 obsidian_daily_note.unwrap_or("%Y-%m-%d".to_string()),
             )?
+            .set_default("dailynote_phrases", HashMap::<String, i64>::new())?
+            .set_default("dailynote_template", obsidian_daily_note_template)?
+            .set_default("daily_note_folder", obsidian_daily_note_folder)?
+            .set_default(
+                "new_note_folder",
+                obsidian_new_note_folder.unwrap_or("/".to_string()),
+            )?
             .set_default("heading_completions", true)?
+            .set_default("heading_completion_level", 2)?
+            .set_default("heading_breadcrumbs", true)?
+            .set_default("heading_level_labels", false)?
+            .set_default("block_text_completions", false)?
+            .set_default("embed_block_previews", true)?
+            .set_default("infile_ref_summary", true)?
+            .set_default("insensitive_matching", true)?
+            .set_default("case_insensitive_links", true)?
+            .set_default("completion_recency_weight", 0.5)?
+            .set_default("completion_popularity_weight", 0.2)?
+            .set_default("min_chars", 0)?
+            .set_default("completion_limit", 200)?
+            .set_default("self_link_completions", false)?
             .set_default("unresolved_diagnostics", true)?
+            .set_default("diagnostics_debounce", 300)?
             .set_default("title_headings", true)?
+            .set_default("title_from_first_heading", false)?
+            .set_default("link_text_aliases", false)?
+            .set_default("attachment_completions", false)?
+            .set_default(
+                "attachment_extensions",
+                IMAGE_EXTENSIONS
+                    .into_iter()
+                    .chain([
+                        "pdf", "mp3", "wav", "m4a", "ogg", "flac", "mp4", "webm", "mov",
+                    ])
+                    .collect::<Vec<_>>(),
+            )?
+            .set_default("link_extensions", ["md", "canvas"].to_vec())?
+            .set_default("block_display_text", true)?
+            .set_default("block_id_style", "alphanumeric")?
+            .set_default("block_id_length", 6)?
+            .set_default("link_snippets", true)?
+            .set_default("range_formatting", false)?
+            .set_default("canonical_link_case", false)?
+            .set_default("link_completion_suffix", "")?
+            .set_default("create_unresolved_notes", false)?
+            .set_default("hover_backlink_count", true)?
+            .set_default("link_inlay_hints", true)?
+            .set_default("tag_hover_notes", 10)?
+            .set_default("link_path_encoding", "angle")?
+            .set_default("markdown_link_paths", "relative")?
+            .set_default("markdown_heading_anchors", "text")?
             .set_default("semantic_tokens", true)?
             .set_default("tags_in_codeblocks", true)?
-            .set_default("references_in_codeblocks", true)?
+            .set_default("references_in_codeblocks", false)?
+            .set_default("exclude", Vec::<String>::new())?
+            .set_default("index_ignored_files", false)?
+            .set_default("cache_folder", "~/.cache/moxide")?
             .set_override_option(
                 "semantic_tokens",
                 capabilities.text_document.as_ref().and_then(|it| {
@@ -54,6 +319,17 @@ impl Settings {
                     }
                 }),
             )?
+            .set_override_option(
+                "link_snippets",
+                capabilities
+                    .text_document
+                    .as_ref()
+                    .and_then(|it| it.completion.as_ref()?.completion_item.as_ref())
+                    .and_then(|it| match it.snippet_support {
+                        Some(true) => None,
+                        _ => Some(false),
+                    }),
+            )?
             .build()
             .map_err(|err| anyhow!("Build err: {err}"))?;
 
@@ -61,13 +337,40 @@ impl Settings {
 
         anyhow::Ok(settings)
     }
+
+    /// The format new daily notes are created and linked with
+    pub fn dailynote_format(&self) -> &str {
+        self.dailynote
+            .first()
+            .map(String::as_str)
+            .unwrap_or("%Y-%m-%d")
+    }
 }
 
-fn obsidian_dailynote_converted(root_dir: &Path) -> Option<String> {
+/// A setting that is either a single string or a list of them
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(one) => vec![one],
+        OneOrMany::Many(many) => many,
+    })
+}
+
+fn obsidian_dailynote_config(root_dir: &Path) -> Option<HashMap<String, String>> {
     let daily_notes_config_file = root_dir.join(".obsidian").join("daily-notes.json");
     let file = std::fs::read(daily_notes_config_file).ok();
-    let config: Option<HashMap<String, String>> =
-        file.and_then(|file| serde_json::from_slice(&file).ok());
+
+    file.and_then(|file| serde_json::from_slice(&file).ok())
+}
+
+fn obsidian_dailynote_converted(root_dir: &Path) -> Option<String> {
+    let config = obsidian_dailynote_config(root_dir);
 
     let daily_note = config.as_ref().and_then(|config| {
         config
@@ -78,6 +381,43 @@ fn obsidian_dailynote_converted(root_dir: &Path) -> Option<String> {
     daily_note
 }
 
+/// Obsidian stores the template path without the `.md` extension
+fn obsidian_dailynote_template(root_dir: &Path) -> Option<String> {
+    let config = obsidian_dailynote_config(root_dir)?;
+
+    config
+        .get("template")
+        .filter(|template| !template.is_empty())
+        .map(|template| match template.ends_with(".md") {
+            true => template.to_string(),
+            false => format!("{}.md", template),
+        })
+}
+
+fn obsidian_dailynote_folder(root_dir: &Path) -> Option<String> {
+    let config = obsidian_dailynote_config(root_dir)?;
+
+    config
+        .get("folder")
+        .map(|folder| folder.trim_matches('/').to_string())
+        .filter(|folder| !folder.is_empty())
+}
+
+/// Obsidian's "Default location for new notes"
+fn obsidian_new_note_folder(root_dir: &Path) -> Option<String> {
+    let file = std::fs::read(root_dir.join(".obsidian").join("app.json")).ok()?;
+    let config: serde_json::Value = serde_json::from_slice(&file).ok()?;
+
+    match config.get("newFileLocation")?.as_str()? {
+        "current" => Some(".".to_string()),
+        "folder" => config
+            .get("newFileFolderPath")?
+            .as_str()
+            .map(|folder| folder.to_string()),
+        _ => None,
+    }
+}
+
 use std::collections::HashMap;
 
 // GPT-4 code
@@ -120,7 +460,7 @@ fn convert_momentjs_to_chrono_format(moment_format: &str) -> String {
 #[cfg(test)]
 mod test {
 
-    use crate::config::convert_momentjs_to_chrono_format;
+    use crate::config::{convert_momentjs_to_chrono_format, one_or_many, NewNoteFolder};
 
     #[test]
     fn test_format_conversion() {
@@ -128,4 +468,26 @@ mod test {
         let chrono_format = convert_momentjs_to_chrono_format(moment_format);
         assert_eq!(chrono_format, "%Y-%m-%d");
     }
+
+    #[test]
+    fn test_one_or_many() {
+        assert_eq!(
+            one_or_many(serde_json::json!("%Y-%m-%d")).unwrap(),
+            vec!["%Y-%m-%d"]
+        );
+        assert_eq!(
+            one_or_many(serde_json::json!(["%Y-%m-%d", "%Y/%m/%d"])).unwrap(),
+            vec!["%Y-%m-%d", "%Y/%m/%d"]
+        );
+    }
+
+    #[test]
+    fn test_new_note_folder() {
+        assert_eq!(NewNoteFolder::from("/".to_string()), NewNoteFolder::Root);
+        assert_eq!(NewNoteFolder::from(".".to_string()), NewNoteFolder::Current);
+        assert_eq!(
+            NewNoteFolder::from("/inbox/".to_string()),
+            NewNoteFolder::Folder("inbox".to_string())
+        );
+    }
 }
@@ -0,0 +1,395 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use chrono::{Datelike, Duration, Months, NaiveDate, Weekday};
+use tower_lsp::lsp_types::{
+    CreateFile, CreateFileOptions, DocumentChangeOperation, DocumentChanges, OneOf,
+    OptionalVersionedTextDocumentIdentifier, Range, ResourceOp, TextDocumentEdit, TextEdit, Url,
+    WorkspaceEdit,
+};
+
+/// The command opening a daily note, creating it if it doesn't exist
+pub const OPEN_DAILY_NOTE_COMMAND: &str = "markdown-oxide.openDailyNote";
+
+/// Parse a relative date phrase like `tomorrow`, `in 3 days`, `2 weeks from now`, `a month ago`, `next year` or
+/// `last friday` into a date relative to `today`. `custom_phrases` maps additional phrases to a day offset from today.
+/// Phrases that could mean more than one date, like a bare `friday`, are not dates.
+pub fn parse_relative_date(
+    phrase: &str,
+    today: NaiveDate,
+    custom_phrases: &HashMap<String, i64>,
+) -> Option<NaiveDate> {
+    let phrase = phrase.trim().to_lowercase();
+    let words = phrase.split_whitespace().collect::<Vec<_>>();
+
+    if let Some(offset) = custom_phrases
+        .iter()
+        .find(|(custom, _)| custom.to_lowercase() == words.join(" "))
+        .map(|(_, offset)| *offset)
+    {
+        return today.checked_add_signed(Duration::try_days(offset)?);
+    }
+
+    match words.as_slice() {
+        ["today"] => Some(today),
+        ["tomorrow"] => shift(today, 1, "day"),
+        ["yesterday"] => shift(today, -1, "day"),
+        ["day", "after", "tomorrow"] => shift(today, 2, "day"),
+        ["day", "before", "yesterday"] => shift(today, -2, "day"),
+        ["in", n, unit] | [n, unit, "from", "now" | "today"] => shift(today, count(n)?, unit),
+        [n, unit, "ago"] => shift(today, -count(n)?, unit),
+        ["next", weekday] => match weekday.parse::<Weekday>() {
+            Ok(weekday) => shift(
+                today,
+                match days_between(today.weekday(), weekday) {
+                    0 => 7,
+                    days => days,
+                },
+                "day",
+            ),
+            Err(_) => shift(today, 1, weekday),
+        },
+        ["last", weekday] => match weekday.parse::<Weekday>() {
+            Ok(weekday) => shift(
+                today,
+                match days_between(weekday, today.weekday()) {
+                    0 => -7,
+                    days => -days,
+                },
+                "day",
+            ),
+            Err(_) => shift(today, -1, weekday),
+        },
+        _ => None,
+    }
+}
+
+/// The label for a date relative to today; only dates within a week of today have one.
+pub fn relative_date_string(date: NaiveDate, today: NaiveDate) -> Option<String> {
+    match (date - today).num_days() {
+        0 => Some("today".to_string()),
+        1 => Some("tomorrow".to_string()),
+        2..=7 => Some(format!("next {}", date.format("%A"))),
+        -1 => Some("yesterday".to_string()),
+        -7..=-2 => Some(format!("last {}", date.format("%A"))),
+        _ => None,
+    }
+}
+
+/// The folder daily notes are kept in; the vault root if no folder is configured
+pub fn dailynote_folder(root_dir: &Path, folder: Option<&str>) -> PathBuf {
+    match folder {
+        Some(folder) => root_dir.join(folder),
+        None => root_dir.to_path_buf(),
+    }
+}
+
+/// Path of the daily note for a date. The format may contain folders, like `%Y/%m/%Y-%m-%d`
+pub fn dailynote_path(
+    date: NaiveDate,
+    root_dir: &Path,
+    format: &str,
+    folder: Option<&str>,
+) -> PathBuf {
+    dailynote_folder(root_dir, folder).join(format!("{}.md", date.format(format)))
+}
+
+/// The name used to link to the daily note for a date; this is only the file name, which is enough to resolve it
+pub fn dailynote_refname(date: NaiveDate, format: &str) -> String {
+    date.format(format)
+        .to_string()
+        .rsplit('/')
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// The edit that creates the daily note at `path`, filled with the template if one is configured. `{{title}}` and
+/// `{{date}}` in the template are replaced with the note's refname. Nothing is overwritten if the note exists.
+pub fn create_dailynote_edit(
+    path: &Path,
+    refname: &str,
+    root_dir: &Path,
+    template: Option<&str>,
+) -> Option<WorkspaceEdit> {
+    let uri = Url::from_file_path(path).ok()?;
+
+    let template_text = template
+        .and_then(|template| {
+            let template_path = root_dir.join(shellexpand::tilde(template).as_ref());
+
+            std::fs::read_to_string(template_path).ok()
+        })
+        .map(|text| {
+            text.replace("{{title}}", refname)
+                .replace("{{date}}", refname)
+        });
+
+    let create = DocumentChangeOperation::Op(ResourceOp::Create(CreateFile {
+        uri: uri.clone(),
+        options: Some(CreateFileOptions {
+            ignore_if_exists: Some(true),
+            overwrite: Some(false),
+        }),
+        annotation_id: None,
+    }));
+
+    let fill_template = template_text.map(|new_text| {
+        DocumentChangeOperation::Edit(TextDocumentEdit {
+            text_document: OptionalVersionedTextDocumentIdentifier { uri, version: None },
+            edits: vec![OneOf::Left(TextEdit {
+                range: Range::default(),
+                new_text,
+            })],
+        })
+    });
+
+    Some(WorkspaceEdit {
+        document_changes: Some(DocumentChanges::Operations(
+            std::iter::once(create).chain(fill_template).collect(),
+        )),
+        ..Default::default()
+    })
+}
+
+/// The date of the daily note at `path`, trying each of the formats in order. Notes in the daily note folder are parsed
+/// by their path relative to the folder; notes elsewhere are parsed by their file name.
+pub fn dailynote_date(
+    path: &Path,
+    root_dir: &Path,
+    formats: &[impl AsRef<str>],
+    folder: Option<&str>,
+) -> Option<NaiveDate> {
+    let relative = path
+        .strip_prefix(dailynote_folder(root_dir, folder))
+        .ok()
+        .and_then(|relative| relative.to_str())
+        .map(|relative| relative.replace('\\', "/"));
+    let filename = path.file_name()?.to_str()?.replace(".md", "");
+
+    formats.iter().find_map(|format| {
+        let format = format.as_ref();
+        let in_folder = relative.as_ref().and_then(|relative| {
+            NaiveDate::parse_from_str(relative.trim_end_matches(".md"), format).ok()
+        });
+
+        in_folder.or_else(|| {
+            let filename_format = format.rsplit('/').next()?;
+
+            NaiveDate::parse_from_str(&filename, filename_format).ok()
+        })
+    })
+}
+
+/// The date `n` days, weeks, months or years from `date`; months and years end on the last day of a shorter month
+fn shift(date: NaiveDate, n: i64, unit: &str) -> Option<NaiveDate> {
+    let months = |n: i64| -> Option<NaiveDate> {
+        let months = Months::new(u32::try_from(n.unsigned_abs()).ok()?);
+        match n < 0 {
+            true => date.checked_sub_months(months),
+            false => date.checked_add_months(months),
+        }
+    };
+
+    match unit {
+        "day" | "days" => date.checked_add_signed(Duration::try_days(n)?),
+        "week" | "weeks" => date.checked_add_signed(Duration::try_weeks(n)?),
+        "month" | "months" => months(n),
+        "year" | "years" => months(n.checked_mul(12)?),
+        _ => None,
+    }
+}
+
+/// A count written as digits or as a small number word, like `3`, `a` or `three`
+fn count(word: &str) -> Option<i64> {
+    const WORDS: [&str; 13] = [
+        "zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "ten",
+        "eleven", "twelve",
+    ];
+
+    match word {
+        "a" | "an" => Some(1),
+        word => word.parse::<u32>().ok().map(i64::from).or_else(|| {
+            WORDS
+                .iter()
+                .position(|number| *number == word)
+                .map(|n| n as i64)
+        }),
+    }
+}
+
+/// Days from `from` forward to the next `to`, in 0..7
+fn days_between(from: Weekday, to: Weekday) -> i64 {
+    (to.num_days_from_monday() as i64 - from.num_days_from_monday() as i64).rem_euclid(7)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, path::Path};
+
+    use chrono::NaiveDate;
+
+    use super::{dailynote_date, dailynote_path, dailynote_refname, parse_relative_date};
+
+    // a wednesday
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 4, 17).unwrap()
+    }
+
+    fn parse(phrase: &str) -> Option<NaiveDate> {
+        parse_relative_date(phrase, today(), &HashMap::new())
+    }
+
+    #[test]
+    fn test_simple_phrases() {
+        assert_eq!(parse("today"), Some(today()));
+        assert_eq!(parse("Tomorrow"), NaiveDate::from_ymd_opt(2024, 4, 18));
+        assert_eq!(parse("yesterday"), NaiveDate::from_ymd_opt(2024, 4, 16));
+    }
+
+    #[test]
+    fn test_offset_phrases() {
+        assert_eq!(parse("in 3 days"), NaiveDate::from_ymd_opt(2024, 4, 20));
+        assert_eq!(parse("in 1 day"), NaiveDate::from_ymd_opt(2024, 4, 18));
+        assert_eq!(parse("2 days ago"), NaiveDate::from_ymd_opt(2024, 4, 15));
+        assert_eq!(parse("in 2 weeks"), NaiveDate::from_ymd_opt(2024, 5, 1));
+        assert_eq!(parse("in many days"), None);
+    }
+
+    #[test]
+    fn test_weekday_phrases() {
+        assert_eq!(parse("next monday"), NaiveDate::from_ymd_opt(2024, 4, 22));
+        assert_eq!(
+            parse("next wednesday"),
+            NaiveDate::from_ymd_opt(2024, 4, 24)
+        );
+        assert_eq!(parse("last friday"), NaiveDate::from_ymd_opt(2024, 4, 12));
+        assert_eq!(parse("last tuesday"), NaiveDate::from_ymd_opt(2024, 4, 16));
+        assert_eq!(parse("next someday"), None);
+    }
+
+    #[test]
+    fn test_natural_phrases() {
+        assert_eq!(
+            parse("2 weeks from now"),
+            NaiveDate::from_ymd_opt(2024, 5, 1)
+        );
+        assert_eq!(parse("in a week"), NaiveDate::from_ymd_opt(2024, 4, 24));
+        assert_eq!(
+            parse("three days ago"),
+            NaiveDate::from_ymd_opt(2024, 4, 14)
+        );
+        assert_eq!(
+            parse("day after tomorrow"),
+            NaiveDate::from_ymd_opt(2024, 4, 19)
+        );
+        assert_eq!(parse("next week"), NaiveDate::from_ymd_opt(2024, 4, 24));
+        assert_eq!(parse("last month"), NaiveDate::from_ymd_opt(2024, 3, 17));
+        assert_eq!(parse("in 2 years"), NaiveDate::from_ymd_opt(2026, 4, 17));
+        assert_eq!(parse("next fri"), NaiveDate::from_ymd_opt(2024, 4, 19));
+    }
+
+    #[test]
+    fn test_month_ends() {
+        let january_end = NaiveDate::from_ymd_opt(2024, 1, 31).unwrap();
+
+        assert_eq!(
+            parse_relative_date("in 1 month", january_end, &HashMap::new()),
+            NaiveDate::from_ymd_opt(2024, 2, 29)
+        );
+    }
+
+    #[test]
+    fn test_ambiguous_phrases() {
+        assert_eq!(parse("friday"), None);
+        assert_eq!(parse("next"), None);
+        assert_eq!(parse("in some weeks"), None);
+        assert_eq!(parse("next decade"), None);
+    }
+
+    #[test]
+    fn test_custom_phrases() {
+        let custom = HashMap::from([("end of week".to_string(), 3)]);
+
+        assert_eq!(
+            parse_relative_date("end of  week", today(), &custom),
+            NaiveDate::from_ymd_opt(2024, 4, 20)
+        );
+    }
+
+    #[test]
+    fn test_dailynote_folder() {
+        let root_dir = Path::new("/home/vault");
+        let format = "%Y/%m/%Y-%m-%d";
+
+        assert_eq!(
+            dailynote_path(today(), root_dir, format, Some("daily")),
+            Path::new("/home/vault/daily/2024/04/2024-04-17.md")
+        );
+        assert_eq!(dailynote_refname(today(), format), "2024-04-17");
+
+        assert_eq!(
+            dailynote_date(
+                Path::new("/home/vault/daily/2024/04/2024-04-17.md"),
+                root_dir,
+                &[format],
+                Some("daily")
+            ),
+            Some(today())
+        );
+        assert_eq!(
+            dailynote_date(
+                Path::new("/home/vault/2024-04-17.md"),
+                root_dir,
+                &["%Y-%m-%d"],
+                Some("daily")
+            ),
+            Some(today())
+        );
+        assert_eq!(
+            dailynote_date(
+                Path::new("/home/vault/daily/notes.md"),
+                root_dir,
+                &["%Y-%m-%d"],
+                Some("daily")
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_legacy_dailynote_formats() {
+        let root_dir = Path::new("/home/vault");
+        let formats = ["%Y-%m-%d", "%Y/%m/%d"];
+
+        assert_eq!(
+            dailynote_date(
+                Path::new("/home/vault/2024-04-17.md"),
+                root_dir,
+                &formats,
+                None
+            ),
+            Some(today())
+        );
+        assert_eq!(
+            dailynote_date(
+                Path::new("/home/vault/2024/04/17.md"),
+                root_dir,
+                &formats,
+                None
+            ),
+            Some(today())
+        );
+        assert_eq!(
+            dailynote_date(
+                Path::new("/home/vault/2024/04/17.md"),
+                root_dir,
+                &formats[..1],
+                None
+            ),
+            None
+        );
+    }
+}
@@ -0,0 +1,62 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Runs the last of a burst of tasks once the burst pauses, like the diagnostics of a file that is being typed in
+#[derive(Debug, Clone, Default)]
+pub struct Debounce {
+    /// The number of tasks started so far; a task only runs if no other was started while it waited
+    started: Arc<AtomicUsize>,
+}
+
+impl Debounce {
+    /// Run `task` after `delay`, unless another task is started before then. A task that is already running is left to
+    /// finish rather than stopped halfway, so the task after it runs on the latest state.
+    pub fn run(&self, delay: Duration, task: impl Future<Output = ()> + Send + 'static) {
+        let this = self.started.fetch_add(1, Ordering::SeqCst) + 1;
+        let started = self.started.clone();
+
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+
+            if started.load(Ordering::SeqCst) == this {
+                task.await
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use super::Debounce;
+
+    #[tokio::test]
+    async fn test_debounce() {
+        let debounce = Debounce::default();
+        let runs = Arc::new(Mutex::new(vec![]));
+
+        // a burst of keystrokes runs the last task once
+        for keystroke in 0..100 {
+            let runs = runs.clone();
+            debounce.run(Duration::from_millis(50), async move {
+                runs.lock().unwrap().push(keystroke)
+            });
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(*runs.lock().unwrap(), vec![99]);
+
+        // as does each burst after a pause
+        for keystroke in 100..110 {
+            let runs = runs.clone();
+            debounce.run(Duration::from_millis(50), async move {
+                runs.lock().unwrap().push(keystroke)
+            });
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(*runs.lock().unwrap(), vec![99, 109]);
+    }
+}
@@ -1,11 +1,18 @@
-use std::path::{Path, PathBuf};
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+};
 
+use itertools::Itertools;
 use rayon::prelude::*;
 use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, Url};
 
 use crate::{
-    config::Settings,
-    vault::{self, Reference, Referenceable, Vault},
+    codeactions::normalize,
+    config::{ResolutionMode, Settings},
+    vault::{
+        self, get_obsidian_ref_path, MDIndexedBlock, Reference, ReferenceData, Referenceable, Vault,
+    },
 };
 
 pub fn path_unresolved_references<'a>(
@@ -17,10 +24,17 @@ pub fn path_unresolved_references<'a>(
 
     let unresolved = pathreferences
         .into_par_iter()
+        // `[[notes]]` may go to `notes.canvas`, which isn't a note
+        .filter(|(_, reference)| !vault.links_to_attachment(reference))
         .filter(|(path, reference)| {
-            let matched_option = referenceables
-                .iter()
-                .find(|referenceable| reference.references(vault.root_dir(), path, referenceable));
+            let matched_option = referenceables.iter().find(|referenceable| {
+                reference.references(
+                    vault.root_dir(),
+                    path,
+                    referenceable,
+                    vault.case_insensitive_links(),
+                )
+            });
 
             matched_option.is_some_and(|matched| {
                 matches!(
@@ -36,41 +50,282 @@ pub fn path_unresolved_references<'a>(
     Some(unresolved)
 }
 
+/// The indexed blocks in the file whose index is also used by another block in it, in the order they appear
+pub fn duplicate_indexed_blocks<'a>(vault: &'a Vault, path: &Path) -> Vec<&'a MDIndexedBlock> {
+    let Some(mdfile) = vault.md_files.get(path) else {
+        return vec![];
+    };
+
+    mdfile
+        .indexed_blocks
+        .iter()
+        .filter(|block| {
+            mdfile
+                .indexed_blocks
+                .iter()
+                .filter(|other| other.index == block.index)
+                .count()
+                > 1
+        })
+        .collect()
+}
+
+/// The file part of an unresolved link, when it is the file that can't be found rather than a heading or block in it
+pub fn unresolved_link_file<'a>(
+    vault: &Vault,
+    path: &Path,
+    reference: &'a Reference,
+) -> Option<&'a str> {
+    let (file, is_wikilink) = match reference {
+        Reference::WikiFileLink(data) | Reference::MDFileLink(data) => {
+            return Some(data.reference_text.as_str())
+        }
+        Reference::WikiHeadingLink(_, file, _) | Reference::WikiIndexedBlockLink(_, file, _) => {
+            (file, true)
+        }
+        Reference::MDHeadingLink(_, file, _) | Reference::MDIndexedBlockLink(_, file, _) => {
+            (file, false)
+        }
+        _ => return None,
+    };
+    let data = ReferenceData {
+        reference_text: file.clone(),
+        ..Default::default()
+    };
+    let file_reference = match is_wikilink {
+        true => Reference::WikiFileLink(data),
+        false => Reference::MDFileLink(data),
+    };
+
+    let file_exists = file.is_empty()
+        || vault.md_files.iter().any(|(file_path, mdfile)| {
+            Referenceable::File(file_path, mdfile).matches_reference(
+                vault.root_dir(),
+                &file_reference,
+                path,
+                vault.case_insensitive_links(),
+            )
+        });
+
+    (!file_exists).then_some(file.as_str())
+}
+
+/// Whether a markdown link's path goes out of the vault, like `../other-vault/note.md`. Each workspace folder is
+/// its own vault, so a link into another one can't be resolved.
+pub fn leaves_vault(vault: &Vault, path: &Path, reference: &Reference) -> bool {
+    let file = match reference {
+        Reference::MDFileLink(data) => &data.reference_text,
+        Reference::MDHeadingLink(_, file, _) | Reference::MDIndexedBlockLink(_, file, _) => file,
+        _ => return false,
+    };
+    if file.is_empty() || file.starts_with('/') {
+        return false;
+    }
+
+    path.parent()
+        .is_some_and(|folder| !normalize(&folder.join(file)).starts_with(vault.root_dir()))
+}
+
+/// The notes whose name is a few edits away from the file of an unresolved link, like `projects/foo` for
+/// `projetcs/foo`, closest first. Links with a `/` are compared to the paths of the notes from the vault root.
+pub fn similar_files<'a>(vault: &'a Vault, link_file: &str) -> Vec<(&'a Path, String)> {
+    let link_file = link_file
+        .trim_start_matches("./")
+        .trim_start_matches('/')
+        .trim_end_matches(".md");
+    let by_path = link_file.contains('/');
+    let normalize = |text: &str| match vault.case_insensitive_links() {
+        true => text.to_lowercase().chars().collect::<Vec<_>>(),
+        false => text.chars().collect::<Vec<_>>(),
+    };
+    let link_chars = normalize(link_file);
+    // short names have few letters to spare
+    let max_distance = (link_chars.len() / 4).clamp(1, 3);
+
+    vault
+        .md_files
+        .keys()
+        .filter_map(|file_path| {
+            let name = match by_path {
+                true => Cow::Owned(get_obsidian_ref_path(vault.root_dir(), file_path)?),
+                false => Cow::Borrowed(file_path.file_stem()?.to_str()?),
+            };
+            // most notes are too long or too short to be close, which is cheaper to tell than the distance
+            if name.chars().count().abs_diff(link_chars.len()) > max_distance
+                || vault.is_excluded(file_path)
+            {
+                return None;
+            }
+            let distance = bounded_levenshtein(&link_chars, &normalize(&name), max_distance)?;
+
+            (distance > 0).then_some((distance, file_path.as_path(), name.into_owned()))
+        })
+        .sorted_by(|(a, _, a_name), (b, _, b_name)| a.cmp(b).then_with(|| a_name.cmp(b_name)))
+        .take(SIMILAR_FILES)
+        .map(|(_, file_path, name)| (file_path, name))
+        .collect()
+}
+
+/// The number of similar notes suggested for an unresolved link
+const SIMILAR_FILES: usize = 3;
+
+/// The edit distance between `a` and `b`, if it is at most `max`. Only the rows of the distance table are kept, and
+/// the comparison stops as soon as every entry in a row is over `max`.
+fn bounded_levenshtein(a: &[char], b: &[char], max: usize) -> Option<usize> {
+    if a.len().abs_diff(b.len()) > max {
+        return None;
+    }
+
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, a_char) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+
+        if current.iter().min().is_some_and(|min| *min > max) {
+            return None;
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    Some(previous[b.len()]).filter(|distance| *distance <= max)
+}
+
 pub fn diagnostics(
     vault: &Vault,
     settings: &Settings,
     (path, _uri): (&PathBuf, &Url),
 ) -> Option<Vec<Diagnostic>> {
+    // excluded files, like templates, are full of placeholder links
+    if vault.is_excluded(path) {
+        return Some(vec![]);
+    }
+
+    let duplicates = duplicate_indexed_blocks(vault, path)
+        .into_iter()
+        .map(|block| Diagnostic {
+            range: *block.range,
+            message: format!(
+                "Duplicate block id ^{}; links to #^{} in this file are ambiguous",
+                block.index, block.index
+            ),
+            source: Some("Obsidian LS".into()),
+            severity: Some(DiagnosticSeverity::WARNING),
+            ..Default::default()
+        });
+
     if !settings.unresolved_diagnostics {
-        return None;
+        return Some(duplicates.collect());
     }
 
     let unresolved = path_unresolved_references(vault, path)?;
 
     let allreferences = vault.select_references(None)?;
 
+    let severity = match settings.resolution_mode {
+        Some(_) => DiagnosticSeverity::WARNING,
+        None => DiagnosticSeverity::INFORMATION,
+    };
+
     let diags: Vec<Diagnostic> = unresolved
         .into_par_iter()
-        .map(|(path, reference)| Diagnostic {
+        .filter_map(|(ref_path, reference)| {
+            let missing_file = unresolved_link_file(vault, path, reference);
+            let other_vault = missing_file.is_some() && leaves_vault(vault, path, reference);
+
+            // a link to a missing note is a placeholder, unless its name is a typo of an existing note's; the notes it
+            // may be a typo of are only suggested by the hover and the code action, which are asked for one link
+            if settings.resolution_mode == Some(ResolutionMode::Loose)
+                && !other_vault
+                && missing_file.is_some_and(|file| similar_files(vault, file).is_empty())
+            {
+                return None;
+            }
+
+            Some((ref_path, reference, other_vault))
+        })
+        .map(|(ref_path, reference, other_vault)| Diagnostic {
             range: *reference.data().range,
             message: match allreferences
                 .iter()
                 .filter(|(other_path, otherreference)| {
                     otherreference.matches_type(reference)
                         && (!matches!(reference, vault::Reference::Footnote(_))
-                            || **other_path == *path)
+                            || **other_path == *ref_path)
                         && otherreference.data().reference_text == reference.data().reference_text
                 })
                 .count()
             {
+                _ if other_vault => {
+                    "Links between vaults aren't supported; this note is outside the vault"
+                        .to_string()
+                }
                 num if num > 1 => format!("Unresolved Reference used {} times", num),
                 _ => "Unresolved Reference".to_string(),
             },
             source: Some("Obsidian LS".into()),
-            severity: Some(DiagnosticSeverity::INFORMATION),
+            severity: Some(severity),
             ..Default::default()
         })
         .collect();
 
-    Some(diags)
+    Some(diags.into_iter().chain(duplicates).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vault::TestVault;
+
+    use super::{bounded_levenshtein, similar_files};
+
+    #[test]
+    fn test_bounded_levenshtein() {
+        let distance = |a: &str, b: &str, max| {
+            bounded_levenshtein(
+                &a.chars().collect::<Vec<_>>(),
+                &b.chars().collect::<Vec<_>>(),
+                max,
+            )
+        };
+
+        assert_eq!(distance("projetcs/foo", "projects/foo", 3), Some(2));
+        assert_eq!(distance("meeting", "meetings", 1), Some(1));
+        assert_eq!(distance("note", "note", 1), Some(0));
+        // too far apart, or too different in length, to be a typo
+        assert_eq!(distance("kitten", "sitting", 2), None);
+        assert_eq!(distance("foo", "foobarbaz", 3), None);
+    }
+
+    #[test]
+    fn test_similar_files() {
+        let test = TestVault::with_settings(
+            &[
+                ("projects/foo.md", ""),
+                ("projects/fooo.md", ""),
+                ("meetings.md", ""),
+                ("templates/meeting.md", ""),
+                ("a-much-longer-name.md", ""),
+            ],
+            |settings| settings.exclude = vec!["templates".into()],
+        );
+        let similar = |link: &str| {
+            similar_files(&test.vault, link)
+                .into_iter()
+                .map(|(_, name)| name)
+                .collect::<Vec<_>>()
+        };
+
+        // by path from the vault root when the link has one, closest first
+        assert_eq!(
+            similar("projetcs/foo"),
+            vec!["projects/foo", "projects/fooo"]
+        );
+        // by name, without excluded notes
+        assert_eq!(similar("Meeting"), vec!["meetings"]);
+        assert_eq!(similar("zzz"), Vec::<String>::new());
+    }
 }
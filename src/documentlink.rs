@@ -0,0 +1,166 @@
+use std::path::Path;
+
+use itertools::Itertools;
+use tower_lsp::lsp_types::{DocumentLink, DocumentLinkParams, Url};
+
+use crate::vault::{Reference, Referenceable, Vault};
+
+/// Every wikilink and markdown link in the file, with the file it goes to as the target. Links to headings and blocks
+/// have the line as a fragment, `#L12`, for the clients that scroll to it. Unresolved links have no target. Links to
+/// attachments, like `![[diagram.png]]` or `[[board]]` for `board.canvas`, go to the attachment.
+pub fn document_links(
+    vault: &Vault,
+    _params: &DocumentLinkParams,
+    path: &Path,
+) -> Option<Vec<DocumentLink>> {
+    let references = vault.select_references(Some(path))?;
+
+    // the referenceables by the name of their file, which links by name and by path both end with; selected once for
+    // the whole file rather than for each link
+    let referenceables = vault.select_referenceable_nodes(None);
+    let by_name = referenceables
+        .iter()
+        .filter(|referenceable| !referenceable.is_unresolved())
+        .filter_map(|referenceable| {
+            let name = referenceable
+                .get_path()
+                .file_stem()?
+                .to_str()?
+                .to_lowercase();
+            Some((name, referenceable))
+        })
+        .into_group_map();
+    let own_name = path.file_stem()?.to_str()?.to_lowercase();
+
+    let links = references
+        .into_iter()
+        .filter(|(_, reference)| !vault.links_to_attachment(reference))
+        .filter_map(|(_, reference)| {
+            let file_ref_text = match reference {
+                Reference::WikiFileLink(data) | Reference::MDFileLink(data) => &data.reference_text,
+                Reference::WikiHeadingLink(_, file, _)
+                | Reference::WikiIndexedBlockLink(_, file, _)
+                | Reference::MDHeadingLink(_, file, _)
+                | Reference::MDIndexedBlockLink(_, file, _) => file,
+                Reference::Tag(..) | Reference::Footnote(..) | Reference::LinkRef(..) => {
+                    return None
+                }
+            };
+            // `[[#Heading]]` goes to the file it is in
+            let name = match file_ref_text.as_str() {
+                "" => own_name.clone(),
+                file => file.rsplit('/').next()?.to_lowercase(),
+            };
+
+            let target = by_name
+                .get(&name)
+                .into_iter()
+                .flatten()
+                .find(|referenceable| {
+                    reference.references(
+                        vault.root_dir(),
+                        path,
+                        referenceable,
+                        vault.case_insensitive_links(),
+                    )
+                });
+
+            Some(DocumentLink {
+                range: *reference.data().range,
+                tooltip: match target {
+                    Some(_) => None,
+                    None => Some(format!("Unresolved: {}", reference.data().reference_text)),
+                },
+                target: target.and_then(|target| target_url(target)),
+                data: None,
+            })
+        });
+
+    let attachment_links =
+        vault
+            .select_attachment_links(path)
+            .into_iter()
+            .map(|link| DocumentLink {
+                range: link.range,
+                target: Url::from_file_path(link.attachment).ok(),
+                tooltip: None,
+                data: None,
+            });
+
+    Some(
+        links
+            .chain(attachment_links)
+            .sorted_by_key(|link| link.range.start)
+            .collect(),
+    )
+}
+
+fn target_url(referenceable: &Referenceable) -> Option<Url> {
+    let mut url = Url::from_file_path(referenceable.get_path()).ok()?;
+
+    match referenceable {
+        Referenceable::Heading(..) | Referenceable::IndexedBlock(..) => {
+            let line = referenceable.get_range()?.start.line;
+            url.set_fragment(Some(&format!("L{}", line + 1)));
+        }
+        _ => {}
+    }
+
+    Some(url)
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::{
+        DocumentLinkParams, PartialResultParams, TextDocumentIdentifier, Url,
+        WorkDoneProgressParams,
+    };
+
+    use crate::vault::TestVault;
+
+    use super::document_links;
+
+    #[test]
+    fn test_document_links() {
+        let test = TestVault::new(&[
+            (
+                "notes/today.md",
+                "# Today\n\n[[other]] [x](../other.md#Heading) [[#Today]]\n[[board]] ![[diagram.png]] [[missing]]\n",
+            ),
+            ("other.md", "text\n\n## Heading\n"),
+            ("board.canvas", "{}"),
+            ("assets/diagram.png", ""),
+        ]);
+        let path = test.path("notes/today.md");
+        let params = DocumentLinkParams {
+            text_document: TextDocumentIdentifier {
+                uri: Url::from_file_path(&path).unwrap(),
+            },
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        };
+        let url = |file: &str, fragment: Option<&str>| {
+            let mut url = Url::from_file_path(test.path(file)).unwrap();
+            url.set_fragment(fragment);
+            url
+        };
+
+        let targets = document_links(&test.vault, &params, &path)
+            .unwrap()
+            .into_iter()
+            .map(|link| link.target)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            targets,
+            vec![
+                Some(url("other.md", None)),
+                Some(url("other.md", Some("L3"))),
+                Some(url("notes/today.md", Some("L1"))),
+                Some(url("board.canvas", None)),
+                Some(url("assets/diagram.png", None)),
+                None,
+            ]
+        );
+    }
+}
@@ -0,0 +1,276 @@
+use std::path::Path;
+
+use itertools::Itertools;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::{
+    CreateFile, CreateFileOptions, DocumentChangeOperation, DocumentChanges, OneOf,
+    OptionalVersionedTextDocumentIdentifier, Position, Range, ResourceOp, TextDocumentEdit,
+    TextEdit, Url, WorkspaceEdit,
+};
+
+use crate::{
+    codeactions::converted_link,
+    config::{LinkStyle, Settings},
+    resolve::new_note_path,
+    vault::{heading_link_text, Reference, ReferenceData, Vault},
+};
+
+/// The command moving the selected text to a new note and linking to the note in its place
+pub const EXTRACT_NOTE_COMMAND: &str = "markdown-oxide.extractNote";
+
+/// The argument of the `markdown-oxide.extractNote` command
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ExtractNoteArgs {
+    pub uri: Url,
+    pub range: Range,
+    /// Embed the new note, `![[note]]`, rather than link to it
+    #[serde(default)]
+    pub embed: bool,
+    /// The title of the new note, which starts the note as a `#` heading; the note is named by the first line of the
+    /// selection if unset
+    #[serde(default)]
+    pub title: Option<String>,
+}
+
+/// The edit creating the note from the selection and replacing the selection with a link to it. The note goes where a
+/// wikilink to it would create it, named by its title without the characters a file name or link can't have; a
+/// number is added to names that are taken. None if nothing is selected.
+pub fn extract_note_edit(
+    vault: &Vault,
+    settings: &Settings,
+    path: &Path,
+    args: &ExtractNoteArgs,
+) -> Option<WorkspaceEdit> {
+    let rope = vault.ropes.get(path)?;
+    // positions count UTF-16 code units, as the client does
+    let char_index = |position: Position| {
+        let line_start = rope.try_line_to_char(position.line as usize).ok()?;
+        let utf16_index = rope.char_to_utf16_cu(line_start) + position.character as usize;
+        rope.try_utf16_cu_to_char(utf16_index).ok()
+    };
+    let selection = rope
+        .get_slice(char_index(args.range.start)?..char_index(args.range.end)?)?
+        .to_string();
+    let selection = selection.trim_matches('\n');
+    if selection.trim().is_empty() {
+        return None;
+    }
+
+    let title = args
+        .title
+        .as_deref()
+        .map(str::trim)
+        .filter(|title| !title.is_empty());
+    let first_line = selection
+        .lines()
+        .find(|line| !line.trim().is_empty())
+        .map(line_title)?;
+    let name = note_name(title.unwrap_or(&first_line)).unwrap_or("Untitled".to_string());
+
+    // a name is taken by a note anywhere in the vault, which a wikilink by the name would go to
+    let taken = |name: &str| {
+        vault.md_files.keys().any(|path| {
+            path.file_stem()
+                .and_then(|stem| stem.to_str())
+                .is_some_and(|stem| stem.to_lowercase() == name.to_lowercase())
+        })
+    };
+    let (new_path, name) = (1..)
+        .map(|n| match n {
+            1 => name.clone(),
+            n => format!("{} {}", name, n),
+        })
+        .map(|name| Some((new_note_path(vault, settings, path, &name, true)?, name)))
+        .find(|candidate| {
+            !matches!(candidate, Some((new_path, name)) if new_path.exists() || taken(name))
+        })??;
+    let uri = Url::from_file_path(new_path).ok()?;
+
+    let content = match title {
+        Some(title) => format!("# {}\n\n{}\n", title, selection),
+        None => format!("{}\n", selection),
+    };
+
+    let wikilink = format!("[[{}]]", name);
+    let link = match settings.link_style {
+        Some(LinkStyle::Markdown) => {
+            let reference = Reference::WikiFileLink(ReferenceData {
+                reference_text: name.clone(),
+                display_text: Some(title.unwrap_or(&name).to_string()),
+                ..Default::default()
+            });
+            converted_link(vault, settings, &reference, path).unwrap_or(wikilink)
+        }
+        _ => wikilink,
+    };
+    let link = match args.embed {
+        true => format!("!{}", link),
+        false => link,
+    };
+    // a selection of whole lines ends at the start of the next line, which is kept on its own line
+    let link = match args.range.end.line > args.range.start.line && args.range.end.character == 0 {
+        true => format!("{}\n", link),
+        false => link,
+    };
+
+    let edit = |uri: &Url, range: Range, new_text: String| {
+        DocumentChangeOperation::Edit(TextDocumentEdit {
+            text_document: OptionalVersionedTextDocumentIdentifier {
+                uri: uri.clone(),
+                version: None,
+            },
+            edits: vec![OneOf::Left(TextEdit { range, new_text })],
+        })
+    };
+
+    Some(WorkspaceEdit {
+        document_changes: Some(DocumentChanges::Operations(vec![
+            DocumentChangeOperation::Op(ResourceOp::Create(CreateFile {
+                uri: uri.clone(),
+                options: Some(CreateFileOptions {
+                    ignore_if_exists: Some(false),
+                    overwrite: Some(false),
+                }),
+                annotation_id: None,
+            })),
+            edit(&uri, Range::default(), content),
+            edit(&Url::from_file_path(path).ok()?, args.range, link),
+        ])),
+        ..Default::default()
+    })
+}
+
+/// The text of a line without heading, list and quote markers or link syntax
+fn line_title(line: &str) -> String {
+    static MARKER_RE: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"^(\s*(#+|[-*+>]|\d+[.)]|\[.\])\s+)+").unwrap());
+
+    heading_link_text(&MARKER_RE.replace(line, ""))
+}
+
+/// A file name for a note with the title: the characters that can't be in a file name or a wikilink are left out, as
+/// is a leading `.` that would hide the note, and long titles are cut at a word
+fn note_name(title: &str) -> Option<String> {
+    const MAX_CHARS: usize = 60;
+
+    let cleaned = title.replace(
+        [
+            '/', '\\', ':', '*', '?', '"', '<', '>', '|', '[', ']', '#', '^',
+        ],
+        " ",
+    );
+    let words = cleaned.split_whitespace().collect_vec();
+    // a first word longer than that is kept whole
+    let count = (1..=words.len())
+        .take_while(|&count| words[..count].join(" ").chars().count() <= MAX_CHARS)
+        .last()
+        .unwrap_or(words.len().min(1));
+    let name = words[..count].join(" ");
+    let name = name.trim_start_matches('.').trim();
+
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::{
+        DocumentChangeOperation::{Edit, Op},
+        DocumentChanges, OneOf, Position, Range, ResourceOp, TextDocumentEdit, TextEdit, Url,
+    };
+
+    use crate::vault::{apply_edits, TestVault};
+
+    use super::{extract_note_edit, line_title, note_name, ExtractNoteArgs};
+
+    /// The new note's path from the vault root, its text and the text of the note the selection was extracted from
+    fn extract(test_vault: &TestVault, file: &str, range: Range) -> (String, String, String) {
+        let path = test_vault.path(file);
+        let args = ExtractNoteArgs {
+            uri: Url::from_file_path(&path).unwrap(),
+            range,
+            embed: false,
+            title: None,
+        };
+        let edit =
+            extract_note_edit(&test_vault.vault, &test_vault.settings, &path, &args).unwrap();
+        let Some(DocumentChanges::Operations(operations)) = edit.document_changes else {
+            panic!("expected document changes")
+        };
+        let [Op(ResourceOp::Create(create)), Edit(content), Edit(link)] = &operations[..] else {
+            panic!("expected the note to be created and linked")
+        };
+        let edits = |edit: &TextDocumentEdit| {
+            edit.edits
+                .iter()
+                .map(|edit| match edit {
+                    OneOf::Left(edit) => edit.clone(),
+                    OneOf::Right(edit) => edit.text_edit.clone(),
+                })
+                .collect::<Vec<TextEdit>>()
+        };
+        let new_file = create.uri.to_file_path().unwrap();
+
+        (
+            new_file
+                .strip_prefix(&test_vault.root_dir)
+                .unwrap()
+                .to_string_lossy()
+                .to_string(),
+            apply_edits("", &edits(content)),
+            apply_edits(&test_vault.vault.ropes[&path].to_string(), &edits(link)),
+        )
+    }
+
+    #[test]
+    fn test_extract_note_edit() {
+        let test_vault = TestVault::new(&[
+            ("note.md", "Idea\nmore of it\nnext\n"),
+            ("folder/idea.md", ""),
+        ]);
+
+        // whole lines, named apart from the note of the same name in another folder
+        let range = Range::new(Position::new(0, 0), Position::new(2, 0));
+        assert_eq!(
+            extract(&test_vault, "note.md", range),
+            (
+                "Idea 2.md".to_string(),
+                "Idea\nmore of it\n".to_string(),
+                "[[Idea 2]]\nnext\n".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_extract_note_edit_utf16() {
+        // the emoji is two UTF-16 code units but one char
+        let test_vault = TestVault::new(&[("note.md", "😀 Plan the launch\n")]);
+
+        let range = Range::new(Position::new(0, 3), Position::new(0, 18));
+        let (new_file, content, _) = extract(&test_vault, "note.md", range);
+        assert_eq!(new_file, "Plan the launch.md");
+        assert_eq!(content, "Plan the launch\n");
+    }
+
+    #[test]
+    fn test_note_name() {
+        assert_eq!(
+            line_title("## Meeting: [[Alice|her]] notes"),
+            "Meeting: her notes"
+        );
+        assert_eq!(line_title("- [ ] **Plan** the launch"), "Plan the launch");
+
+        assert_eq!(
+            note_name("Meeting: Q3/Q4 plans?").as_deref(),
+            Some("Meeting Q3 Q4 plans")
+        );
+        assert_eq!(note_name(".hidden idea").as_deref(), Some("hidden idea"));
+        assert_eq!(note_name("#^|").as_deref(), None);
+        // cut at a word
+        assert_eq!(
+            note_name(&"word ".repeat(20)),
+            Some("word ".repeat(12).trim_end().to_string())
+        );
+    }
+}
@@ -0,0 +1,93 @@
+use std::path::Path;
+
+use tower_lsp::lsp_types::{FoldingRange, FoldingRangeKind, FoldingRangeParams};
+
+use crate::vault::{Rangeable, Vault};
+
+pub fn folding_ranges(
+    vault: &Vault,
+    _params: &FoldingRangeParams,
+    path: &Path,
+) -> Option<Vec<FoldingRange>> {
+    let mdfile = vault.md_files.get(path)?;
+    let last_line = vault.ropes.get(path)?.len_lines().saturating_sub(1) as u32;
+
+    let frontmatter = vault
+        .select_frontmatter_end_line(path)
+        .map(|end| folding_range(0, end as u32, Some(FoldingRangeKind::Region)));
+
+    // the last section extends to the end of the file
+    let sections = mdfile.headings.iter().map(|heading| {
+        let end = mdfile.heading_section_end(heading).unwrap_or(last_line);
+
+        folding_range(heading.range.start.line, end, None)
+    });
+
+    let callouts = mdfile
+        .callouts
+        .iter()
+        .map(|callout| folding_range(callout.range().start.line, callout.last_line, None));
+
+    Some(
+        frontmatter
+            .into_iter()
+            .chain(sections)
+            .chain(callouts)
+            .filter(|range| range.end_line > range.start_line)
+            .collect(),
+    )
+}
+
+fn folding_range(start_line: u32, end_line: u32, kind: Option<FoldingRangeKind>) -> FoldingRange {
+    FoldingRange {
+        start_line,
+        end_line,
+        kind,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::{FoldingRangeKind, FoldingRangeParams, TextDocumentIdentifier, Url};
+
+    use crate::vault::TestVault;
+
+    use super::folding_ranges;
+
+    #[test]
+    fn test_folding_ranges() {
+        let text = "---\ntitle: Note\n---\n# One\ntext\n## Two\nmore\n## Three\n> [!note] Tip\n> first\n> second\n# Four\nend";
+        let test = TestVault::new(&[("note.md", text)]);
+        let path = test.path("note.md");
+        let params = FoldingRangeParams {
+            text_document: TextDocumentIdentifier {
+                uri: Url::from_file_path(&path).unwrap(),
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+
+        let ranges = folding_ranges(&test.vault, &params, &path)
+            .unwrap()
+            .into_iter()
+            .map(|range| (range.start_line, range.end_line, range.kind))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            ranges,
+            vec![
+                // the frontmatter
+                (0, 2, Some(FoldingRangeKind::Region)),
+                // a section ends before the next heading of its level or higher, after its nested sections
+                (3, 10, None),
+                (5, 6, None),
+                (7, 10, None),
+                // the last section runs to the end of the file
+                (11, 12, None),
+                // the callout
+                (8, 10, None),
+            ]
+        );
+    }
+}
@@ -0,0 +1,248 @@
+use std::path::Path;
+
+use tower_lsp::lsp_types::{Range, TextEdit};
+
+use crate::{
+    codeactions::converted_link,
+    config::{LinkStyle, Settings},
+    resolve::existing_file,
+    vault::{get_obsidian_ref_path, Rangeable, Reference, Vault},
+};
+
+/// Rewrite the file's links to the `link_style` setting, keeping their display text and infile refs. Links in code are
+/// literal text and links to notes that don't exist yet have no known path, so both are left as they are. Formatting
+/// the result again changes nothing.
+pub fn format_links(vault: &Vault, settings: &Settings, path: &Path) -> Option<Vec<TextEdit>> {
+    let link_style = settings.link_style?;
+    let md_file = vault.md_files.get(path)?;
+
+    let edits = md_file
+        .references
+        .iter()
+        .filter(|reference| match link_style {
+            LinkStyle::Wiki => is_markdown_link(reference),
+            LinkStyle::Markdown => is_wikilink(reference),
+        })
+        .filter(|reference| {
+            !md_file
+                .codeblocks
+                .iter()
+                .any(|codeblock| codeblock.includes(*reference))
+        })
+        .filter(|reference| {
+            vault
+                .select_referenceables_for_reference(reference, path)
+                .iter()
+                .any(|referenceable| !referenceable.is_unresolved())
+        })
+        .flat_map(|reference| {
+            let new_text = converted_link(vault, settings, reference, path)?;
+
+            // display text that can't be written in the other style, like a `|` in a wikilink, would break the link
+            let intact = {
+                let mut converted = Reference::new(&new_text);
+                match (converted.next(), converted.next()) {
+                    (Some(converted), None) => {
+                        is_wikilink(&converted) == (link_style == LinkStyle::Wiki)
+                    }
+                    _ => false,
+                }
+            };
+
+            intact.then_some(TextEdit {
+                range: *reference.data().range,
+                new_text,
+            })
+        })
+        .collect();
+
+    Some(edits)
+}
+
+/// Tidy the links in the range: the spaces around a wikilink's file, heading and display text and around a markdown
+/// link's display text are trimmed, so `[[ foo | bar]]` becomes `[[foo|bar]]`. With `canonical_link_case`, the file
+/// is written as the note is named. Links in code and the text around links are left as they are.
+pub fn format_link_range(
+    vault: &Vault,
+    settings: &Settings,
+    path: &Path,
+    range: Range,
+) -> Option<Vec<TextEdit>> {
+    let md_file = vault.md_files.get(path)?;
+
+    let edits = md_file
+        .references
+        .iter()
+        .filter(|reference| {
+            let reference_range = reference.data().range;
+            reference_range.start <= range.end && reference_range.end >= range.start
+        })
+        .filter(|reference| {
+            !md_file
+                .codeblocks
+                .iter()
+                .any(|codeblock| codeblock.includes(*reference))
+        })
+        .filter_map(|reference| {
+            let reference_range = *reference.data().range;
+            let line = vault.select_line(path, reference_range.start.line as isize)?;
+            let text = String::from_iter(line.get(
+                reference_range.start.character as usize..reference_range.end.character as usize,
+            )?);
+
+            let new_text = match reference {
+                _ if is_wikilink(reference) => {
+                    tidy_wikilink(&text, |file| canonical_file(vault, settings, path, file))?
+                }
+                _ if is_markdown_link(reference) => tidy_markdown_link(&text)?,
+                _ => return None,
+            };
+
+            (new_text != text).then_some(TextEdit {
+                range: reference_range,
+                new_text,
+            })
+        })
+        .collect();
+
+    Some(edits)
+}
+
+/// A wikilink (or embed) with the spaces inside it trimmed; `canonical` gives the file as the note is named
+fn tidy_wikilink(text: &str, canonical: impl Fn(&str) -> Option<String>) -> Option<String> {
+    let (embed, text) = match text.strip_prefix('!') {
+        Some(text) => ("!", text),
+        None => ("", text),
+    };
+    let inner = text.strip_prefix("[[")?.strip_suffix("]]")?;
+
+    let (target, display) = match inner.split_once('|') {
+        Some((target, display)) => (target, Some(display.trim())),
+        None => (inner, None),
+    };
+    let (file, infile_ref) = match target.split_once('#') {
+        Some((file, infile_ref)) => (file.trim(), Some(infile_ref.trim())),
+        None => (target.trim(), None),
+    };
+    let file = canonical(file).unwrap_or(file.to_string());
+
+    Some(format!(
+        "{}[[{}{}{}]]",
+        embed,
+        file,
+        infile_ref
+            .map(|infile_ref| format!("#{}", infile_ref))
+            .unwrap_or_default(),
+        display
+            .filter(|display| !display.is_empty())
+            .map(|display| format!("|{}", display))
+            .unwrap_or_default()
+    ))
+}
+
+/// A markdown link with the spaces around its display text trimmed
+fn tidy_markdown_link(text: &str) -> Option<String> {
+    let (display, rest) = text.strip_prefix('[')?.split_once("](")?;
+
+    Some(format!("[{}]({}", display.trim(), rest))
+}
+
+/// The file of a wikilink written as the note it links to is named, when they differ only in case
+fn canonical_file(vault: &Vault, settings: &Settings, path: &Path, file: &str) -> Option<String> {
+    if !settings.canonical_link_case || file.is_empty() {
+        return None;
+    }
+
+    let target = existing_file(vault, path, file, true)?;
+    let (root, name) = match file.strip_prefix('/') {
+        Some(file) => ("/", file),
+        None => ("", file),
+    };
+    let canonical = match name.contains('/') {
+        true => get_obsidian_ref_path(vault.root_dir(), &target)?,
+        false => target.file_stem()?.to_str()?.to_string(),
+    };
+
+    (canonical != name && canonical.to_lowercase() == name.to_lowercase())
+        .then(|| format!("{}{}", root, canonical))
+}
+
+fn is_wikilink(reference: &Reference) -> bool {
+    matches!(
+        reference,
+        Reference::WikiFileLink(..)
+            | Reference::WikiHeadingLink(..)
+            | Reference::WikiIndexedBlockLink(..)
+    )
+}
+
+fn is_markdown_link(reference: &Reference) -> bool {
+    matches!(
+        reference,
+        Reference::MDFileLink(..)
+            | Reference::MDHeadingLink(..)
+            | Reference::MDIndexedBlockLink(..)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config::LinkStyle,
+        vault::{apply_edits, TestVault},
+    };
+
+    use super::{format_links, tidy_markdown_link, tidy_wikilink};
+
+    #[test]
+    fn test_format_links_twice() {
+        let mut test = TestVault::with_settings(
+            &[
+                ("note.md", "# Some Heading\n\ntext ^block\n"),
+                (
+                    "today.md",
+                    "[[note]], [[note#Some Heading]], [[note#^block]] and [[note|shown]]\n",
+                ),
+            ],
+            |settings| settings.link_style = Some(LinkStyle::Markdown),
+        );
+        let path = test.path("today.md");
+
+        let text = test.vault.ropes.get(&path).unwrap().to_string();
+        let edits = format_links(&test.vault, &test.settings, &path).unwrap();
+        let formatted = apply_edits(&text, &edits);
+        // links without display text are given the heading or the note's name, rather than an empty `[]`
+        assert_eq!(
+            formatted,
+            "[note](note.md), [Some Heading](<note.md#Some Heading>), [note](note.md#^block) and [shown](note.md)\n"
+        );
+
+        test.edit(&path, &formatted);
+        assert_eq!(
+            format_links(&test.vault, &test.settings, &path).unwrap(),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_tidy_links() {
+        let tidy = |text| tidy_wikilink(text, |_| None);
+
+        assert_eq!(tidy("[[ foo ]]").as_deref(), Some("[[foo]]"));
+        assert_eq!(tidy("[[foo | bar]]").as_deref(), Some("[[foo|bar]]"));
+        assert_eq!(
+            tidy("![[ notes/foo # Some Heading |  bar ]]").as_deref(),
+            Some("![[notes/foo#Some Heading|bar]]")
+        );
+        assert_eq!(tidy("[[foo| ]]").as_deref(), Some("[[foo]]"));
+        assert_eq!(
+            tidy_wikilink("[[ my note|bar]]", |_| Some("My Note".into())).as_deref(),
+            Some("[[My Note|bar]]")
+        );
+
+        assert_eq!(
+            tidy_markdown_link("[ bar ](foo.md#heading)").as_deref(),
+            Some("[bar](foo.md#heading)")
+        );
+    }
+}
@@ -1,6 +1,6 @@
 use std::path::Path;
 
-use tower_lsp::lsp_types::{Location, Position, Url};
+use tower_lsp::lsp_types::{Location, Position, Range, Url};
 
 use crate::vault::{Referenceable, Vault};
 
@@ -9,6 +9,14 @@ pub fn goto_definition(
     cursor_position: Position,
     path: &Path,
 ) -> Option<Vec<Location>> {
+    // Links to attachments, like `![[diagram.png]]`, open the file
+    if let Some(attachment) = vault.select_attachment_at_position(path, cursor_position) {
+        return Some(vec![Location {
+            uri: Url::from_file_path(attachment).ok()?,
+            range: Range::default(),
+        }]);
+    }
+
     // First, find the link that the cursor is in. Get a links for the file and match the cursor position up to one of them
     let reference = vault.select_reference_at_position(path, cursor_position)?;
     // Now we have the reference text. We need to find where this is actually referencing, or if it is referencing anything.
@@ -21,7 +29,7 @@ pub fn goto_definition(
             .into_iter()
             .filter_map(|linkable| {
                 let range = match linkable {
-                    Referenceable::File(..) => tower_lsp::lsp_types::Range {
+                    Referenceable::File(..) => Range {
                         start: Position {
                             line: 0,
                             character: 0,
@@ -0,0 +1,191 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+};
+
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::Url;
+
+use crate::{
+    resolve::existing_file,
+    vault::{get_obsidian_ref_path, Reference, Vault},
+};
+
+/// The command returning the links between the notes of the vault
+pub const EXPORT_GRAPH_COMMAND: &str = "markdown-oxide.exportGraph";
+
+/// The argument of the `markdown-oxide.exportGraph` command
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct ExportGraphArgs {
+    /// A file in the vault to export; the vault of the first workspace folder if unset
+    pub uri: Option<Url>,
+    /// Keep the notes matched by the `exclude` setting
+    pub include_excluded: bool,
+}
+
+/// A link from a note to another
+#[derive(Serialize, Debug, PartialEq)]
+pub struct GraphLink {
+    /// The note linked to, by its path from the vault root without `.md`; what the link names if it is unresolved
+    pub target: String,
+    pub kind: GraphLinkKind,
+    /// Whether the note linked to doesn't exist
+    pub unresolved: bool,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum GraphLinkKind {
+    Wiki,
+    Markdown,
+    /// `![[file]]` or `![](file.md)`
+    Embed,
+}
+
+/// The link graph of a vault: a node for each note and each unresolved link target
+#[derive(Serialize, Debug, PartialEq)]
+pub struct LinkGraph {
+    /// The notes each note links to, keyed by the note's path from the vault root
+    pub notes: BTreeMap<String, Vec<GraphLink>>,
+    /// The targets of unresolved links, the notes that don't exist yet
+    pub unresolved: BTreeSet<String>,
+}
+
+/// The link graph of the vault. Links to headings and blocks are links to their note; links within a note are left
+/// out, as are links to and from excluded notes unless `include_excluded`.
+pub fn link_graph(vault: &Vault, include_excluded: bool) -> LinkGraph {
+    let notes: BTreeMap<String, Vec<GraphLink>> = vault
+        .md_files
+        .keys()
+        .filter(|path| include_excluded || !vault.is_excluded(path))
+        .filter_map(|path| {
+            Some((
+                get_obsidian_ref_path(vault.root_dir(), path)?,
+                note_links(vault, path, include_excluded),
+            ))
+        })
+        .collect();
+
+    let unresolved = notes
+        .values()
+        .flatten()
+        .filter(|link| link.unresolved)
+        .map(|link| link.target.clone())
+        .collect();
+
+    LinkGraph { notes, unresolved }
+}
+
+fn note_links(vault: &Vault, path: &Path, include_excluded: bool) -> Vec<GraphLink> {
+    vault
+        .select_references(Some(path))
+        .unwrap_or_default()
+        .into_iter()
+        .sorted_by_key(|(_, reference)| reference.data().range.start)
+        .filter_map(|(_, reference)| {
+            let (file, is_wikilink) = match reference {
+                Reference::WikiFileLink(data) => (&data.reference_text, true),
+                Reference::MDFileLink(data) => (&data.reference_text, false),
+                Reference::WikiHeadingLink(_, file, _)
+                | Reference::WikiIndexedBlockLink(_, file, _) => (file, true),
+                Reference::MDHeadingLink(_, file, _)
+                | Reference::MDIndexedBlockLink(_, file, _) => (file, false),
+                _ => return None,
+            };
+            if file.is_empty() {
+                return None;
+            }
+
+            let kind = match vault.is_embed(path, reference) {
+                true => GraphLinkKind::Embed,
+                false if is_wikilink => GraphLinkKind::Wiki,
+                false => GraphLinkKind::Markdown,
+            };
+
+            match existing_file(vault, path, file, is_wikilink) {
+                Some(target) if target == path => None,
+                Some(target) if !include_excluded && vault.is_excluded(&target) => None,
+                Some(target) => Some(GraphLink {
+                    target: get_obsidian_ref_path(vault.root_dir(), &target)?,
+                    kind,
+                    unresolved: false,
+                }),
+                None => Some(GraphLink {
+                    target: file.to_string(),
+                    kind,
+                    unresolved: true,
+                }),
+            }
+        })
+        .unique_by(|link| (link.target.clone(), link.kind))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vault::TestVault;
+
+    use super::{link_graph, GraphLink, GraphLinkKind};
+
+    fn link(target: &str, kind: GraphLinkKind, unresolved: bool) -> GraphLink {
+        GraphLink {
+            target: target.into(),
+            kind,
+            unresolved,
+        }
+    }
+
+    #[test]
+    fn test_link_graph() {
+        let test = TestVault::with_settings(
+            &[
+                (
+                    "projects/foo.md",
+                    "# Foo\n\n[[bar]] and [[bar#Heading]]\n\n[bar](../bar.md)\n\n![[bar]]\n\n[[missing]]\n\n[[#Foo]]\n\n[[templates/daily]]\n",
+                ),
+                ("bar.md", "# Heading\n\n[[projects/foo]]\n\n![[ideas]]\n"),
+                ("templates/daily.md", "[[bar]]\n"),
+            ],
+            |settings| settings.exclude = vec!["templates".into()],
+        );
+
+        let graph = link_graph(&test.vault, false);
+        assert_eq!(
+            graph.notes.keys().collect::<Vec<_>>(),
+            vec!["bar", "projects/foo"]
+        );
+        assert_eq!(
+            graph.notes["projects/foo"],
+            vec![
+                link("bar", GraphLinkKind::Wiki, false),
+                link("bar", GraphLinkKind::Markdown, false),
+                link("bar", GraphLinkKind::Embed, false),
+                link("missing", GraphLinkKind::Wiki, true),
+            ]
+        );
+        assert_eq!(
+            graph.notes["bar"],
+            vec![
+                link("projects/foo", GraphLinkKind::Wiki, false),
+                link("ideas", GraphLinkKind::Embed, true),
+            ]
+        );
+        assert_eq!(
+            graph.unresolved.iter().collect::<Vec<_>>(),
+            vec!["ideas", "missing"]
+        );
+
+        let graph = link_graph(&test.vault, true);
+        assert_eq!(
+            graph.notes["templates/daily"],
+            vec![link("bar", GraphLinkKind::Wiki, false)]
+        );
+        assert!(graph.notes["projects/foo"].contains(&link(
+            "templates/daily",
+            GraphLinkKind::Wiki,
+            false
+        )));
+    }
+}
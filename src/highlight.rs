@@ -0,0 +1,127 @@
+use std::path::Path;
+
+use tower_lsp::lsp_types::{DocumentHighlight, DocumentHighlightKind, Position};
+
+use crate::vault::{Referenceable, Vault};
+
+/// The links in the file to what is under the cursor, which is a link or a heading, block, tag or footnote. Headings,
+/// blocks and footnotes defined in the file are highlighted as writes, the links to them as reads.
+pub fn document_highlights(
+    vault: &Vault,
+    cursor_position: Position,
+    path: &Path,
+) -> Option<Vec<DocumentHighlight>> {
+    let referenceables = match (
+        vault.select_referenceable_at_position(path, cursor_position),
+        vault.select_reference_at_position(path, cursor_position),
+    ) {
+        // anywhere else in the file is the file itself, which would highlight nothing useful
+        (Some(Referenceable::File(..)), None) | (None, None) => return None,
+        (Some(referenceable @ Referenceable::Tag(..)), Some(_)) | (Some(referenceable), None) => {
+            vec![referenceable]
+        }
+        (_, Some(reference)) => vault.select_referenceables_for_reference(reference, path),
+    };
+
+    let definitions = referenceables
+        .iter()
+        .filter(|referenceable| {
+            referenceable.get_path() == path
+                && matches!(
+                    referenceable,
+                    Referenceable::Heading(..)
+                        | Referenceable::IndexedBlock(..)
+                        | Referenceable::Footnote(..)
+                        | Referenceable::LinkRefDef(..)
+                )
+        })
+        .filter_map(|referenceable| {
+            Some(DocumentHighlight {
+                range: *referenceable.get_range()?,
+                kind: Some(DocumentHighlightKind::WRITE),
+            })
+        });
+
+    let links = vault
+        .select_references(Some(path))?
+        .into_iter()
+        .filter(|(_, reference)| {
+            !vault.links_to_attachment(reference)
+                && referenceables.iter().any(|referenceable| {
+                    reference.references(
+                        vault.root_dir(),
+                        path,
+                        referenceable,
+                        vault.case_insensitive_links(),
+                    )
+                })
+        })
+        .map(|(_, reference)| DocumentHighlight {
+            range: *reference.data().range,
+            kind: Some(DocumentHighlightKind::READ),
+        });
+
+    Some(definitions.chain(links).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+    use tower_lsp::lsp_types::{DocumentHighlightKind, Position};
+
+    use crate::vault::TestVault;
+
+    use super::document_highlights;
+
+    #[test]
+    fn test_document_highlights() {
+        let test = TestVault::new(&[
+            (
+                "note.md",
+                "# Plan\n[[#Plan]] and [[other]] #tag\nsee [[other#Part]] [[other]] #tag\n",
+            ),
+            ("other.md", "## Part\n"),
+        ]);
+        let path = test.path("note.md");
+        let highlights = |line, character| {
+            document_highlights(&test.vault, Position::new(line, character), &path)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|highlight| {
+                    let range = highlight.range;
+                    let kind = match highlight.kind {
+                        Some(DocumentHighlightKind::WRITE) => "write",
+                        _ => "read",
+                    };
+                    (
+                        range.start.line,
+                        range.start.character,
+                        range.end.character,
+                        kind,
+                    )
+                })
+                .sorted()
+                .collect::<Vec<_>>()
+        };
+
+        // the heading is written, and read by the link to it in the same file, from either of them
+        let heading = vec![(0, 0, 6, "write"), (1, 0, 9, "read")];
+        assert_eq!(highlights(0, 3), heading);
+        assert_eq!(highlights(1, 3), heading);
+
+        // the links to another note, not those to its headings
+        assert_eq!(
+            highlights(1, 17),
+            vec![(1, 14, 23, "read"), (2, 19, 28, "read")]
+        );
+
+        // the tag, where it is used in the file
+        assert_eq!(
+            highlights(1, 26),
+            vec![(1, 24, 28, "read"), (2, 29, 33, "read")]
+        );
+
+        // elsewhere in the file, nothing
+        assert_eq!(highlights(1, 11), vec![]);
+    }
+}
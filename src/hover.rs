@@ -1,29 +1,102 @@
 use std::path::Path;
 
-use tower_lsp::lsp_types::{Hover, HoverContents, HoverParams};
+use tower_lsp::lsp_types::{Hover, HoverContents, HoverParams, MarkupContent};
 
 use crate::{
-    ui::{preview_reference, preview_referenceable},
-    vault::Vault,
+    config::Settings,
+    ui::{
+        preview_attachment, preview_embed, preview_reference, preview_referenceable, preview_tag,
+        preview_unresolved,
+    },
+    vault::{Reference, Referenceable, Vault},
 };
 
-pub fn hover(vault: &Vault, params: &HoverParams, path: &Path) -> Option<Hover> {
+pub fn hover(
+    vault: &Vault,
+    params: &HoverParams,
+    path: &Path,
+    settings: &Settings,
+) -> Option<Hover> {
     let cursor_position = params.text_document_position_params.position;
 
+    if let Some(attachment) = vault.select_attachment_at_position(path, cursor_position) {
+        return preview_attachment(vault, attachment).map(|markup| Hover {
+            contents: HoverContents::Markup(markup),
+            range: None,
+        });
+    }
+
     match (
         vault.select_reference_at_position(path, cursor_position),
         vault.select_referenceable_at_position(path, cursor_position),
     ) {
-        (Some(reference), _) => preview_reference(vault, path, reference).map(|markup| Hover {
-            contents: HoverContents::Markup(markup),
-            range: None,
-        }),
-        (None, Some(referenceable)) => {
-            preview_referenceable(vault, &referenceable).map(|markup| Hover {
+        (Some(Reference::Tag(data)), _) => {
+            let tag = data.reference_text.trim_start_matches('#');
+            preview_tag(vault, tag, settings.tag_hover_notes).map(|markup| Hover {
                 contents: HoverContents::Markup(markup),
                 range: None,
             })
         }
+        (Some(reference), _) => preview_unresolved(vault, path, reference)
+            // an embed of a heading or block previews all that it transcludes
+            .or_else(|| {
+                if !vault.is_embed(path, reference) {
+                    return None;
+                }
+
+                vault
+                    .select_referenceables_for_reference(reference, path)
+                    .first()
+                    .and_then(|referenceable| preview_embed(vault, referenceable))
+            })
+            .or_else(|| {
+                let preview = preview_reference(vault, path, reference)?;
+                let referenceables = vault.select_referenceables_for_reference(reference, path);
+                Some(with_backlink_count(
+                    vault,
+                    settings,
+                    referenceables.first(),
+                    preview,
+                ))
+            })
+            .map(|markup| Hover {
+                contents: HoverContents::Markup(markup),
+                range: None,
+            }),
+        (None, Some(referenceable)) => preview_referenceable(vault, &referenceable)
+            .map(|preview| with_backlink_count(vault, settings, Some(&referenceable), preview))
+            .map(|markup| Hover {
+                contents: HoverContents::Markup(markup),
+                range: None,
+            }),
         _ => None,
     }
 }
+
+/// The preview of a note, headed by the number of links to it from other files
+fn with_backlink_count(
+    vault: &Vault,
+    settings: &Settings,
+    referenceable: Option<&Referenceable>,
+    mut preview: MarkupContent,
+) -> MarkupContent {
+    let Some(referenceable @ Referenceable::File(path, _)) = referenceable else {
+        return preview;
+    };
+    if !settings.hover_backlink_count {
+        return preview;
+    }
+
+    let count = vault
+        .select_references_for_referenceable(referenceable)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(ref_path, _)| ref_path != path)
+        .count();
+
+    preview.value = match count {
+        1 => format!("📎 1 backlink\n\n{}", preview.value),
+        count => format!("📎 {} backlinks\n\n{}", count, preview.value),
+    };
+    preview
+}
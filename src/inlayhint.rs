@@ -0,0 +1,155 @@
+use std::path::Path;
+
+use tower_lsp::lsp_types::{InlayHint, InlayHintLabel, InlayHintParams};
+
+use crate::{
+    completion::block_display_text,
+    config::Settings,
+    vault::{heading_link_text, Reference, Referenceable, Vault},
+};
+
+/// A hint after each link to a heading or block in the range with what it links to: the first few words of a block, or
+/// the text of the heading when the link doesn't already say it, like for the slug of a markdown link. An embed of
+/// a heading also says how many lines of its section it transcludes. Links to whole files and unresolved links get
+/// none.
+pub fn inlay_hints(
+    vault: &Vault,
+    settings: &Settings,
+    path: &Path,
+    params: &InlayHintParams,
+) -> Option<Vec<InlayHint>> {
+    if !settings.link_inlay_hints {
+        return None;
+    }
+
+    let md_file = vault.md_files.get(path)?;
+    let range = params.range;
+
+    let hints = md_file
+        .references
+        .iter()
+        .filter(|reference| {
+            let reference_range = reference.data().range;
+            reference_range.start <= range.end && reference_range.end >= range.start
+        })
+        .filter_map(|reference| {
+            let infile_ref = match reference {
+                Reference::WikiHeadingLink(_, _, infile_ref)
+                | Reference::MDHeadingLink(_, _, infile_ref)
+                | Reference::WikiIndexedBlockLink(_, _, infile_ref)
+                | Reference::MDIndexedBlockLink(_, _, infile_ref) => infile_ref,
+                _ => return None,
+            };
+
+            let label = match vault
+                .select_referenceables_for_reference(reference, path)
+                .into_iter()
+                .find(|referenceable| !referenceable.is_unresolved())?
+            {
+                Referenceable::Heading(heading_path, heading)
+                    if vault.is_embed(path, reference) =>
+                {
+                    let lines = vault
+                        .select_heading_section(heading_path, heading)?
+                        .lines()
+                        .count();
+                    match heading.anchor.link_text != heading_link_text(infile_ref) {
+                        true => format!("{} ({} lines)", heading.anchor.link_text, lines),
+                        false => format!("{} lines", lines),
+                    }
+                }
+                Referenceable::Heading(_, heading)
+                    if heading.anchor.link_text != heading_link_text(infile_ref) =>
+                {
+                    heading.anchor.link_text.clone()
+                }
+                Referenceable::IndexedBlock(block_path, block) => {
+                    let line = vault.select_line(block_path, block.range.start.line as isize)?;
+                    let block_text = String::from_iter(line);
+                    block_display_text(
+                        block_text
+                            .trim()
+                            .trim_end_matches(&format!("^{}", block.index)),
+                    )?
+                }
+                _ => return None,
+            };
+
+            Some(InlayHint {
+                position: reference.data().range.end,
+                label: InlayHintLabel::String(label),
+                kind: None,
+                text_edits: None,
+                tooltip: None,
+                padding_left: Some(true),
+                padding_right: None,
+                data: None,
+            })
+        })
+        .collect();
+
+    Some(hints)
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+    use tower_lsp::lsp_types::{
+        InlayHintLabel, InlayHintParams, Position, Range, TextDocumentIdentifier, Url,
+    };
+
+    use crate::vault::TestVault;
+
+    use super::inlay_hints;
+
+    #[test]
+    fn test_inlay_hints() {
+        let files = [
+            (
+                "note.md",
+                "[[other#My *Heading*]] [[other#Plan]] [x](other.md#plan)\n[[other#^abc]] [[other]] [[missing#Plan]]\n![[other#Plan]]\n",
+            ),
+            (
+                "other.md",
+                "# My **Heading**\n\n# Plan\n\nfirst words of the block ^abc\n",
+            ),
+        ];
+        let hints = |test: &TestVault| {
+            let path = test.path("note.md");
+            let params = InlayHintParams {
+                text_document: TextDocumentIdentifier {
+                    uri: Url::from_file_path(&path).unwrap(),
+                },
+                range: Range {
+                    start: Position::new(0, 0),
+                    end: Position::new(3, 0),
+                },
+                work_done_progress_params: Default::default(),
+            };
+
+            inlay_hints(&test.vault, &test.settings, &path, &params).map(|hints| {
+                hints
+                    .into_iter()
+                    .map(|hint| match hint.label {
+                        InlayHintLabel::String(label) => (hint.position, label),
+                        InlayHintLabel::LabelParts(_) => panic!("expected a string label"),
+                    })
+                    .sorted()
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        // none for links that already say the heading, for file links and for unresolved links
+        assert_eq!(
+            hints(&TestVault::new(&files)),
+            Some(vec![
+                (Position::new(0, 56), "Plan".to_string()),
+                (Position::new(1, 14), "first words of the block".to_string()),
+                (Position::new(2, 15), "3 lines".to_string()),
+            ])
+        );
+
+        let test = TestVault::with_settings(&files, |settings| settings.link_inlay_hints = false);
+        assert_eq!(hints(&test), None);
+    }
+}
@@ -1,11 +1,15 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::ops::{Deref, DerefMut};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
-use completion::get_completions;
+use completion::{
+    get_completions, resolve_link_completion, CancelOnDrop, CancelToken, RecentFiles,
+};
 use config::Settings;
+use debounce::Debounce;
 use diagnostics::diagnostics;
 use itertools::Itertools;
 use rayon::prelude::*;
@@ -21,27 +25,51 @@ use tower_lsp::lsp_types::*;
 use tower_lsp::{Client, LanguageServer, LspService, Server};
 use vault::Vault;
 
+mod backlinks;
 mod codeactions;
 mod codelens;
 mod completion;
 mod config;
+mod daily;
+mod debounce;
 mod diagnostics;
+mod documentlink;
+mod extract;
+mod folding;
+mod formatting;
 mod gotodef;
+mod graph;
+mod highlight;
 mod hover;
+mod inlayhint;
 mod macros;
+mod mentions;
 mod references;
 mod rename;
+mod resolve;
+mod selection;
 mod symbol;
+mod taghierarchy;
 mod tokens;
 mod ui;
 mod vault;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct Backend {
     client: Client,
-    vault: Arc<RwLock<Option<Vault>>>,
+    /// A vault for each workspace folder
+    vaults: Arc<RwLock<Vec<Vault>>>,
     opened_files: Arc<RwLock<HashSet<PathBuf>>>,
-    settings: Arc<RwLock<Option<Settings>>>,
+    /// When each file was last opened or edited, for ranking recent files in link completions
+    recent_files: Arc<RwLock<RecentFiles>>,
+    /// The settings of each vault, by its root
+    settings: Arc<RwLock<HashMap<PathBuf, Settings>>>,
+    /// The old and new paths of renames whose links were edited by `willRenameFiles`, before the files were moved
+    renamed_links: Arc<RwLock<HashSet<(PathBuf, PathBuf)>>>,
+    /// Cancels the completion request in flight
+    completion_cancel: Arc<RwLock<CancelToken>>,
+    /// Publishes diagnostics and refreshes the client's semantic tokens and inlay hints once edits pause
+    refresh: Debounce,
 }
 
 struct TextDocumentItem {
@@ -62,14 +90,20 @@ impl Backend {
             return;
         };
 
-        let Ok(settings) = self.bind_settings(|settings| Ok(settings.clone())).await else {
+        let Ok(settings) = self
+            .bind_settings(&path, |settings| Ok(settings.clone()))
+            .await
+        else {
             return;
         };
 
+        let timer = std::time::Instant::now();
+
+        // the file is reparsed on every edit, unlike the refreshes, as completions read the vault as it is typed in;
+        // reparsing one file takes well under a millisecond
         let guard = self
-            .bind_vault_mut(|vault| {
-                let text = &params.text;
-                Vault::update_vault(&settings, vault, (&path, text));
+            .bind_vault_mut(&path, |vault| {
+                vault.update_file(&settings, &path, &params.text);
 
                 Ok(())
             })
@@ -77,52 +111,66 @@ impl Backend {
         drop(guard);
 
         self.client
-            .log_message(MessageType::WARNING, "Update Vault Done")
+            .log_message(
+                MessageType::WARNING,
+                format!("Update Vault Done in {}ms", timer.elapsed().as_millis()),
+            )
             .await;
 
-        match self.publish_diagnostics().await {
-            Ok(_) => (),
-            Err(e) => {
-                self.client
-                    .log_message(
-                        MessageType::ERROR,
-                        format!("Failed calculating diagnostics on vault update {:?}", e),
-                    )
-                    .await
-            }
-        }
-
-        if settings.semantic_tokens {
-            let _ = self.client.semantic_tokens_refresh().await;
-        }
+        self.debounce_refresh(&settings);
     }
 
-    async fn reconstruct_vault(&self) {
+    /// Index the vault off of the async runtime, reporting `$/progress` to the client as files are parsed. Each vault
+    /// reports with its own token, so the progress of vaults indexed at once isn't mixed up. The folders of
+    /// `nested_roots`, the other vaults inside this one, are left out.
+    async fn index_vault(
+        &self,
+        settings: &Settings,
+        root_dir: &Path,
+        nested_roots: &[PathBuf],
+    ) -> Option<Vault> {
+        let token = ProgressToken::String(format!("moxide/index/{}", root_dir.display()));
         let progress = self
             .client
-            .progress(ProgressToken::Number(1), "Constructing Vault")
+            .progress(token, "Indexing Vault")
+            .with_percentage(0)
             .begin()
             .await;
 
         let timer = std::time::Instant::now();
 
-        let Ok(settings) = self.bind_settings(|settings| Ok(settings.clone())).await else {
-            return;
+        // (indexed, total)
+        let counts = Arc::new((AtomicUsize::new(0), AtomicUsize::new(0)));
+        let mut task = {
+            let settings = settings.clone();
+            let root_dir = root_dir.to_path_buf();
+            let nested_roots = nested_roots.to_vec();
+            let counts = counts.clone();
+            tokio::task::spawn_blocking(move || {
+                Vault::construct_vault(&settings, &root_dir, &nested_roots, |indexed, total| {
+                    counts.0.fetch_max(indexed, Ordering::Relaxed);
+                    counts.1.store(total, Ordering::Relaxed);
+                })
+            })
         };
 
-        {
-            let _ = self
-                .bind_vault_mut(|vault| {
-                    let Ok(new_vault) = Vault::construct_vault(&settings, vault.root_dir()) else {
-                        return Err(Error::new(ErrorCode::ServerError(0)));
-                    };
-
-                    *vault = new_vault;
-
-                    Ok(())
-                })
-                .await;
-        }
+        let vault = loop {
+            tokio::select! {
+                result = &mut task => break result.ok().and_then(|vault| vault.ok()),
+                _ = tokio::time::sleep(std::time::Duration::from_millis(100)) => {
+                    let indexed = counts.0.load(Ordering::Relaxed);
+                    let total = counts.1.load(Ordering::Relaxed);
+                    if total > 0 {
+                        progress
+                            .report_with_message(
+                                format!("{}/{} notes", indexed, total),
+                                (indexed * 100 / total) as u32,
+                            )
+                            .await;
+                    }
+                }
+            }
+        };
 
         let elapsed = timer.elapsed();
 
@@ -139,6 +187,40 @@ impl Backend {
                 .await;
         }
 
+        vault
+    }
+
+    /// Index the vault the file is in again
+    async fn reconstruct_vault(&self, path: &Path) {
+        let Ok(settings) = self
+            .bind_settings(path, |settings| Ok(settings.clone()))
+            .await
+        else {
+            return;
+        };
+        let Ok(root_dir) = self
+            .bind_vault(path, |vault| Ok(vault.root_dir().clone()))
+            .await
+        else {
+            return;
+        };
+
+        let root_dirs = self.settings.read().await.keys().cloned().collect_vec();
+
+        // the vault is only locked to swap in the new one, not while it is being indexed
+        let Some(new_vault) = self
+            .index_vault(&settings, &root_dir, &nested_roots(&root_dirs, &root_dir))
+            .await
+        else {
+            return;
+        };
+        {
+            let mut vaults = self.vaults.write().await;
+            if let Some(index) = vault_index(&vaults, &root_dir) {
+                vaults[index] = new_vault;
+            }
+        }
+
         match self.publish_diagnostics().await {
             Ok(_) => (),
             Err(e) => {
@@ -157,6 +239,136 @@ impl Backend {
         let _ = self.client.semantic_tokens_refresh().await;
     }
 
+    /// Open the daily note `offset` days from today, creating it from the daily note template if it doesn't exist.
+    /// The note is in the vault of the first workspace folder. Returns the path of the note.
+    async fn open_daily_note(&self, offset: i64) -> Result<Option<Value>> {
+        let root_dir = self.first_root_dir().await?;
+        let settings = self
+            .bind_settings(&root_dir, |settings| Ok(settings.clone()))
+            .await?;
+
+        let date = chrono::Duration::try_days(offset)
+            .and_then(|offset| chrono::Local::now().date_naive().checked_add_signed(offset))
+            .ok_or_else(|| Error::invalid_params("Daily note offset is out of range"))?;
+        let path = daily::dailynote_path(
+            date,
+            &root_dir,
+            settings.dailynote_format(),
+            settings.daily_note_folder.as_deref(),
+        );
+        let uri = Url::from_file_path(&path)
+            .map_err(|_| Error::invalid_params("Daily note path is not a valid uri"))?;
+
+        if !path.exists() {
+            let refname = daily::dailynote_refname(date, settings.dailynote_format());
+
+            if let Some(edit) = daily::create_dailynote_edit(
+                &path,
+                &refname,
+                &root_dir,
+                settings.dailynote_template.as_deref(),
+            ) {
+                let _ = self.client.apply_edit(edit).await;
+            }
+        }
+
+        let _ = self
+            .client
+            .show_document(ShowDocumentParams {
+                uri,
+                external: None,
+                take_focus: Some(true),
+                selection: None,
+            })
+            .await;
+
+        Ok(Some(Value::String(path.to_string_lossy().into())))
+    }
+
+    /// The custom `markdown-oxide/backlinks` request: the links to a note, grouped by the file they are in
+    async fn backlinks(
+        &self,
+        params: backlinks::BacklinksParams,
+    ) -> Result<Option<Vec<backlinks::FileBacklinks>>> {
+        let path = params_path!(params)?;
+        self.bind_vault(&path, |vault| Ok(backlinks::backlinks(vault, &path)))
+            .await
+    }
+
+    /// The custom `markdown-oxide/resolveLink` request: where the link under the cursor goes, for clients that
+    /// navigate on their own
+    async fn resolve_link(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> Result<Option<resolve::ResolvedLink>> {
+        let path = params_path!(params)?;
+        let settings = self
+            .bind_settings(&path, |settings| Ok(settings.clone()))
+            .await?;
+        self.bind_vault(&path, |vault| {
+            Ok(resolve::resolve_link(
+                vault,
+                &settings,
+                params.position,
+                &path,
+            ))
+        })
+        .await
+    }
+
+    /// The custom `markdown-oxide/unlinkedMentions` request: where a note's name or aliases are written as plain text
+    /// in other notes, for linking them
+    async fn unlinked_mentions(
+        &self,
+        params: mentions::UnlinkedMentionsParams,
+    ) -> Result<Option<Vec<mentions::FileMentions>>> {
+        let path = params_path!(params)?;
+        self.bind_vault(&path, |vault| Ok(mentions::unlinked_mentions(vault, &path)))
+            .await
+    }
+
+    /// The custom `markdown-oxide/tagHierarchy` request: the parent and children of a nested tag, for tag tree views
+    async fn tag_hierarchy(
+        &self,
+        params: taghierarchy::TagHierarchyParams,
+    ) -> Result<Option<taghierarchy::TagHierarchy>> {
+        let path = params_path!(params)?;
+        self.bind_vault(&path, |vault| {
+            Ok(taghierarchy::tag_hierarchy(vault, params.tag.as_deref()))
+        })
+        .await
+    }
+
+    /// Publish the diagnostics and refresh the semantic tokens and inlay hints once there have been no edits for
+    /// `diagnostics_debounce` milliseconds, rather than on every keystroke, for the latest text
+    fn debounce_refresh(&self, settings: &Settings) {
+        let backend = self.clone();
+        let (semantic_tokens, link_inlay_hints) =
+            (settings.semantic_tokens, settings.link_inlay_hints);
+
+        let delay = std::time::Duration::from_millis(settings.diagnostics_debounce);
+        self.refresh.run(delay, async move {
+            if let Err(e) = backend.publish_diagnostics().await {
+                backend
+                    .client
+                    .log_message(
+                        MessageType::ERROR,
+                        format!("Failed calculating diagnostics on vault update {:?}", e),
+                    )
+                    .await
+            }
+
+            if semantic_tokens {
+                let _ = backend.client.semantic_tokens_refresh().await;
+            }
+
+            // the blocks and headings linked to may have changed in other files
+            if link_inlay_hints {
+                let _ = backend.client.inlay_hint_refresh().await;
+            }
+        });
+    }
+
     async fn publish_diagnostics(&self) -> Result<()> {
         let timer = std::time::Instant::now();
 
@@ -173,17 +385,18 @@ impl Backend {
             })
             .await?;
 
-        let settings = self.bind_settings(|settings| Ok(settings.clone())).await?;
+        let settings = self.settings.read().await.clone();
 
         let diagnostics = self
-            .bind_vault(|vault| {
+            .bind_vaults(|vaults| {
                 Ok(uris
                     .par_iter()
                     .filter_map(|uri| {
                         let path = uri.to_file_path().ok()?;
+                        let vault = &vaults[vault_index(vaults, &path)?];
+                        let settings = settings.get(vault.root_dir())?;
 
-                        diagnostics(vault, &settings, (&path, uri))
-                            .map(|diags| (uri.clone(), diags))
+                        diagnostics(vault, settings, (&path, uri)).map(|diags| (uri.clone(), diags))
                     })
                     .collect::<Vec<_>>())
             })
@@ -218,17 +431,54 @@ impl Backend {
     /// TODO: Hopefully rust async closures will be more convienient to use eventually and this can accept an async closure; this would enable better logging
     /// in the call back functions. (though to get aroudn this, the callback could return a Result of a writer style monad, which could be logged async outside of
     /// the callback)
-    async fn bind_vault<T>(&self, callback: impl FnOnce(&Vault) -> Result<T>) -> Result<T> {
-        let guard = self.vault.read().await;
-        let Some(vault) = guard.deref() else {
+    ///
+    /// The vault is the one the file at `path` is in.
+    async fn bind_vault<T>(
+        &self,
+        path: &Path,
+        callback: impl FnOnce(&Vault) -> Result<T>,
+    ) -> Result<T> {
+        let guard = self.vaults.read().await;
+        let Some(index) = vault_index(&guard, path) else {
             return Err(Error::new(ErrorCode::ServerError(0)));
         };
 
-        callback(vault)
+        callback(&guard[index])
     }
 
-    async fn bind_vault_mut<T>(&self, callback: impl Fn(&mut Vault) -> Result<T>) -> Result<T> {
-        if let Err(e) = self.vault.try_write() {
+    /// Record that the file was opened or edited now
+    async fn touch_recent_file(&self, uri: &Url) {
+        if let Ok(path) = uri.to_file_path() {
+            self.recent_files
+                .write()
+                .await
+                .insert(path, std::time::SystemTime::now());
+        }
+    }
+
+    /// The root of the vault of the first workspace folder, for requests that aren't about a file
+    async fn first_root_dir(&self) -> Result<PathBuf> {
+        self.bind_vaults(|vaults| {
+            let vault = vaults
+                .first()
+                .ok_or(Error::new(ErrorCode::ServerError(0)))?;
+            Ok(vault.root_dir().clone())
+        })
+        .await
+    }
+
+    /// Like `bind_vault`, for requests about every vault in the workspace
+    async fn bind_vaults<T>(&self, callback: impl FnOnce(&[Vault]) -> Result<T>) -> Result<T> {
+        let guard = self.vaults.read().await;
+        callback(&guard)
+    }
+
+    async fn bind_vault_mut<T>(
+        &self,
+        path: &Path,
+        callback: impl Fn(&mut Vault) -> Result<T>,
+    ) -> Result<T> {
+        if let Err(e) = self.vaults.try_write() {
             self.client
                 .log_message(
                     MessageType::ERROR,
@@ -241,17 +491,25 @@ impl Backend {
                 .await;
         }
 
-        let mut guard = self.vault.write().await;
-        let Some(ref mut vault) = *guard else {
+        let mut guard = self.vaults.write().await;
+        let Some(index) = vault_index(&guard, path) else {
             return Err(Error::new(ErrorCode::ServerError(0)));
         };
 
-        callback(vault)
+        callback(&mut guard[index])
     }
 
-    async fn bind_settings<T>(&self, callback: impl FnOnce(&Settings) -> Result<T>) -> Result<T> {
+    /// The settings of the vault the file at `path` is in
+    async fn bind_settings<T>(
+        &self,
+        path: &Path,
+        callback: impl FnOnce(&Settings) -> Result<T>,
+    ) -> Result<T> {
+        let root_dir = self
+            .bind_vault(path, |vault| Ok(vault.root_dir().clone()))
+            .await?;
         let guard = self.settings.read().await;
-        let Some(settings) = guard.deref() else {
+        let Some(settings) = guard.get(&root_dir) else {
             return Err(Error::new(ErrorCode::ServerError(1)));
         };
 
@@ -291,33 +549,74 @@ impl Backend {
 #[tower_lsp::async_trait]
 impl LanguageServer for Backend {
     async fn initialize(&self, i: InitializeParams) -> Result<InitializeResult> {
-
-        let root_dir = match i.root_uri {
-            Some(uri) => uri.to_file_path().or(Err(Error::new(ErrorCode::InvalidParams)))?,
-            None => std::env::current_dir().or(Err(Error::new(ErrorCode::InvalidParams)))?,
+        // each workspace folder is its own vault, with its own settings
+        let root_dirs = match (i.workspace_folders, i.root_uri) {
+            (Some(folders), _) if !folders.is_empty() => folders
+                .iter()
+                .map(|folder| folder.uri.to_file_path())
+                .collect::<std::result::Result<Vec<_>, _>>()
+                .or(Err(Error::new(ErrorCode::InvalidParams)))?,
+            (_, Some(uri)) => {
+                vec![uri
+                    .to_file_path()
+                    .or(Err(Error::new(ErrorCode::InvalidParams)))?]
+            }
+            _ => vec![std::env::current_dir().or(Err(Error::new(ErrorCode::InvalidParams)))?],
         };
 
-        let read_settings = match Settings::new(&root_dir, &i.capabilities) {
-            Ok(settings) => settings,
-            Err(e) => {
+        // a folder whose settings can't be read or that can't be indexed is left out, rather than failing the others
+        for root_dir in &root_dirs {
+            let read_settings = match Settings::new(root_dir, &i.capabilities) {
+                Ok(settings) => settings,
+                Err(e) => {
+                    self.client
+                        .log_message(
+                            MessageType::ERROR,
+                            format!("Failed to read settings of {} {:?}", root_dir.display(), e),
+                        )
+                        .await;
+                    continue;
+                }
+            };
+
+            let Some(vault) = self
+                .index_vault(
+                    &read_settings,
+                    root_dir,
+                    &nested_roots(&root_dirs, root_dir),
+                )
+                .await
+            else {
                 self.client
                     .log_message(
                         MessageType::ERROR,
-                        format!("Failed to read settings {:?}", e),
+                        format!("Failed to index {}", root_dir.display()),
                     )
                     .await;
-                return Err(Error::new(ErrorCode::ServerError(1)));
-            }
-        };
+                continue;
+            };
+            self.vaults.write().await.push(vault);
+            self.settings
+                .write()
+                .await
+                .insert(root_dir.clone(), read_settings);
+        }
 
-        let Ok(vault) = Vault::construct_vault(&read_settings, &root_dir) else {
+        if self.vaults.read().await.is_empty() {
             return Err(Error::new(ErrorCode::ServerError(0)));
-        };
-        let mut value = self.vault.write().await;
-        *value = Some(vault);
+        }
 
-        let mut settings = self.settings.write().await;
-        *settings = Some(read_settings);
+        // documents are only formatted to rewrite their links, so other formatters are left alone without a link style,
+        // and ranges only when their links are to be tidied
+        let (link_formatting, range_formatting) = {
+            let settings = self.settings.read().await;
+            (
+                settings
+                    .values()
+                    .any(|settings| settings.link_style.is_some()),
+                settings.values().any(|settings| settings.range_formatting),
+            )
+        };
 
         let file_op_reg = FileOperationRegistrationOptions {
             filters: std::iter::once(FileOperationFilter {
@@ -330,6 +629,22 @@ impl LanguageServer for Backend {
             })
             .collect(),
         };
+        // renaming a folder moves the notes in it
+        let rename_op_reg = FileOperationRegistrationOptions {
+            filters: file_op_reg
+                .filters
+                .iter()
+                .cloned()
+                .chain(std::iter::once(FileOperationFilter {
+                    pattern: FileOperationPattern {
+                        options: None,
+                        glob: "**/*".into(),
+                        matches: Some(FileOperationPatternKind::Folder),
+                    },
+                    ..Default::default()
+                }))
+                .collect(),
+        };
 
         return Ok(InitializeResult {
             server_info: None,
@@ -338,13 +653,16 @@ impl LanguageServer for Backend {
                     TextDocumentSyncKind::FULL,
                 )),
                 completion_provider: Some(CompletionOptions {
-                    resolve_provider: Some(false),
+                    resolve_provider: Some(true),
                     trigger_characters: Some(vec![
                         "[".into(),
                         " ".into(),
                         "(".into(),
                         "#".into(),
+                        "^".into(),
+                        "|".into(),
                         ">".into(),
+                        "!".into(),
                     ]),
                     work_done_progress_options: Default::default(),
                     all_commit_characters: None,
@@ -353,25 +671,48 @@ impl LanguageServer for Backend {
                 // definition: Some(GotoCapability::default()),
                 definition_provider: Some(OneOf::Left(true)),
                 references_provider: Some(OneOf::Left(true)),
-                rename_provider: Some(OneOf::Left(true)),
+                document_highlight_provider: Some(OneOf::Left(true)),
+                rename_provider: Some(OneOf::Right(RenameOptions {
+                    prepare_provider: Some(true),
+                    work_done_progress_options: Default::default(),
+                })),
                 hover_provider: Some(HoverProviderCapability::Simple(true)),
+                selection_range_provider: Some(SelectionRangeProviderCapability::Simple(true)),
+                folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
                 document_symbol_provider: Some(OneOf::Left(true)),
                 workspace_symbol_provider: Some(OneOf::Left(true)),
                 code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+                document_formatting_provider: link_formatting.then_some(OneOf::Left(true)),
+                document_range_formatting_provider: range_formatting.then_some(OneOf::Left(true)),
                 workspace: Some(WorkspaceServerCapabilities {
+                    workspace_folders: Some(WorkspaceFoldersServerCapabilities {
+                        supported: Some(true),
+                        change_notifications: None,
+                    }),
                     file_operations: Some(WorkspaceFileOperationsServerCapabilities {
                         did_create: Some(file_op_reg.clone()),
-                        did_rename: Some(file_op_reg.clone()),
+                        will_rename: Some(rename_op_reg.clone()),
+                        did_rename: Some(rename_op_reg),
                         did_delete: Some(file_op_reg.clone()),
                         ..Default::default()
                     }),
-                    ..Default::default()
                 }),
                 code_lens_provider: Some(CodeLensOptions {
-                    resolve_provider: None,
+                    resolve_provider: Some(true),
+                }),
+                inlay_hint_provider: Some(OneOf::Left(true)),
+                document_link_provider: Some(DocumentLinkOptions {
+                    resolve_provider: Some(false),
+                    work_done_progress_options: Default::default(),
                 }),
                 execute_command_provider: Some(ExecuteCommandOptions {
-                    commands: vec!["apply_edits".into()],
+                    commands: vec![
+                        "apply_edits".into(),
+                        daily::OPEN_DAILY_NOTE_COMMAND.into(),
+                        resolve::CREATE_NOTE_COMMAND.into(),
+                        extract::EXTRACT_NOTE_COMMAND.into(),
+                        graph::EXPORT_GRAPH_COMMAND.into(),
+                    ],
                     ..Default::default()
                 }),
                 semantic_tokens_provider: Some(
@@ -406,30 +747,35 @@ impl LanguageServer for Backend {
     async fn code_lens(&self, params: CodeLensParams) -> Result<Option<Vec<CodeLens>>> {
         let path = params_path!(params)?;
 
-        self.bind_vault(|vault| Ok(codelens::code_lens(vault, &path, &params)))
-            .await
+        self.bind_vault(&path, |vault| {
+            Ok(codelens::code_lens(vault, &path, &params))
+        })
+        .await
+    }
+
+    async fn code_lens_resolve(&self, params: CodeLens) -> Result<CodeLens> {
+        let path = codelens::lens_path(&params)
+            .ok_or_else(|| Error::invalid_params("Code lens has no file"))?;
+
+        self.bind_vault(&path, |vault| {
+            Ok(codelens::resolve_code_lens(vault, params))
+        })
+        .await
     }
 
     async fn initialized(&self, _: InitializedParams) {
-        let settings = self
-            .bind_settings(|settings| Ok(settings.clone()))
-            .await
-            .unwrap();
+        let settings = self.settings.read().await.clone();
         self.client
             .log_message(MessageType::WARNING, format!("Settings: {:?}", settings))
             .await;
 
-        let Ok(root_path) = self.bind_vault(|vault| Ok(vault.root_dir().clone())).await else {
-            return;
-        };
-
-        let Ok(_root_uri) = Url::from_directory_path(root_path) else {
+        if self.vaults.read().await.is_empty() {
             return;
-        };
+        }
 
         let value = serde_json::to_value(DidChangeWatchedFilesRegistrationOptions {
             watchers: vec![FileSystemWatcher {
-                glob_pattern: GlobPattern::String("**/*.md".into()),
+                glob_pattern: GlobPattern::String("**/*".into()),
                 kind: None,
             }],
         })
@@ -464,6 +810,8 @@ impl LanguageServer for Backend {
                 .log_message(MessageType::LOG, "Added file")
                 .await;
 
+            self.touch_recent_file(&params.text_document.uri).await;
+
             self.update_vault(TextDocumentItem {
                 uri: params.text_document.uri,
                 text: params.text_document.text,
@@ -501,6 +849,8 @@ impl LanguageServer for Backend {
     }
 
     async fn did_change(&self, mut params: DidChangeTextDocumentParams) {
+        self.touch_recent_file(&params.text_document.uri).await;
+
         self.update_vault(TextDocumentItem {
             uri: params.text_document.uri,
             text: params.content_changes.remove(0).text,
@@ -508,16 +858,167 @@ impl LanguageServer for Backend {
         .await;
     }
 
-    async fn did_change_watched_files(&self, _params: DidChangeWatchedFilesParams) {
-        self.reconstruct_vault().await
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        // only the changed files are reparsed; the whole vault is reconstructed if one of them can't be read
+        let timer = std::time::Instant::now();
+        let mut changed = false;
+        for change in &params.changes {
+            let Ok(path) = change.uri.to_file_path() else {
+                let root_dirs = self.bind_vaults(|vaults| {
+                    Ok(vaults
+                        .iter()
+                        .map(|vault| vault.root_dir().clone())
+                        .collect_vec())
+                });
+                for root_dir in root_dirs.await.unwrap_or_default() {
+                    self.reconstruct_vault(&root_dir).await;
+                }
+                return;
+            };
+
+            // the watcher reports every file of the workspace, like those in `.git`, which the vault doesn't index
+            let indexed = self.bind_vault(&path, |vault| Ok(vault.is_indexed(&path)));
+            if !indexed.await.unwrap_or(false) {
+                continue;
+            }
+
+            // a deleted folder is reported by its path alone
+            if change.typ == FileChangeType::DELETED {
+                let removed = self
+                    .bind_vault_mut(&path, |vault| Ok(vault.remove_file(&path)))
+                    .await;
+                changed |= removed.unwrap_or(false);
+                continue;
+            }
+
+            // attachments, like images, are only indexed by their path
+            if path.extension().and_then(|ext| ext.to_str()) != Some("md") {
+                if path.is_file() {
+                    let _ = self
+                        .bind_vault_mut(&path, |vault| {
+                            vault.add_attachment(&path);
+                            Ok(())
+                        })
+                        .await;
+                }
+                continue;
+            }
+
+            let Ok(text) = std::fs::read_to_string(&path) else {
+                return self.reconstruct_vault(&path).await;
+            };
+            let Ok(settings) = self
+                .bind_settings(&path, |settings| Ok(settings.clone()))
+                .await
+            else {
+                continue;
+            };
+
+            let updated = self
+                .bind_vault_mut(&path, |vault| {
+                    vault.update_file(&settings, &path, &text);
+                    Ok(())
+                })
+                .await;
+            changed |= updated.is_ok();
+        }
+
+        self.client
+            .log_message(
+                MessageType::WARNING,
+                format!(
+                    "Updated {} watched files in {}ms",
+                    params.changes.len(),
+                    timer.elapsed().as_millis()
+                ),
+            )
+            .await;
+
+        // the diagnostics are published once for all of the changes, like the files of a folder that was moved
+        if changed {
+            let _ = self.publish_diagnostics().await;
+        }
+    }
+
+    /// The links are edited before the files are moved, so that the edit and the move are applied together.
+    /// Files are renamed within one vault; its links are the ones edited.
+    async fn will_rename_files(&self, params: RenameFilesParams) -> Result<Option<WorkspaceEdit>> {
+        let renames = rename::file_renames(&params.files);
+        let Some((old_path, _)) = renames.first().cloned() else {
+            return Ok(None);
+        };
+        let settings = self
+            .bind_settings(&old_path, |settings| Ok(settings.clone()))
+            .await?;
+
+        let edit = self
+            .bind_vault(&old_path, |vault| {
+                Ok(rename::rename_files(vault, &settings, &renames, false))
+            })
+            .await?;
+        self.renamed_links.write().await.extend(renames);
+
+        Ok(edit)
+    }
+
+    async fn did_rename_files(&self, params: RenameFilesParams) {
+        let renames = rename::file_renames(&params.files);
+        let Some((old_path, _)) = renames.first().cloned() else {
+            return;
+        };
+
+        let Ok(settings) = self
+            .bind_settings(&old_path, |settings| Ok(settings.clone()))
+            .await
+        else {
+            return;
+        };
+
+        // renames whose links were already edited in `will_rename_files` aren't edited twice
+        let unedited = {
+            let mut renamed_links = self.renamed_links.write().await;
+            renames
+                .iter()
+                .filter(|rename| !renamed_links.remove(*rename))
+                .cloned()
+                .collect_vec()
+        };
+
+        // the links are found with the files at their old paths, before the vault is updated
+        let edit = self
+            .bind_vault(&old_path, |vault| {
+                Ok(rename::rename_files(vault, &settings, &unedited, true))
+            })
+            .await;
+        let _ = self
+            .bind_vault_mut(&old_path, |vault| {
+                vault.rename_files(&settings, &renames);
+                Ok(())
+            })
+            .await;
+        let _ = self
+            .bind_opened_files_mut(|files| {
+                *files = files
+                    .drain()
+                    .map(|path| vault::moved_path(&path, &renames).unwrap_or(path))
+                    .collect();
+                Ok(())
+            })
+            .await;
+
+        if let Ok(Some(edit)) = edit {
+            let _ = self.client.apply_edit(edit).await;
+        }
+
+        let _ = self.publish_diagnostics().await;
     }
 
     async fn goto_definition(
         &self,
         params: GotoDefinitionParams,
     ) -> Result<Option<GotoDefinitionResponse>> {
-        self.bind_vault(|vault| {
-            let path = params_path!(params.text_document_position_params)?;
+        let path = params_path!(params.text_document_position_params)?;
+        self.bind_vault(&path, |vault| {
             Ok(
                 goto_definition(vault, params.text_document_position_params.position, &path)
                     .map(GotoDefinitionResponse::Array),
@@ -527,8 +1028,8 @@ impl LanguageServer for Backend {
     }
 
     async fn references(&self, params: ReferenceParams) -> Result<Option<Vec<Location>>> {
-        self.bind_vault(|vault| {
-            let path = params_position_path!(params)?;
+        let path = params_position_path!(params)?;
+        self.bind_vault(&path, |vault| {
             Ok(references(
                 vault,
                 params.text_document_position.position,
@@ -538,6 +1039,21 @@ impl LanguageServer for Backend {
         .await
     }
 
+    async fn document_highlight(
+        &self,
+        params: DocumentHighlightParams,
+    ) -> Result<Option<Vec<DocumentHighlight>>> {
+        let path = params_path!(params.text_document_position_params)?;
+        self.bind_vault(&path, |vault| {
+            Ok(highlight::document_highlights(
+                vault,
+                params.text_document_position_params.position,
+                &path,
+            ))
+        })
+        .await
+    }
+
     async fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
         self.client
             .log_message(MessageType::WARNING, "Completions Started")
@@ -549,27 +1065,76 @@ impl LanguageServer for Backend {
         let files = self
             .bind_opened_files(|files| Ok(files.clone().into_iter().collect::<Box<[_]>>()))
             .await?;
+        let recent_files = self.recent_files.read().await.clone();
 
-        let Ok(settings) = self.bind_settings(|settings| Ok(settings.to_owned())).await else {
+        let Ok(settings) = self
+            .bind_settings(&path, |settings| Ok(settings.to_owned()))
+            .await
+        else {
             return Err(Error::new(ErrorCode::ServerError(2)));
         }; // TODO: this is bad
 
-        let res = self
-            .bind_vault(|vault| Ok(get_completions(vault, &files, &params, &path, &settings)))
-            .await;
+        // a newer completion request makes the one in flight stale, and tower-lsp drops the future of a request the
+        // client cancels with `$/cancelRequest`; either way the completers stop early
+        let cancel = CancelToken::default();
+        let stale = std::mem::replace(&mut *self.completion_cancel.write().await, cancel.clone());
+        stale.cancel();
+        let _cancel_on_drop = CancelOnDrop(cancel.clone());
+
+        // computed off the async runtime, so that the future can be dropped while the completers run
+        let vaults = self.vaults.clone();
+        let task_cancel = cancel.clone();
+        let res = tokio::task::spawn_blocking(move || {
+            let guard = vaults.blocking_read();
+            let Some(index) = vault_index(&guard, &path) else {
+                return Err(Error::new(ErrorCode::ServerError(0)));
+            };
+
+            Ok(get_completions(
+                &guard[index],
+                &files,
+                &recent_files,
+                &params,
+                &path,
+                &settings,
+                &task_cancel,
+            ))
+        })
+        .await
+        .map_err(|_| Error::internal_error())?;
 
         let elapsed = timer.elapsed();
 
         self.client
             .log_message(
                 MessageType::WARNING,
-                format!("Completions Done took {}ms", elapsed.as_millis()),
+                match cancel.is_cancelled() {
+                    true => format!("Completions Cancelled after {}ms", elapsed.as_millis()),
+                    false => format!("Completions Done took {}ms", elapsed.as_millis()),
+                },
             )
             .await;
 
+        // the client doesn't want the result of a cancelled or stale request, and the completers stopped partway
+        if cancel.is_cancelled() {
+            return Err(Error {
+                code: ErrorCode::RequestCancelled,
+                message: "Completions cancelled".into(),
+                data: None,
+            });
+        }
+
         res
     }
 
+    async fn completion_resolve(&self, params: CompletionItem) -> Result<CompletionItem> {
+        let Some(path) = data_path(params.data.as_ref()) else {
+            return Ok(params);
+        };
+        self.bind_vault(&path, |vault| Ok(resolve_link_completion(vault, params)))
+            .await
+    }
+
     async fn execute_command(&self, params: ExecuteCommandParams) -> Result<Option<Value>> {
         match params {
             ExecuteCommandParams { command, .. } if *command == *"apply_edits" => {
@@ -585,14 +1150,106 @@ impl LanguageServer for Backend {
 
                 Ok(None)
             }
+            ExecuteCommandParams { command, .. } if *command == *daily::OPEN_DAILY_NOTE_COMMAND => {
+                let offset = params
+                    .arguments
+                    .first()
+                    .and_then(|arg| arg.get("offset"))
+                    .and_then(|offset| offset.as_i64())
+                    .unwrap_or(0);
+
+                self.open_daily_note(offset).await
+            }
+            ExecuteCommandParams { command, .. } if *command == *resolve::CREATE_NOTE_COMMAND => {
+                let edits = params
+                    .arguments
+                    .into_iter()
+                    .filter_map(|arg| serde_json::from_value::<resolve::CreateNoteArgs>(arg).ok())
+                    .filter_map(|args| resolve::create_note_edit(&args))
+                    .collect_vec();
+
+                for edit in edits {
+                    let _ = self.client.apply_edit(edit).await;
+                }
+
+                Ok(None)
+            }
+            ExecuteCommandParams { command, .. } if *command == *extract::EXTRACT_NOTE_COMMAND => {
+                let Some(args) =
+                    params.arguments.into_iter().next().and_then(|arg| {
+                        serde_json::from_value::<extract::ExtractNoteArgs>(arg).ok()
+                    })
+                else {
+                    return Ok(None);
+                };
+                let path = args
+                    .uri
+                    .to_file_path()
+                    .map_err(|_| Error::new(ErrorCode::ServerError(0)))?;
+
+                let settings = self
+                    .bind_settings(&path, |settings| Ok(settings.clone()))
+                    .await?;
+                let edit = self
+                    .bind_vault(&path, |vault| {
+                        Ok(extract::extract_note_edit(vault, &settings, &path, &args))
+                    })
+                    .await?;
+
+                if let Some(edit) = edit {
+                    let _ = self.client.apply_edit(edit).await;
+                }
+
+                Ok(None)
+            }
+            ExecuteCommandParams { command, .. } if *command == *graph::EXPORT_GRAPH_COMMAND => {
+                let args = params
+                    .arguments
+                    .into_iter()
+                    .next()
+                    .and_then(|arg| serde_json::from_value::<graph::ExportGraphArgs>(arg).ok())
+                    .unwrap_or_default();
+
+                let path = match args.uri.as_ref().and_then(|uri| uri.to_file_path().ok()) {
+                    Some(path) => path,
+                    None => self.first_root_dir().await?,
+                };
+
+                self.bind_vault(&path, |vault| {
+                    Ok(serde_json::to_value(graph::link_graph(vault, args.include_excluded)).ok())
+                })
+                .await
+            }
             _ => Ok(None),
         }
     }
 
     async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
-        self.bind_vault(|vault| {
-            let path = params_path!(params.text_document_position_params)?;
-            Ok(hover::hover(vault, &params, &path))
+        let path = params_path!(params.text_document_position_params)?;
+        let settings = self
+            .bind_settings(&path, |settings| Ok(settings.clone()))
+            .await?;
+        self.bind_vault(&path, |vault| {
+            Ok(hover::hover(vault, &params, &path, &settings))
+        })
+        .await
+    }
+
+    async fn selection_range(
+        &self,
+        params: SelectionRangeParams,
+    ) -> Result<Option<Vec<SelectionRange>>> {
+        let path = params_path!(params)?;
+        self.bind_vault(&path, |vault| {
+            Ok(selection::selection_ranges(vault, &params, &path))
+        })
+        .await
+    }
+
+    async fn folding_range(&self, params: FoldingRangeParams) -> Result<Option<Vec<FoldingRange>>> {
+        let path = params_path!(params)?;
+        self.bind_vault(&path, |vault| {
+            Ok(folding::folding_ranges(vault, &params, &path))
         })
         .await
     }
@@ -601,33 +1258,101 @@ impl LanguageServer for Backend {
         &self,
         params: DocumentSymbolParams,
     ) -> Result<Option<DocumentSymbolResponse>> {
-        self.bind_vault(|vault| {
-            let path = params_path!(params)?;
-            Ok(document_symbol(vault, &params, &path))
-        })
-        .await
+        let path = params_path!(params)?;
+        self.bind_vault(&path, |vault| Ok(document_symbol(vault, &params, &path)))
+            .await
     }
 
     async fn symbol(
         &self,
         params: WorkspaceSymbolParams,
     ) -> Result<Option<Vec<SymbolInformation>>> {
-        self.bind_vault(|vault| Ok(workspace_symbol(vault, &params)))
-            .await
+        self.bind_vaults(|vaults| {
+            Ok(Some(
+                vaults
+                    .iter()
+                    .flat_map(|vault| workspace_symbol(vault, &params).unwrap_or_default())
+                    .collect(),
+            ))
+        })
+        .await
+    }
+
+    async fn prepare_rename(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> Result<Option<PrepareRenameResponse>> {
+        let path = params_path!(params)?;
+        self.bind_vault(&path, |vault| {
+            rename::prepare_rename(vault, &params, &path)
+                .map(Some)
+                .map_err(Error::invalid_params)
+        })
+        .await
     }
 
     async fn rename(&self, params: RenameParams) -> Result<Option<WorkspaceEdit>> {
-        self.bind_vault(|vault| {
-            let path = params_position_path!(params)?;
-            Ok(rename::rename(vault, &params, &path))
+        let path = params_position_path!(params)?;
+        self.bind_vault(&path, |vault| Ok(rename::rename(vault, &params, &path)))
+            .await
+    }
+
+    async fn document_link(&self, params: DocumentLinkParams) -> Result<Option<Vec<DocumentLink>>> {
+        let path = params_path!(params)?;
+        self.bind_vault(&path, |vault| {
+            Ok(documentlink::document_links(vault, &params, &path))
+        })
+        .await
+    }
+
+    async fn inlay_hint(&self, params: InlayHintParams) -> Result<Option<Vec<InlayHint>>> {
+        let path = params_path!(params)?;
+        let settings = self
+            .bind_settings(&path, |settings| Ok(settings.clone()))
+            .await?;
+        self.bind_vault(&path, |vault| {
+            Ok(inlayhint::inlay_hints(vault, &settings, &path, &params))
         })
         .await
     }
 
     async fn code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
-        self.bind_vault(|vault| {
-            let path = params_path!(params)?;
-            Ok(codeactions::code_actions(vault, &params, &path))
+        let path = params_path!(params)?;
+        let settings = self
+            .bind_settings(&path, |settings| Ok(settings.clone()))
+            .await?;
+        self.bind_vault(&path, |vault| {
+            Ok(codeactions::code_actions(vault, &settings, &params, &path))
+        })
+        .await
+    }
+
+    async fn formatting(&self, params: DocumentFormattingParams) -> Result<Option<Vec<TextEdit>>> {
+        let path = params_path!(params)?;
+        let settings = self
+            .bind_settings(&path, |settings| Ok(settings.clone()))
+            .await?;
+        self.bind_vault(&path, |vault| {
+            Ok(formatting::format_links(vault, &settings, &path))
+        })
+        .await
+    }
+
+    async fn range_formatting(
+        &self,
+        params: DocumentRangeFormattingParams,
+    ) -> Result<Option<Vec<TextEdit>>> {
+        let path = params_path!(params)?;
+        let settings = self
+            .bind_settings(&path, |settings| Ok(settings.clone()))
+            .await?;
+        self.bind_vault(&path, |vault| {
+            Ok(formatting::format_link_range(
+                vault,
+                &settings,
+                &path,
+                params.range,
+            ))
         })
         .await
     }
@@ -636,13 +1361,15 @@ impl LanguageServer for Backend {
         &self,
         params: SemanticTokensParams,
     ) -> Result<Option<SemanticTokensResult>> {
-        let settings = self.bind_settings(|settings| Ok(settings.clone())).await?;
+        let path = params_path!(params)?;
+        let settings = self
+            .bind_settings(&path, |settings| Ok(settings.clone()))
+            .await?;
 
         let timer = std::time::Instant::now();
 
-        let path = params_path!(params)?;
         let res = self
-            .bind_vault(|vault| {
+            .bind_vault(&path, |vault| {
                 Ok(tokens::semantic_tokens_full(
                     vault, &path, params, &settings,
                 ))
@@ -662,16 +1389,128 @@ impl LanguageServer for Backend {
     }
 }
 
+/// The vault the file is in; the innermost one when a workspace folder is inside another
+fn vault_index(vaults: &[Vault], path: &Path) -> Option<usize> {
+    vaults
+        .iter()
+        .enumerate()
+        .filter(|(_, vault)| path.starts_with(vault.root_dir()))
+        .max_by_key(|(_, vault)| vault.root_dir().components().count())
+        .map(|(index, _)| index)
+}
+
+/// The roots of the other workspace folders inside the root, which are vaults of their own
+fn nested_roots(root_dirs: &[PathBuf], root_dir: &Path) -> Vec<PathBuf> {
+    root_dirs
+        .iter()
+        .filter(|other| *other != root_dir && other.starts_with(root_dir))
+        .cloned()
+        .collect()
+}
+
+/// The file of a completion item or code lens, from the `uri` in its data
+fn data_path(data: Option<&Value>) -> Option<PathBuf> {
+    let uri = data?.get("uri")?.as_str()?;
+    Url::parse(uri).ok()?.to_file_path().ok()
+}
+
 #[tokio::main]
 async fn main() {
     let stdin = tokio::io::stdin();
     let stdout = tokio::io::stdout();
 
-    let (service, socket) = LspService::new(|client| Backend {
+    let (service, socket) = LspService::build(|client| Backend {
         client,
-        vault: Arc::new(None.into()),
+        vaults: Arc::new(Vec::new().into()),
         opened_files: Arc::new(HashSet::new().into()),
-        settings: Arc::new(None.into()),
-    });
+        recent_files: Arc::new(HashMap::new().into()),
+        settings: Arc::new(HashMap::new().into()),
+        renamed_links: Arc::new(HashSet::new().into()),
+        completion_cancel: Arc::new(CancelToken::default().into()),
+        refresh: Debounce::default(),
+    })
+    .custom_method(backlinks::BACKLINKS_METHOD, Backend::backlinks)
+    .custom_method(resolve::RESOLVE_LINK_METHOD, Backend::resolve_link)
+    .custom_method(taghierarchy::TAG_HIERARCHY_METHOD, Backend::tag_hierarchy)
+    .custom_method(
+        mentions::UNLINKED_MENTIONS_METHOD,
+        Backend::unlinked_mentions,
+    )
+    .finish();
     Server::new(stdin, stdout, socket).serve(service).await;
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use crate::vault::TestVault;
+
+    use super::{nested_roots, vault_index};
+
+    #[test]
+    fn test_vault_index() {
+        let test = TestVault::new(&[
+            ("note.md", "[[inner note]]"),
+            ("inner/inner note.md", ""),
+            ("inner/deeper/note.md", ""),
+            ("inner notes/note.md", ""),
+        ]);
+        let vaults = [
+            test.folder_vault("", &["inner"]),
+            test.folder_vault("inner", &[]),
+        ];
+
+        // a file is in the innermost vault it is in
+        assert_eq!(vault_index(&vaults, &test.path("note.md")), Some(0));
+        assert_eq!(
+            vault_index(&vaults, &test.path("inner notes/note.md")),
+            Some(0)
+        );
+        assert_eq!(
+            vault_index(&vaults, &test.path("inner/inner note.md")),
+            Some(1)
+        );
+        assert_eq!(
+            vault_index(&vaults, &test.path("inner/deeper/note.md")),
+            Some(1)
+        );
+        assert_eq!(vault_index(&vaults, Path::new("/elsewhere/note.md")), None);
+
+        // and only in that one
+        assert!(!vaults[0]
+            .md_files
+            .contains_key(&test.path("inner/inner note.md")));
+        assert!(vaults[1]
+            .md_files
+            .contains_key(&test.path("inner/inner note.md")));
+    }
+
+    #[test]
+    fn test_nested_roots() {
+        let root_dirs = [
+            "/vault",
+            "/vault/inner",
+            "/vault/inner/deeper",
+            "/vault2",
+            "/other",
+        ]
+        .map(PathBuf::from);
+
+        assert_eq!(
+            nested_roots(&root_dirs, Path::new("/vault")),
+            vec![
+                PathBuf::from("/vault/inner"),
+                PathBuf::from("/vault/inner/deeper")
+            ]
+        );
+        assert_eq!(
+            nested_roots(&root_dirs, Path::new("/vault/inner")),
+            vec![PathBuf::from("/vault/inner/deeper")]
+        );
+        assert_eq!(
+            nested_roots(&root_dirs, Path::new("/other")),
+            Vec::<PathBuf>::new()
+        );
+    }
+}
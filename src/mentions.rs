@@ -0,0 +1,224 @@
+use std::{ops::Range as CharRange, path::Path};
+
+use itertools::Itertools;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::{Position, Range, TextDocumentIdentifier, Url};
+
+use crate::vault::{Rangeable, Vault};
+
+/// The method of the custom request for the unlinked mentions of a note
+pub const UNLINKED_MENTIONS_METHOD: &str = "markdown-oxide/unlinkedMentions";
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UnlinkedMentionsParams {
+    pub text_document: TextDocumentIdentifier,
+}
+
+/// The unlinked mentions of a note in one file
+#[derive(Serialize, Debug, PartialEq)]
+pub struct FileMentions {
+    pub uri: Url,
+    pub mentions: Vec<Mention>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Mention {
+    pub range: Range,
+    /// The whole line the mention is on, for context
+    pub text: String,
+}
+
+/// The places the note's name or one of its aliases is written as plain text in other files, outside of links, code
+/// and frontmatter, grouped by the file they are in. Names are matched regardless of case, as whole words.
+pub fn unlinked_mentions(vault: &Vault, path: &Path) -> Option<Vec<FileMentions>> {
+    let names = mention_names(vault, path)?;
+
+    Some(
+        vault
+            .md_files
+            .keys()
+            .filter(|other| other.as_path() != path && !vault.is_excluded(other))
+            .sorted()
+            .collect_vec()
+            .into_par_iter()
+            .filter_map(|other| {
+                let rope = vault.ropes.get(other)?;
+                let body_start = vault
+                    .select_frontmatter_end_line(other)
+                    .map_or(0, |end| end + 1);
+
+                let mentions = (body_start..rope.len_lines())
+                    .flat_map(|line| {
+                        line_mentions(vault, other, line as u32, &names)
+                            .into_iter()
+                            .map(move |range| (line, range))
+                    })
+                    .map(|(line, range)| Mention {
+                        range: Range {
+                            start: Position {
+                                line: line as u32,
+                                character: range.start as u32,
+                            },
+                            end: Position {
+                                line: line as u32,
+                                character: range.end as u32,
+                            },
+                        },
+                        text: rope
+                            .line(line)
+                            .to_string()
+                            .trim_end_matches(['\n', '\r'])
+                            .to_string(),
+                    })
+                    .collect_vec();
+
+                if mentions.is_empty() {
+                    return None;
+                }
+
+                Some(FileMentions {
+                    uri: Url::from_file_path(other).ok()?,
+                    mentions,
+                })
+            })
+            .collect(),
+    )
+}
+
+/// The names a note is mentioned by: its file name and its aliases
+pub fn mention_names(vault: &Vault, path: &Path) -> Option<Vec<String>> {
+    let mdfile = vault.md_files.get(path)?;
+
+    Some(
+        std::iter::once(path.file_stem()?.to_str()?.to_string())
+            .chain(
+                mdfile
+                    .metadata
+                    .iter()
+                    .flat_map(|metadata| metadata.aliases())
+                    .cloned(),
+            )
+            .filter(|name| !name.trim().is_empty())
+            .unique()
+            .collect(),
+    )
+}
+
+/// The character ranges of the mentions of any of the names on a line of the file that aren't in a link or in code.
+/// The caller leaves out the lines of the frontmatter.
+pub fn line_mentions(
+    vault: &Vault,
+    path: &Path,
+    line: u32,
+    names: &[String],
+) -> Vec<CharRange<usize>> {
+    let (Some(mdfile), Some(chars)) = (
+        vault.md_files.get(path),
+        vault.select_line(path, line as isize),
+    ) else {
+        return vec![];
+    };
+
+    let linked_or_code = |range: &CharRange<usize>| {
+        [range.start, range.end - 1].into_iter().any(|character| {
+            let position = Position {
+                line,
+                character: character as u32,
+            };
+
+            mdfile
+                .references
+                .iter()
+                .any(|reference| reference.includes_position(position))
+                || mdfile
+                    .codeblocks
+                    .iter()
+                    .any(|codeblock| codeblock.includes_position(position))
+        })
+    };
+
+    mentions_in_line(&chars, names)
+        .into_iter()
+        .filter(|range| !linked_or_code(range))
+        .collect()
+}
+
+/// The character ranges on the line where one of the names is written as a whole word, regardless of case. Where
+/// mentions overlap, the longer name wins, so the alias `Project Alpha` is matched rather than a note named `Alpha`.
+fn mentions_in_line(line: &[char], names: &[String]) -> Vec<CharRange<usize>> {
+    // each character is lowered on its own, so the indexes stay those of the line
+    let lower = |chars: &[char]| {
+        chars
+            .iter()
+            .map(|c| c.to_lowercase().next().unwrap_or(*c))
+            .collect_vec()
+    };
+    let line_lower = lower(line);
+    let is_word = |c: Option<&char>| c.is_some_and(|c| c.is_alphanumeric() || *c == '_');
+
+    names
+        .iter()
+        .map(|name| lower(&name.chars().collect_vec()))
+        .filter(|name| !name.is_empty())
+        .flat_map(|name| {
+            line_lower
+                .windows(name.len())
+                .positions(|window| window == name.as_slice())
+                .map(|start| start..start + name.len())
+                .filter(|range| {
+                    !is_word(range.start.checked_sub(1).and_then(|i| line.get(i)))
+                        && !is_word(line.get(range.end))
+                })
+                .collect_vec()
+        })
+        .sorted_by_key(|range| (std::cmp::Reverse(range.len()), range.start))
+        .fold(Vec::<CharRange<usize>>::new(), |mut kept, range| {
+            if !kept
+                .iter()
+                .any(|other| other.start < range.end && range.start < other.end)
+            {
+                kept.push(range);
+            }
+            kept
+        })
+        .into_iter()
+        .sorted_by_key(|range| range.start)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::mentions_in_line;
+
+    #[test]
+    fn test_mentions_in_line() {
+        let mentions = |line: &str, names: &[&str]| {
+            mentions_in_line(
+                &line.chars().collect::<Vec<_>>(),
+                &names
+                    .iter()
+                    .map(|name| name.to_string())
+                    .collect::<Vec<_>>(),
+            )
+        };
+
+        assert_eq!(
+            mentions("Talked about project alpha and Alpha today", &["Alpha"]),
+            vec![21..26, 31..36]
+        );
+        // whole words only
+        assert_eq!(
+            mentions("Alphabet and alpha_2", &["Alpha"]),
+            Vec::<std::ops::Range<usize>>::new()
+        );
+        // the longer name wins where they overlap
+        assert_eq!(
+            mentions("Project Alpha, then Alpha", &["Alpha", "Project Alpha"]),
+            vec![0..13, 20..25]
+        );
+        assert_eq!(mentions("Café notes", &["café"]), vec![0..4]);
+        assert_eq!(mentions("C++ tips", &["C++"]), vec![0..3]);
+    }
+}
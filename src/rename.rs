@@ -1,30 +1,140 @@
 use std::iter;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use tower_lsp::lsp_types::{
-    DocumentChangeOperation, DocumentChanges, OneOf, OptionalVersionedTextDocumentIdentifier,
-    RenameFile, RenameParams, ResourceOp, TextDocumentEdit, TextEdit, Url, WorkspaceEdit,
+    DocumentChangeOperation, DocumentChanges, FileRename, OneOf,
+    OptionalVersionedTextDocumentIdentifier, Position, PrepareRenameResponse, Range, RenameFile,
+    RenameParams, ResourceOp, TextDocumentEdit, TextDocumentPositionParams, TextEdit, Url,
+    WorkspaceEdit,
 };
 
-use crate::vault::{MDHeading, Reference, Referenceable, Vault};
+use crate::completion::{markdown_link_path, percent_encode_refname};
+use crate::config::{LinkPathEncoding, Settings};
+use crate::selection::display_text_range;
+use crate::vault::{
+    get_obsidian_ref_path, heading_link_text, heading_slug, moved_path, HeadingAnchor, MDHeading,
+    Reference, Referenceable, Vault,
+};
+
+/// The range `rename` will replace: the display text of an alias link, the text of a heading or tag, or, anywhere else
+/// in the file, the file's name as a placeholder. Links that are not renamed from here are refused with a message.
+pub fn prepare_rename(
+    vault: &Vault,
+    params: &TextDocumentPositionParams,
+    path: &Path,
+) -> Result<PrepareRenameResponse, &'static str> {
+    let position = params.position;
+
+    if let Some((.., range)) = alias_link_at_position(vault, path, position) {
+        return Ok(PrepareRenameResponse::Range(range));
+    }
+
+    let referenceable = vault
+        .select_referenceable_at_position(path, position)
+        .ok_or("The file is not in the vault")?;
+
+    match referenceable {
+        Referenceable::Heading(path, heading) => heading_text_range(vault, path, heading)
+            .map(PrepareRenameResponse::Range)
+            .ok_or("The heading has no text to rename"),
+        Referenceable::Tag(path, tag) => {
+            let line = vault
+                .select_line(path, tag.range.start.line as isize)
+                .ok_or("The tag is not in the file")?;
+            // the `#` is kept
+            let start = match line.get(tag.range.start.character as usize) {
+                Some('#') => tag.range.start.character + 1,
+                _ => tag.range.start.character,
+            };
+
+            Ok(PrepareRenameResponse::Range(Range {
+                start: Position {
+                    line: tag.range.start.line,
+                    character: start,
+                },
+                end: tag.range.end,
+            }))
+        }
+        Referenceable::File(path, _) => match vault.select_reference_at_position(path, position) {
+            // renaming from a link would rename the file the link is in
+            Some(reference) => {
+                let resolved = vault
+                    .select_referenceables_for_reference(reference, path)
+                    .into_iter()
+                    .any(|referenceable| !referenceable.is_unresolved());
+
+                match (reference, resolved) {
+                    (Reference::Tag(..), _) => Err("The tag can't be renamed"),
+                    (_, false) => Err("Can't rename an unresolved link; create the file first"),
+                    (_, true) => Err("Rename the linked file or heading where it is defined"),
+                }
+            }
+            None => Ok(PrepareRenameResponse::RangeWithPlaceholder {
+                range: Range {
+                    start: position,
+                    end: position,
+                },
+                placeholder: path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .ok_or("The file has no name")?
+                    .to_string(),
+            }),
+        },
+        _ => Err("Only files, headings, tags, and the display text of alias links can be renamed"),
+    }
+}
+
+/// The text of the heading, without the `#`s
+fn heading_text_range(vault: &Vault, path: &Path, heading: &MDHeading) -> Option<Range> {
+    let line = vault.select_line(path, heading.range.start.line as isize)?;
+    let heading_chars = line.get(heading.range.start.character as usize..)?;
+
+    let text_start = heading_chars
+        .iter()
+        .position(|c| *c != '#' && !c.is_whitespace())?;
+    let text_len = heading_chars.len()
+        - text_start
+        - heading_chars
+            .iter()
+            .rev()
+            .take_while(|c| c.is_whitespace())
+            .count();
+
+    let start = heading.range.start.character + text_start as u32;
+
+    Some(Range {
+        start: Position {
+            line: heading.range.start.line,
+            character: start,
+        },
+        end: Position {
+            line: heading.range.start.line,
+            character: start + text_len as u32,
+        },
+    })
+}
 
 pub fn rename(vault: &Vault, params: &RenameParams, path: &Path) -> Option<WorkspaceEdit> {
+    if let Some(edit) = rename_alias(vault, params, path) {
+        return Some(edit);
+    }
+
     let position = params.text_document_position.position;
     let referenceable = vault.select_referenceable_at_position(path, position)?;
 
     let (referenceable_document_change, new_ref_name): (Option<DocumentChangeOperation>, String) =
         match referenceable {
             Referenceable::Heading(path, heading) => {
-                let new_text = format!("{} {}", "#".repeat(heading.level.0), params.new_name); // move this obsidian syntax specific stuff to the vault
-
+                // only the text is replaced, the range prepare_rename gives
                 let change_op = DocumentChangeOperation::Edit(TextDocumentEdit {
                     text_document: tower_lsp::lsp_types::OptionalVersionedTextDocumentIdentifier {
                         uri: Url::from_file_path(path).ok()?,
                         version: None,
                     },
                     edits: vec![OneOf::Left(TextEdit {
-                        range: *heading.range,
-                        new_text,
+                        range: heading_text_range(vault, path, heading)?,
+                        new_text: params.new_name.clone(),
                     })],
                 });
 
@@ -32,6 +142,10 @@ pub fn rename(vault: &Vault, params: &RenameParams, path: &Path) -> Option<Works
                     path,
                     &MDHeading {
                         heading_text: params.new_name.clone(),
+                        anchor: HeadingAnchor {
+                            link_text: heading_link_text(&params.new_name),
+                            slug: heading_slug(&params.new_name),
+                        },
                         ..heading.clone()
                     },
                 )
@@ -147,15 +261,21 @@ pub fn rename(vault: &Vault, params: &RenameParams, path: &Path) -> Option<Works
                     })
                 }
                 Reference::Tag(data) => {
-                    let new_text = format!(
-                        "#{}",
-                        data.reference_text.replacen(
-                            &*referenceable.get_refname(vault.root_dir())?,
-                            &new_ref_name,
-                            1
-                        )
+                    let new_tag = data.reference_text.replacen(
+                        &*referenceable.get_refname(vault.root_dir())?,
+                        &new_ref_name,
+                        1,
                     );
 
+                    // tags in the frontmatter are written without `#`
+                    let in_frontmatter = vault
+                        .select_frontmatter_end_line(path)
+                        .is_some_and(|end| (data.range.start.line as usize) < end);
+                    let new_text = match in_frontmatter {
+                        true => new_tag,
+                        false => format!("#{}", new_tag),
+                    };
+
                     Some(TextDocumentEdit {
                         text_document: OptionalVersionedTextDocumentIdentifier {
                             uri: Url::from_file_path(path).ok()?,
@@ -261,3 +381,374 @@ pub fn rename(vault: &Vault, params: &RenameParams, path: &Path) -> Option<Works
         ..Default::default()
     })
 }
+
+/// Renaming the display text of a file link, when it is one of the file's aliases, renames the display text of every
+/// link to the file with that alias and appends the new alias to the file's frontmatter `aliases`
+fn rename_alias(vault: &Vault, params: &RenameParams, path: &Path) -> Option<WorkspaceEdit> {
+    let (alias, referenceable, _) =
+        alias_link_at_position(vault, path, params.text_document_position.position)?;
+    let Referenceable::File(file_path, _) = referenceable else {
+        return None;
+    };
+
+    let references = vault.select_references_for_referenceable(&referenceable)?;
+
+    let references_changes = references
+        .into_iter()
+        .filter(|(_, reference)| {
+            matches!(
+                reference,
+                Reference::WikiFileLink(..) | Reference::MDFileLink(..)
+            ) && reference.data().display_text.as_ref() == Some(alias)
+        })
+        .filter_map(|(path, reference)| {
+            Some(TextDocumentEdit {
+                text_document: OptionalVersionedTextDocumentIdentifier {
+                    uri: Url::from_file_path(path).ok()?,
+                    version: None,
+                },
+                edits: vec![OneOf::Left(TextEdit {
+                    range: display_text_range(vault, path, reference)?,
+                    new_text: params.new_name.clone(),
+                })],
+            })
+        });
+
+    let alias_change = vault
+        .select_add_alias_edit(file_path, &params.new_name)
+        .and_then(|edit| {
+            Some(TextDocumentEdit {
+                text_document: OptionalVersionedTextDocumentIdentifier {
+                    uri: Url::from_file_path(file_path).ok()?,
+                    version: None,
+                },
+                edits: vec![OneOf::Left(edit)],
+            })
+        });
+
+    Some(WorkspaceEdit {
+        document_changes: Some(DocumentChanges::Operations(
+            references_changes
+                .chain(alias_change)
+                .map(DocumentChangeOperation::Edit)
+                .collect(),
+        )),
+        ..Default::default()
+    })
+}
+
+/// The link under the cursor whose display text is one of the aliases of the file it links to, with the alias, the
+/// file, and the range of the display text
+fn alias_link_at_position<'a>(
+    vault: &'a Vault,
+    path: &'a Path,
+    position: Position,
+) -> Option<(&'a String, Referenceable<'a>, Range)> {
+    let reference = vault.select_reference_at_position(path, position)?;
+
+    let (Reference::WikiFileLink(data) | Reference::MDFileLink(data)) = reference else {
+        return None;
+    };
+    let alias = data.display_text.as_ref()?;
+
+    let range = display_text_range(vault, path, reference)?;
+    if position.character < range.start.character || position.character > range.end.character {
+        return None;
+    }
+
+    let referenceable = vault
+        .select_referenceables_for_reference(reference, path)
+        .into_iter()
+        .find(|referenceable| matches!(referenceable, Referenceable::File(..)))?;
+    let Referenceable::File(_, mdfile) = referenceable else {
+        return None;
+    };
+
+    if !mdfile
+        .metadata
+        .iter()
+        .flat_map(|metadata| metadata.aliases())
+        .any(|it| it == alias)
+    {
+        return None;
+    }
+
+    Some((alias, referenceable, range))
+}
+
+/// The old and new paths of the files or folders in a `willRenameFiles` or `didRenameFiles` notification
+pub fn file_renames(files: &[FileRename]) -> Vec<(PathBuf, PathBuf)> {
+    files
+        .iter()
+        .filter_map(|file| {
+            Some((
+                Url::parse(&file.old_uri).ok()?.to_file_path().ok()?,
+                Url::parse(&file.new_uri).ok()?.to_file_path().ok()?,
+            ))
+        })
+        .collect()
+}
+
+/// The edits to the links to files that were renamed or moved by the editor, like from its file explorer, rather than
+/// with a rename request. `renames` are the old and new paths of files or folders; the vault still has the files at
+/// their old paths. Links in files that were moved themselves are edited at the new path once `files_moved`, and at
+/// the old path before the move.
+pub fn rename_files(
+    vault: &Vault,
+    settings: &Settings,
+    renames: &[(PathBuf, PathBuf)],
+    files_moved: bool,
+) -> Option<WorkspaceEdit> {
+    let root_dir = vault.root_dir();
+
+    let edits = vault
+        .md_files
+        .iter()
+        .filter_map(|(old_path, mdfile)| Some((old_path, moved_path(old_path, renames)?, mdfile)))
+        .flat_map(|(old_path, new_path, mdfile)| {
+            vault
+                .select_references_for_referenceable(&Referenceable::File(old_path, mdfile))
+                .into_iter()
+                .flatten()
+                .filter_map(move |(old_ref_path, reference)| {
+                    // markdown link paths are relative to where the linking file will be
+                    let ref_path =
+                        moved_path(old_ref_path, renames).unwrap_or(old_ref_path.to_path_buf());
+                    let data = reference.data();
+                    let display = data.display_text.as_deref();
+
+                    let new_text = match reference {
+                        Reference::WikiFileLink(..)
+                        | Reference::WikiHeadingLink(..)
+                        | Reference::WikiIndexedBlockLink(..) => {
+                            let (file, infile) = match reference {
+                                Reference::WikiHeadingLink(_, file, infile)
+                                | Reference::WikiIndexedBlockLink(_, file, infile) => {
+                                    (file.as_str(), Some(infile))
+                                }
+                                _ => (data.reference_text.as_str(), None),
+                            };
+                            // links by path from the vault root keep linking by path
+                            let refname = match file.contains('/') {
+                                true => get_obsidian_ref_path(root_dir, &new_path)?,
+                                false => new_path.file_stem()?.to_str()?.to_string(),
+                            };
+                            if refname == file {
+                                return None;
+                            }
+
+                            format!(
+                                "[[{}{}{}]]",
+                                refname,
+                                infile
+                                    .map(|infile| format!("#{}", infile))
+                                    .unwrap_or_default(),
+                                display
+                                    .map(|display| format!("|{}", display))
+                                    .unwrap_or_default()
+                            )
+                        }
+                        Reference::MDFileLink(..)
+                        | Reference::MDHeadingLink(..)
+                        | Reference::MDIndexedBlockLink(..) => {
+                            let infile = match reference {
+                                Reference::MDHeadingLink(_, _, infile)
+                                | Reference::MDIndexedBlockLink(_, _, infile) => Some(infile),
+                                _ => None,
+                            };
+                            let link_path =
+                                markdown_link_path(settings, root_dir, &ref_path, &new_path)?;
+                            // keep the `.md` only if the link had it
+                            let link_path =
+                                match vault.markdown_link_has_extension(old_ref_path, reference) {
+                                    true => link_path,
+                                    false => link_path
+                                        .strip_suffix(".md")
+                                        .unwrap_or(&link_path)
+                                        .to_string(),
+                                };
+                            let link = format!(
+                                "{}{}",
+                                link_path,
+                                infile
+                                    .map(|infile| format!("#{}", infile))
+                                    .unwrap_or_default()
+                            );
+
+                            let link = match settings.link_path_encoding {
+                                LinkPathEncoding::Percent => percent_encode_refname(&link),
+                                LinkPathEncoding::Angle if link.contains(' ') => {
+                                    format!("<{}>", link)
+                                }
+                                LinkPathEncoding::Angle => link,
+                            };
+
+                            format!("[{}]({})", display.unwrap_or(""), link)
+                        }
+                        Reference::Tag(..) | Reference::Footnote(..) | Reference::LinkRef(..) => {
+                            return None
+                        }
+                    };
+
+                    let edited_path = match files_moved {
+                        true => ref_path.as_path(),
+                        false => old_ref_path,
+                    };
+
+                    Some(TextDocumentEdit {
+                        text_document: OptionalVersionedTextDocumentIdentifier {
+                            uri: Url::from_file_path(edited_path).ok()?,
+                            version: None,
+                        },
+                        edits: vec![OneOf::Left(TextEdit {
+                            range: *data.range,
+                            new_text,
+                        })],
+                    })
+                })
+        })
+        .chain(attachment_link_edits(vault, settings, renames, files_moved))
+        .map(DocumentChangeOperation::Edit)
+        .collect::<Vec<_>>();
+
+    if edits.is_empty() {
+        return None;
+    }
+
+    Some(WorkspaceEdit {
+        document_changes: Some(DocumentChanges::Operations(edits)),
+        ..Default::default()
+    })
+}
+
+/// The edits to the links to attachments, like images, that were moved, for `rename_files`. Wikilinks by name keep
+/// linking by name, which a move doesn't change, and links without the extension keep leaving it out.
+fn attachment_link_edits(
+    vault: &Vault,
+    settings: &Settings,
+    renames: &[(PathBuf, PathBuf)],
+    files_moved: bool,
+) -> Vec<TextDocumentEdit> {
+    let root_dir = vault.root_dir();
+
+    vault
+        .md_files
+        .keys()
+        .flat_map(|old_ref_path| {
+            let ref_path = moved_path(old_ref_path, renames).unwrap_or(old_ref_path.to_path_buf());
+
+            vault
+                .select_attachment_links(old_ref_path)
+                .into_iter()
+                .filter_map(|link| {
+                    let new_path = moved_path(link.attachment, renames)?;
+                    if link.wiki && !link.file.contains('/') {
+                        return None;
+                    }
+                    // wikilinks with a path and markdown links with a leading `/` go from the vault root
+                    let from_root = || {
+                        Some(
+                            pathdiff::diff_paths(&new_path, root_dir)?
+                                .to_str()?
+                                .replace('\\', "/"),
+                        )
+                    };
+                    let link_path = match (link.wiki, link.file.starts_with('/')) {
+                        (_, true) => format!("/{}", from_root()?),
+                        (true, false) => from_root()?,
+                        (false, false) => {
+                            markdown_link_path(settings, root_dir, &ref_path, &new_path)?
+                        }
+                    };
+                    let link_path = match Path::new(&link.file).extension() {
+                        Some(_) => link_path,
+                        None => Path::new(&link_path)
+                            .with_extension("")
+                            .to_str()?
+                            .to_string(),
+                    };
+                    if link_path == link.file {
+                        return None;
+                    }
+
+                    let display = link.display.as_deref();
+                    let new_text = match link.wiki {
+                        true => format!(
+                            "[[{}{}]]",
+                            link_path,
+                            display
+                                .map(|display| format!("|{}", display))
+                                .unwrap_or_default()
+                        ),
+                        false => {
+                            let link_path = match settings.link_path_encoding {
+                                LinkPathEncoding::Percent => percent_encode_refname(&link_path),
+                                LinkPathEncoding::Angle if link_path.contains(' ') => {
+                                    format!("<{}>", link_path)
+                                }
+                                LinkPathEncoding::Angle => link_path,
+                            };
+                            format!("[{}]({})", display.unwrap_or(""), link_path)
+                        }
+                    };
+
+                    let edited_path = match files_moved {
+                        true => ref_path.as_path(),
+                        false => old_ref_path,
+                    };
+
+                    Some(TextDocumentEdit {
+                        text_document: OptionalVersionedTextDocumentIdentifier {
+                            uri: Url::from_file_path(edited_path).ok()?,
+                            version: None,
+                        },
+                        edits: vec![OneOf::Left(TextEdit {
+                            range: link.range,
+                            new_text,
+                        })],
+                    })
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use tower_lsp::lsp_types::{DocumentChangeOperation, DocumentChanges, OneOf, TextEdit};
+
+    use crate::vault::{apply_edits, TestVault};
+
+    use super::rename_files;
+
+    #[test]
+    fn test_rename_files_moves_attachment_links() {
+        let text = "![[diagram.png]] ![[assets/diagram.png|shown]] [pic](assets/diagram.png) [root](/assets/diagram.png)\n";
+        let test_vault = TestVault::new(&[("note.md", text), ("assets/diagram.png", "")]);
+
+        let renames: Vec<(PathBuf, PathBuf)> =
+            vec![(test_vault.path("assets"), test_vault.path("images"))];
+        let edit = rename_files(&test_vault.vault, &test_vault.settings, &renames, false).unwrap();
+        let Some(DocumentChanges::Operations(operations)) = edit.document_changes else {
+            panic!("expected document changes")
+        };
+        let edits: Vec<TextEdit> = operations
+            .into_iter()
+            .flat_map(|operation| match operation {
+                DocumentChangeOperation::Edit(edit) => edit.edits,
+                _ => vec![],
+            })
+            .map(|edit| match edit {
+                OneOf::Left(edit) => edit,
+                OneOf::Right(edit) => edit.text_edit,
+            })
+            .collect();
+
+        assert_eq!(
+            apply_edits(text, &edits),
+            "![[diagram.png]] ![[images/diagram.png|shown]] [pic](images/diagram.png) [root](/images/diagram.png)\n"
+        );
+    }
+}
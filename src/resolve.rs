@@ -0,0 +1,175 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::{
+    CreateFile, CreateFileOptions, DocumentChangeOperation, DocumentChanges, OneOf,
+    OptionalVersionedTextDocumentIdentifier, Position, Range, ResourceOp, TextDocumentEdit,
+    TextEdit, Url, WorkspaceEdit,
+};
+
+use crate::{
+    codeactions::normalize,
+    config::{NewNoteFolder, Settings},
+    vault::{Reference, ReferenceData, Referenceable, Vault},
+};
+
+/// The method of the custom request for the target of the link under the cursor
+pub const RESOLVE_LINK_METHOD: &str = "markdown-oxide/resolveLink";
+
+/// The command creating the missing note of a link, sent with accepted completions of unresolved links
+pub const CREATE_NOTE_COMMAND: &str = "markdown-oxide.createNote";
+
+/// The argument of the `markdown-oxide.createNote` command
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct CreateNoteArgs {
+    pub uri: Url,
+    /// The heading the note is created with, for a link to a heading in it
+    pub heading: Option<String>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct ResolvedLink {
+    pub uri: Url,
+    /// The heading or block linked to; None for a link to a whole note, or to a heading or block that doesn't exist
+    pub range: Option<Range>,
+    /// Whether the target exists; the uri of a link to a missing note is where the note would be created
+    pub resolved: bool,
+}
+
+/// Where the link under the cursor goes, whether or not it exists; None if the cursor isn't on a link
+pub fn resolve_link(
+    vault: &Vault,
+    settings: &Settings,
+    cursor_position: Position,
+    path: &Path,
+) -> Option<ResolvedLink> {
+    let reference = vault.select_reference_at_position(path, cursor_position)?;
+    let referenceables = vault.select_referenceables_for_reference(reference, path);
+
+    if let Some(referenceable) = referenceables
+        .iter()
+        .find(|referenceable| !referenceable.is_unresolved())
+    {
+        return Some(ResolvedLink {
+            uri: Url::from_file_path(referenceable.get_path()).ok()?,
+            range: match referenceable {
+                Referenceable::File(..) => None,
+                _ => referenceable.get_range().map(|range| *range),
+            },
+            resolved: true,
+        });
+    }
+
+    let (file, is_wikilink) = match reference {
+        Reference::WikiFileLink(data) => (&data.reference_text, true),
+        Reference::MDFileLink(data) => (&data.reference_text, false),
+        Reference::WikiHeadingLink(_, file, _) | Reference::WikiIndexedBlockLink(_, file, _) => {
+            (file, true)
+        }
+        Reference::MDHeadingLink(_, file, _) | Reference::MDIndexedBlockLink(_, file, _) => {
+            (file, false)
+        }
+        _ => return None,
+    };
+
+    // the note of a missing heading or block may exist
+    let target = match existing_file(vault, path, file, is_wikilink) {
+        Some(target) => target,
+        None => new_note_path(vault, settings, path, file, is_wikilink)?,
+    };
+
+    Some(ResolvedLink {
+        uri: Url::from_file_path(target).ok()?,
+        range: None,
+        resolved: false,
+    })
+}
+
+/// Where the missing note of a link is created. A markdown link's path is relative to the linking file, or to the
+/// vault root with a leading `/`. A wikilink by path is from the vault root, and one by name goes in the
+/// `new_note_folder`.
+pub fn new_note_path(
+    vault: &Vault,
+    settings: &Settings,
+    path: &Path,
+    file: &str,
+    is_wikilink: bool,
+) -> Option<PathBuf> {
+    let folder = match &settings.new_note_folder {
+        _ if file.starts_with('/') => vault.root_dir().to_path_buf(),
+        _ if !is_wikilink => path.parent()?.to_path_buf(),
+        _ if file.contains('/') => vault.root_dir().to_path_buf(),
+        NewNoteFolder::Root => vault.root_dir().to_path_buf(),
+        NewNoteFolder::Current => path.parent()?.to_path_buf(),
+        NewNoteFolder::Folder(folder) => vault.root_dir().join(folder),
+    };
+
+    Some(normalize(
+        &folder.join(format!("{}.md", file.trim_start_matches('/'))),
+    ))
+}
+
+/// The edit creating the note, starting with the heading if there is one; a note that already exists is left as it is
+pub fn create_note_edit(args: &CreateNoteArgs) -> Option<WorkspaceEdit> {
+    if args.uri.to_file_path().ok()?.exists() {
+        return None;
+    }
+
+    let create = DocumentChangeOperation::Op(ResourceOp::Create(CreateFile {
+        uri: args.uri.clone(),
+        options: Some(CreateFileOptions {
+            ignore_if_exists: Some(true),
+            overwrite: Some(false),
+        }),
+        annotation_id: None,
+    }));
+
+    let heading = args.heading.as_ref().map(|heading| {
+        DocumentChangeOperation::Edit(TextDocumentEdit {
+            text_document: OptionalVersionedTextDocumentIdentifier {
+                uri: args.uri.clone(),
+                version: None,
+            },
+            edits: vec![OneOf::Left(TextEdit {
+                range: Range::default(),
+                new_text: format!("# {}\n", heading),
+            })],
+        })
+    });
+
+    Some(WorkspaceEdit {
+        document_changes: Some(DocumentChanges::Operations(
+            std::iter::once(create).chain(heading).collect(),
+        )),
+        ..Default::default()
+    })
+}
+
+/// The note a link goes to, whether or not the heading or block it links to exists
+pub fn existing_file(vault: &Vault, path: &Path, file: &str, is_wikilink: bool) -> Option<PathBuf> {
+    if file.is_empty() {
+        return Some(path.to_path_buf());
+    }
+
+    let data = ReferenceData {
+        reference_text: file.to_string(),
+        ..Default::default()
+    };
+    let file_reference = match is_wikilink {
+        true => Reference::WikiFileLink(data),
+        false => Reference::MDFileLink(data),
+    };
+
+    vault
+        .md_files
+        .iter()
+        .find(|(file_path, mdfile)| {
+            Referenceable::File(file_path, mdfile).matches_reference(
+                vault.root_dir(),
+                &file_reference,
+                path,
+                vault.case_insensitive_links(),
+            )
+        })
+        .map(|(file_path, _)| file_path.clone())
+}
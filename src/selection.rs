@@ -0,0 +1,265 @@
+use std::path::Path;
+
+use tower_lsp::lsp_types::{Position, Range, SelectionRange, SelectionRangeParams};
+
+use crate::vault::{MDHeading, Reference, Vault};
+
+pub fn selection_ranges(
+    vault: &Vault,
+    params: &SelectionRangeParams,
+    path: &Path,
+) -> Option<Vec<SelectionRange>> {
+    let ranges = params
+        .positions
+        .iter()
+        .map(|position| selection_range(vault, path, *position))
+        .collect();
+
+    Some(ranges)
+}
+
+/// Expands from the display text of a link, to the whole link, to the paragraph, to each enclosing heading section
+fn selection_range(vault: &Vault, path: &Path, position: Position) -> SelectionRange {
+    let reference = vault.select_reference_at_position(path, position);
+
+    let ranges = [
+        reference.and_then(|reference| display_text_range(vault, path, reference)),
+        reference.map(|reference| *reference.data().range),
+        paragraph_range(vault, path, position.line),
+    ]
+    .into_iter()
+    .flatten()
+    .filter(|range| contains(range, position))
+    .chain(section_ranges(vault, path, position.line));
+
+    // each range must contain the one before it
+    let mut nested: Vec<Range> = vec![];
+    for range in ranges {
+        match nested.last() {
+            Some(last) if range == *last || !contains_range(&range, last) => {}
+            _ => nested.push(range),
+        }
+    }
+
+    nested
+        .into_iter()
+        .rev()
+        .fold(None, |parent, range| {
+            Some(SelectionRange {
+                range,
+                parent: parent.map(Box::new),
+            })
+        })
+        .unwrap_or(SelectionRange {
+            range: Range {
+                start: position,
+                end: position,
+            },
+            parent: None,
+        })
+}
+
+/// The range of the display text of a link, like `display` in `[[file|display]]` or `[display](file)`
+pub fn display_text_range(vault: &Vault, path: &Path, reference: &Reference) -> Option<Range> {
+    let display_text = reference.data().display_text.as_ref()?;
+    let range = reference.data().range;
+    let display_len = display_text.chars().count() as u32;
+
+    let start = match reference {
+        // [display](path)
+        Reference::MDFileLink(..)
+        | Reference::MDHeadingLink(..)
+        | Reference::MDIndexedBlockLink(..) => range.start.character + 1,
+        // [[path|display]]
+        Reference::WikiFileLink(..)
+        | Reference::WikiHeadingLink(..)
+        | Reference::WikiIndexedBlockLink(..) => {
+            range.end.character.checked_sub(display_len + 2)?
+        }
+        _ => return None,
+    };
+
+    let line = String::from_iter(vault.select_line(path, range.start.line as isize)?);
+    let in_line = line
+        .chars()
+        .skip(start as usize)
+        .take(display_len as usize)
+        .collect::<String>();
+
+    (in_line == *display_text).then_some(Range {
+        start: Position {
+            line: range.start.line,
+            character: start,
+        },
+        end: Position {
+            line: range.start.line,
+            character: start + display_len,
+        },
+    })
+}
+
+/// The block of non-empty lines around the line, not including headings
+fn paragraph_range(vault: &Vault, path: &Path, line: u32) -> Option<Range> {
+    let headings = vault.select_headings(path);
+    let is_paragraph_line = |line: u32| {
+        // the underline of a setext heading is part of the heading
+        let is_heading = headings.is_some_and(|headings| {
+            headings
+                .iter()
+                .any(|it| (it.range.start.line..=it.range.end.line).contains(&line))
+        });
+
+        !is_heading
+            && vault
+                .select_line(path, line as isize)
+                .is_some_and(|text| !String::from_iter(text).trim().is_empty())
+    };
+
+    if !is_paragraph_line(line) {
+        return None;
+    }
+
+    let start = (0..line)
+        .rev()
+        .take_while(|line| is_paragraph_line(*line))
+        .last()
+        .unwrap_or(line);
+    let end = (line + 1..)
+        .take_while(|line| is_paragraph_line(*line))
+        .last()
+        .unwrap_or(line);
+
+    Some(Range {
+        start: Position {
+            line: start,
+            character: 0,
+        },
+        end: Position {
+            line: end,
+            character: line_length(vault, path, end)?,
+        },
+    })
+}
+
+/// The sections of the headings the line is under, innermost first
+fn section_ranges(vault: &Vault, path: &Path, line: u32) -> Vec<Range> {
+    let Some(mdfile) = vault.md_files.get(path) else {
+        return vec![];
+    };
+    let Some(heading) = mdfile
+        .headings
+        .iter()
+        .rev()
+        .find(|heading| heading.range.start.line <= line)
+    else {
+        return vec![];
+    };
+
+    let last_line = vault
+        .ropes
+        .get(path)
+        .map(|rope| rope.len_lines().saturating_sub(1) as u32)
+        .unwrap_or(line);
+
+    let section_range = |heading: &MDHeading| {
+        let end = mdfile.heading_section_end(heading).unwrap_or(last_line);
+
+        Range {
+            start: Position {
+                line: heading.range.start.line,
+                character: 0,
+            },
+            end: Position {
+                line: end,
+                character: line_length(vault, path, end).unwrap_or(0),
+            },
+        }
+    };
+
+    std::iter::once(heading)
+        .chain(mdfile.heading_ancestors(heading).into_iter().rev())
+        .map(section_range)
+        .collect()
+}
+
+fn line_length(vault: &Vault, path: &Path, line: u32) -> Option<u32> {
+    let text = String::from_iter(vault.select_line(path, line as isize)?);
+
+    Some(text.trim_end_matches(['\n', '\r']).chars().count() as u32)
+}
+
+fn contains(range: &Range, position: Position) -> bool {
+    (range.start.line, range.start.character) <= (position.line, position.character)
+        && (position.line, position.character) <= (range.end.line, range.end.character)
+}
+
+fn contains_range(outer: &Range, inner: &Range) -> bool {
+    contains(outer, inner.start) && contains(outer, inner.end)
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::{Position, Range, SelectionRange};
+
+    use crate::vault::TestVault;
+
+    use super::selection_range;
+
+    /// The ranges from the innermost out
+    fn ranges(selection: SelectionRange) -> Vec<Range> {
+        std::iter::successors(Some(selection), |selection| {
+            selection.parent.as_deref().cloned()
+        })
+        .map(|selection| selection.range)
+        .collect()
+    }
+
+    fn range(start: (u32, u32), end: (u32, u32)) -> Range {
+        Range {
+            start: Position::new(start.0, start.1),
+            end: Position::new(end.0, end.1),
+        }
+    }
+
+    #[test]
+    fn test_selection_ranges() {
+        let text = "# Top\n\n## Section\n\nSome text with [[other|Other note]] here\nand a second line\n\n## Next\nmore\n";
+        let test = TestVault::new(&[("note.md", text)]);
+        let path = test.path("note.md");
+        let at = |line, character| {
+            ranges(selection_range(
+                &test.vault,
+                &path,
+                Position::new(line, character),
+            ))
+        };
+
+        // the display text, the link, the paragraph, then the sections it is in
+        assert_eq!(
+            at(4, 25),
+            vec![
+                range((4, 23), (4, 33)),
+                range((4, 15), (4, 35)),
+                range((4, 0), (5, 17)),
+                range((2, 0), (6, 0)),
+                range((0, 0), (9, 0)),
+            ]
+        );
+
+        // a heading line is in its own section, not a paragraph
+        assert_eq!(at(2, 3), vec![range((2, 0), (6, 0)), range((0, 0), (9, 0))]);
+    }
+
+    #[test]
+    fn test_setext_heading_selection_ranges() {
+        let text = "Title\n=====\nsome text\n";
+        let test = TestVault::new(&[("note.md", text)]);
+        let path = test.path("note.md");
+
+        // the underline isn't part of the paragraph under it
+        assert_eq!(
+            ranges(selection_range(&test.vault, &path, Position::new(2, 2))),
+            vec![range((2, 0), (2, 9)), range((0, 0), (3, 0))]
+        );
+    }
+}
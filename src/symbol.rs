@@ -15,6 +15,7 @@ pub fn workspace_symbol(
     let referenceables = vault.select_referenceable_nodes(None);
     let symbol_informations = referenceables
         .into_iter()
+        .filter(|referenceable| !vault.is_excluded(referenceable.get_path()))
         .flat_map(|referenceable| {
             let range = match referenceable {
                 Referenceable::File(..) => tower_lsp::lsp_types::Range {
@@ -139,32 +140,32 @@ mod test {
             MDHeading {
                 level: HeadingLevel(1),
                 heading_text: "First".to_string(),
-                range: Default::default(),
+                ..Default::default()
             },
             MDHeading {
                 level: HeadingLevel(2),
                 heading_text: "Second".to_string(),
-                range: Default::default(),
+                ..Default::default()
             },
             MDHeading {
                 level: HeadingLevel(3),
                 heading_text: "Third".to_string(),
-                range: Default::default(),
+                ..Default::default()
             },
             MDHeading {
                 level: HeadingLevel(2),
                 heading_text: "Second".to_string(),
-                range: Default::default(),
+                ..Default::default()
             },
             MDHeading {
                 level: HeadingLevel(1),
                 heading_text: "First".to_string(),
-                range: Default::default(),
+                ..Default::default()
             },
             MDHeading {
                 level: HeadingLevel(1),
                 heading_text: "First".to_string(),
-                range: Default::default(),
+                ..Default::default()
             },
         ];
 
@@ -175,20 +176,20 @@ mod test {
                 heading: MDHeading {
                     level: HeadingLevel(1),
                     heading_text: "First".to_string(),
-                    range: Default::default(),
+                    ..Default::default()
                 },
                 children: Some(vec![
                     symbol::Node {
                         heading: MDHeading {
                             level: HeadingLevel(2),
                             heading_text: "Second".to_string(),
-                            range: Default::default(),
+                            ..Default::default()
                         },
                         children: Some(vec![symbol::Node {
                             heading: MDHeading {
                                 level: HeadingLevel(3),
                                 heading_text: "Third".to_string(),
-                                range: Default::default(),
+                                ..Default::default()
                             },
                             children: None,
                         }]),
@@ -197,7 +198,7 @@ mod test {
                         heading: MDHeading {
                             level: HeadingLevel(2),
                             heading_text: "Second".to_string(),
-                            range: Default::default(),
+                            ..Default::default()
                         },
                         children: None,
                     },
@@ -207,7 +208,7 @@ mod test {
                 heading: MDHeading {
                     level: HeadingLevel(1),
                     heading_text: "First".to_string(),
-                    range: Default::default(),
+                    ..Default::default()
                 },
                 children: None,
             },
@@ -215,7 +216,7 @@ mod test {
                 heading: MDHeading {
                     level: HeadingLevel(1),
                     heading_text: "First".to_string(),
-                    range: Default::default(),
+                    ..Default::default()
                 },
                 children: None,
             },
@@ -230,27 +231,27 @@ mod test {
             MDHeading {
                 level: HeadingLevel(1),
                 heading_text: "First".to_string(),
-                range: Default::default(),
+                ..Default::default()
             },
             MDHeading {
                 level: HeadingLevel(2),
                 heading_text: "Second".to_string(),
-                range: Default::default(),
+                ..Default::default()
             },
             MDHeading {
                 level: HeadingLevel(3),
                 heading_text: "Third".to_string(),
-                range: Default::default(),
+                ..Default::default()
             },
             MDHeading {
                 level: HeadingLevel(1),
                 heading_text: "First".to_string(),
-                range: Default::default(),
+                ..Default::default()
             },
             MDHeading {
                 level: HeadingLevel(1),
                 heading_text: "First".to_string(),
-                range: Default::default(),
+                ..Default::default()
             },
         ];
 
@@ -261,19 +262,19 @@ mod test {
                 heading: MDHeading {
                     level: HeadingLevel(1),
                     heading_text: "First".to_string(),
-                    range: Default::default(),
+                    ..Default::default()
                 },
                 children: Some(vec![symbol::Node {
                     heading: MDHeading {
                         level: HeadingLevel(2),
                         heading_text: "Second".to_string(),
-                        range: Default::default(),
+                        ..Default::default()
                     },
                     children: Some(vec![symbol::Node {
                         heading: MDHeading {
                             level: HeadingLevel(3),
                             heading_text: "Third".to_string(),
-                            range: Default::default(),
+                            ..Default::default()
                         },
                         children: None,
                     }]),
@@ -283,7 +284,7 @@ mod test {
                 heading: MDHeading {
                     level: HeadingLevel(1),
                     heading_text: "First".to_string(),
-                    range: Default::default(),
+                    ..Default::default()
                 },
                 children: None,
             },
@@ -291,7 +292,7 @@ mod test {
                 heading: MDHeading {
                     level: HeadingLevel(1),
                     heading_text: "First".to_string(),
-                    range: Default::default(),
+                    ..Default::default()
                 },
                 children: None,
             },
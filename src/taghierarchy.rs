@@ -0,0 +1,131 @@
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::TextDocumentIdentifier;
+
+use crate::vault::{Referenceable, Vault};
+
+/// The method of the custom request for the parent and children of a nested tag
+pub const TAG_HIERARCHY_METHOD: &str = "markdown-oxide/tagHierarchy";
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TagHierarchyParams {
+    /// A file in the vault whose tags are navigated
+    pub text_document: TextDocumentIdentifier,
+    /// The tag, with or without its `#`; the top level tags are its children if unset
+    pub tag: Option<String>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct TagHierarchy {
+    pub tag: Option<TagNode>,
+    pub parent: Option<TagNode>,
+    pub children: Vec<TagNode>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct TagNode {
+    /// The full tag, like `project/active`
+    pub tag: String,
+    /// The uses of the tag and of the tags nested in it
+    pub count: usize,
+}
+
+/// The tag's parent and the tags nested directly in it, grouping the vault's tags by their `/` segments. A tag that is
+/// only used nested, like `project` in `#project/active`, is still in the hierarchy. None if the vault has no such tag.
+pub fn tag_hierarchy(vault: &Vault, tag: Option<&str>) -> Option<TagHierarchy> {
+    let tags = vault
+        .select_referenceable_nodes(None)
+        .into_iter()
+        .filter_map(|referenceable| match referenceable {
+            Referenceable::Tag(path, tag) if !vault.is_excluded(path) => Some(tag.tag_ref.as_str()),
+            _ => None,
+        })
+        .collect_vec();
+
+    hierarchy(&tags, tag.map(|tag| tag.trim_start_matches('#')))
+}
+
+fn hierarchy(tags: &[&str], tag: Option<&str>) -> Option<TagHierarchy> {
+    let node = |tag: &str| TagNode {
+        tag: tag.to_string(),
+        count: tags.iter().filter(|used| is_within(used, tag)).count(),
+    };
+
+    let (tag, parent, prefix) = match tag.filter(|tag| !tag.is_empty()) {
+        Some(tag) => {
+            let tag = node(tag);
+            if tag.count == 0 {
+                return None;
+            }
+
+            let parent = tag.tag.rsplit_once('/').map(|(parent, _)| node(parent));
+            let prefix = format!("{}/", tag.tag);
+            (Some(tag), parent, prefix)
+        }
+        None => (None, None, String::new()),
+    };
+
+    let children = tags
+        .iter()
+        .filter_map(|used| used.strip_prefix(&prefix))
+        .filter_map(|rest| rest.split('/').next())
+        .filter(|segment| !segment.is_empty())
+        .unique()
+        .sorted()
+        .map(|segment| node(&format!("{}{}", prefix, segment)))
+        .collect();
+
+    Some(TagHierarchy {
+        tag,
+        parent,
+        children,
+    })
+}
+
+/// Whether the used tag is the tag or nested in it
+fn is_within(used: &str, tag: &str) -> bool {
+    used.strip_prefix(tag)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hierarchy, TagNode};
+
+    #[test]
+    fn test_tag_hierarchy() {
+        let tags = [
+            "project/active/urgent",
+            "project/active",
+            "project/archived",
+            "project/active/urgent",
+            "projects",
+            "idea",
+        ];
+        let node = |tag: &str, count| TagNode {
+            tag: tag.into(),
+            count,
+        };
+
+        let active = hierarchy(&tags, Some("project/active")).unwrap();
+        assert_eq!(active.tag, Some(node("project/active", 3)));
+        assert_eq!(active.parent, Some(node("project", 4)));
+        assert_eq!(active.children, vec![node("project/active/urgent", 2)]);
+
+        let project = hierarchy(&tags, Some("project")).unwrap();
+        assert_eq!(project.parent, None);
+        assert_eq!(
+            project.children,
+            vec![node("project/active", 3), node("project/archived", 1)]
+        );
+
+        let roots = hierarchy(&tags, None).unwrap();
+        assert_eq!(
+            roots.children,
+            vec![node("idea", 1), node("project", 4), node("projects", 1)]
+        );
+
+        assert_eq!(hierarchy(&tags, Some("proj")), None);
+    }
+}
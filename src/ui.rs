@@ -1,9 +1,18 @@
 use std::path::Path;
 
 use itertools::Itertools;
-use tower_lsp::lsp_types::{MarkupContent, MarkupKind};
+use once_cell::sync::Lazy;
+use percent_encoding::percent_decode_str;
+use regex::{Captures, Regex};
+use tower_lsp::lsp_types::{MarkupContent, MarkupKind, Url};
 
-use crate::vault::{get_obsidian_ref_path, Preview, Reference, Referenceable, Vault};
+use crate::{
+    diagnostics::{similar_files, unresolved_link_file},
+    vault::{
+        get_obsidian_ref_path, is_image, MDHeading, MDIndexedBlock, Preview, Reference,
+        Referenceable, Vault,
+    },
+};
 
 fn referenceable_string(vault: &Vault, referenceables: &[Referenceable]) -> Option<String> {
     let referenceable = referenceables.first()?;
@@ -12,13 +21,17 @@ fn referenceable_string(vault: &Vault, referenceables: &[Referenceable]) -> Opti
 
     let written_text_preview = match preview {
         Some(Preview::Empty) => "No Text".into(),
-        Some(Preview::Text(text)) => match referenceable {
-            Referenceable::File(_, _) => format!("`File Preview:`\n\n{}", text),
-            Referenceable::Heading(_, _) => format!("`Heading Preview:`\n\n{}", text),
-            Referenceable::IndexedBlock(_, _) => format!("`Block Preview:`\n\n{}", text),
-            Referenceable::Footnote(_, _) => format!("`Footnote Preview:`\n\n{}", text),
-            _ => format!("`Preview:`\n{}", text),
-        },
+        Some(Preview::Text(text)) => {
+            let text = render_preview(vault, referenceable.get_path(), &text);
+
+            match referenceable {
+                Referenceable::File(_, _) => format!("`File Preview:`\n\n{}", text),
+                Referenceable::Heading(_, _) => format!("`Heading Preview:`\n\n{}", text),
+                Referenceable::IndexedBlock(_, _) => format!("`Block Preview:`\n\n{}", text),
+                Referenceable::Footnote(_, _) => format!("`Footnote Preview:`\n\n{}", text),
+                _ => format!("`Preview:`\n{}", text),
+            }
+        }
         None => "No Preview".into(),
     };
 
@@ -50,6 +63,83 @@ fn referenceable_string(vault: &Vault, referenceables: &[Referenceable]) -> Opti
     ))
 }
 
+/// Longest preview text shown, in bytes; the preview is cut at the end of the line that reaches it
+const PREVIEW_LENGTH: usize = 1500;
+
+/// The preview text as markdown that clients render: HTML comments are removed, embedded images link to their files,
+/// and callouts are given a bold title in place of the `[!type]` that clients don't understand
+fn render_preview(vault: &Vault, path: &Path, text: &str) -> String {
+    static COMMENT_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?s)<!--.*?-->").unwrap());
+    // ![[image.png]], ![[image.png|300]]
+    static WIKI_EMBED_RE: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"!\[\[(?<file>[^\[\]\|#]+)(\|[^\[\]]*)?\]\]").unwrap());
+    // ![alt](image.png)
+    static MD_IMAGE_RE: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"!\[(?<alt>[^\[\]]*)\]\((?<src><[^>]+>|[^\s)]+)\)").unwrap());
+    // > [!note] Title
+    static CALLOUT_RE: Lazy<Regex> = Lazy::new(|| {
+        Regex::new(r"(?m)^(?<quote>(>[ \t]*)+)\[!(?<type>[^\]]+)\][+-]?[ \t]*(?<title>.*)$")
+            .unwrap()
+    });
+
+    let text = COMMENT_RE.replace_all(text, "");
+
+    let text = WIKI_EMBED_RE.replace_all(&text, |captures: &Captures| {
+        let file = &captures["file"];
+        if !is_image(Path::new(file)) {
+            return captures[0].to_string();
+        }
+        match image_url(vault, path, file, true) {
+            Some(url) => format!("![{}]({})", file, url),
+            None => captures[0].to_string(),
+        }
+    });
+
+    let text = MD_IMAGE_RE.replace_all(&text, |captures: &Captures| {
+        let src = captures["src"]
+            .trim_start_matches('<')
+            .trim_end_matches('>');
+        match image_url(vault, path, src, false) {
+            Some(url) => format!("![{}]({})", &captures["alt"], url),
+            None => captures[0].to_string(),
+        }
+    });
+
+    let text = CALLOUT_RE.replace_all(&text, |captures: &Captures| {
+        let title = match captures["title"].trim() {
+            "" => {
+                let callout_type = &captures["type"];
+                let mut chars = callout_type.chars();
+                chars
+                    .next()
+                    .map(|first| first.to_uppercase().chain(chars).collect::<String>())
+                    .unwrap_or_default()
+            }
+            title => title.to_string(),
+        };
+
+        format!("{}**{}**", &captures["quote"], title)
+    });
+
+    let mut length = 0;
+    text.split_inclusive('\n')
+        .take_while(|line| {
+            let fits = length < PREVIEW_LENGTH;
+            length += line.len();
+            fits
+        })
+        .collect()
+}
+
+/// A file url for an image in the vault, the attachment that the embed links to. None for urls and for images that
+/// can't be found.
+fn image_url(vault: &Vault, path: &Path, src: &str, wiki: bool) -> Option<Url> {
+    let src = percent_decode_str(src).decode_utf8().ok()?;
+    let image = vault.select_linked_attachment(path, &src, wiki)?;
+
+    Url::from_file_path(image).ok()
+}
+
 pub fn preview_referenceable(
     vault: &Vault,
     referenceable: &Referenceable,
@@ -62,6 +152,75 @@ pub fn preview_referenceable(
     })
 }
 
+/// The whole block that embedding it transcludes, such as a list item with its nested items
+pub fn preview_block_embed(
+    vault: &Vault,
+    path: &Path,
+    block: &MDIndexedBlock,
+) -> Option<MarkupContent> {
+    let text = vault.select_block_embed(path, block)?;
+
+    Some(MarkupContent {
+        kind: MarkupKind::Markdown,
+        value: format!("`Embed Preview:`\n\n{}", render_preview(vault, path, &text)),
+    })
+}
+
+/// A heading with its whole section, as embedding it, `![[file#heading]]`, transcludes
+pub fn preview_heading_embed(
+    vault: &Vault,
+    path: &Path,
+    heading: &MDHeading,
+) -> Option<MarkupContent> {
+    let text = vault.select_heading_section(path, heading)?;
+
+    Some(MarkupContent {
+        kind: MarkupKind::Markdown,
+        value: format!("`Embed Preview:`\n\n{}", render_preview(vault, path, &text)),
+    })
+}
+
+/// What an embed transcludes: a heading's whole section or a whole block. None for other embeds, which are
+/// previewed like links.
+pub fn preview_embed(vault: &Vault, referenceable: &Referenceable) -> Option<MarkupContent> {
+    match referenceable {
+        Referenceable::Heading(path, heading) => preview_heading_embed(vault, path, heading),
+        Referenceable::IndexedBlock(path, block) => preview_block_embed(vault, path, block),
+        _ => None,
+    }
+}
+
+/// An attachment's path from the vault root and its size; images are shown too, for clients that render them
+pub fn preview_attachment(vault: &Vault, path: &Path) -> Option<MarkupContent> {
+    let relative = pathdiff::diff_paths(path, vault.root_dir())?;
+    let relative = relative.to_str()?;
+    let size = std::fs::metadata(path)
+        .map(|meta| file_size(meta.len()))
+        .unwrap_or_else(|_| "missing".into());
+    let details = format!("`{}` ({})", relative, size);
+
+    let value = match is_image(path) {
+        true => {
+            let url = Url::from_file_path(path).ok()?;
+            format!("![{}]({})\n\n{}", relative, url, details)
+        }
+        false => details,
+    };
+
+    Some(MarkupContent {
+        kind: MarkupKind::Markdown,
+        value,
+    })
+}
+
+fn file_size(bytes: u64) -> String {
+    match bytes {
+        0..=1023 => format!("{} B", bytes),
+        1024..=1048575 => format!("{:.1} KB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MB", bytes as f64 / 1048576.0),
+    }
+}
+
 use Reference::*;
 
 pub fn preview_reference(
@@ -91,3 +250,136 @@ pub fn preview_reference(
         Tag(_) => None,
     }
 }
+
+/// Why a link is unresolved: the note it links to doesn't exist, or the note has no such heading or block. Notes with
+/// similar names are suggested, and the code action that creates the target is named when there is one.
+pub fn preview_unresolved(
+    vault: &Vault,
+    reference_path: &Path,
+    reference: &Reference,
+) -> Option<MarkupContent> {
+    let referenceables = vault.select_referenceables_for_reference(reference, reference_path);
+    if referenceables.is_empty() || !referenceables.iter().all(Referenceable::is_unresolved) {
+        return None;
+    }
+
+    let message = match (
+        unresolved_link_file(vault, reference_path, reference),
+        reference,
+    ) {
+        (Some(file), _) => {
+            let suggestions = similar_files(vault, file)
+                .into_iter()
+                .map(|(_, name)| format!("`{}`", name))
+                .join(", ");
+
+            format!(
+                "Unresolved link — no note named `{}` exists{}",
+                file,
+                match suggestions.is_empty() {
+                    true => String::new(),
+                    false => format!("\n\nDid you mean {}?", suggestions),
+                }
+            )
+        }
+        (None, WikiHeadingLink(_, file, heading) | MDHeadingLink(_, file, heading)) => {
+            format!("Unresolved link — `{}` has no heading `{}`", file, heading)
+        }
+        (None, WikiIndexedBlockLink(_, file, index) | MDIndexedBlockLink(_, file, index)) => {
+            format!("Unresolved link — `{}` has no block `^{}`", file, index)
+        }
+        _ => return None,
+    };
+
+    let hint = match reference {
+        WikiFileLink(..) => "\n\nUse the `Create File` code action to create the note.",
+        WikiHeadingLink(..) => {
+            "\n\nUse the `Append Heading` code action to add the heading, creating the note if it doesn't exist."
+        }
+        _ => "",
+    };
+
+    Some(MarkupContent {
+        kind: MarkupKind::Markdown,
+        value: format!("{}{}", message, hint),
+    })
+}
+
+/// How many notes have the tag (without `#`), with links to up to `sample` of them, most recently modified first
+pub fn preview_tag(vault: &Vault, tag: &str, sample: usize) -> Option<MarkupContent> {
+    let files = vault.select_tagged_files(tag);
+
+    let notes = match files.len() {
+        1 => "1 note".to_string(),
+        count => format!("{} notes", count),
+    };
+
+    let links = files
+        .into_iter()
+        .sorted_by_key(|path| {
+            std::cmp::Reverse(
+                std::fs::metadata(path)
+                    .and_then(|meta| meta.modified())
+                    .ok(),
+            )
+        })
+        .take(sample)
+        .flat_map(|path| {
+            let refname = get_obsidian_ref_path(vault.root_dir(), path)?;
+            Some(format!("- [[{}]]", refname))
+        })
+        .join("\n");
+
+    Some(MarkupContent {
+        kind: MarkupKind::Markdown,
+        value: format!("`#{}` is used in {}\n\n{}", tag, notes, links),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::Url;
+
+    use crate::vault::TestVault;
+
+    use super::render_preview;
+
+    #[test]
+    fn test_preview_images() {
+        let test = TestVault::new(&[
+            ("notes/note.md", "# Note\n"),
+            ("assets/diagram.png", ""),
+            ("assets/my diagram.png", ""),
+        ]);
+        let note = test.path("notes/note.md");
+        let url = |file: &str| Url::from_file_path(test.path(file)).unwrap();
+        let preview = |text: &str| render_preview(&test.vault, &note, text);
+
+        // embeds by name, from the note and from the vault root all go to the attachment
+        assert_eq!(
+            preview("![[diagram.png|300]]"),
+            format!("![diagram.png]({})", url("assets/diagram.png"))
+        );
+        assert_eq!(
+            preview("![alt](../assets/diagram.png)"),
+            format!("![alt]({})", url("assets/diagram.png"))
+        );
+        assert_eq!(
+            preview("![alt](/assets/my%20diagram.png)"),
+            format!("![alt]({})", url("assets/my diagram.png"))
+        );
+        assert_eq!(
+            preview("![alt](<assets/my diagram.png>)"),
+            format!("![alt]({})", url("assets/my diagram.png"))
+        );
+
+        // images that aren't in the vault, and urls, are left as they are
+        for text in [
+            "![[missing.png]]",
+            "![alt](missing.png)",
+            "![alt](https://example.com/diagram.png)",
+        ] {
+            assert_eq!(preview(text), text);
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Settings;
+
+use super::MDFile;
+
+/// Bump when the parsed file format changes so that caches written by older versions are thrown away
+const CACHE_VERSION: u32 = 5;
+
+/// Parsed files from the last time the vault was indexed, keyed by path and modified time
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct VaultCache {
+    version: u32,
+    /// The settings that change how files are parsed; `(tags_in_codeblocks, references_in_codeblocks)`
+    parse_settings: (bool, bool),
+    files: HashMap<PathBuf, CachedFile>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct CachedFile {
+    modified: SystemTime,
+    md_file: MDFile,
+}
+
+impl VaultCache {
+    pub fn new(context: &Settings) -> VaultCache {
+        VaultCache {
+            version: CACHE_VERSION,
+            parse_settings: parse_settings(context),
+            files: HashMap::new(),
+        }
+    }
+
+    /// The cache for the vault; empty if there is none or it was written by another version or with other settings
+    pub fn load(context: &Settings, root_dir: &Path) -> VaultCache {
+        std::fs::read(cache_path(context, root_dir))
+            .ok()
+            .and_then(|bytes| serde_json::from_slice::<VaultCache>(&bytes).ok())
+            .filter(|cache| {
+                cache.version == CACHE_VERSION && cache.parse_settings == parse_settings(context)
+            })
+            .unwrap_or_else(|| VaultCache::new(context))
+    }
+
+    pub fn save(&self, context: &Settings, root_dir: &Path) -> std::io::Result<()> {
+        let path = cache_path(context, root_dir);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        std::fs::write(path, serde_json::to_vec(self)?)
+    }
+
+    /// The cached parse of the file, if it hasn't been modified since
+    pub fn get(&self, path: &Path, modified: SystemTime) -> Option<&MDFile> {
+        self.files
+            .get(path)
+            .filter(|cached| cached.modified == modified)
+            .map(|cached| &cached.md_file)
+    }
+
+    pub fn insert(&mut self, path: PathBuf, modified: SystemTime, md_file: MDFile) {
+        self.files.insert(path, CachedFile { modified, md_file });
+    }
+}
+
+fn parse_settings(context: &Settings) -> (bool, bool) {
+    (context.tags_in_codeblocks, context.references_in_codeblocks)
+}
+
+/// Caches are kept out of the vault, in the `cache_folder`, named by the vault's path
+fn cache_path(context: &Settings, root_dir: &Path) -> PathBuf {
+    let name = root_dir.to_string_lossy().replace(['/', '\\', ':'], "_");
+
+    PathBuf::from(shellexpand::tilde(&context.cache_folder).as_ref()).join(format!("{}.json", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::Path,
+        time::{Duration, SystemTime},
+    };
+
+    use crate::vault::{MDFile, MDTag};
+
+    use super::{VaultCache, CACHE_VERSION};
+
+    #[test]
+    fn test_cache_roundtrip() {
+        let path = Path::new("/home/vault/note.md");
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let md_file = MDFile {
+            tags: vec![MDTag {
+                tag_ref: "project".into(),
+                range: Default::default(),
+            }],
+            path: path.into(),
+            ..Default::default()
+        };
+
+        let mut cache = VaultCache {
+            version: CACHE_VERSION,
+            parse_settings: (true, true),
+            ..Default::default()
+        };
+        cache.insert(path.into(), modified, md_file.clone());
+
+        let json = serde_json::to_string(&cache).unwrap();
+        let loaded = serde_json::from_str::<VaultCache>(&json).unwrap();
+
+        assert_eq!(loaded, cache);
+        assert_eq!(loaded.get(path, modified), Some(&md_file));
+        assert_eq!(loaded.get(path, modified + Duration::from_secs(1)), None);
+    }
+}
@@ -1,10 +1,22 @@
+use std::ops::Range as ByteRange;
+
 use once_cell::sync::Lazy;
 use regex::Regex;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::{Position, Range, TextEdit};
 
-#[derive(Deserialize, Debug, Clone, Hash, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, Clone, Hash, PartialEq, Eq)]
 pub struct MDMetadata {
     aliases: Vec<String>,
+    /// Every frontmatter property with its scalar values; list values are flattened
+    properties: Vec<(String, Vec<String>)>,
+}
+
+/// The frontmatter fields read into typed values
+#[derive(Deserialize)]
+struct Frontmatter {
+    #[serde(default)]
+    aliases: Vec<String>,
 }
 
 impl MDMetadata {
@@ -18,19 +30,246 @@ impl MDMetadata {
 
         let metadata_match = metadata_match?;
 
-        let md_metadata = serde_yaml::from_str::<MDMetadata>(metadata_match.as_str());
+        let yaml = serde_yaml::from_str::<serde_yaml::Value>(metadata_match.as_str()).ok()?;
+
+        let properties = yaml
+            .as_mapping()?
+            .iter()
+            .flat_map(|(key, value)| {
+                let values = match value {
+                    serde_yaml::Value::Sequence(values) => values.iter().flat_map(scalar).collect(),
+                    value => scalar(value).into_iter().collect(),
+                };
 
-        md_metadata.ok()
+                Some((key.as_str()?.to_string(), values))
+            })
+            .collect();
+
+        let frontmatter = serde_yaml::from_value::<Frontmatter>(yaml).ok()?;
+
+        Some(MDMetadata {
+            aliases: frontmatter.aliases,
+            properties,
+        })
     }
 
     pub fn aliases(&self) -> &[String] {
         &self.aliases
     }
+
+    pub fn properties(&self) -> &[(String, Vec<String>)] {
+        &self.properties
+    }
+}
+
+fn scalar(value: &serde_yaml::Value) -> Option<String> {
+    match value {
+        serde_yaml::Value::String(string) => Some(string.clone()),
+        serde_yaml::Value::Number(number) => Some(number.to_string()),
+        serde_yaml::Value::Bool(bool) => Some(bool.to_string()),
+        _ => None,
+    }
+}
+
+/// The line of the closing `---` of the frontmatter at the start of the text
+pub fn frontmatter_end_line(text: &str) -> Option<usize> {
+    let mut lines = text.lines();
+
+    if lines.next()?.trim_end() != "---" {
+        return None;
+    }
+
+    lines
+        .position(|line| line.trim_end() == "---")
+        .map(|position| position + 1)
+}
+
+/// The tags in the frontmatter `tags` property, without `#`, with their byte ranges in the text. The property can be
+/// a list, `tags: [project, rust]` or one item per line, or a single string of tags, `tags: project rust`
+pub fn frontmatter_tags(text: &str) -> Vec<(&str, ByteRange<usize>)> {
+    let Some(end_line) = frontmatter_end_line(text) else {
+        return vec![];
+    };
+
+    // each line with its byte offset in the text
+    let lines = text
+        .split_inclusive('\n')
+        .scan(0, |offset, line| {
+            let start = *offset;
+            *offset += line.len();
+            Some((start, line.trim_end()))
+        })
+        .take(end_line)
+        .collect::<Vec<_>>();
+
+    static TAGS_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^tags?:[ \t]*(?<value>.*)$").unwrap());
+    // a list item may have no indent, `- project`, but the `---` that ends the frontmatter is not one
+    static ITEM_RE: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"^[ \t]*-(?<item>([ \t].*)?)$").unwrap());
+    static TAG_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r#"[^\s,\[\]"'#]+"#).unwrap());
+
+    let Some((line_nr, value)) = lines
+        .iter()
+        .enumerate()
+        .skip(1)
+        .find_map(|(line_nr, (_, line))| Some((line_nr, TAGS_RE.captures(line)?.name("value")?)))
+    else {
+        return vec![];
+    };
+
+    let values = std::iter::once((lines[line_nr].0, value)).chain(
+        lines[line_nr + 1..]
+            .iter()
+            .map_while(|(offset, line)| Some((*offset, ITEM_RE.captures(line)?.name("item")?))),
+    );
+
+    values
+        .flat_map(|(offset, value)| {
+            TAG_RE.find_iter(value.as_str()).map(move |tag| {
+                let start = offset + value.start() + tag.start();
+                (tag.as_str(), start..start + tag.len())
+            })
+        })
+        .filter(|(tag, _)| tag.chars().any(|c| c.is_alphabetic()))
+        .collect()
+}
+
+/// The edit that appends the alias to the `aliases` of the frontmatter at the start of the text, adding the property or
+/// the whole frontmatter if they are missing. None if the alias is already there.
+pub fn add_alias_edit(text: &str, alias: &str) -> Option<TextEdit> {
+    if MDMetadata::new(text).is_some_and(|metadata| metadata.aliases.iter().any(|it| it == alias)) {
+        return None;
+    }
+
+    let alias = yaml_string(alias);
+    let insert = |line: usize, character: usize, new_text: String| {
+        let position = Position {
+            line: line as u32,
+            character: character as u32,
+        };
+
+        TextEdit {
+            range: Range {
+                start: position,
+                end: position,
+            },
+            new_text,
+        }
+    };
+
+    let Some(end_line) = frontmatter_end_line(text) else {
+        return Some(insert(0, 0, format!("---\naliases:\n  - {}\n---\n", alias)));
+    };
+
+    let lines = text.lines().map(|line| line.trim_end()).collect::<Vec<_>>();
+
+    static ALIASES_RE: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"^aliases:\s*(?<value>.*)$").unwrap());
+
+    let Some((line_nr, value)) = (1..end_line).find_map(|line_nr| {
+        let value = ALIASES_RE.captures(lines[line_nr])?.name("value")?;
+        Some((line_nr, value))
+    }) else {
+        return Some(insert(end_line, 0, format!("aliases:\n  - {}\n", alias)));
+    };
+
+    let line = lines[line_nr];
+    let value_start = line[..value.start()].chars().count();
+    // aliases: [alias] # comment
+    let value = value.as_str()[..comment_start(value.as_str())].trim_end();
+
+    match value {
+        // aliases:
+        //   - alias
+        "" => {
+            let (last_line_nr, indent) = (line_nr + 1..end_line)
+                .map_while(|item_nr| {
+                    let item = lines[item_nr];
+                    let indent = &item[..item.len() - item.trim_start().len()];
+                    // items may be written at the start of the line, like `aliases:` itself
+                    let item = item.trim_start();
+                    (item == "-" || item.starts_with("- ")).then_some((item_nr, indent))
+                })
+                .last()
+                .unwrap_or((line_nr, "  "));
+
+            Some(insert(
+                last_line_nr,
+                lines[last_line_nr].chars().count(),
+                format!("\n{}- {}", indent, alias),
+            ))
+        }
+        // aliases: [alias, alias]
+        flow if flow.starts_with('[') && flow.ends_with(']') => {
+            let separator = match flow[1..flow.len() - 1].trim().is_empty() {
+                true => "",
+                false => ", ",
+            };
+
+            Some(insert(
+                line_nr,
+                value_start + flow.chars().count() - 1,
+                format!("{}{}", separator, alias),
+            ))
+        }
+        // aliases: alias
+        scalar => Some(TextEdit {
+            range: Range {
+                start: Position {
+                    line: line_nr as u32,
+                    character: value_start as u32,
+                },
+                end: Position {
+                    line: line_nr as u32,
+                    character: (value_start + scalar.chars().count()) as u32,
+                },
+            },
+            new_text: format!("[{}, {}]", scalar, alias),
+        }),
+    }
+}
+
+/// The byte index where the yaml comment of a value starts, at a `#` after whitespace outside of quotes, or the length
+/// of the value if it has none
+fn comment_start(value: &str) -> usize {
+    let mut quote = None;
+    let mut previous = ' ';
+    for (index, char) in value.char_indices() {
+        match (quote, char) {
+            (None, '#') if previous.is_whitespace() => return index,
+            (None, '"' | '\'') => quote = Some(char),
+            (Some(open), _) if char == open => quote = None,
+            _ => (),
+        }
+        previous = char;
+    }
+
+    value.len()
+}
+
+/// The alias as a yaml string, quoted if it has characters that yaml would read differently or yaml would read it as
+/// something other than a string, like `123`, `true` or `null`
+fn yaml_string(text: &str) -> String {
+    let needs_quotes = text
+        .starts_with(['-', '?', '!', '&', '*', '|', '>', '%', '@', '`', '\'', '"'])
+        || text.contains([':', '#', ',', '[', ']', '{', '}'])
+        || text.trim() != text
+        || !matches!(
+            serde_yaml::from_str(text),
+            Ok(serde_yaml::Value::String(string)) if string == text
+        );
+
+    match needs_quotes {
+        true => format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\"")),
+        false => text.to_string(),
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::vault::metadata::MDMetadata;
+    use crate::vault::metadata::{
+        add_alias_edit, frontmatter_end_line, frontmatter_tags, yaml_string, MDMetadata,
+    };
 
     #[test]
     fn test_aliases() {
@@ -50,4 +289,152 @@ aliases:
         .unwrap();
         assert_eq!(metadata.aliases(), &["alias1", "alias2"]);
     }
+
+    #[test]
+    fn test_properties() {
+        let metadata = MDMetadata::new(
+            r"---
+status: done
+tags:
+    - project
+    - rust
+priority: 1
+---",
+        )
+        .unwrap();
+
+        assert!(metadata.aliases().is_empty());
+        assert_eq!(
+            metadata.properties(),
+            &[
+                ("status".to_string(), vec!["done".to_string()]),
+                (
+                    "tags".to_string(),
+                    vec!["project".to_string(), "rust".to_string()]
+                ),
+                ("priority".to_string(), vec!["1".to_string()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_frontmatter_end_line() {
+        assert_eq!(
+            frontmatter_end_line("---\naliases: []\ntags: []\n---\n# Heading"),
+            Some(3)
+        );
+        assert_eq!(frontmatter_end_line("---\naliases: []\n"), None);
+        assert_eq!(frontmatter_end_line("# Heading\n---\n---"), None);
+    }
+
+    #[test]
+    fn test_frontmatter_tags() {
+        let text = "---\ntags: [project, \"rust/lsp\"]\n---\n#inline";
+        assert_eq!(
+            frontmatter_tags(text),
+            vec![("project", 11..18), ("rust/lsp", 21..29)]
+        );
+
+        let text = "---\naliases: []\ntags:\n  - project\n  - 2024\nstatus: done\n---";
+        assert_eq!(frontmatter_tags(text), vec![("project", 26..33)]);
+
+        let text = "---\ntags:\n- project\n- rust\n---\n- not a tag";
+        assert_eq!(
+            frontmatter_tags(text),
+            vec![("project", 12..19), ("rust", 22..26)]
+        );
+
+        assert_eq!(
+            frontmatter_tags("---\ntags: project rust\n---"),
+            vec![("project", 10..17), ("rust", 18..22)]
+        );
+        assert_eq!(frontmatter_tags("tags: project"), vec![]);
+    }
+
+    /// The text with the edit applied
+    fn with_alias(text: &str, alias: &str) -> Option<String> {
+        let edit = add_alias_edit(text, alias)?;
+        let offset = |position: tower_lsp::lsp_types::Position| {
+            text.split_inclusive('\n')
+                .take(position.line as usize)
+                .map(str::len)
+                .sum::<usize>()
+                + position.character as usize
+        };
+
+        let mut text = text.to_string();
+        text.replace_range(
+            offset(edit.range.start)..offset(edit.range.end),
+            &edit.new_text,
+        );
+
+        Some(text)
+    }
+
+    #[test]
+    fn test_add_alias_edit() {
+        assert_eq!(
+            with_alias("# Heading", "alias").as_deref(),
+            Some("---\naliases:\n  - alias\n---\n# Heading")
+        );
+        assert_eq!(
+            with_alias("---\ntags: [rust]\n---\n# Heading", "alias").as_deref(),
+            Some("---\ntags: [rust]\naliases:\n  - alias\n---\n# Heading")
+        );
+        assert_eq!(
+            with_alias("---\naliases: [one, two]\n---", "alias").as_deref(),
+            Some("---\naliases: [one, two, alias]\n---")
+        );
+        assert_eq!(
+            with_alias("---\naliases: []\n---", "alias").as_deref(),
+            Some("---\naliases: [alias]\n---")
+        );
+        assert_eq!(
+            with_alias("---\naliases:\n    - one\ntags: []\n---", "alias: two").as_deref(),
+            Some("---\naliases:\n    - one\n    - \"alias: two\"\ntags: []\n---")
+        );
+        assert_eq!(
+            with_alias("---\naliases: one\n---", "alias").as_deref(),
+            Some("---\naliases: [one, alias]\n---")
+        );
+        assert_eq!(with_alias("---\naliases: [alias]\n---", "alias"), None);
+    }
+
+    #[test]
+    fn test_add_alias_edit_unindented_list() {
+        assert_eq!(
+            with_alias("---\naliases:\n- one\n- two\ntags: []\n---", "alias").as_deref(),
+            Some("---\naliases:\n- one\n- two\n- alias\ntags: []\n---")
+        );
+    }
+
+    #[test]
+    fn test_add_alias_edit_comment() {
+        assert_eq!(
+            with_alias("---\naliases: [one, two] # the old names\n---", "alias").as_deref(),
+            Some("---\naliases: [one, two, alias] # the old names\n---")
+        );
+        assert_eq!(
+            with_alias("---\naliases: one # the old name\n---", "alias").as_deref(),
+            Some("---\naliases: [one, alias] # the old name\n---")
+        );
+        assert_eq!(
+            with_alias("---\naliases: [\"C #1\"]\n---", "alias").as_deref(),
+            Some("---\naliases: [\"C #1\", alias]\n---")
+        );
+    }
+
+    #[test]
+    fn test_yaml_string() {
+        assert_eq!(yaml_string("alias"), "alias");
+        assert_eq!(yaml_string("123"), "\"123\"");
+        assert_eq!(yaml_string("1.5"), "\"1.5\"");
+        assert_eq!(yaml_string("true"), "\"true\"");
+        assert_eq!(yaml_string("null"), "\"null\"");
+        assert_eq!(yaml_string("~"), "\"~\"");
+        assert_eq!(
+            with_alias("---\naliases: [one]\n---", "2024").as_deref(),
+            Some("---\naliases: [one, \"2024\"]\n---")
+        );
+    }
 }
@@ -1,94 +1,410 @@
+mod cache;
 mod metadata;
 mod parsing;
+#[cfg(test)]
+mod test_vault;
 
 use std::{
     char,
     collections::{HashMap, HashSet},
+    ffi::OsStr,
     hash::Hash,
     iter,
     ops::{Deref, DerefMut, Not, Range},
     path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
     time::SystemTime,
 };
 
+use ignore::{gitignore::Gitignore, WalkBuilder};
+use indexmap::IndexMap;
 use itertools::Itertools;
+use nanoid::nanoid;
 use once_cell::sync::Lazy;
 use pathdiff::diff_paths;
+use percent_encoding::percent_decode_str;
 use rayon::prelude::*;
 use regex::{Captures, Match, Regex};
 use ropey::Rope;
 use serde::{Deserialize, Serialize};
-use tower_lsp::lsp_types::Position;
-use walkdir::WalkDir;
+use tower_lsp::lsp_types::{Position, TextEdit};
+
+/// The markdown files and the attachments of the vault. Hidden folders, like `.obsidian` and `.trash`, and the files
+/// matched by `.gitignore` are left out, whether or not the vault is a git repository, unless `index_ignored`.
+///
+/// Symlinked files and folders are followed, except for a symlink to a folder it is in, which would loop. A file reached
+/// through more than one path, like through a symlink to a folder of the vault, is listed once: by its path without
+/// symlinks if it has one, else by its shortest path.
+///
+/// The folders of `nested_roots`, the roots of other vaults inside this one, are left out as well; their files are in
+/// those vaults.
+fn vault_files(
+    root_dir: &Path,
+    index_ignored: bool,
+    nested_roots: &[PathBuf],
+) -> (Vec<PathBuf>, Vec<PathBuf>) {
+    let real_root = root_dir.canonicalize().ok();
+    let is_real_path = |path: &Path, real_path: &Path| {
+        real_root
+            .as_ref()
+            .zip(path.strip_prefix(root_dir).ok())
+            .is_some_and(|(real_root, relative)| real_root.join(relative) == real_path)
+    };
+
+    WalkBuilder::new(root_dir)
+        .standard_filters(!index_ignored)
+        .require_git(false)
+        .follow_links(true)
+        .filter_entry({
+            let nested_roots = nested_roots.to_vec();
+            move |e| {
+                e.depth() == 0
+                    || !nested_roots.iter().any(|root| root == e.path())
+                        && (index_ignored || !is_skipped_name(e.file_name()))
+            }
+        })
+        .build()
+        // the walk yields an error for each symlink loop, which is skipped
+        .flatten()
+        .filter(|f| f.file_type().is_some_and(|file_type| file_type.is_file()))
+        .map(|f| f.into_path())
+        .filter_map(|path| Some((path.canonicalize().ok()?, path)))
+        .sorted_by_key(|(real_path, path)| {
+            (
+                real_path.clone(),
+                !is_real_path(path, real_path),
+                path.components().count(),
+            )
+        })
+        .unique_by(|(real_path, _)| real_path.clone())
+        .map(|(_, path)| path)
+        .partition(|path| path.extension().and_then(|e| e.to_str()) == Some("md"))
+}
 
-impl Vault {
-    pub fn construct_vault(context: &Settings, root_dir: &Path) -> Result<Vault, std::io::Error> {
-        let md_file_paths = WalkDir::new(root_dir)
-            .into_iter()
-            .filter_entry(|e| {
-                !e.file_name()
-                    .to_str()
-                    .map(|s| s.starts_with('.') || s == "logseq") // TODO: This is a temporary fix; a hidden config is better
-                    .unwrap_or(false)
-            })
-            .flatten()
-            .filter(|f| f.path().extension().and_then(|e| e.to_str()) == Some("md"))
-            .collect_vec();
+/// Whether the walk of `vault_files` skips a file or folder by its name: hidden ones, like `.obsidian`, and Logseq's
+/// folder of its own files
+fn is_skipped_name(name: &OsStr) -> bool {
+    let name = name.to_string_lossy();
+    name.starts_with('.') || name == "logseq"
+}
 
-        let md_files: HashMap<PathBuf, MDFile> = md_file_paths
-            .par_iter()
-            .flat_map(|p| {
-                let text = std::fs::read_to_string(p.path())?;
-                let md_file = MDFile::new(context, &text, PathBuf::from(p.path()));
+/// Whether `vault_files` leaves out a file of the vault, for files that are created after it is indexed: files skipped
+/// by name, or in a folder skipped by name, and files matched by a `.ignore` or `.gitignore` of the vault, of which the
+/// one in the deepest folder decides, as in the walk
+fn is_ignored(root_dir: &Path, path: &Path) -> bool {
+    let Ok(relative) = path.strip_prefix(root_dir) else {
+        return true;
+    };
+    if relative
+        .components()
+        .any(|component| is_skipped_name(component.as_os_str()))
+    {
+        return true;
+    }
 
-                return Ok::<(PathBuf, MDFile), std::io::Error>((p.path().into(), md_file));
+    path.ancestors()
+        .skip(1)
+        .take_while(|folder| folder.starts_with(root_dir))
+        .find_map(|folder| {
+            [".ignore", ".gitignore"].iter().find_map(|name| {
+                let ignore_file = folder.join(name);
+                if !ignore_file.is_file() {
+                    return None;
+                }
+
+                match Gitignore::new(ignore_file)
+                    .0
+                    .matched_path_or_any_parents(path, false)
+                {
+                    ignore::Match::None => None,
+                    matched => Some(matched.is_ignore()),
+                }
             })
-            .collect();
+        })
+        .unwrap_or(false)
+}
 
-        let ropes: HashMap<PathBuf, Rope> = md_file_paths
-            .iter()
-            .flat_map(|p| {
-                let text = std::fs::read_to_string(p.path())?;
-                let rope = Rope::from_str(&text);
+impl Vault {
+    /// Parse the vault's files in parallel, calling `progress` with the number of files indexed so far and the total
+    /// after each one. Files that can't be read are left out of the vault, as are the folders of `nested_roots`, the
+    /// other vaults inside this one.
+    pub fn construct_vault(
+        context: &Settings,
+        root_dir: &Path,
+        nested_roots: &[PathBuf],
+        progress: impl Fn(usize, usize) + Sync,
+    ) -> Result<Vault, std::io::Error> {
+        let (md_file_paths, attachments) =
+            vault_files(root_dir, context.index_ignored_files, nested_roots);
+
+        let total = md_file_paths.len();
+        let indexed = AtomicUsize::new(0);
+
+        // files that haven't been modified since the last index are not parsed again
+        let cache = VaultCache::load(context, root_dir);
+
+        let parsed: Vec<(PathBuf, MDFile, Rope, Option<SystemTime>)> = md_file_paths
+            .into_par_iter()
+            .filter_map(|path| {
+                let parsed = std::fs::read_to_string(&path).ok().map(|text| {
+                    let modified = std::fs::metadata(&path)
+                        .and_then(|meta| meta.modified())
+                        .ok();
+                    let md_file = modified
+                        .and_then(|modified| cache.get(&path, modified))
+                        .cloned()
+                        .unwrap_or_else(|| MDFile::new(context, &text, path.clone()));
+
+                    (path, md_file, Rope::from_str(&text), modified)
+                });
+                progress(indexed.fetch_add(1, Ordering::Relaxed) + 1, total);
 
-                return Ok::<(PathBuf, Rope), std::io::Error>((p.path().into(), rope));
+                parsed
             })
             .collect();
 
+        let mut cache = VaultCache::new(context);
+        let mut md_files = HashMap::with_capacity(parsed.len());
+        let mut ropes = HashMap::with_capacity(parsed.len());
+        for (path, md_file, rope, modified) in parsed {
+            if let Some(modified) = modified {
+                cache.insert(path.clone(), modified, md_file.clone());
+            }
+            ropes.insert(path.clone(), rope);
+            md_files.insert(path, md_file);
+        }
+        // the cache only speeds up the next start; the vault is fine without it
+        let _ = cache.save(context, root_dir);
+
+        let mut displayed_links = HashMap::<String, HashSet<PathBuf>>::new();
+        for (path, md_file) in &md_files {
+            for name in md_file.references.iter().filter_map(displayed_link_name) {
+                displayed_links
+                    .entry(name)
+                    .or_default()
+                    .insert(path.clone());
+            }
+        }
+
+        let mut file_names = HashMap::<String, HashSet<PathBuf>>::new();
+        for path in md_files.keys().chain(&attachments) {
+            if let Some(name) = file_name_key(path, context.case_insensitive_links) {
+                file_names.entry(name).or_default().insert(path.clone());
+            }
+        }
+
         Ok(Vault {
             ropes: ropes.into(),
             md_files: md_files.into(),
+            attachments,
             root_dir: root_dir.into(),
+            case_insensitive_links: context.case_insensitive_links,
+            exclude: context
+                .exclude
+                .iter()
+                .filter_map(|pattern| glob::Pattern::new(pattern).ok())
+                .collect(),
+            link_extensions: context
+                .link_extensions
+                .iter()
+                .map(|extension| extension.trim_start_matches('.').to_lowercase())
+                .collect(),
+            index_ignored: context.index_ignored_files,
+            displayed_links,
+            file_names,
         })
     }
 
-    pub fn update_vault(context: &Settings, old: &mut Vault, new_file: (&PathBuf, &str)) {
-        let new_md_file = MDFile::new(context, new_file.1, new_file.0.clone());
-        let new = old.md_files.get_mut(new_file.0);
+    /// Reparse a single file, replacing only its entries; the rest of the vault is left as is
+    pub fn update_file(&mut self, context: &Settings, path: &Path, text: &str) {
+        self.index_displayed_links(path, false);
+        self.md_files
+            .insert(path.into(), MDFile::new(context, text, path.into()));
+        self.ropes.insert(path.into(), Rope::from_str(text));
+        self.index_displayed_links(path, true);
+        self.index_file_name(path, true);
+    }
 
-        match new {
-            Some(file) => {
-                *file = new_md_file;
-            }
-            None => {
-                old.md_files.insert(new_file.0.into(), new_md_file);
-            }
+    /// Add the file's links with a display text to `displayed_links`, or remove them
+    fn index_displayed_links(&mut self, path: &Path, add: bool) {
+        let Some(md_file) = self.md_files.get(path) else {
+            return;
+        };
+
+        for name in md_file.references.iter().filter_map(displayed_link_name) {
+            let files = self.displayed_links.entry(name).or_default();
+            match add {
+                true => files.insert(path.into()),
+                false => files.remove(path),
+            };
+        }
+    }
+
+    /// Add the file to `file_names`, or remove it
+    fn index_file_name(&mut self, path: &Path, add: bool) {
+        let Some(name) = file_name_key(path, self.case_insensitive_links) else {
+            return;
+        };
+
+        let files = self.file_names.entry(name).or_default();
+        match add {
+            true => files.insert(path.into()),
+            false => files.remove(path),
         };
+    }
+
+    /// Remove a note or an attachment, or all of those in a folder; false if the vault didn't have any
+    pub fn remove_file(&mut self, path: &Path) -> bool {
+        let notes = self
+            .md_files
+            .keys()
+            .filter(|note| note.starts_with(path))
+            .cloned()
+            .collect_vec();
+        for note in &notes {
+            self.index_displayed_links(note, false);
+            self.index_file_name(note, false);
+            self.md_files.remove(note);
+            self.ropes.remove(note);
+        }
+
+        let (removed, attachments) = std::mem::take(&mut self.attachments)
+            .into_iter()
+            .partition::<Vec<_>, _>(|attachment| attachment.starts_with(path));
+        self.attachments = attachments;
+        for attachment in &removed {
+            self.index_file_name(attachment, false);
+        }
+
+        !notes.is_empty() || !removed.is_empty()
+    }
+
+    /// Whether the vault indexes the file, as it does the files it is built from: files in hidden folders, like
+    /// `.obsidian` and `.git`, and files matched by `.gitignore` are left out, unless `index_ignored_files`
+    pub fn is_indexed(&self, path: &Path) -> bool {
+        path.starts_with(&self.root_dir)
+            && (self.index_ignored || !is_ignored(&self.root_dir, path))
+    }
 
-        let new_rope = Rope::from_str(new_file.1);
-        let rope_entry = old.ropes.get_mut(new_file.0);
+    /// Add a file that isn't a note, like an image, to the files links can go to, unless it isn't indexed
+    pub fn add_attachment(&mut self, path: &Path) {
+        if self.is_indexed(path) && !self.attachments.iter().any(|attachment| attachment == path) {
+            self.attachments.push(path.into());
+            self.index_file_name(path, true);
+        }
+    }
 
-        match rope_entry {
-            Some(rope) => {
-                *rope = new_rope;
+    /// Move the files that were renamed, or that are in folders that were renamed, to their new paths; `renames` are
+    /// the old and new paths
+    pub fn rename_files(&mut self, context: &Settings, renames: &[(PathBuf, PathBuf)]) {
+        let moved = self
+            .ropes
+            .keys()
+            .filter_map(|path| Some((path.clone(), moved_path(path, renames)?)))
+            .collect::<Vec<_>>();
+
+        for (old_path, new_path) in moved {
+            let Some(rope) = self.ropes.get(&old_path) else {
+                continue;
+            };
+            let text = rope.to_string();
+
+            self.remove_file(&old_path);
+            if new_path.extension().is_some_and(|ext| ext == "md") {
+                self.update_file(context, &new_path, &text);
             }
-            None => {
-                old.ropes.insert(new_file.0.into(), new_rope);
+        }
+
+        let moved_attachments = self
+            .attachments
+            .iter()
+            .filter_map(|path| Some((path.clone(), moved_path(path, renames)?)))
+            .collect::<Vec<_>>();
+
+        for (old_path, new_path) in moved_attachments {
+            self.index_file_name(&old_path, false);
+            self.index_file_name(&new_path, true);
+            if let Some(attachment) = self
+                .attachments
+                .iter_mut()
+                .find(|attachment| **attachment == old_path)
+            {
+                *attachment = new_path;
             }
         }
     }
 }
 
+/// The new path of a file that was renamed, or that is in a folder that was renamed
+pub fn moved_path(path: &Path, renames: &[(PathBuf, PathBuf)]) -> Option<PathBuf> {
+    renames.iter().find_map(|(old, new)| {
+        let rest = path.strip_prefix(old).ok()?;
+        match rest.as_os_str().is_empty() {
+            true => Some(new.clone()),
+            false => Some(new.join(rest)),
+        }
+    })
+}
+
+/// The name of the file for `Vault::file_names`, lowercased when links are case insensitive
+fn file_name_key(path: &Path, case_insensitive: bool) -> Option<String> {
+    let name = path.file_name()?.to_str()?;
+    match case_insensitive {
+        true => Some(name.to_lowercase()),
+        false => Some(name.to_string()),
+    }
+}
+
+/// The lowercased name of the file a link with a display text goes to, for `Vault::displayed_links`; None for other
+/// references
+fn displayed_link_name(reference: &Reference) -> Option<String> {
+    let (Reference::WikiFileLink(data) | Reference::MDFileLink(data)) = reference else {
+        return None;
+    };
+    if data
+        .display_text
+        .as_deref()
+        .unwrap_or_default()
+        .trim()
+        .is_empty()
+    {
+        return None;
+    }
+
+    let name = data.reference_text.rsplit('/').next().unwrap_or_default();
+    Some(
+        name.trim_end_matches(".md")
+            .replace("%20", " ")
+            .to_lowercase(),
+    )
+}
+
+/// Extensions of the attachments that are images, which previews show and completions label as images
+pub const IMAGE_EXTENSIONS: [&str; 7] = ["png", "jpg", "jpeg", "gif", "svg", "webp", "bmp"];
+
+/// Whether the file is an image, by its extension in any case
+pub fn is_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            IMAGE_EXTENSIONS
+                .iter()
+                .any(|image| image.eq_ignore_ascii_case(ext))
+        })
+}
+
+/// A link to an attachment, like `![[diagram.png]]` or `[pic](assets/pic.png)`
+#[derive(Debug, Clone)]
+pub struct AttachmentLink<'a> {
+    pub attachment: &'a Path,
+    /// The range of the link, without the `!` of an embed
+    pub range: tower_lsp::lsp_types::Range,
+    /// The file as it is written in the link, percent decoded
+    pub file: String,
+    pub display: Option<String>,
+    pub wiki: bool,
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct MyHashMap<B: Hash>(HashMap<PathBuf, B>);
 
@@ -135,7 +451,24 @@ impl Hash for Vault {
 pub struct Vault {
     pub md_files: MyHashMap<MDFile>,
     pub ropes: MyHashMap<Rope>,
+    /// Files in the vault that aren't notes, like images and PDFs
+    pub attachments: Vec<PathBuf>,
     root_dir: PathBuf,
+    /// Whether links resolve to files whose names differ only in case
+    case_insensitive_links: bool,
+    /// Patterns of the paths, from the vault root, that are left out of completions, workspace symbols and diagnostics
+    exclude: Vec<glob::Pattern>,
+    /// The extensions wikilinks without one go to, in order of priority
+    link_extensions: Vec<String>,
+    /// Whether hidden and ignored files are indexed
+    index_ignored: bool,
+    /// The notes linking with a display text, like `[[2023-report|Annual Report]]`, by the lowercased name of the file
+    /// they link to; kept as files are updated, so the display texts of the links to a file are found without going
+    /// through every link of the vault
+    displayed_links: HashMap<String, HashSet<PathBuf>>,
+    /// The notes and attachments by file name, lowercased when links are case insensitive; kept as files are added and
+    /// removed, so wikilinks without an extension find the files named like them without going through every file
+    file_names: HashMap<String, HashSet<PathBuf>>,
 }
 
 /// Methods using vaults data
@@ -233,41 +566,107 @@ impl Vault {
                     .flat_map(|file| file.get_referenceables())
                     .collect::<Vec<_>>();
 
-                let resolved_referenceables_refnames: HashSet<String> = resolved_referenceables
+                // references are compared to the refnames by this key, so that links to files differing only in case
+                // and links to headings written differently aren't unresolved
+                let refname_key = |text: &str| {
+                    let (file, infile_ref) = match text.split_once('#') {
+                        Some((file, infile_ref)) if infile_ref.starts_with('^') => {
+                            (file, Some(infile_ref.to_string()))
+                        }
+                        Some((file, heading)) => (file, Some(heading_link_text(heading))),
+                        None => (text, None),
+                    };
+                    let file = match self.case_insensitive_links {
+                        true => file.to_lowercase(),
+                        false => file.to_string(),
+                    };
+
+                    match infile_ref {
+                        Some(infile_ref) => format!("{}#{}", file, infile_ref),
+                        None => file,
+                    }
+                };
+
+                let resolved_refnames = resolved_referenceables
+                    .par_iter()
+                    .flat_map(|resolved| resolved.get_refname(self.root_dir()))
+                    .collect::<Vec<_>>();
+
+                // `[[/folder/file]]` is from the vault root, so it is only resolved by the full path
+                let resolved_referenceables_paths: HashSet<String> = resolved_refnames
+                    .par_iter()
+                    .map(|refname| refname_key(refname))
+                    .collect();
+
+                let resolved_referenceables_refnames: HashSet<String> = resolved_refnames
+                    .par_iter()
+                    .flat_map(|refname| {
+                        Some(refname_key(&format!(
+                            "{}{}",
+                            refname.link_file_key()?,
+                            refname
+                                .infile_ref
+                                .as_ref()
+                                .map(|refe| format!("#{}", refe))
+                                .unwrap_or("".to_string())
+                        )))
+                    })
+                    .chain(resolved_referenceables_paths.par_iter().cloned())
+                    .collect();
+
+                // markdown links may go to a heading by its slug
+                let resolved_heading_slugs: HashSet<String> = resolved_referenceables
                     .par_iter()
                     .flat_map(|resolved| {
-                        resolved.get_refname(self.root_dir()).and_then(|refname| {
-                            vec![
-                                refname.to_string(),
-                                format!(
-                                    "{}{}",
-                                    refname.link_file_key()?,
-                                    refname
-                                        .infile_ref
-                                        .map(|refe| format!("#{}", refe))
-                                        .unwrap_or("".to_string())
-                                ),
-                            ]
-                            .into()
-                        })
+                        let Referenceable::Heading(_, heading) = resolved else {
+                            return None;
+                        };
+                        let refname = resolved.get_refname(self.root_dir())?;
+                        let slug = &heading.anchor.slug;
+
+                        Some(
+                            vec![refname.path.clone()?, refname.link_file_key()?]
+                                .into_iter()
+                                .map(|file| refname_key(&format!("{}#{}", file, slug)))
+                                .collect_vec(),
+                        )
                     })
                     .flatten()
                     .collect();
 
+                // links in excluded files, like the placeholders in templates, don't make unresolved referenceables
                 let unresolved = self.select_references(None).map(|references| {
                     references
                         .iter()
+                        .filter(|(path, _)| !self.is_excluded(path))
                         .unique_by(|(_, reference)| &reference.data().reference_text)
                         .par_bridge()
                         .into_par_iter()
                         .filter(|(_, reference)| {
-                            !resolved_referenceables_refnames
-                                .contains(&reference.data().reference_text)
+                            match reference.data().reference_text.strip_prefix('/') {
+                                Some(from_root) => {
+                                    !resolved_referenceables_paths.contains(&refname_key(from_root))
+                                }
+                                None => !resolved_referenceables_refnames
+                                    .contains(&refname_key(&reference.data().reference_text)),
+                            }
+                        })
+                        .filter(|(_, reference)| match reference {
+                            Reference::MDHeadingLink(_, file, heading) => {
+                                let slug = format!(
+                                    "{}#{}",
+                                    file.trim_start_matches('/'),
+                                    heading_slug(heading)
+                                );
+
+                                !resolved_heading_slugs.contains(&refname_key(&slug))
+                            }
+                            _ => true,
                         })
                         .flat_map(|(_, reference)| match reference {
                             Reference::WikiFileLink(data) | Reference::MDFileLink(data) => {
                                 let mut path = self.root_dir().clone();
-                                path.push(&reference.data().reference_text);
+                                path.push(reference.data().reference_text.trim_start_matches('/'));
 
 Some(Referenceable::UnresovledFile(path, &data.reference_text))
 
@@ -284,14 +683,14 @@ Some(Referenceable::UnresovledFile(path, &data.reference_text))
                             Reference::WikiHeadingLink(_data, end_path, heading)
                             | Reference::MDHeadingLink(_data, end_path, heading) => {
                                 let mut path = self.root_dir().clone();
-                                path.push(end_path);
+                                path.push(end_path.trim_start_matches('/'));
 
                                 Some(Referenceable::UnresolvedHeading(path, end_path, heading))
                             }
                             Reference::WikiIndexedBlockLink(_data, end_path, index)
                             | Reference::MDIndexedBlockLink(_data, end_path, index) => {
                                 let mut path = self.root_dir().clone();
-                                path.push(end_path);
+                                path.push(end_path.trim_start_matches('/'));
 
                                 Some(Referenceable::UnresovledIndexedBlock(path, end_path, index))
                             }
@@ -310,6 +709,127 @@ Some(Referenceable::UnresovledFile(path, &data.reference_text))
         }
     }
 
+    /// Whether the markdown link in the file at `path` is written with the `.md` of the note, like `[x](note.md)`
+    /// rather than `[x](note)`; the `.md` isn't part of the link's reference text
+    pub fn markdown_link_has_extension(&self, path: &Path, reference: &Reference) -> bool {
+        let range = reference.data().range;
+
+        self.select_line(path, range.start.line as isize)
+            .and_then(|line| {
+                let text = String::from_iter(
+                    line.get(range.start.character as usize..range.end.character as usize)?,
+                );
+                let (_, target) = text.rsplit_once("](")?;
+                let file = target
+                    .trim_start_matches('<')
+                    .split(['#', '>', ')'])
+                    .next()?;
+
+                Some(file.to_lowercase().ends_with(".md"))
+            })
+            .unwrap_or(false)
+    }
+
+    /// Whether the link in the file at `path` is an embed, preceded by a `!`
+    pub fn is_embed(&self, path: &Path, reference: &Reference) -> bool {
+        let start = reference.data().range.start;
+
+        start.character > 0
+            && self
+                .select_line(path, start.line as isize)
+                .and_then(|line| line.get(start.character as usize - 1).copied())
+                == Some('!')
+    }
+
+    /// The files that embed the file at `path`, directly or through at most `depth - 1` other files embedded in
+    /// between. Only files are followed, not which heading or block is embedded, so only the file of each embed is
+    /// resolved. Stops, with the embedders found so far, once `cancelled`.
+    pub fn select_embedders(
+        &self,
+        path: &Path,
+        depth: usize,
+        cancelled: impl Fn() -> bool,
+    ) -> HashSet<PathBuf> {
+        // links by name and by path both end with the name of the file
+        let by_name = self
+            .md_files
+            .iter()
+            .filter_map(|(file, md_file)| {
+                Some((file.file_stem()?.to_str()?.to_lowercase(), (file, md_file)))
+            })
+            .into_group_map();
+
+        // embedded file -> the files embedding it
+        let embedded_in = self
+            .md_files
+            .iter()
+            .take_while(|_| !cancelled())
+            .flat_map(|(from, md_file)| {
+                md_file
+                    .references
+                    .iter()
+                    .map(move |reference| (from.as_path(), reference))
+            })
+            .filter_map(|(from, reference)| {
+                let file_ref_text = match reference {
+                    Reference::WikiFileLink(data) | Reference::MDFileLink(data) => {
+                        &data.reference_text
+                    }
+                    Reference::WikiHeadingLink(_, file, _)
+                    | Reference::WikiIndexedBlockLink(_, file, _)
+                    | Reference::MDHeadingLink(_, file, _)
+                    | Reference::MDIndexedBlockLink(_, file, _) => file,
+                    _ => return None,
+                };
+                self.is_embed(from, reference)
+                    .then_some((from, file_ref_text))
+            })
+            .flat_map(|(from, file_ref_text)| {
+                // `![[#Heading]]` embeds the file it is in
+                if file_ref_text.is_empty() {
+                    return vec![(from.to_path_buf(), from)];
+                }
+
+                let name = file_ref_text.rsplit('/').next().unwrap_or_default();
+                by_name
+                    .get(&name.to_lowercase())
+                    .into_iter()
+                    .flatten()
+                    .filter(|(file, md_file)| {
+                        matches_path_or_file(
+                            file_ref_text,
+                            Referenceable::File(file, md_file).get_refname(&self.root_dir),
+                            &self.root_dir,
+                            from,
+                            self.case_insensitive_links,
+                        )
+                    })
+                    .map(|(file, _)| (file.to_path_buf(), from))
+                    .collect_vec()
+            })
+            .into_group_map();
+
+        let mut embedders = HashSet::new();
+        let mut frontier = vec![path.to_path_buf()];
+        for _ in 0..depth {
+            let mut next = vec![];
+            for embedded in &frontier {
+                for from in embedded_in.get(embedded).into_iter().flatten() {
+                    if embedders.insert(from.to_path_buf()) {
+                        next.push(from.to_path_buf());
+                    }
+                }
+            }
+
+            if next.is_empty() {
+                break;
+            }
+            frontier = next;
+        }
+
+        embedders
+    }
+
     pub fn select_line(&self, path: &Path, line: isize) -> Option<Vec<char>> {
         let rope = self.ropes.get(path)?;
 
@@ -319,16 +839,317 @@ Some(Referenceable::UnresovledFile(path, &data.reference_text))
             .map(|slice| slice.chars().collect_vec())
     }
 
+    /// The attachment linked to under the cursor, like the image of `![[diagram.png]]` or `[pic](assets/pic.png)`
+    pub fn select_attachment_at_position(&self, path: &Path, position: Position) -> Option<&Path> {
+        let line = String::from_iter(self.select_line(path, position.line as isize)?);
+        self.line_attachment_links(path, position.line, &line)
+            .into_iter()
+            .find(|link| {
+                (link.range.start.character..=link.range.end.character)
+                    .contains(&position.character)
+            })
+            .map(|link| link.attachment)
+    }
+
+    /// The links to attachments in a file, which aren't parsed as references
+    pub fn select_attachment_links(&self, path: &Path) -> Vec<AttachmentLink<'_>> {
+        let Some(rope) = self.ropes.get(path) else {
+            return vec![];
+        };
+
+        rope.lines()
+            .enumerate()
+            .flat_map(|(line_number, line)| {
+                self.line_attachment_links(path, line_number as u32, &line.to_string())
+            })
+            .collect()
+    }
+
+    /// The attachment that a link in the file at `path` goes to, from the decoded file part of the link. Wikilinks go
+    /// to the attachment by name, the one with the shortest path as in Obsidian, or by path from the vault root;
+    /// markdown links by path from the linking file, or from the vault root.
+    pub fn select_linked_attachment(&self, path: &Path, file: &str, wiki: bool) -> Option<&Path> {
+        if file.contains("://") {
+            return None;
+        }
+        let extension = Path::new(file).extension();
+        if extension.is_some_and(|ext| ext.eq_ignore_ascii_case("md")) {
+            return None;
+        }
+
+        let eq = |a: &str, b: &str| match self.case_insensitive_links {
+            true => a.to_lowercase() == b.to_lowercase(),
+            false => a == b,
+        };
+        let from_root = |target: &str| {
+            self.attachments
+                .iter()
+                .find(|attachment| {
+                    diff_paths(attachment, &self.root_dir)
+                        .and_then(|relative| relative.to_str().map(|it| it.replace('\\', "/")))
+                        .is_some_and(|relative| eq(&relative, target))
+                })
+                .map(PathBuf::as_path)
+        };
+
+        match (wiki, extension) {
+            (true, None) => self.extensionless_link_attachment(file),
+            (_, None) => None,
+            (true, Some(_)) if file.contains('/') => from_root(file.trim_start_matches('/')),
+            (true, Some(_)) => self
+                .attachments
+                .iter()
+                .filter(|attachment| {
+                    attachment
+                        .file_name()
+                        .and_then(|name| name.to_str())
+                        .is_some_and(|name| eq(name, file))
+                })
+                .min_by_key(|attachment| attachment.components().count())
+                .map(PathBuf::as_path),
+            (false, Some(_)) => match file.strip_prefix('/') {
+                Some(file) => from_root(file),
+                None => resolve_relative_path(file, &self.root_dir, path)
+                    .and_then(|target| from_root(&target))
+                    .or_else(|| from_root(file)),
+            },
+        }
+    }
+
+    /// The links to attachments in a line of a file. Wikilinks go to the attachment by name, or by path from the vault
+    /// root; markdown links by path from the linking file, or from the vault root with a leading `/`.
+    fn line_attachment_links(
+        &self,
+        path: &Path,
+        line_number: u32,
+        line: &str,
+    ) -> Vec<AttachmentLink<'_>> {
+        static WIKI_ATTACHMENT_RE: Lazy<Regex> = Lazy::new(|| {
+            Regex::new(r"\[\[(?<file>[^\[\]\|#]+)(\|(?<display>[^\[\]]*))?\]\]").unwrap()
+        });
+        static MD_ATTACHMENT_RE: Lazy<Regex> = Lazy::new(|| {
+            Regex::new(r"\[(?<display>[^\[\]]*)\]\((<(?<angled>[^<>]+)>|(?<file>[^\s()<>]+))\)")
+                .unwrap()
+        });
+
+        let range = |captures: &Captures| {
+            let whole = captures.get(0)?;
+            let start = line[..whole.start()].chars().count() as u32;
+            let end = start + whole.as_str().chars().count() as u32;
+            Some(tower_lsp::lsp_types::Range {
+                start: Position::new(line_number, start),
+                end: Position::new(line_number, end),
+            })
+        };
+        let wiki_links = WIKI_ATTACHMENT_RE
+            .captures_iter(line)
+            .filter_map(|captures| {
+                let file = captures.name("file")?.as_str().trim();
+                let attachment = self.select_linked_attachment(path, file, true)?;
+
+                Some(AttachmentLink {
+                    attachment,
+                    range: range(&captures)?,
+                    file: file.to_string(),
+                    display: captures
+                        .name("display")
+                        .map(|display| display.as_str().to_string()),
+                    wiki: true,
+                })
+            });
+
+        let md_links = MD_ATTACHMENT_RE.captures_iter(line).filter_map(|captures| {
+            let file = captures.name("angled").or(captures.name("file"))?.as_str();
+            let file = percent_decode_str(file).decode_utf8().ok()?;
+            let attachment = self.select_linked_attachment(path, &file, false)?;
+
+            Some(AttachmentLink {
+                attachment,
+                range: range(&captures)?,
+                file: file.to_string(),
+                display: captures
+                    .name("display")
+                    .map(|display| display.as_str().to_string()),
+                wiki: false,
+            })
+        });
+
+        wiki_links.chain(md_links).collect()
+    }
+
+    /// The attachment a wikilink without an extension, like `[[notes]]`, goes to: of the files named like the link, the
+    /// one with the first of the `link_extensions` that the vault has. None when that is the note, `notes.md`, or
+    /// there is no such file. A link with a `/` is matched against the paths from the vault root.
+    pub fn extensionless_link_attachment(&self, file: &str) -> Option<&Path> {
+        let file = file.trim_start_matches('/');
+        let eq = |a: &str, b: &str| match self.case_insensitive_links {
+            true => a.to_lowercase() == b.to_lowercase(),
+            false => a == b,
+        };
+
+        self.link_extensions.iter().find_map(|extension| {
+            let link = format!("{}.{}", file, extension);
+            let name = link.rsplit('/').next().unwrap_or_default();
+            let mut named = self
+                .file_names
+                .get(&match self.case_insensitive_links {
+                    true => name.to_lowercase(),
+                    false => name.to_string(),
+                })
+                .into_iter()
+                .flatten()
+                // a link with a `/` is matched against the paths from the vault root
+                .filter(|path| {
+                    !link.contains('/')
+                        || diff_paths(path, &self.root_dir)
+                            .and_then(|relative| Some(relative.to_str()?.replace('\\', "/")))
+                            .is_some_and(|relative| eq(&relative, &link))
+                });
+
+            match extension.as_str() {
+                "md" => named.next().map(|_| None),
+                // as with attachments linked by name, the one with the shortest path
+                _ => named
+                    .min_by_key(|path| (path.components().count(), path.as_path()))
+                    .map(|path| Some(path.as_path())),
+            }
+        })?
+    }
+
+    /// The links with a display text that may go to a file named `file_name`, with the notes they are in
+    pub fn select_displayed_links(&self, file_name: &str) -> Vec<(&Path, &Reference)> {
+        let file_name = file_name.to_lowercase();
+
+        self.displayed_links
+            .get(&file_name)
+            .into_iter()
+            .flatten()
+            .filter_map(|path| Some((path.as_path(), self.md_files.get(path)?)))
+            .flat_map(|(path, md_file)| {
+                md_file
+                    .references
+                    .iter()
+                    .filter(|reference| displayed_link_name(reference).as_ref() == Some(&file_name))
+                    .map(move |reference| (path, reference))
+            })
+            .collect()
+    }
+
+    /// Whether a wikilink without an extension goes to an attachment rather than to the note of the same name, like
+    /// `[[notes]]` to `notes.canvas` when `canvas` comes before `md` in the `link_extensions`
+    pub fn links_to_attachment(&self, reference: &Reference) -> bool {
+        match reference {
+            Reference::WikiFileLink(data) => self
+                .extensionless_link_attachment(&data.reference_text)
+                .is_some(),
+            _ => false,
+        }
+    }
+
     pub fn select_headings(&self, path: &Path) -> Option<&Vec<MDHeading>> {
         let md_file = self.md_files.get(path)?;
         let headings = &md_file.headings;
         Some(headings)
     }
 
+    /// Select the frontmatter properties used in the vault, each with the distinct values it has been given
+    pub fn select_properties(&self) -> IndexMap<&str, Vec<&str>> {
+        let mut properties = IndexMap::<&str, Vec<&str>>::new();
+
+        for (key, values) in self
+            .md_files
+            .values()
+            .flat_map(|file| file.metadata.iter())
+            .flat_map(|metadata| metadata.properties())
+        {
+            let known = properties.entry(key.as_str()).or_default();
+            for value in values {
+                if !known.contains(&value.as_str()) {
+                    known.push(value.as_str());
+                }
+            }
+        }
+
+        properties
+    }
+
+    /// The line of the closing `---` of the file's frontmatter, if it has any
+    pub fn select_frontmatter_end_line(&self, path: &Path) -> Option<usize> {
+        let rope = self.ropes.get(path)?;
+
+        frontmatter_end_line(&rope.to_string())
+    }
+
+    /// Select the edit that appends the alias to the `aliases` in the file's frontmatter; None if it is already there
+    pub fn select_add_alias_edit(&self, path: &Path, alias: &str) -> Option<TextEdit> {
+        let rope = self.ropes.get(path)?;
+
+        add_alias_edit(&rope.to_string(), alias)
+    }
+
+    /// Select the edit that adds the heading to the file, at the end of the `section` heading's section if the file has
+    /// one
+    pub fn select_new_heading_edit(
+        &self,
+        path: &Path,
+        heading: &str,
+        section: Option<&str>,
+    ) -> Option<TextEdit> {
+        let rope = self.ropes.get(path)?;
+        let md_file = self.md_files.get(path)?;
+
+        Some(new_heading_edit(
+            &rope.to_string(),
+            &md_file.headings,
+            heading,
+            section,
+        ))
+    }
+
+    /// Select the files with the tag (without `#`) or a tag nested under it
+    pub fn select_tagged_files(&self, tag: &str) -> Vec<&PathBuf> {
+        self.md_files
+            .iter()
+            .filter(|(_, file)| file.has_tag(tag))
+            .map(|(path, _)| path)
+            .collect()
+    }
+
+    /// Select the distinct callout types (`> [!type]`) used anywhere in the vault
+    pub fn select_callout_types(&self) -> Vec<&str> {
+        self.md_files
+            .values()
+            .flat_map(|file| file.callouts.iter())
+            .map(|callout| callout.callout_type.as_str())
+            .unique_by(|callout_type| callout_type.to_lowercase())
+            .collect()
+    }
+
     pub fn root_dir(&self) -> &PathBuf {
         &self.root_dir
     }
 
+    pub fn case_insensitive_links(&self) -> bool {
+        self.case_insensitive_links
+    }
+
+    /// Whether the file is excluded from completions, workspace symbols and diagnostics by the `exclude` setting. A
+    /// pattern matching a folder excludes everything in it. Links to excluded files still resolve.
+    pub fn is_excluded(&self, path: &Path) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.root_dir) else {
+            return false;
+        };
+
+        relative
+            .ancestors()
+            .filter(|ancestor| !ancestor.as_os_str().is_empty())
+            .any(|ancestor| {
+                self.exclude
+                    .iter()
+                    .any(|pattern| pattern.matches_path(ancestor))
+            })
+    }
+
     pub fn select_references_for_referenceable(
         &self,
         referenceable: &Referenceable,
@@ -339,7 +1160,12 @@ Some(Referenceable::UnresovledFile(path, &data.reference_text))
             references
                 .into_par_iter()
                 .filter(|(ref_path, reference)| {
-                    referenceable.matches_reference(&self.root_dir, reference, ref_path)
+                    referenceable.matches_reference(
+                        &self.root_dir,
+                        reference,
+                        ref_path,
+                        self.case_insensitive_links,
+                    ) && !self.links_to_attachment(reference)
                 })
                 .map(|(path, reference)| {
                     match std::fs::metadata(path).and_then(|meta| meta.modified()) {
@@ -361,11 +1187,22 @@ Some(Referenceable::UnresovledFile(path, &data.reference_text))
         reference: &Reference,
         reference_path: &Path,
     ) -> Vec<Referenceable> {
+        if self.links_to_attachment(reference) {
+            return vec![];
+        }
+
         let referenceables = self.select_referenceable_nodes(None);
 
         referenceables
             .into_iter()
-            .filter(|i| reference.references(self.root_dir(), reference_path, i))
+            .filter(|i| {
+                reference.references(
+                    self.root_dir(),
+                    reference_path,
+                    i,
+                    self.case_insensitive_links,
+                )
+            })
             .collect()
     }
 }
@@ -404,30 +1241,53 @@ impl Vault {
                     .into(),
                 )
             }
-            Referenceable::Heading(_, _) => {
-                let range = referenceable.get_range()?;
+            Referenceable::Heading(path, heading) => {
+                let range = heading.range;
+                // stop at the next heading of the same or a higher level
+                let end = self
+                    .md_files
+                    .get(*path)
+                    .and_then(|file| file.heading_section_end(heading))
+                    .map_or(range.end.line + 10, |end| end.min(range.end.line + 10));
                 Some(
-                    (range.start.line..=range.end.line + 10)
+                    (range.start.line..=end)
                         .filter_map(|ln| self.select_line(referenceable.get_path(), ln as isize)) // flatten those options!
                         .map(String::from_iter)
                         .join("")
                         .into(),
                 )
             }
-            Referenceable::IndexedBlock(_, _) => {
-                let range = referenceable.get_range()?;
-                self.select_line(referenceable.get_path(), range.start.line as isize)
-                    .map(String::from_iter)
-                    .map(Into::into)
+            // the whole block, as it is embedded, like a list item with its nested items
+            Referenceable::IndexedBlock(path, block) => {
+                self.select_block_embed(path, block).map(Into::into)
             }
-            Referenceable::File(_, _) => {
-                Some(
-                    (0..=13)
-                        .filter_map(|ln| self.select_line(referenceable.get_path(), ln as isize)) // flatten those options!
-                        .map(String::from_iter)
-                        .join("")
-                        .into(),
-                )
+            Referenceable::File(path, file) => {
+                // skip the frontmatter, and the blank lines after it
+                let after_frontmatter = self
+                    .select_frontmatter_end_line(path)
+                    .map_or(0, |end| end + 1);
+                let start = (after_frontmatter..)
+                    .map_while(|ln| self.select_line(path, ln as isize).map(|line| (ln, line)))
+                    .find(|(_, line)| !String::from_iter(line).trim().is_empty())
+                    .map_or(after_frontmatter, |(ln, _)| ln);
+
+                let content = (start..=start + 13)
+                    .filter_map(|ln| self.select_line(path, ln as isize))
+                    .map(String::from_iter)
+                    .join("");
+
+                let properties = file
+                    .metadata
+                    .iter()
+                    .flat_map(|metadata| metadata.properties())
+                    .filter(|(key, _)| matches!(key.as_str(), "title" | "tags"))
+                    .map(|(key, values)| format!("`{}: {}`", key, values.join(", ")))
+                    .join(" ");
+
+                match properties.is_empty() {
+                    true => Some(content.into()),
+                    false => Some(format!("{}\n\n{}", properties, content).into()),
+                }
             }
             Referenceable::Tag(_, _) => None,
             Referenceable::UnresovledFile(_, _) => None,
@@ -436,36 +1296,111 @@ impl Vault {
         }
     }
 
+    /// Select the text that embedding the block, `![[file#^index]]`, transcludes. An index on a heading embeds the
+    /// heading's whole section.
+    pub fn select_block_embed(&self, path: &Path, block: &MDIndexedBlock) -> Option<String> {
+        let rope = self.ropes.get(path)?;
+
+        match self.md_files.get(path).and_then(|file| {
+            file.headings
+                .iter()
+                .find(|heading| heading.range.start.line == block.range.start.line)
+        }) {
+            Some(heading) => self
+                .select_heading_section(path, heading)
+                .map(|section| section.replacen(&format!(" ^{}", block.index), "", 1)),
+            None => block_embed_text(&rope.to_string(), block),
+        }
+    }
+
+    /// Select the text that embedding the heading, `![[file#heading]]`, transcludes: the heading with its whole
+    /// section, until the next heading of the same or a higher level
+    pub fn select_heading_section(&self, path: &Path, heading: &MDHeading) -> Option<String> {
+        let rope = self.ropes.get(path)?;
+        let file = self.md_files.get(path)?;
+
+        Some(heading_section_text(
+            &rope.to_string(),
+            &file.headings,
+            heading,
+        ))
+    }
+
+    /// A block index, in the `block_id_style`, that is not yet used in the file at `path`. A timestamp that is already
+    /// used gets a `-1`, `-2`, ... suffix.
+    pub fn new_block_index(&self, path: &Path, settings: &Settings) -> String {
+        let existing = self
+            .md_files
+            .get(path)
+            .map(|file| {
+                file.indexed_blocks
+                    .iter()
+                    .map(|block| block.index.as_str())
+                    .collect_vec()
+            })
+            .unwrap_or_default();
+
+        if settings.block_id_style == BlockIdStyle::Timestamp {
+            let timestamp = chrono::Local::now().format("%Y%m%d%H%M%S").to_string();
+
+            return std::iter::once(timestamp.clone())
+                .chain((1..).map(|n| format!("{}-{}", timestamp, n)))
+                .find(|id| !existing.contains(&id.as_str()))
+                .unwrap_or(timestamp);
+        }
+
+        static ALPHABET: Lazy<Vec<char>> = Lazy::new(|| ('a'..='z').chain('0'..='9').collect());
+        let length = settings.block_id_length.max(1);
+
+        loop {
+            let rand_id = nanoid!(length, &ALPHABET);
+
+            if !existing.contains(&rand_id.as_str()) {
+                return rand_id;
+            }
+        }
+    }
+
     pub fn select_blocks(&self) -> Vec<Block<'_>> {
         self.ropes
             .par_iter()
-            .map(|(path, rope)| {
-                rope.lines()
-                    .enumerate()
-                    .flat_map(|(i, line)| {
-                        let string = line.as_str()?;
-
-                        Some(Block {
-                            text: string.trim(),
-                            range: MyRange(tower_lsp::lsp_types::Range {
-                                start: Position {
-                                    line: i as u32,
-                                    character: 0,
-                                },
-                                end: Position {
-                                    line: i as u32,
-                                    character: string.len() as u32,
-                                },
-                            }),
-                            file: path,
-                        })
-                    })
-                    .collect::<Vec<_>>()
-            })
+            .map(|(path, rope)| file_blocks(path, rope))
             .flatten()
-            .filter(|block| !block.text.is_empty())
             .collect()
     }
+
+    /// The non empty lines of one file, whether or not it is opened
+    pub fn select_file_blocks(&self, path: &Path) -> Vec<Block<'_>> {
+        self.ropes
+            .get_key_value(path)
+            .map(|(path, rope)| file_blocks(path, rope))
+            .unwrap_or_default()
+    }
+}
+
+fn file_blocks<'a>(path: &'a Path, rope: &'a Rope) -> Vec<Block<'a>> {
+    rope.lines()
+        .enumerate()
+        .flat_map(|(i, line)| {
+            let string = line.as_str()?;
+
+            Some(Block {
+                text: string.trim(),
+                range: MyRange(tower_lsp::lsp_types::Range {
+                    start: Position {
+                        line: i as u32,
+                        character: 0,
+                    },
+                    end: Position {
+                        line: i as u32,
+                        character: string.len() as u32,
+                    },
+                }),
+                file: path,
+            })
+        })
+        .filter(|block| !block.text.is_empty())
+        .collect()
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize, Copy)]
@@ -540,7 +1475,7 @@ impl Rangeable for Reference {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Default, Hash, Clone)]
+#[derive(Debug, PartialEq, Eq, Default, Hash, Clone, Serialize, Deserialize)]
 pub struct MDFile {
     pub references: Vec<Reference>,
     pub headings: Vec<MDHeading>,
@@ -551,19 +1486,24 @@ pub struct MDFile {
     pub link_reference_definitions: Vec<MDLinkReferenceDefinition>,
     pub metadata: Option<MDMetadata>,
     pub codeblocks: Vec<MDCodeBlock>,
+    pub callouts: Vec<MDCallout>,
 }
 
 impl MDFile {
     fn new(context: &Settings, text: &str, path: PathBuf) -> MDFile {
         let code_blocks = MDCodeBlock::new(text).collect_vec();
+        let comments = MDComment::new(text).collect_vec();
         let links = match context {
             Settings {
                 references_in_codeblocks: false,
                 ..
             } => Reference::new(text)
                 .filter(|it| !code_blocks.iter().any(|codeblock| codeblock.includes(it)))
+                .filter(|it| !comments.iter().any(|comment| comment.includes(it)))
+                .collect_vec(),
+            _ => Reference::new(text)
+                .filter(|it| !comments.iter().any(|comment| comment.includes(it)))
                 .collect_vec(),
-            _ => Reference::new(text).collect_vec(),
         };
         let headings = MDHeading::new(text)
             .filter(|it| !code_blocks.iter().any(|codeblock| codeblock.includes(it)));
@@ -579,10 +1519,15 @@ impl MDFile {
                 ..
             } => MDTag::new(text)
                 .filter(|it| !code_blocks.iter().any(|codeblock| codeblock.includes(it)))
+                .filter(|it| !comments.iter().any(|comment| comment.includes(it)))
+                .collect_vec(),
+            _ => MDTag::new(text)
+                .filter(|it| !comments.iter().any(|comment| comment.includes(it)))
                 .collect_vec(),
-            _ => MDTag::new(text).collect_vec(),
         };
         let metadata = MDMetadata::new(text);
+        let callouts = MDCallout::new(text)
+            .filter(|it| !code_blocks.iter().any(|codeblock| codeblock.includes(it)));
 
         MDFile {
             references: links,
@@ -593,6 +1538,7 @@ impl MDFile {
             path,
             link_reference_definitions: link_refs.collect(),
             metadata,
+            callouts: callouts.collect(),
             codeblocks: code_blocks,
         }
     }
@@ -600,6 +1546,46 @@ impl MDFile {
     pub fn file_name(&self) -> Option<&str> {
         self.path.file_stem()?.to_str()
     }
+
+    /// Whether the file has the tag (without `#`) or one nested under it, like `#project/active` for `project`
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|it| {
+            it.tag_ref == tag
+                || it
+                    .tag_ref
+                    .strip_prefix(tag)
+                    .is_some_and(|rest| rest.starts_with('/'))
+        })
+    }
+
+    /// The last line of the heading's section, before the next heading of the same or a higher level; None if the
+    /// section runs to the end of the file
+    pub fn heading_section_end(&self, heading: &MDHeading) -> Option<u32> {
+        section_end(&self.headings, heading)
+    }
+
+    /// The headings `heading` is nested under, outermost first
+    pub fn heading_ancestors(&self, heading: &MDHeading) -> Vec<&MDHeading> {
+        let Some(index) = self.headings.iter().position(|it| it == heading) else {
+            return vec![];
+        };
+
+        let mut level = &heading.level;
+        let mut ancestors = self.headings[..index]
+            .iter()
+            .rev()
+            .filter(|it| {
+                let is_ancestor = it.level < *level;
+                if is_ancestor {
+                    level = &it.level;
+                }
+                is_ancestor
+            })
+            .collect_vec();
+
+        ancestors.reverse();
+        ancestors
+    }
 }
 
 impl MDFile {
@@ -614,6 +1600,7 @@ impl MDFile {
             link_reference_definitions,
             metadata: _,
             codeblocks: _,
+            callouts: _,
         } = self;
 
         iter::once(Referenceable::File(&self.path, self))
@@ -642,7 +1629,7 @@ impl MDFile {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Default, Clone, Hash)]
+#[derive(Debug, PartialEq, Eq, Default, Clone, Hash, Serialize, Deserialize)]
 pub struct ReferenceData {
     pub reference_text: String,
     pub display_text: Option<String>,
@@ -659,7 +1646,7 @@ impl Hash for Reference {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub enum Reference {
     Tag(ReferenceData),
     WikiFileLink(ReferenceData),
@@ -687,9 +1674,16 @@ impl Default for Reference {
 
 use Reference::*;
 
-use crate::config::Settings;
+use crate::config::{BlockIdStyle, Settings};
+
+use self::{
+    cache::VaultCache,
+    metadata::{add_alias_edit, frontmatter_end_line, frontmatter_tags, MDMetadata},
+    parsing::{lines_with_offsets, MDCallout, MDCodeBlock, MDComment},
+};
 
-use self::{metadata::MDMetadata, parsing::MDCodeBlock};
+#[cfg(test)]
+pub use self::test_vault::{apply_edits, TestVault};
 
 impl Reference {
     pub fn data(&self) -> &ReferenceData {
@@ -722,9 +1716,9 @@ impl Reference {
 
     pub fn new(text: &str) -> impl Iterator<Item = Reference> + '_ {
         static WIKI_LINK_RE: Lazy<Regex> = Lazy::new(|| {
-            Regex::new(r"\[\[(?<filepath>[^\[\]\|\.\#]+)(\#(?<infileref>[^\[\]\.\|]+))?(\|(?<display>[^\[\]\.\|]+))?\]\]")
+            Regex::new(r"\[\[(?<filepath>[^\[\]\|\.\#]*)(\#(?<infileref>[^\[\]\|]+))?(\|(?<display>[^\[\]\.\|]+))?\]\]")
                 .unwrap()
-        }); // A [[link]] that does not have any [ or ] in it
+        }); // A [[link]] that does not have any [ or ] in it; [[#heading]] links to a heading in the same file
 
         let wiki_links = WIKI_LINK_RE
             .captures_iter(text)
@@ -734,7 +1728,7 @@ impl Reference {
             });
 
         static MD_LINK_RE: Lazy<Regex> = Lazy::new(|| {
-            Regex::new(r"\[(?<display>[^\[\]\.]*)\]\(<?(?<filepath>(\.?\/)?[^\[\]\|\.\#<>]+)(?<ending>\.[^\# <>]+)?(\#(?<infileref>[^\[\]\.\|<>]+))?>?\)")
+            Regex::new(r"\[(?<display>[^\[\]\.]*)\]\(<?(?<filepath>(\.{1,2}\/|\/)*[^\[\]\|\.\#<>]+)(?<ending>\.[^\# <>]+)?(\#(?<infileref>[^\[\]\.\|<>]+))?>?\)")
                 .expect("MD Link Not Constructing")
         }); // [display](relativePath)
 
@@ -820,6 +1814,7 @@ impl Reference {
         root_dir: &Path,
         file_path: &Path,
         referenceable: &Referenceable,
+        case_insensitive: bool,
     ) -> bool {
         let text = &self.data().reference_text;
         match referenceable {
@@ -864,7 +1859,13 @@ impl Reference {
                 | WikiFileLink(ReferenceData {
                     reference_text: file_ref_text,
                     ..
-                }) => matches_path_or_file(file_ref_text, referenceable.get_refname(root_dir)),
+                }) => matches_path_or_file(
+                    file_ref_text,
+                    referenceable.get_refname(root_dir),
+                    root_dir,
+                    file_path,
+                    case_insensitive,
+                ),
                 Tag(_) => false,
                 WikiHeadingLink(_, _, _) => false,
                 WikiIndexedBlockLink(_, _, _) => false,
@@ -892,8 +1893,28 @@ impl Reference {
                 | WikiIndexedBlockLink(.., file_ref_text, link_infile_ref)
                 | MDHeadingLink(.., file_ref_text, link_infile_ref)
                 | MDIndexedBlockLink(.., file_ref_text, link_infile_ref) => {
-                    matches_path_or_file(file_ref_text, referenceable.get_refname(root_dir))
-                        && link_infile_ref == infile_ref
+                    let is_md_link = matches!(self, MDHeadingLink(..));
+
+                    matches_path_or_file(
+                        file_ref_text,
+                        referenceable.get_refname(root_dir),
+                        root_dir,
+                        file_path,
+                        case_insensitive,
+                    ) && match referenceable {
+                        // markdown links may go to the slug of the heading, as on GitHub
+                        Referenceable::Heading(_, heading) => {
+                            heading_link_text(link_infile_ref) == heading.anchor.link_text
+                                || is_md_link
+                                    && heading_slug(link_infile_ref) == heading.anchor.slug
+                        }
+                        Referenceable::UnresolvedHeading(..) => {
+                            heading_link_text(link_infile_ref) == heading_link_text(infile_ref)
+                                || is_md_link
+                                    && heading_slug(link_infile_ref) == heading_slug(infile_ref)
+                        }
+                        _ => heading_link_text(link_infile_ref) == heading_link_text(infile_ref),
+                    }
                 }
                 Tag(_) => false,
                 WikiFileLink(_) => false,
@@ -953,6 +1974,8 @@ trait ParseableReferenceConstructor {
     fn new_heading(data: ReferenceData, path: &str, heading: &str) -> Reference;
     fn new_file_link(data: ReferenceData) -> Reference;
     fn new_indexed_block_link(data: ReferenceData, path: &str, index: &str) -> Reference;
+    /// The link text as it refers to the file or infile ref
+    fn decode(text: &str) -> String;
 } // TODO: Turn this into a macro
 
 struct WikiReferenceConstructor;
@@ -968,6 +1991,9 @@ impl ParseableReferenceConstructor for WikiReferenceConstructor {
     fn new_indexed_block_link(data: ReferenceData, path: &str, index: &str) -> Reference {
         Reference::WikiIndexedBlockLink(data, path.into(), index.into())
     }
+    fn decode(text: &str) -> String {
+        text.to_string()
+    }
 }
 
 impl ParseableReferenceConstructor for MDReferenceConstructor {
@@ -980,6 +2006,10 @@ impl ParseableReferenceConstructor for MDReferenceConstructor {
     fn new_indexed_block_link(data: ReferenceData, path: &str, index: &str) -> Reference {
         Reference::MDIndexedBlockLink(data, path.into(), index.into())
     }
+    /// Markdown link paths may be percent encoded; `My%20Note` links to `My Note.md`
+    fn decode(text: &str) -> String {
+        percent_decode_str(text).decode_utf8_lossy().to_string()
+    }
 }
 
 fn generic_link_constructor<T: ParseableReferenceConstructor>(
@@ -997,37 +2027,45 @@ fn generic_link_constructor<T: ParseableReferenceConstructor>(
         return None;
     }
 
-    match (range, file_path, infile_ref, display_text) {
+    let filepath = T::decode(file_path.as_str());
+    let infile_ref = infile_ref.map(|infile| T::decode(infile.as_str()));
+
+    // only links to headings and blocks can leave out the file
+    if filepath.is_empty() && infile_ref.is_none() {
+        return None;
+    }
+
+    match (range, filepath, infile_ref, display_text) {
         // Pure file reference as there is no infileref such as #... for headings or #^... for indexed blocks
         (full, filepath, None, display) => Some(T::new_file_link(ReferenceData {
-            reference_text: filepath.as_str().into(),
+            reference_text: filepath,
             range: MyRange::from_range(&Rope::from_str(text), full.range()),
             display_text: display.map(|d| d.as_str().into()),
         })),
-        (full, filepath, Some(infile), display) if infile.as_str().get(0..1) == Some("^") => {
+        (full, filepath, Some(infile), display) if infile.get(0..1) == Some("^") => {
             Some(T::new_indexed_block_link(
                 ReferenceData {
-                    reference_text: format!("{}#{}", filepath.as_str(), infile.as_str()),
+                    reference_text: format!("{}#{}", filepath, infile),
                     range: MyRange::from_range(&Rope::from_str(text), full.range()),
                     display_text: display.map(|d| d.as_str().into()),
                 },
-                filepath.as_str(),
-                &infile.as_str()[1..], // drop the ^ for the index
+                &filepath,
+                &infile[1..], // drop the ^ for the index
             ))
         }
         (full, filepath, Some(infile), display) => Some(T::new_heading(
             ReferenceData {
-                reference_text: format!("{}#{}", filepath.as_str(), infile.as_str()),
+                reference_text: format!("{}#{}", filepath, infile),
                 range: MyRange::from_range(&Rope::from_str(text), full.range()),
                 display_text: display.map(|d| d.as_str().into()),
             },
-            filepath.as_str(),
-            infile.as_str(),
+            &filepath,
+            &infile,
         )),
     }
 }
 
-#[derive(Eq, PartialEq, Debug, PartialOrd, Ord, Clone, Hash)]
+#[derive(Eq, PartialEq, Debug, PartialOrd, Ord, Clone, Hash, Serialize, Deserialize)]
 pub struct HeadingLevel(pub usize);
 
 impl Default for HeadingLevel {
@@ -1036,11 +2074,21 @@ impl Default for HeadingLevel {
     }
 }
 
-#[derive(Debug, Default, PartialEq, Eq, Clone)]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct MDHeading {
     pub heading_text: String,
     pub range: MyRange,
     pub level: HeadingLevel,
+    /// What links to the heading are matched by, worked out once when the file is parsed
+    pub anchor: HeadingAnchor,
+}
+
+/// The link text and the slug of a heading. Like on GitHub, the slug of a heading that has the same slug as an
+/// earlier heading of the file is numbered: the second `## Notes` is `notes-1`.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct HeadingAnchor {
+    pub link_text: String,
+    pub slug: String,
 }
 
 impl Hash for MDHeading {
@@ -1050,6 +2098,50 @@ impl Hash for MDHeading {
     }
 }
 
+/// The text used to link to a heading, and that links to headings are matched by; both the heading and the link are
+/// normalized, so `[[file#My *Heading*!]]` links to `# My **Heading**!`.
+///
+/// - links in the heading become their display text: `[[file|display]]` and `[display](file)` become `display`
+/// - markdown formatting is removed: `**`, `__`, `*`, `~~`, `==` and backticks
+/// - `[`, `]`, `|`, `#` and `^`, which can't be in a link, become spaces
+/// - runs of whitespace become one space and the ends are trimmed
+///
+/// Everything else is kept as is, including case, emoji and trailing punctuation.
+pub fn heading_link_text(heading: &str) -> String {
+    static WIKI_LINK_RE: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"\[\[([^\]\|]*\|)?(?<display>[^\]]*)\]\]").unwrap());
+    static MD_LINK_RE: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"\[(?<display>[^\]]*)\]\([^\)]*\)").unwrap());
+    static FORMATTING_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\*\*|__|~~|==|`|\*").unwrap());
+
+    // most headings and links have none of the links and formatting that are removed
+    if !heading.contains(['[', ']', '*', '_', '~', '=', '`', '|', '#', '^']) {
+        return heading.split_whitespace().join(" ");
+    }
+
+    let text = WIKI_LINK_RE.replace_all(heading, "$display");
+    let text = MD_LINK_RE.replace_all(&text, "$display");
+    let text = FORMATTING_RE.replace_all(&text, "");
+
+    text.replace(['[', ']', '|', '#', '^'], " ")
+        .split_whitespace()
+        .join(" ")
+}
+
+/// The GitHub style anchor of a heading, which most static site generators use too: its link text lowercased, without
+/// punctuation and with a hyphen for each space, like `whats-new-in-v20` for `What's New in v2.0?`
+pub fn heading_slug(heading: &str) -> String {
+    heading_link_text(heading)
+        .to_lowercase()
+        .chars()
+        .filter(|char| char.is_alphanumeric() || matches!(char, ' ' | '-' | '_'))
+        .map(|char| match char {
+            ' ' => '-',
+            char => char,
+        })
+        .collect()
+}
+
 #[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
 pub struct MyRange(pub tower_lsp::lsp_types::Range);
 
@@ -1115,18 +2207,99 @@ impl MDHeading {
                 },
             )
             .map(|(full_heading, heading_match, starter)| {
+                // the anchor is set below, once the headings are in order
                 return MDHeading {
                     heading_text: heading_match.as_str().trim_end().into(),
                     range: MyRange::from_range(&Rope::from_str(text), full_heading.range()),
                     level: HeadingLevel(starter.as_str().len()),
+                    anchor: HeadingAnchor::default(),
                 };
             });
 
+        // the headings are in the order of the file, which sections, ancestors and numbered slugs depend on
+        let mut slugs = HashMap::<String, usize>::new();
         headings
+            .chain(setext_headings(text))
+            .sorted_by_key(|heading| (heading.range.start.line, heading.range.start.character))
+            .map(move |heading| {
+                let slug = heading_slug(&heading.heading_text);
+                let count = slugs.entry(slug.clone()).or_default();
+                let slug = match *count {
+                    0 => slug,
+                    count => format!("{}-{}", slug, count),
+                };
+                *count += 1;
+
+                MDHeading {
+                    anchor: HeadingAnchor {
+                        link_text: heading_link_text(&heading.heading_text),
+                        slug,
+                    },
+                    ..heading
+                }
+            })
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+/// Headings underlined with `===` (level 1) or `---` (level 2). Only single line paragraphs are taken as headings; a
+/// `---` after a blank line is a thematic break and the frontmatter fences are never underlines.
+fn setext_headings(text: &str) -> Vec<MDHeading> {
+    static UNDERLINE_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^ {0,3}(=+|-+)[ \t]*$").unwrap());
+    // lines that start something other than a paragraph: headings, quotes, list items, fences, tables and html
+    static NOT_PARAGRAPH_RE: Lazy<Regex> = Lazy::new(|| {
+        Regex::new(r"^ {0,3}(#{1,6}( |$)|>|[-*+]([ \t]|$)|\d+[.)]([ \t]|$)|```|~~~|\||<)").unwrap()
+    });
+
+    let rope = Rope::from_str(text);
+    let lines = lines_with_offsets(text).collect_vec();
+    let content_start = frontmatter_end_line(text).map_or(0, |end| end + 1);
+
+    let mut headings = vec![];
+    let mut line_nr = content_start;
+    while let (Some((offset, line)), Some((underline_offset, underline))) =
+        (lines.get(line_nr), lines.get(line_nr + 1))
+    {
+        let after_paragraph_break = line_nr == content_start
+            || headings
+                .last()
+                .is_some_and(|heading: &MDHeading| heading.range.end.line as usize + 1 == line_nr)
+            || lines.get(line_nr - 1).is_some_and(|(_, previous)| {
+                previous.trim().is_empty() || NOT_PARAGRAPH_RE.is_match(previous)
+            });
+        let heading = UNDERLINE_RE
+            .captures(underline)
+            .filter(|_| after_paragraph_break)
+            .filter(|_| {
+                !line.trim().is_empty() && !line.starts_with("    ") && !line.starts_with('\t')
+            })
+            .filter(|_| !NOT_PARAGRAPH_RE.is_match(line) && !UNDERLINE_RE.is_match(line))
+            .and_then(|captures| captures.get(1));
+
+        match heading {
+            Some(underline_chars) => {
+                let start = offset + (line.len() - line.trim_start().len());
+                headings.push(MDHeading {
+                    heading_text: line.trim().to_string(),
+                    range: MyRange::from_range(
+                        &rope,
+                        start..underline_offset + underline_chars.end(),
+                    ),
+                    level: HeadingLevel(match underline_chars.as_str().starts_with('=') {
+                        true => 1,
+                        false => 2,
+                    }),
+                    anchor: HeadingAnchor::default(),
+                });
+                line_nr += 2;
+            }
+            None => line_nr += 1,
+        }
+    }
+
+    headings
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct MDIndexedBlock {
     /// THe index of the block; does not include '^'
     pub index: String,
@@ -1159,7 +2332,157 @@ impl MDIndexedBlock {
     } // Make this better identify the full blocks
 }
 
-#[derive(Debug, Eq, PartialEq, Clone)]
+/// The last line of the heading's section among the headings of its file; None if the section runs to the end of the
+/// file
+fn section_end(headings: &[MDHeading], heading: &MDHeading) -> Option<u32> {
+    headings
+        .iter()
+        .find(|other| {
+            other.range.start.line > heading.range.start.line && other.level <= heading.level
+        })
+        .map(|next| next.range.start.line.saturating_sub(1))
+}
+
+/// The heading's line and its section, without the blank lines before the next heading
+fn heading_section_text(text: &str, headings: &[MDHeading], heading: &MDHeading) -> String {
+    let start = heading.range.start.line as usize;
+    let lines = text.lines().skip(start);
+    let section = match section_end(headings, heading) {
+        Some(end) => lines.take(end as usize + 1 - start).collect_vec(),
+        None => lines.collect_vec(),
+    };
+
+    section
+        .into_iter()
+        .rev()
+        .skip_while(|line| line.trim().is_empty())
+        .collect_vec()
+        .into_iter()
+        .rev()
+        .join("\n")
+}
+
+/// The whole block an index belongs to, without the index: a list item with the items and paragraphs nested under it,
+/// a whole blockquote or callout, or the paragraph the index ends
+fn block_embed_text(text: &str, block: &MDIndexedBlock) -> Option<String> {
+    static LIST_ITEM_RE: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"^[ \t]*([-*+]|\d+[.)])[ \t]").unwrap());
+    static HEADING_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^#{1,6}[ \t]").unwrap());
+
+    let lines = text.lines().collect_vec();
+    let block_line = block.range.start.line as usize;
+    let line = lines.get(block_line)?;
+    // a tab indents a nested item as far as four spaces
+    let indent = |line: &str| {
+        line.chars()
+            .take_while(|char| char.is_whitespace())
+            .map(|char| if char == '\t' { 4 } else { 1 })
+            .sum::<usize>()
+    };
+    let is_quote = |line: &str| line.trim_start().starts_with('>');
+
+    let (start, end) = if is_quote(line) {
+        let start = lines[..block_line]
+            .iter()
+            .rev()
+            .take_while(|previous| is_quote(previous))
+            .count();
+        let end = lines[block_line + 1..]
+            .iter()
+            .take_while(|next| is_quote(next))
+            .count();
+        (block_line - start, block_line + end)
+    } else if LIST_ITEM_RE.is_match(line) {
+        // blank lines between the nested items don't end the item, but trailing ones aren't part of it
+        let end = lines[block_line + 1..]
+            .iter()
+            .take_while(|nested| nested.trim().is_empty() || indent(nested) > indent(line))
+            .enumerate()
+            .filter(|(_, nested)| !nested.trim().is_empty())
+            .last()
+            .map_or(0, |(last, _)| last + 1);
+        (block_line, block_line + end)
+    } else {
+        let start = lines[..block_line]
+            .iter()
+            .rev()
+            .take_while(|previous| {
+                !previous.trim().is_empty()
+                    && !HEADING_RE.is_match(previous)
+                    && !LIST_ITEM_RE.is_match(previous)
+            })
+            .count();
+        (block_line - start, block_line)
+    };
+
+    Some(
+        lines[start..=end]
+            .iter()
+            .map(|line| line.replace(&format!(" ^{}", block.index), ""))
+            .join("\n"),
+    )
+}
+
+/// The edit adding the heading one level below the `section` heading, before the next heading that isn't nested in the
+/// section; a `#` heading at the end of the text if there is no such section
+fn new_heading_edit(
+    text: &str,
+    headings: &[MDHeading],
+    heading: &str,
+    section: Option<&str>,
+) -> TextEdit {
+    let section = section.and_then(|section| {
+        headings
+            .iter()
+            .find(|it| heading_link_text(&it.heading_text) == heading_link_text(section))
+    });
+    let hashes = "#".repeat(section.map_or(1, |section| (section.level.0 + 1).min(6)));
+
+    let next_heading = section.and_then(|section| {
+        headings.iter().find(|other| {
+            other.range.start.line > section.range.start.line && other.level <= section.level
+        })
+    });
+
+    let (position, new_text) = match next_heading {
+        Some(next) => (
+            Position {
+                line: next.range.start.line,
+                character: 0,
+            },
+            format!("{} {}\n\n", hashes, heading),
+        ),
+        None => {
+            let lines = text.split('\n').collect_vec();
+            let trimmed = text.trim_end_matches('\n');
+            // a blank line before the heading, without adding to one that is already there
+            let separator = match text.len() - trimmed.len() {
+                _ if trimmed.is_empty() => "",
+                0 => "\n\n",
+                1 => "\n",
+                _ => "",
+            };
+
+            (
+                Position {
+                    line: lines.len() as u32 - 1,
+                    character: lines.last().map_or(0, |line| line.encode_utf16().count()) as u32,
+                },
+                format!("{}{} {}\n", separator, hashes, heading),
+            )
+        }
+    };
+
+    TextEdit {
+        range: tower_lsp::lsp_types::Range {
+            start: position,
+            end: position,
+        },
+        new_text,
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
 pub struct MDFootnote {
     pub index: String,
     pub footnote_text: String,
@@ -1197,7 +2520,7 @@ impl MDFootnote {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct MDTag {
     pub tag_ref: String,
     pub range: MyRange,
@@ -1226,11 +2549,21 @@ impl MDTag {
                 range: MyRange::from_range(&Rope::from_str(text), full.range()),
             });
 
-        tagged_blocks
+        // tags in the frontmatter are ranged over the tag text, as they have no `#`
+        let frontmatter_tags = frontmatter_tags(text);
+        let rope = (!frontmatter_tags.is_empty()).then(|| Rope::from_str(text));
+        let frontmatter_tags = frontmatter_tags.into_iter().flat_map(move |(tag, range)| {
+            Some(MDTag {
+                tag_ref: tag.into(),
+                range: MyRange::from_range(rope.as_ref()?, range),
+            })
+        });
+
+        frontmatter_tags.chain(tagged_blocks)
     }
 }
 
-#[derive(Clone, Hash, Eq, PartialEq, Debug)]
+#[derive(Clone, Hash, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub struct MDLinkReferenceDefinition {
     pub link_ref_name: String,
     pub range: MyRange,
@@ -1359,13 +2692,13 @@ impl Referenceable<'_> {
                 .map(|refpath| {
                     (
                         refpath.clone(),
-                        format!("{}#{}", refpath, heading.heading_text),
+                        format!("{}#{}", refpath, heading.anchor.link_text),
                     )
                 })
                 .map(|(path, full_refname)| Refname {
                     full_refname,
                     path: path.into(),
-                    infile_ref: <std::string::String as Clone>::clone(&heading.heading_text).into(),
+                    infile_ref: heading.anchor.link_text.clone().into(),
                 }),
 
             Referenceable::IndexedBlock(path, index) => get_obsidian_ref_path(root_dir, path)
@@ -1418,6 +2751,7 @@ impl Referenceable<'_> {
         root_dir: &Path,
         reference: &Reference,
         reference_path: &Path,
+        case_insensitive: bool,
     ) -> bool {
         let text = &reference.data().reference_text;
         match &self {
@@ -1456,15 +2790,19 @@ impl Referenceable<'_> {
                     ..
                 })
                 | MDHeadingLink(.., file_ref_text, _)
-                | MDIndexedBlockLink(.., file_ref_text, _) => {
-                    matches_path_or_file(file_ref_text, self.get_refname(root_dir))
-                }
+                | MDIndexedBlockLink(.., file_ref_text, _) => matches_path_or_file(
+                    file_ref_text,
+                    self.get_refname(root_dir),
+                    root_dir,
+                    reference_path,
+                    case_insensitive,
+                ),
                 Tag(_) => false,
                 Footnote(_) => false,
                 LinkRef(_) => false,
             },
 
-            _ => reference.references(root_dir, reference_path, self),
+            _ => reference.references(root_dir, reference_path, self, case_insensitive),
         }
     }
 
@@ -1506,45 +2844,95 @@ impl Referenceable<'_> {
     }
 }
 
-fn matches_path_or_file(file_ref_text: &str, refname: Option<Refname>) -> bool {
+/// `file_ref_text` may be a file name, a path from the vault root, or a path relative to the referencing file
+fn matches_path_or_file(
+    file_ref_text: &str,
+    refname: Option<Refname>,
+    root_dir: &Path,
+    reference_path: &Path,
+    case_insensitive: bool,
+) -> bool {
+    let eq = |a: &str, b: &str| match case_insensitive {
+        true => a.to_lowercase() == b.to_lowercase(),
+        false => a == b,
+    };
+
     (|| {
         let refname = refname?;
         let refname_path = refname.path.clone()?; // this function should not be used for tags, ... only for heading, files, indexed blocks
 
+        // [[#heading]] is in the same file
+        if file_ref_text.is_empty() {
+            return Some(get_obsidian_ref_path(root_dir, reference_path)? == refname_path);
+        }
+
         if file_ref_text.contains('/') {
             let file_ref_text = file_ref_text.replace(r"%20", " ");
             let file_ref_text = file_ref_text.replace(r"\ ", " ");
 
+            let relative_match = resolve_relative_path(&file_ref_text, root_dir, reference_path)
+                .is_some_and(|path| eq(&path, &refname_path));
+
             let chars: Vec<char> = file_ref_text.chars().collect();
             match chars.as_slice() {
                 &['.', '/', ref path @ ..] | &['/', ref path @ ..] => {
-                    Some(String::from_iter(path) == refname_path)
+                    Some(eq(&String::from_iter(path), &refname_path) || relative_match)
                 }
-                path => Some(String::from_iter(path) == refname_path),
+                path => Some(eq(&String::from_iter(path), &refname_path) || relative_match),
             }
         } else {
             let last_segment = refname.link_file_key()?;
 
-            Some(file_ref_text == last_segment)
+            Some(eq(file_ref_text, &last_segment))
         }
     })()
     .is_some_and(|b| b)
 }
 
+/// The vault path (as in a refname) of `file_ref_text` resolved relative to the folder of the referencing file
+fn resolve_relative_path(
+    file_ref_text: &str,
+    root_dir: &Path,
+    reference_path: &Path,
+) -> Option<String> {
+    let joined = reference_path.parent()?.join(file_ref_text);
+
+    let mut resolved = PathBuf::new();
+    for component in joined.components() {
+        match component {
+            std::path::Component::ParentDir => {
+                resolved.pop();
+            }
+            std::path::Component::CurDir => {}
+            component => resolved.push(component),
+        }
+    }
+
+    let relative = resolved.strip_prefix(root_dir).ok()?;
+
+    Some(relative.to_str()?.replace('\\', "/"))
+}
+
 // tests
 #[cfg(test)]
 mod vault_tests {
+    use std::collections::HashMap;
     use std::path::{Path, PathBuf};
 
     use itertools::Itertools;
+    use ropey::Rope;
     use tower_lsp::lsp_types::{Position, Range};
 
-    use crate::vault::{HeadingLevel, ReferenceData};
+    use crate::vault::{HeadingAnchor, HeadingLevel, ReferenceData};
     use crate::vault::{MDLinkReferenceDefinition, Refname};
 
     use super::Reference::*;
     use super::Vault;
-    use super::{MDFile, MDFootnote, MDHeading, MDIndexedBlock, MDTag, Reference, Referenceable};
+    use super::{
+        block_embed_text, heading_link_text, heading_section_text, heading_slug, moved_path,
+        new_heading_edit, vault_files, MDFile, MDFootnote, MDHeading, MDIndexedBlock, MDTag,
+        Reference, Referenceable,
+    };
 
     #[test]
     fn wiki_link_parsing() {
@@ -1630,6 +3018,34 @@ mod vault_tests {
         assert_eq!(parsed, expected)
     }
 
+    #[test]
+    fn wiki_link_same_file_heading_parsing() {
+        let text = "This is a [[#heading]] and not a [[]]";
+        let parsed = Reference::new(text).collect_vec();
+
+        let expected = vec![WikiHeadingLink(
+            ReferenceData {
+                reference_text: "#heading".into(),
+                range: tower_lsp::lsp_types::Range {
+                    start: tower_lsp::lsp_types::Position {
+                        line: 0,
+                        character: 10,
+                    },
+                    end: tower_lsp::lsp_types::Position {
+                        line: 0,
+                        character: 22,
+                    },
+                }
+                .into(),
+                ..ReferenceData::default()
+            },
+            "".into(),
+            "heading".into(),
+        )];
+
+        assert_eq!(parsed, expected)
+    }
+
     #[test]
     fn wiki_link_indexedblock_parsing() {
         let text = "This is a [[link#^index1]]";
@@ -1877,13 +3293,67 @@ mod vault_tests {
                     },
                     end: Position {
                         line: 0,
-                        character: 51,
+                        character: 51,
+                    },
+                }
+                .into(),
+            },
+            "path/to/link".into(),
+            "heading".into(),
+        )];
+
+        assert_eq!(parsed, expected)
+    }
+
+    #[test]
+    fn md_relative_link_parsing() {
+        let text = "Test text test text [link](../../projects/foo.md)";
+
+        let parsed = Reference::new(text).collect_vec();
+
+        let expected = vec![Reference::MDFileLink(ReferenceData {
+            reference_text: "../../projects/foo".into(),
+            display_text: Some("link".into()),
+            range: Range {
+                start: Position {
+                    line: 0,
+                    character: 20,
+                },
+                end: Position {
+                    line: 0,
+                    character: 49,
+                },
+            }
+            .into(),
+        })];
+
+        assert_eq!(parsed, expected)
+    }
+
+    #[test]
+    fn md_percent_encoded_link_parsing() {
+        let text = "Test text test text [link](path/to/Caf%C3%A9%20Notes#My%20Heading)";
+
+        let parsed = Reference::new(text).collect_vec();
+
+        let expected = vec![Reference::MDHeadingLink(
+            ReferenceData {
+                reference_text: "path/to/Café Notes#My Heading".into(),
+                display_text: Some("link".into()),
+                range: Range {
+                    start: Position {
+                        line: 0,
+                        character: 20,
+                    },
+                    end: Position {
+                        line: 0,
+                        character: 66,
                     },
                 }
                 .into(),
             },
-            "path/to/link".into(),
-            "heading".into(),
+            "path/to/Café Notes".into(),
+            "My Heading".into(),
         )];
 
         assert_eq!(parsed, expected)
@@ -2008,6 +3478,10 @@ more text
                     },
                 }
                 .into(),
+                anchor: HeadingAnchor {
+                    link_text: "This is a heading".into(),
+                    slug: "this-is-a-heading".into(),
+                },
                 ..Default::default()
             },
             MDHeading {
@@ -2024,6 +3498,10 @@ more text
                 }
                 .into(),
                 level: HeadingLevel(2),
+                anchor: HeadingAnchor {
+                    link_text: "This shoudl be a heading!".into(),
+                    slug: "this-shoudl-be-a-heading".into(),
+                },
             },
         ];
 
@@ -2071,14 +3549,504 @@ more text
     }
 
     #[test]
-    fn test_linkable_reference_heading() {
-        let path = Path::new("/home/vault/test.md");
+    fn test_relative_path_reference() {
+        let path = Path::new("/home/vault/projects/foo.md");
         let path_buf = path.to_path_buf();
-        let md_heading = MDHeading {
-            heading_text: "Test Heading".into(),
-            range: tower_lsp::lsp_types::Range::default().into(),
+        let md_file = MDFile::default();
+        let linkable: Referenceable = Referenceable::File(&path_buf, &md_file);
+
+        let root_dir = Path::new("/home/vault");
+        let reference_path = Path::new("/home/vault/notes/daily/today.md");
+
+        let reference = |text: &str| {
+            MDFileLink(ReferenceData {
+                reference_text: text.into(),
+                ..Default::default()
+            })
+        };
+
+        assert!(linkable.matches_reference(
+            root_dir,
+            &reference("../../projects/foo"),
+            reference_path,
+            false
+        ));
+        assert!(linkable.matches_reference(
+            root_dir,
+            &reference("projects/foo"),
+            reference_path,
+            false
+        ));
+        assert!(!linkable.matches_reference(
+            root_dir,
+            &reference("../projects/foo"),
+            reference_path,
+            false
+        ));
+    }
+
+    #[test]
+    fn test_moved_path() {
+        let renames = vec![
+            (
+                PathBuf::from("/home/vault/old.md"),
+                PathBuf::from("/home/vault/new.md"),
+            ),
+            (
+                PathBuf::from("/home/vault/projects"),
+                PathBuf::from("/home/vault/archive/projects"),
+            ),
+        ];
+
+        assert_eq!(
+            moved_path(Path::new("/home/vault/old.md"), &renames),
+            Some(PathBuf::from("/home/vault/new.md"))
+        );
+        assert_eq!(
+            moved_path(Path::new("/home/vault/projects/a/note.md"), &renames),
+            Some(PathBuf::from("/home/vault/archive/projects/a/note.md"))
+        );
+        // only whole path components are matched
+        assert_eq!(
+            moved_path(Path::new("/home/vault/projects-2/note.md"), &renames),
+            None
+        );
+        assert_eq!(
+            moved_path(Path::new("/home/vault/other.md"), &renames),
+            None
+        );
+    }
+
+    #[test]
+    fn test_excluded_paths() {
+        let vault = Vault {
+            md_files: HashMap::new().into(),
+            ropes: HashMap::new().into(),
+            attachments: vec![],
+            root_dir: PathBuf::from("/home/vault"),
+            case_insensitive_links: true,
+            exclude: ["templates", ".trash/**", "**/*.excalidraw.md"]
+                .into_iter()
+                .map(|pattern| glob::Pattern::new(pattern).unwrap())
+                .collect(),
+            link_extensions: vec!["md".into()],
+            index_ignored: false,
+            displayed_links: HashMap::new(),
+            file_names: HashMap::new(),
+        };
+
+        assert!(vault.is_excluded(Path::new("/home/vault/templates/daily.md")));
+        assert!(vault.is_excluded(Path::new("/home/vault/templates/weekly/review.md")));
+        assert!(vault.is_excluded(Path::new("/home/vault/.trash/old.md")));
+        assert!(vault.is_excluded(Path::new("/home/vault/drawings/map.excalidraw.md")));
+
+        assert!(!vault.is_excluded(Path::new("/home/vault/notes/templates.md")));
+        assert!(!vault.is_excluded(Path::new("/home/vault/projects/templates-old/a.md")));
+        assert!(!vault.is_excluded(Path::new("/elsewhere/templates/daily.md")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_vault_files_through_symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let dir = std::env::temp_dir().join(format!("oxide-{}", nanoid::nanoid!()));
+        let (vault, shared) = (dir.join("vault"), dir.join("shared"));
+        std::fs::create_dir_all(vault.join("notes")).unwrap();
+        std::fs::create_dir_all(&shared).unwrap();
+        std::fs::write(vault.join("notes/a.md"), "[[b]]").unwrap();
+        std::fs::write(shared.join("b.md"), "[[a]]").unwrap();
+        std::fs::write(shared.join("pic.png"), "").unwrap();
+        // a shared folder from outside the vault, a second path to a folder of the vault, and a loop
+        symlink(&shared, vault.join("shared")).unwrap();
+        symlink(vault.join("notes"), vault.join("linked notes")).unwrap();
+        symlink(&vault, vault.join("notes/vault")).unwrap();
+
+        let (md_files, attachments) = vault_files(&vault, false, &[]);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            md_files.into_iter().sorted().collect_vec(),
+            vec![vault.join("notes/a.md"), vault.join("shared/b.md")]
+        );
+        assert_eq!(attachments, vec![vault.join("shared/pic.png")]);
+    }
+
+    #[test]
+    fn test_vault_files_nested_roots() {
+        let test = super::TestVault::new(&[
+            ("note.md", ""),
+            ("inner/note.md", ""),
+            ("inner/pic.png", ""),
+            ("inner vault/note.md", ""),
+        ]);
+
+        let (md_files, attachments) = vault_files(&test.root_dir, false, &[test.path("inner")]);
+
+        assert_eq!(
+            md_files.into_iter().sorted().collect_vec(),
+            vec![test.path("inner vault/note.md"), test.path("note.md")]
+        );
+        assert_eq!(attachments, Vec::<PathBuf>::new());
+    }
+
+    #[test]
+    fn test_select_attachment_at_position() {
+        let note = PathBuf::from("/home/vault/notes/today.md");
+        let vault = Vault {
+            md_files: HashMap::new().into(),
+            ropes: HashMap::from([(
+                note.clone(),
+                Rope::from_str(
+                    "![[diagram.png]] [pic](../assets/pic.png) [[note]] [[/assets/pic.png]]\n",
+                ),
+            )])
+            .into(),
+            attachments: vec![
+                PathBuf::from("/home/vault/archive/old/diagram.png"),
+                PathBuf::from("/home/vault/assets/diagram.png"),
+                PathBuf::from("/home/vault/assets/pic.png"),
+            ],
+            root_dir: PathBuf::from("/home/vault"),
+            case_insensitive_links: false,
+            exclude: vec![],
+            link_extensions: vec!["md".into()],
+            index_ignored: false,
+            displayed_links: HashMap::new(),
+            file_names: HashMap::new(),
+        };
+        let at =
+            |character| vault.select_attachment_at_position(&note, Position { line: 0, character });
+
+        // by name, the attachment with the shortest path
+        assert_eq!(at(5), Some(Path::new("/home/vault/assets/diagram.png")));
+        assert_eq!(at(25), Some(Path::new("/home/vault/assets/pic.png")));
+        assert_eq!(at(45), None);
+        assert_eq!(at(60), Some(Path::new("/home/vault/assets/pic.png")));
+    }
+
+    #[test]
+    fn test_extensionless_link_attachment() {
+        let vault = |link_extensions: &[&str]| {
+            let mut vault = Vault {
+                md_files: HashMap::from([(
+                    PathBuf::from("/home/vault/notes.md"),
+                    MDFile::default(),
+                )])
+                .into(),
+                ropes: HashMap::new().into(),
+                attachments: vec![],
+                root_dir: PathBuf::from("/home/vault"),
+                case_insensitive_links: true,
+                exclude: vec![],
+                link_extensions: link_extensions.iter().map(|ext| ext.to_string()).collect(),
+                index_ignored: false,
+                displayed_links: HashMap::new(),
+                file_names: HashMap::new(),
+            };
+            vault.index_file_name(Path::new("/home/vault/notes.md"), true);
+            vault.add_attachment(Path::new("/home/vault/notes.canvas"));
+            vault.add_attachment(Path::new("/home/vault/boards/plan.canvas"));
+            vault
+        };
+
+        // the note wins by default
+        let default = vault(&["md", "canvas"]);
+        assert_eq!(default.extensionless_link_attachment("notes"), None);
+        assert_eq!(
+            default.extensionless_link_attachment("Plan"),
+            Some(Path::new("/home/vault/boards/plan.canvas"))
+        );
+        assert_eq!(
+            default.extensionless_link_attachment("/boards/plan"),
+            Some(Path::new("/home/vault/boards/plan.canvas"))
+        );
+        assert_eq!(default.extensionless_link_attachment("missing"), None);
+
+        let canvas_first = vault(&["canvas", "md"]);
+        assert_eq!(
+            canvas_first.extensionless_link_attachment("notes"),
+            Some(Path::new("/home/vault/notes.canvas"))
+        );
+
+        // only the listed extensions are linked without one
+        assert_eq!(vault(&["md"]).extensionless_link_attachment("plan"), None);
+
+        // the files are looked up by name as they are added and removed
+        let mut vault = vault(&["md", "canvas"]);
+        vault.add_attachment(Path::new("/home/vault/plan.canvas"));
+        assert_eq!(
+            vault.extensionless_link_attachment("plan"),
+            Some(Path::new("/home/vault/plan.canvas"))
+        );
+        vault.remove_file(Path::new("/home/vault/boards"));
+        vault.remove_file(Path::new("/home/vault/plan.canvas"));
+        assert_eq!(vault.extensionless_link_attachment("plan"), None);
+    }
+
+    #[test]
+    fn test_select_embedders() {
+        let files = [
+            ("a", "Top\n![[b]]"),
+            ("b", "![[c#Heading]] ![[a]]"),
+            ("c", "# Heading\n[[d]]"),
+            ("d", "![[a]]"),
+        ]
+        .map(|(name, text)| (PathBuf::from(format!("/home/vault/{}.md", name)), text));
+        let vault = Vault {
+            md_files: files
+                .iter()
+                .map(|(path, text)| {
+                    let file = MDFile {
+                        references: Reference::new(text).collect(),
+                        headings: MDHeading::new(text).collect(),
+                        path: path.clone(),
+                        ..Default::default()
+                    };
+                    (path.clone(), file)
+                })
+                .collect::<HashMap<_, _>>()
+                .into(),
+            ropes: files
+                .iter()
+                .map(|(path, text)| (path.clone(), Rope::from_str(text)))
+                .collect::<HashMap<_, _>>()
+                .into(),
+            attachments: vec![],
+            root_dir: PathBuf::from("/home/vault"),
+            case_insensitive_links: true,
+            exclude: vec![],
+            link_extensions: vec!["md".into()],
+            index_ignored: false,
+            displayed_links: HashMap::new(),
+            file_names: HashMap::new(),
+        };
+        let embedders = |name: &str, depth| {
+            vault
+                .select_embedders(
+                    Path::new(&format!("/home/vault/{}.md", name)),
+                    depth,
+                    || false,
+                )
+                .into_iter()
+                .map(|path| path.file_stem().unwrap().to_string_lossy().to_string())
+                .sorted()
+                .collect_vec()
+        };
+
+        assert_eq!(embedders("c", 1), vec!["b"]);
+        assert_eq!(embedders("c", 4), vec!["a", "b", "d"]);
+        // a plain link isn't followed
+        assert_eq!(embedders("d", 4), Vec::<String>::new());
+        // the traversal stops going around the cycle of a and b
+        assert_eq!(embedders("a", 10), vec!["a", "b", "d"]);
+        // nothing is resolved once cancelled
+        assert!(vault
+            .select_embedders(Path::new("/home/vault/c.md"), 4, || true)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_select_file_blocks() {
+        let note = PathBuf::from("/home/vault/note.md");
+        let other = PathBuf::from("/home/vault/other.md");
+        let vault = Vault {
+            md_files: HashMap::new().into(),
+            ropes: HashMap::from([
+                (
+                    note.clone(),
+                    Rope::from_str("# Note\n\n  first block\nsecond ^abc\n"),
+                ),
+                (other.clone(), Rope::from_str("other block\n")),
+            ])
+            .into(),
+            attachments: vec![],
+            root_dir: PathBuf::from("/home/vault"),
+            case_insensitive_links: false,
+            exclude: vec![],
+            link_extensions: vec!["md".into()],
+            index_ignored: false,
+            displayed_links: HashMap::new(),
+            file_names: HashMap::new(),
+        };
+
+        let blocks = vault.select_file_blocks(&note);
+        assert_eq!(
+            blocks
+                .iter()
+                .map(|block| (block.text, block.range.start.line))
+                .collect_vec(),
+            vec![("# Note", 0), ("first block", 2), ("second ^abc", 3)]
+        );
+        assert!(blocks.iter().all(|block| block.file == note));
+
+        assert!(vault
+            .select_file_blocks(Path::new("/home/vault/missing.md"))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_vault_root_reference() {
+        let root_dir = Path::new("/home/vault");
+        let reference_path = Path::new("/home/vault/notes/daily/today.md");
+        let md_file = MDFile::default();
+        let nested = PathBuf::from("/home/vault/projects/foo.md");
+        let top_level = PathBuf::from("/home/vault/foo.md");
+
+        let reference = |text: &str| {
+            WikiFileLink(ReferenceData {
+                reference_text: text.into(),
+                ..Default::default()
+            })
+        };
+        let matches = |path: &PathBuf, text: &str| {
+            Referenceable::File(path, &md_file).matches_reference(
+                root_dir,
+                &reference(text),
+                reference_path,
+                false,
+            )
+        };
+
+        assert!(matches(&nested, "/projects/foo"));
+        assert!(matches(&top_level, "/foo"));
+        assert!(!matches(&nested, "/foo"));
+        assert!(!matches(&top_level, "/projects/foo"));
+        // an absolute path on disk to a note in the vault still works
+        assert!(matches(&nested, "/home/vault/projects/foo"));
+    }
+
+    #[test]
+    fn test_case_insensitive_file_reference() {
+        let path = Path::new("/home/vault/projects/my note.md");
+        let path_buf = path.to_path_buf();
+        let md_file = MDFile::default();
+        let linkable: Referenceable = Referenceable::File(&path_buf, &md_file);
+
+        let root_dir = Path::new("/home/vault");
+        let reference_path = Path::new("/home/vault/today.md");
+
+        let reference = |text: &str| {
+            WikiFileLink(ReferenceData {
+                reference_text: text.into(),
+                ..Default::default()
+            })
+        };
+
+        assert!(linkable.matches_reference(root_dir, &reference("My Note"), reference_path, true));
+        assert!(linkable.matches_reference(
+            root_dir,
+            &reference("Projects/My Note"),
+            reference_path,
+            true
+        ));
+        assert!(!linkable.matches_reference(
+            root_dir,
+            &reference("My Note"),
+            reference_path,
+            false
+        ));
+        assert!(linkable.matches_reference(root_dir, &reference("my note"), reference_path, false));
+    }
+
+    #[test]
+    fn test_heading_ancestors() {
+        let heading = |text: &str, level: usize, line: u32| MDHeading {
+            heading_text: text.into(),
+            level: HeadingLevel(level),
+            range: Range {
+                start: Position { line, character: 0 },
+                end: Position { line, character: 1 },
+            }
+            .into(),
+            ..Default::default()
+        };
+
+        let md_file = MDFile {
+            headings: vec![
+                heading("Setup", 1, 0),
+                heading("Dependencies", 2, 1),
+                heading("Python", 3, 2),
+                heading("Tools", 2, 3),
+                heading("Rust", 3, 4),
+            ],
+            ..Default::default()
+        };
+
+        let ancestors = md_file
+            .heading_ancestors(&md_file.headings[4])
+            .into_iter()
+            .map(|heading| heading.heading_text.as_str())
+            .collect_vec();
+
+        assert_eq!(ancestors, vec!["Setup", "Tools"]);
+        assert!(md_file.heading_ancestors(&md_file.headings[0]).is_empty());
+    }
+
+    #[test]
+    fn test_setext_headings() {
+        let text = r"---
+title: Note
+---
+Title
+=====
+Subtitle
+---
+# ATX
+Underlined
+  --
+
+---
+a paragraph
+over two lines
+---
+- list item
+---
+";
+
+        let headings = MDHeading::new(text)
+            .map(|heading| {
+                (
+                    heading.heading_text,
+                    heading.level.0,
+                    heading.range.start.line,
+                    heading.range.end.line,
+                )
+            })
+            .collect_vec();
+
+        assert_eq!(
+            headings,
+            vec![
+                ("Title".to_string(), 1, 3, 4),
+                ("Subtitle".to_string(), 2, 5, 6),
+                ("ATX".to_string(), 1, 7, 7),
+                ("Underlined".to_string(), 2, 8, 9),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_has_tag() {
+        let md_file = MDFile {
+            tags: vec![MDTag {
+                tag_ref: "project/active".into(),
+                range: Default::default(),
+            }],
             ..Default::default()
         };
+
+        assert!(md_file.has_tag("project"));
+        assert!(md_file.has_tag("project/active"));
+        assert!(!md_file.has_tag("proj"));
+        assert!(!md_file.has_tag("project/active/soon"));
+    }
+
+    #[test]
+    fn test_linkable_reference_heading() {
+        let path = Path::new("/home/vault/test.md");
+        let path_buf = path.to_path_buf();
+        let md_heading = MDHeading::new("# Test Heading").next().unwrap();
         let linkable: Referenceable = Referenceable::Heading(&path_buf, &md_heading);
 
         let root_dir = Path::new("/home/vault");
@@ -2094,6 +4062,150 @@ more text
         )
     }
 
+    #[test]
+    fn test_same_file_heading_reference() {
+        let path = Path::new("/home/vault/test.md");
+        let path_buf = path.to_path_buf();
+        let md_heading = MDHeading::new("# Test Heading").next().unwrap();
+        let linkable: Referenceable = Referenceable::Heading(&path_buf, &md_heading);
+
+        let root_dir = Path::new("/home/vault");
+        let reference = WikiHeadingLink(
+            ReferenceData {
+                reference_text: "#Test Heading".into(),
+                ..Default::default()
+            },
+            "".into(),
+            "Test Heading".into(),
+        );
+
+        assert!(reference.references(root_dir, path, &linkable, false));
+        assert!(!reference.references(
+            root_dir,
+            Path::new("/home/vault/other.md"),
+            &linkable,
+            false
+        ));
+    }
+
+    #[test]
+    fn test_heading_link_text() {
+        assert_eq!(heading_link_text("  My   Heading! "), "My Heading!");
+        assert_eq!(
+            heading_link_text("My **bold** and *italic*"),
+            "My bold and italic"
+        );
+        assert_eq!(
+            heading_link_text("__Setup__ `cargo` ~~old~~ ==new=="),
+            "Setup cargo old new"
+        );
+        assert_eq!(heading_link_text("🚀 Launch plan 🎉"), "🚀 Launch plan 🎉");
+        assert_eq!(
+            heading_link_text("See [[other note|the note]] and [docs](https://docs.rs)"),
+            "See the note and docs"
+        );
+        assert_eq!(
+            heading_link_text("[[other note]] #tag a|b ^c"),
+            "other note tag a b c"
+        );
+        assert_eq!(heading_link_text("Version 1.2?"), "Version 1.2?");
+    }
+
+    #[test]
+    fn test_heading_slug() {
+        // the same on GitHub and in MkDocs
+        assert_eq!(heading_slug("Hello, World!"), "hello-world");
+        assert_eq!(heading_slug("What's New in v2.0?"), "whats-new-in-v20");
+        assert_eq!(
+            heading_slug("snake_case and kebab-case"),
+            "snake_case-and-kebab-case"
+        );
+        assert_eq!(
+            heading_slug("`cargo` **build** [[options|Options]]"),
+            "cargo-build-options"
+        );
+        assert_eq!(heading_slug("  Trimmed  "), "trimmed");
+
+        // GitHub keeps letters with accents and a hyphen for every space; MkDocs would give `cafe-menu` and `foo-bar`
+        assert_eq!(heading_slug("Café Menü"), "café-menü");
+        assert_eq!(heading_slug("Foo - Bar"), "foo---bar");
+        assert_eq!(heading_slug("C++ & Rust"), "c--rust");
+        assert_eq!(heading_slug("🚀 Launch"), "-launch");
+    }
+
+    #[test]
+    fn test_heading_slug_reference() {
+        let path = PathBuf::from("/home/vault/test.md");
+        let md_heading = MDHeading::new("# What's New in v2.0?").next().unwrap();
+        let linkable = Referenceable::Heading(&path, &md_heading);
+        let root_dir = Path::new("/home/vault");
+
+        let md_link = MDHeadingLink(
+            ReferenceData::default(),
+            "test".into(),
+            "whats-new-in-v20".into(),
+        );
+        let wikilink = WikiHeadingLink(
+            ReferenceData::default(),
+            "test".into(),
+            "whats-new-in-v20".into(),
+        );
+
+        assert!(md_link.references(root_dir, &path, &linkable, false));
+        // Obsidian only matches wikilinks by the heading text
+        assert!(!wikilink.references(root_dir, &path, &linkable, false));
+
+        // as on GitHub, the slugs of headings with the same slug are numbered
+        let headings = MDHeading::new("## Notes\n\ntext\n\n## Notes\n").collect_vec();
+        let slugs = headings
+            .iter()
+            .map(|it| it.anchor.slug.as_str())
+            .collect_vec();
+        assert_eq!(slugs, vec!["notes", "notes-1"]);
+
+        let md_link =
+            |slug: &str| MDHeadingLink(ReferenceData::default(), "test".into(), slug.into());
+        let [first, second] =
+            [&headings[0], &headings[1]].map(|it| Referenceable::Heading(&path, it));
+        assert!(md_link("notes").references(root_dir, &path, &first, false));
+        assert!(!md_link("notes").references(root_dir, &path, &second, false));
+        assert!(md_link("notes-1").references(root_dir, &path, &second, false));
+        assert!(!md_link("notes-1").references(root_dir, &path, &first, false));
+    }
+
+    #[test]
+    fn test_normalized_heading_reference() {
+        let path = Path::new("/home/vault/test.md");
+        let path_buf = path.to_path_buf();
+        let md_heading = MDHeading::new("# 🚀 My **Heading**!").next().unwrap();
+        let linkable: Referenceable = Referenceable::Heading(&path_buf, &md_heading);
+        let root_dir = Path::new("/home/vault");
+
+        let refname = linkable.get_refname(root_dir).unwrap();
+        assert_eq!(refname.full_refname, "test#🚀 My Heading!");
+
+        for text in [
+            "[[test#🚀 My Heading!]]",
+            "[[test#🚀 My *Heading*!]]",
+            "[[test#🚀  My Heading! ]]",
+        ] {
+            let reference = Reference::new(text).next().unwrap();
+            assert!(
+                linkable.matches_reference(root_dir, &reference, path, false),
+                "{}",
+                text
+            );
+        }
+
+        let reference = Reference::new("[[test#🚀 My Heading]]").next().unwrap();
+        assert!(!linkable.matches_reference(root_dir, &reference, path, false));
+
+        let md_heading = MDHeading::new("# Version 1.2").next().unwrap();
+        let linkable: Referenceable = Referenceable::Heading(&path_buf, &md_heading);
+        let reference = Reference::new("[[test#Version 1.2]]").next().unwrap();
+        assert!(linkable.matches_reference(root_dir, &reference, path, false));
+    }
+
     #[test]
     fn test_linkable_reference_indexed_block() {
         let path = Path::new("/home/vault/test.md");
@@ -2235,6 +4347,122 @@ and a third tag#notatag [[link#not a tag]]
         assert_eq!(parsed, expected)
     }
 
+    #[test]
+    fn test_frontmatter_tag_references() {
+        let text = "---\ntags: [project]\n---\n\nSee #project/rust and #other";
+        let path = PathBuf::from("/home/vault/test.md");
+        let root_dir = Path::new("/home/vault");
+
+        let tags = MDTag::new(text).collect_vec();
+        assert_eq!(
+            tags[0],
+            MDTag {
+                tag_ref: "project".into(),
+                range: Range {
+                    start: Position {
+                        line: 1,
+                        character: 7,
+                    },
+                    end: Position {
+                        line: 1,
+                        character: 14,
+                    },
+                }
+                .into(),
+            }
+        );
+
+        let referenceable = Referenceable::Tag(&path, &tags[0]);
+        let references = Reference::new(text)
+            .filter(|reference| referenceable.matches_reference(root_dir, reference, &path, false))
+            .map(|reference| reference.data().reference_text.clone())
+            .collect_vec();
+
+        assert_eq!(references, vec!["#project", "#project/rust"]);
+    }
+
+    #[test]
+    fn test_block_embed_text() {
+        let text = "# Heading\nFirst line\nsecond line ^para\n\n- item ^item\n    - nested\n    - nested 2\n- next item";
+        let blocks = MDIndexedBlock::new(text).collect_vec();
+
+        assert_eq!(
+            block_embed_text(text, &blocks[0]).as_deref(),
+            Some("First line\nsecond line")
+        );
+        assert_eq!(
+            block_embed_text(text, &blocks[1]).as_deref(),
+            Some("- item\n    - nested\n    - nested 2")
+        );
+
+        // loose nested items and paragraphs, indented with tabs or spaces
+        let text = "- item ^loose\n\t- nested\n\n  continued\n\t\t- deeper\n\n- next item";
+        let blocks = MDIndexedBlock::new(text).collect_vec();
+        assert_eq!(
+            block_embed_text(text, &blocks[0]).as_deref(),
+            Some("- item\n\t- nested\n\n  continued\n\t\t- deeper")
+        );
+
+        // the whole blockquote or callout, whichever line the index is on
+        let text = "Before\n> [!note] Title\n> quoted ^quote\n> more\n\nAfter";
+        let blocks = MDIndexedBlock::new(text).collect_vec();
+        assert_eq!(
+            block_embed_text(text, &blocks[0]).as_deref(),
+            Some("> [!note] Title\n> quoted\n> more")
+        );
+    }
+
+    #[test]
+    fn test_heading_section_text() {
+        let text = "# Top\nintro\n## First\none\n### Nested\ntwo\n\n## Second\nthree\n";
+        let headings = MDHeading::new(text).collect_vec();
+        let section = |index: usize| heading_section_text(text, &headings, &headings[index]);
+
+        // nested headings are part of the section, the next one of the same level ends it
+        assert_eq!(section(1), "## First\none\n### Nested\ntwo");
+        assert_eq!(section(2), "### Nested\ntwo");
+        // the last section runs to the end of the file
+        assert_eq!(section(3), "## Second\nthree");
+        assert_eq!(section(0), text.trim_end());
+    }
+
+    #[test]
+    fn test_new_heading_edit() {
+        let edit = |text: &str, section| {
+            let headings = MDHeading::new(text).collect_vec();
+            let edit = new_heading_edit(text, &headings, "New", section);
+            assert_eq!(edit.range.start, edit.range.end);
+            (edit.range.start, edit.new_text)
+        };
+        let position = |line, character| Position { line, character };
+
+        assert_eq!(
+            edit("# Note\nSome text", None),
+            (position(1, 9), "\n\n# New\n".into())
+        );
+        assert_eq!(
+            edit("# Note\nSome text\n", Some("Missing")),
+            (position(2, 0), "\n# New\n".into())
+        );
+        assert_eq!(edit("", None), (position(0, 0), "# New\n".into()));
+        // columns count UTF-16 code units
+        assert_eq!(
+            edit("# Note\nDone 🎉", None),
+            (position(1, 7), "\n\n# New\n".into())
+        );
+
+        // at the end of the section, after its nested headings
+        let text = "# Note\n## Log\n- a\n### Day\n- b\n## Later\n";
+        assert_eq!(
+            edit(text, Some("Log")),
+            (position(5, 0), "### New\n\n".into())
+        );
+        assert_eq!(
+            edit(text, Some("Later")),
+            (position(6, 0), "\n### New\n".into())
+        );
+    }
+
     #[test]
     fn test_obsidian_footnote() {
         let text = "[^1]: This is a footnote";
@@ -2370,4 +4598,101 @@ Continued
 
         assert_eq!(parsed, expected);
     }
+
+    #[test]
+    fn test_is_indexed() {
+        let files = [
+            (".gitignore", "drafts/\n*.log\n"),
+            ("note.md", ""),
+            ("drafts/idea.md", ""),
+            ("notes/.ignore", "!kept.log\n"),
+            (".obsidian/app.json", ""),
+        ];
+        let test_vault = super::TestVault::new(&files);
+        let is_indexed = |file: &str| test_vault.vault.is_indexed(&test_vault.path(file));
+
+        assert!(is_indexed("new.md"));
+        assert!(is_indexed("assets/new.png"));
+        assert!(!is_indexed("drafts/new.md"));
+        assert!(!is_indexed("debug.log"));
+        assert!(is_indexed("notes/kept.log"));
+        assert!(!is_indexed(".obsidian/workspace.json"));
+        assert!(!is_indexed(".git/index"));
+        assert!(!is_indexed("logseq/config.md"));
+        assert!(!test_vault.vault.is_indexed(Path::new("/elsewhere/note.md")));
+
+        let test_vault =
+            super::TestVault::with_settings(&files, |settings| settings.index_ignored_files = true);
+        assert!(test_vault
+            .vault
+            .is_indexed(&test_vault.path("drafts/new.md")));
+        assert!(test_vault
+            .vault
+            .is_indexed(&test_vault.path(".obsidian/workspace.json")));
+    }
+
+    #[test]
+    fn test_links_to_attachment() {
+        let files = [
+            ("today.md", "[[notes]] [[notes#Notes]]"),
+            ("notes.md", "# Notes\n"),
+            ("notes.canvas", "{}"),
+        ];
+        let test_vault = super::TestVault::with_settings(&files, |settings| {
+            settings.link_extensions = vec!["canvas".into(), "md".into()]
+        });
+        let vault = &test_vault.vault;
+        let today = test_vault.path("today.md");
+        let notes = test_vault.path("notes.md");
+
+        // `[[notes]]` goes to the canvas, so it isn't a reference of the note, though the heading link is
+        let references = vault.select_references(Some(&today)).unwrap();
+        assert!(vault
+            .select_referenceables_for_reference(references[0].1, &today)
+            .is_empty());
+        assert_eq!(
+            vault.select_referenceables_for_reference(references[1].1, &today)[0].get_path(),
+            notes
+        );
+        let note = Referenceable::File(&notes, &vault.md_files[&notes]);
+        let note_references = vault.select_references_for_referenceable(&note).unwrap();
+        assert!(note_references
+            .iter()
+            .all(|(_, reference)| !matches!(reference, Reference::WikiFileLink(..))));
+    }
+
+    #[test]
+    fn test_block_preview() {
+        let text = "# Heading\n\n- item ^item\n    - nested\n- next item\n";
+        let test_vault = super::TestVault::new(&[("note.md", text)]);
+        let path = test_vault.path("note.md");
+        let vault = &test_vault.vault;
+        let block = &vault.md_files[&path].indexed_blocks[0];
+
+        // the whole block, with its nested items, as it is embedded
+        let preview =
+            vault.select_referenceable_preview(&Referenceable::IndexedBlock(&path, block));
+        assert!(
+            matches!(preview, Some(super::Preview::Text(text)) if text == "- item\n    - nested")
+        );
+    }
+
+    #[test]
+    fn test_remove_folder() {
+        let mut test_vault = super::TestVault::new(&[
+            ("projects/a.md", ""),
+            ("projects/old/b.md", ""),
+            ("projects/pic.png", ""),
+            ("projects-archive/c.md", ""),
+        ]);
+        let projects = test_vault.path("projects");
+
+        assert!(test_vault.vault.remove_file(&projects));
+        // the folder named like it isn't in it
+        let notes = test_vault.vault.md_files.keys().cloned().collect_vec();
+        assert_eq!(notes, vec![test_vault.path("projects-archive/c.md")]);
+        assert!(test_vault.vault.attachments.is_empty());
+
+        assert!(!test_vault.vault.remove_file(&projects));
+    }
 }
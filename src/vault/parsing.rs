@@ -1,53 +1,294 @@
 use once_cell::sync::Lazy;
 use regex::Regex;
 use ropey::Rope;
+use serde::{Deserialize, Serialize};
 
 use super::{MyRange, Rangeable};
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct MDCodeBlock {
     range: MyRange,
 }
 
 impl MDCodeBlock {
+    /// Fenced code blocks (with ``` or ~~~), indented code blocks and inline code spans (with any number of backticks).
+    /// A fence that is never closed runs to the end of the file, as it does when the note is rendered.
     pub fn new(text: &str) -> impl Iterator<Item = MDCodeBlock> + '_ {
-        static RE: Lazy<Regex> = Lazy::new(|| {
-            Regex::new(r"(^|\n)(?<fullblock>``` *(?<lang>[^\n]+)?\n(?<code>(\n|.)*?)\n```)")
-                .expect("Codeblock Regex Not Constructing")
-        });
+        let rope = Rope::from_str(text);
 
-        let captures = RE.captures_iter(text);
+        code_ranges(text).into_iter().map(move |range| MDCodeBlock {
+            range: MyRange::from_range(&rope, range),
+        })
+    }
+}
 
-        static SHORT_RE: Lazy<Regex> = Lazy::new(|| {
-            Regex::new(r"(?<fullblock>`[^`\n]+?`)")
-                .expect("Short code-block Regex Not Constructing")
-        });
+/// The byte ranges of the code blocks and code spans
+fn code_ranges(text: &str) -> Vec<std::ops::Range<usize>> {
+    let blocks = code_block_ranges(text);
+
+    // code spans are only looked for in the lines outside of code blocks
+    let spans = lines_with_offsets(text)
+        .filter(|(offset, _)| !blocks.iter().any(|block| block.contains(offset)))
+        .flat_map(|(offset, line)| {
+            code_span_ranges(line)
+                .into_iter()
+                .map(move |span| offset + span.start..offset + span.end)
+        })
+        .collect::<Vec<_>>();
 
-        let short_captures = SHORT_RE.captures_iter(text);
+    blocks.into_iter().chain(spans).collect()
+}
+
+/// An Obsidian comment, `%% hidden %%`, which isn't rendered; the links and tags in it are not parsed
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct MDComment {
+    range: MyRange,
+}
 
-        captures.chain(short_captures).flat_map(|captures| {
-            Some(MDCodeBlock {
-                range: MyRange::from_range(
-                    &Rope::from_str(text),
-                    captures.name("fullblock")?.range(),
-                ),
+impl MDComment {
+    /// Comments on one line or across several; a `%%` in code doesn't start or end one, and a comment that is never
+    /// closed runs to the end of the file, as it does when the note is rendered
+    pub fn new(text: &str) -> impl Iterator<Item = MDComment> + '_ {
+        let rope = Rope::from_str(text);
+
+        comment_ranges(text)
+            .into_iter()
+            .map(move |range| MDComment {
+                range: MyRange::from_range(&rope, range),
             })
-        })
     }
 }
 
+fn comment_ranges(text: &str) -> Vec<std::ops::Range<usize>> {
+    let code = code_ranges(text);
+
+    let mut ranges = vec![];
+    let mut start = None;
+    for (offset, _) in text.match_indices("%%") {
+        if code.iter().any(|range| range.contains(&offset)) {
+            continue;
+        }
+
+        match start {
+            None => start = Some(offset),
+            Some(comment_start) => {
+                ranges.push(comment_start..offset + 2);
+                start = None;
+            }
+        }
+    }
+
+    if let Some(comment_start) = start {
+        ranges.push(comment_start..text.len());
+    }
+
+    ranges
+}
+
+impl Rangeable for MDComment {
+    fn range(&self) -> &MyRange {
+        &self.range
+    }
+}
+
+/// Each line with the byte offset it starts at, without its line break
+pub fn lines_with_offsets(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.split_inclusive('\n').scan(0, |offset, line| {
+        let start = *offset;
+        *offset += line.len();
+        Some((start, line.trim_end_matches(['\n', '\r'])))
+    })
+}
+
+/// The byte ranges of the fenced and indented code blocks, from the start of their first line to the end of their last
+fn code_block_ranges(text: &str) -> Vec<std::ops::Range<usize>> {
+    let mut ranges = vec![];
+    // the fence character, the fence length and where the block starts
+    let mut fence: Option<(char, usize, usize)> = None;
+    // where an indented code block starts and where its last non blank line ends
+    let mut indented: Option<(usize, usize)> = None;
+    let mut prev_blank = true;
+    // indented lines in a list continue a list item; they are not code
+    let mut in_list = false;
+    let mut in_frontmatter = text.starts_with("---\n") || text.starts_with("---\r\n");
+
+    for (i, (offset, line)) in lines_with_offsets(text).enumerate() {
+        let trimmed = line.trim_start();
+        let line_end = offset + line.trim_end().len();
+
+        if in_frontmatter {
+            in_frontmatter = i == 0 || line.trim_end() != "---";
+            continue;
+        }
+
+        if let Some((fence_char, fence_len, start)) = fence {
+            let closing = trimmed.trim_end();
+            if closing.len() >= fence_len && closing.chars().all(|c| c == fence_char) {
+                ranges.push(start..line_end);
+                fence = None;
+                prev_blank = false;
+            }
+            continue;
+        }
+
+        let is_blank = trimmed.is_empty();
+        let is_indented = line.starts_with("    ") || line.starts_with('\t');
+
+        if let Some((start, end)) = indented {
+            if is_indented || is_blank {
+                if !is_blank {
+                    indented = Some((start, line_end));
+                }
+                prev_blank = is_blank;
+                continue;
+            }
+            ranges.push(start..end);
+            indented = None;
+        }
+
+        if let Some((fence_char, fence_len)) = opening_fence(trimmed) {
+            fence = Some((fence_char, fence_len, offset));
+            if line == trimmed {
+                in_list = false;
+            }
+            continue;
+        }
+
+        if is_indented && prev_blank && !in_list {
+            indented = Some((offset, line_end));
+            continue;
+        }
+
+        if !is_blank {
+            static LIST_ITEM_RE: Lazy<Regex> =
+                Lazy::new(|| Regex::new(r"^([-*+]|\d+[.)])(\s|$)").unwrap());
+            if LIST_ITEM_RE.is_match(trimmed) {
+                in_list = true;
+            } else if line == trimmed && prev_blank {
+                in_list = false;
+            }
+        }
+        prev_blank = is_blank;
+    }
+
+    if let Some((_, _, start)) = fence {
+        ranges.push(start..text.trim_end().len().max(start));
+    }
+    if let Some((start, end)) = indented {
+        ranges.push(start..end);
+    }
+
+    ranges
+}
+
+/// The fence character and length of a line opening a fenced code block, like ```rust or ~~~
+fn opening_fence(trimmed: &str) -> Option<(char, usize)> {
+    let fence_char = trimmed.chars().next().filter(|c| matches!(c, '`' | '~'))?;
+    let fence_len = trimmed.chars().take_while(|c| *c == fence_char).count();
+    let info = &trimmed[fence_len..];
+
+    // the info string of a backtick fence can't have backticks, so ```code``` is a code span
+    (fence_len >= 3 && !(fence_char == '`' && info.contains('`')))
+        .then_some((fence_char, fence_len))
+}
+
+/// The byte ranges of the code spans in a line. A span is closed by a run of exactly as many backticks as opened it,
+/// so ``a `[[b]]` c`` is one span; an unclosed run of backticks is literal text.
+fn code_span_ranges(line: &str) -> Vec<std::ops::Range<usize>> {
+    let runs = line.char_indices().filter(|(_, c)| *c == '`').fold(
+        Vec::<(usize, usize)>::new(),
+        |mut runs, (i, _)| {
+            match runs.last_mut() {
+                Some((start, len)) if *start + *len == i => *len += 1,
+                _ => runs.push((i, 1)),
+            }
+            runs
+        },
+    );
+
+    let mut spans = vec![];
+    let mut i = 0;
+    while let Some((start, len)) = runs.get(i) {
+        match runs[i + 1..]
+            .iter()
+            .position(|(_, closing_len)| closing_len == len)
+        {
+            Some(j) => {
+                let (closing_start, closing_len) = runs[i + 1 + j];
+                spans.push(*start..closing_start + closing_len);
+                i += j + 2;
+            }
+            None => i += 1,
+        }
+    }
+
+    spans
+}
+
 impl Rangeable for MDCodeBlock {
     fn range(&self) -> &MyRange {
         &self.range
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct MDCallout {
+    /// The callout type as written between `[!` and `]`
+    pub callout_type: String,
+    /// The last line of the callout's quote block
+    pub last_line: u32,
+    range: MyRange,
+}
+
+impl MDCallout {
+    pub fn new(text: &str) -> impl Iterator<Item = MDCallout> + '_ {
+        static RE: Lazy<Regex> = Lazy::new(|| {
+            Regex::new(r"(?m)^(?<fullcallout>(> *)+\[!(?<type>[^\]\s]+)\][+-]?)")
+                .expect("Callout Regex Not Constructing")
+        });
+
+        let rope = Rope::from_str(text);
+
+        RE.captures_iter(text).flat_map(move |captures| {
+            let full = captures.name("fullcallout")?;
+            let range = MyRange::from_range(&rope, full.range());
+
+            // the callout continues while the lines are quoted at least as deep as its header
+            let depth = full.as_str().matches('>').count();
+            let quote_depth = |line: &str| {
+                line.chars()
+                    .take_while(|c| *c == '>' || *c == ' ')
+                    .filter(|c| *c == '>')
+                    .count()
+            };
+            let continued_lines = text
+                .lines()
+                .skip(range.start.line as usize + 1)
+                .take_while(|line| quote_depth(line) >= depth)
+                .count();
+
+            Some(MDCallout {
+                callout_type: captures.name("type")?.as_str().to_string(),
+                last_line: range.start.line + continued_lines as u32,
+                range,
+            })
+        })
+    }
+}
+
+impl Rangeable for MDCallout {
+    fn range(&self) -> &MyRange {
+        &self.range
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use itertools::Itertools;
     use tower_lsp::lsp_types::{Position, Range};
 
-    use super::MDCodeBlock;
+    use super::{MDCallout, MDCodeBlock, MDComment};
+    use crate::vault::{Rangeable, Reference};
 
     #[test]
     fn test_code_block_parsing() {
@@ -290,4 +531,148 @@ fj aklfjd
 
         assert_eq!(parsed, expected)
     }
+
+    /// (start line, start character, end line, end character) of each code block
+    fn code_block_ranges(text: &str) -> Vec<(u32, u32, u32, u32)> {
+        MDCodeBlock::new(text)
+            .map(|block| {
+                (
+                    block.range.start.line,
+                    block.range.start.character,
+                    block.range.end.line,
+                    block.range.end.character,
+                )
+            })
+            .collect_vec()
+    }
+
+    /// (start line, start character, end line, end character) of each comment
+    fn comment_ranges(text: &str) -> Vec<(u32, u32, u32, u32)> {
+        MDComment::new(text)
+            .map(|comment| {
+                (
+                    comment.range.start.line,
+                    comment.range.start.character,
+                    comment.range.end.line,
+                    comment.range.end.character,
+                )
+            })
+            .collect_vec()
+    }
+
+    #[test]
+    fn test_comments() {
+        assert_eq!(
+            comment_ranges("[[real]] %% [[hidden]] #tag %% [[also real]]"),
+            vec![(0, 9, 0, 30)]
+        );
+        assert_eq!(
+            comment_ranges("%% start\n[[hidden]]\n#hidden end %% [[real]]"),
+            vec![(0, 0, 2, 14)]
+        );
+        // a `%%` in code is literal, and an unclosed comment runs to the end of the file
+        assert_eq!(
+            comment_ranges("`%%` [[real]] %% unclosed\n[[hidden]]"),
+            vec![(0, 14, 1, 10)]
+        );
+    }
+
+    #[test]
+    fn test_links_in_comments() {
+        let text = "[[one]] %% [[two]]\n[[three]] %% [[four]]\n%%\n[[five]]\n%%";
+        let comments = MDComment::new(text).collect_vec();
+
+        let links = Reference::new(text)
+            .filter(|link| !comments.iter().any(|comment| comment.includes(link)))
+            .map(|link| link.data().reference_text.clone())
+            .collect_vec();
+
+        assert_eq!(links, vec!["one", "four"]);
+    }
+
+    #[test]
+    fn test_nested_backtick_code_spans() {
+        let test =
+            "a ``code with `[[not a link]]` inside`` [[link]] `x` and ``` `` ``` and ` unclosed";
+
+        assert_eq!(
+            code_block_ranges(test),
+            vec![(0, 2, 0, 39), (0, 49, 0, 52), (0, 57, 0, 67)]
+        );
+    }
+
+    #[test]
+    fn test_fenced_code_blocks() {
+        let test = r"````md
+```
+[[inside]]
+```
+````
+~~~
+`[[inside]]`
+~~~
+text `span`
+```
+never closed [[inside]]
+";
+
+        // the code span in the tilde fence is part of the block
+        assert_eq!(
+            code_block_ranges(test),
+            vec![(0, 0, 4, 4), (5, 0, 7, 3), (9, 0, 10, 23), (8, 5, 8, 11)]
+        );
+    }
+
+    #[test]
+    fn test_indented_code_blocks() {
+        let test = r"---
+tags:
+
+    - yaml
+---
+Paragraph
+    continued paragraph, not code
+
+    [[code]]
+
+    more [[code]]
+Paragraph
+
+- list item
+
+    continued list item [[link]]
+";
+
+        assert_eq!(code_block_ranges(test), vec![(8, 0, 10, 17)]);
+    }
+
+    #[test]
+    fn test_callout_parsing() {
+        let test = r"> [!note] Title
+> text
+
+> > [!custom-type]- Folded
+> > text
+
+> [!not closed
+[!tip] not a callout";
+
+        let parsed = MDCallout::new(test)
+            .map(|callout| {
+                (
+                    callout.callout_type,
+                    callout.range.start.line,
+                    callout.last_line,
+                )
+            })
+            .collect_vec();
+
+        assert_eq!(
+            parsed,
+            vec![
+                ("note".to_string(), 0, 1),
+                ("custom-type".to_string(), 3, 4)
+            ]
+        )
+    }
 }
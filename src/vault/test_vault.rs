@@ -0,0 +1,101 @@
+use std::path::{Path, PathBuf};
+
+use itertools::Itertools;
+use ropey::Rope;
+use tower_lsp::lsp_types::TextEdit;
+
+use crate::config::Settings;
+
+use super::Vault;
+
+/// A vault written to a new folder of the temp dir, for testing features that read the vault and the files in it. The
+/// vault and its cache are in the folder, which is removed when it is dropped.
+pub struct TestVault {
+    folder: PathBuf,
+    pub root_dir: PathBuf,
+    pub settings: Settings,
+    pub vault: Vault,
+}
+
+impl TestVault {
+    /// A vault of the files, by their path from the vault root and their text, with the default settings
+    pub fn new(files: &[(&str, &str)]) -> TestVault {
+        TestVault::with_settings(files, |_| ())
+    }
+
+    /// A vault of the files, with the default settings as changed by `configure`; the user's settings file isn't read
+    pub fn with_settings(
+        files: &[(&str, &str)],
+        configure: impl FnOnce(&mut Settings),
+    ) -> TestVault {
+        let folder = std::env::temp_dir().join(format!("oxide-{}", nanoid::nanoid!()));
+        std::fs::create_dir_all(folder.join("vault")).unwrap();
+        // the temp dir may be behind a symlink, like on macOS
+        let folder = folder.canonicalize().unwrap();
+        let root_dir = folder.join("vault");
+
+        for (file, text) in files {
+            let path = root_dir.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, text).unwrap();
+        }
+
+        let mut settings = Settings::without_user_settings(&root_dir).unwrap();
+        settings.cache_folder = folder.join("cache").to_string_lossy().into_owned();
+        configure(&mut settings);
+        let vault = Vault::construct_vault(&settings, &root_dir, &[], |_, _| ()).unwrap();
+
+        TestVault {
+            folder,
+            root_dir,
+            settings,
+            vault,
+        }
+    }
+
+    /// The path of a file in the vault, from its path from the vault root
+    pub fn path(&self, file: &str) -> PathBuf {
+        self.root_dir.join(file)
+    }
+
+    /// Another vault of a folder of this one, `""` for the root, without the `nested` folders, as for workspace folders
+    /// inside each other
+    pub fn folder_vault(&self, folder: &str, nested: &[&str]) -> Vault {
+        let root_dir = match folder {
+            "" => self.root_dir.clone(),
+            folder => self.path(folder),
+        };
+        let nested = nested
+            .iter()
+            .map(|folder| self.path(folder))
+            .collect::<Vec<_>>();
+
+        Vault::construct_vault(&self.settings, &root_dir, &nested, |_, _| ()).unwrap()
+    }
+
+    /// Replace the text of a file, as an editor does while the file is edited
+    pub fn edit(&mut self, path: &Path, text: &str) {
+        self.vault.update_file(&self.settings, path, text);
+    }
+}
+
+impl Drop for TestVault {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.folder);
+    }
+}
+
+/// The text with the edits applied; their characters are taken as char indexes, which they are for ascii text
+pub fn apply_edits(text: &str, edits: &[TextEdit]) -> String {
+    let mut rope = Rope::from_str(text);
+    for edit in edits.iter().sorted_by_key(|edit| edit.range.start).rev() {
+        let char_index =
+            |line: u32, character: u32| rope.line_to_char(line as usize) + character as usize;
+        let start = char_index(edit.range.start.line, edit.range.start.character);
+        let end = char_index(edit.range.end.line, edit.range.end.character);
+        rope.remove(start..end);
+        rope.insert(start, &edit.new_text);
+    }
+
+    rope.to_string()
+}